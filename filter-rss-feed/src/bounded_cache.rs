@@ -0,0 +1,186 @@
+use std::fmt;
+use std::num::NonZeroUsize;
+use std::time::Duration;
+
+use lru::LruCache;
+use web_time::Instant;
+
+/// How often expired entries are looked for, so that a cache which is mostly
+/// expired doesn't have to be scanned on every write.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Something kept in a [`BoundedCache`], which knows roughly how much memory
+/// it takes.
+pub(crate) trait Weigh {
+    fn weight(&self) -> usize;
+}
+
+/// An in-memory map for caches which live as long as the process, holding at
+/// most `max_entries` entries and `max_bytes` of them. When either is
+/// exceeded the least recently used entries are dropped.
+pub(crate) struct BoundedCache<V> {
+    entries: LruCache<String, V>,
+    bytes: usize,
+    max_bytes: usize,
+    last_pruned: Instant,
+}
+
+impl<V> fmt::Debug for BoundedCache<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BoundedCache")
+            .field("entries", &self.entries.len())
+            .field("bytes", &self.bytes)
+            .field("max_entries", &self.entries.cap())
+            .field("max_bytes", &self.max_bytes)
+            .finish()
+    }
+}
+
+impl<V: Weigh> BoundedCache<V> {
+    pub fn new(max_entries: usize, max_bytes: usize) -> Self {
+        Self {
+            entries: LruCache::new(NonZeroUsize::new(max_entries).unwrap_or(NonZeroUsize::MIN)),
+            bytes: 0,
+            max_bytes,
+            last_pruned: Instant::now(),
+        }
+    }
+
+    /// The entry for `key`, which becomes the most recently used.
+    pub fn get(&mut self, key: &str) -> Option<&V> {
+        self.entries.get(key)
+    }
+
+    /// Stores `value`, dropping the least recently used entries to make room.
+    /// A value bigger than the whole cache isn't stored at all.
+    pub fn insert(&mut self, key: String, value: V) {
+        self.remove(&key);
+
+        let weight = value.weight();
+        if weight > self.max_bytes {
+            return;
+        }
+
+        if let Some((_, evicted)) = self.entries.push(key, value) {
+            self.bytes = self.bytes.saturating_sub(evicted.weight());
+        }
+        self.bytes += weight;
+
+        while self.bytes > self.max_bytes {
+            let Some((_, evicted)) = self.entries.pop_lru() else {
+                break;
+            };
+            self.bytes = self.bytes.saturating_sub(evicted.weight());
+        }
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<V> {
+        let value = self.entries.pop(key)?;
+        self.bytes = self.bytes.saturating_sub(value.weight());
+        Some(value)
+    }
+
+    /// Drops the entries which are `expired`, if it's been a while since
+    /// this was last done.
+    pub fn prune(&mut self, mut expired: impl FnMut(&V) -> bool) {
+        if self.last_pruned.elapsed() < PRUNE_INTERVAL {
+            return;
+        }
+        self.last_pruned = Instant::now();
+
+        let keys: Vec<String> = self
+            .entries
+            .iter()
+            .filter(|(_, value)| expired(value))
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in keys {
+            self.remove(&key);
+        }
+    }
+
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    #[cfg(test)]
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    #[cfg(test)]
+    pub fn force_prune(&mut self, expired: impl FnMut(&V) -> bool) {
+        self.last_pruned = Instant::now() - PRUNE_INTERVAL;
+        self.prune(expired);
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+
+    impl Weigh for &'static str {
+        fn weight(&self) -> usize {
+            self.len()
+        }
+    }
+
+    #[test]
+    fn test_max_entries() {
+        let mut cache = BoundedCache::new(2, usize::MAX);
+        cache.insert("a".to_string(), "1");
+        cache.insert("b".to_string(), "2");
+        cache.get("a");
+        cache.insert("c".to_string(), "3");
+
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get("a"), Some(&"1"));
+        assert_eq!(cache.get("b"), None);
+        assert_eq!(cache.get("c"), Some(&"3"));
+    }
+
+    #[test]
+    fn test_max_bytes() {
+        let mut cache = BoundedCache::new(100, 10);
+        cache.insert("a".to_string(), "xxxx");
+        cache.insert("b".to_string(), "xxxx");
+        cache.insert("c".to_string(), "xxxx");
+
+        assert_eq!(cache.get("a"), None);
+        assert_eq!(cache.bytes(), 8);
+
+        // Too big to keep at all, and nothing is dropped for it
+        cache.insert("d".to_string(), "xxxxxxxxxxx");
+        assert_eq!(cache.get("d"), None);
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn test_replace() {
+        let mut cache = BoundedCache::new(100, 100);
+        cache.insert("a".to_string(), "xxxx");
+        cache.insert("a".to_string(), "xx");
+
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.bytes(), 2);
+        assert_eq!(cache.remove("a"), Some("xx"));
+        assert_eq!(cache.bytes(), 0);
+    }
+
+    #[test]
+    fn test_prune() {
+        let mut cache = BoundedCache::new(100, 100);
+        cache.insert("a".to_string(), "old");
+        cache.insert("b".to_string(), "new");
+
+        // Not due yet
+        cache.prune(|value| *value == "old");
+        assert_eq!(cache.len(), 2);
+
+        cache.force_prune(|value| *value == "old");
+        assert_eq!(cache.get("a"), None);
+        assert_eq!(cache.get("b"), Some(&"new"));
+        assert_eq!(cache.bytes(), 3);
+    }
+}
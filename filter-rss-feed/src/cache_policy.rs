@@ -0,0 +1,182 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use headers::{Age, CacheControl, Date, Expires, HeaderMapExt};
+use http::HeaderMap;
+
+use crate::http_client::CacheConfig;
+use crate::streaming::UpdateHints;
+
+/// The current time as a `std` `SystemTime`, which the `headers` crate
+/// works with. `SystemTime::now` panics on WASM, so go via `web_time`.
+pub(crate) fn now() -> SystemTime {
+    let since_epoch = web_time::SystemTime::now()
+        .duration_since(web_time::UNIX_EPOCH)
+        .unwrap_or_default();
+
+    UNIX_EPOCH + since_epoch
+}
+
+/// How long the upstream response says it stays fresh for, from the time it
+/// was generated (RFC 9111 §4.2.1). We are a shared cache, so `s-maxage`
+/// takes precedence over `max-age`, which takes precedence over `Expires`.
+/// If the headers don't say, the feed's own update hints are used.
+pub(crate) fn freshness_lifetime(headers: &HeaderMap, hints: &UpdateHints) -> Option<Duration> {
+    if let Some(cache_control) = headers.typed_get::<CacheControl>() {
+        if let Some(lifetime) = cache_control.s_max_age().or(cache_control.max_age()) {
+            return Some(lifetime);
+        }
+    }
+
+    if let Some(expires) = headers.typed_get::<Expires>() {
+        let date = headers
+            .typed_get::<Date>()
+            .map(SystemTime::from)
+            .unwrap_or_else(now);
+
+        return Some(
+            SystemTime::from(expires)
+                .duration_since(date)
+                .unwrap_or_default(),
+        );
+    }
+
+    hints.interval()
+}
+
+/// How long we should cache a filtered response for.
+///
+/// Responses the upstream server says must be revalidated every time are not
+/// cached at all. Otherwise the upstream lifetime, less the time the response
+/// has already spent in upstream caches, is clamped to the configured bounds,
+/// falling back to the configured default when nothing says.
+pub(crate) fn cache_ttl(
+    config: &CacheConfig,
+    headers: &HeaderMap,
+    hints: &UpdateHints,
+) -> Duration {
+    if headers
+        .typed_get::<CacheControl>()
+        .is_some_and(|cache_control| cache_control.no_cache() || cache_control.no_store())
+    {
+        return Duration::ZERO;
+    }
+
+    let min = Duration::from_secs(config.min_ttl_seconds);
+    let max = Duration::from_secs(config.max_ttl_seconds).max(min);
+
+    let age = headers
+        .typed_get::<Age>()
+        .map(Duration::from)
+        .unwrap_or_default();
+
+    freshness_lifetime(headers, hints)
+        .map(|lifetime| lifetime.saturating_sub(age))
+        .unwrap_or(Duration::from_secs(config.ttl_seconds))
+        .clamp(min, max)
+}
+
+/// The `Cache-Control` for our filtered response, replacing the upstream one.
+/// `no-store` and `private` are kept, so that we don't make a feed cacheable
+/// which the publisher didn't want shared.
+pub(crate) fn response_cache_control(headers: &HeaderMap, ttl: Duration) -> CacheControl {
+    let upstream = headers.typed_get::<CacheControl>();
+
+    if upstream.as_ref().is_some_and(CacheControl::no_store) {
+        return CacheControl::new().with_no_store();
+    }
+
+    if ttl.is_zero() {
+        return CacheControl::new().with_no_cache();
+    }
+
+    let cache_control = CacheControl::new().with_max_age(ttl);
+
+    if upstream.as_ref().is_some_and(CacheControl::private) {
+        cache_control.with_private()
+    } else {
+        cache_control.with_public()
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use http::HeaderValue;
+    use test_case::test_case;
+
+    fn config() -> CacheConfig {
+        CacheConfig {
+            ttl_seconds: 300,
+            min_ttl_seconds: 60,
+            max_ttl_seconds: 3600,
+            ..Default::default()
+        }
+    }
+
+    fn headers(headers: &[(&'static str, &'static str)]) -> HeaderMap {
+        headers
+            .iter()
+            .map(|(name, value)| {
+                (
+                    http::HeaderName::from_static(name),
+                    HeaderValue::from_static(value),
+                )
+            })
+            .collect()
+    }
+
+    #[test_case(&[], 300 ; "default when nothing says")]
+    #[test_case(&[("cache-control", "max-age=600")], 600 ; "max-age")]
+    #[test_case(&[("cache-control", "max-age=600, s-maxage=900")], 900 ; "s-maxage wins for a shared cache")]
+    #[test_case(&[("cache-control", "max-age=600"), ("age", "100")], 500 ; "age is subtracted")]
+    #[test_case(&[("cache-control", "max-age=5")], 60 ; "clamped to the minimum")]
+    #[test_case(&[("cache-control", "max-age=86400")], 3600 ; "clamped to the maximum")]
+    #[test_case(&[("cache-control", "no-cache, max-age=600")], 0 ; "no-cache is not cached")]
+    #[test_case(&[("cache-control", "no-store")], 0 ; "no-store is not cached")]
+    #[test_case(&[("date", "Sat, 17 Oct 2026 10:00:00 GMT"), ("expires", "Sat, 17 Oct 2026 10:15:00 GMT")], 900 ; "expires relative to date")]
+    #[test_case(&[("cache-control", "max-age=120"), ("expires", "Sat, 17 Oct 2026 10:15:00 GMT")], 120 ; "max-age wins over expires")]
+    #[test_case(&[("date", "Sat, 17 Oct 2026 10:00:00 GMT"), ("expires", "Sat, 17 Oct 2026 09:00:00 GMT")], 60 ; "expires in the past")]
+    fn test_cache_ttl(input: &[(&'static str, &'static str)], expected_secs: u64) {
+        assert_eq!(
+            cache_ttl(&config(), &headers(input), &UpdateHints::default()),
+            Duration::from_secs(expected_secs)
+        );
+    }
+
+    #[test_case(&[], Some(30), 1800 ; "feed ttl when headers don't say")]
+    #[test_case(&[("cache-control", "max-age=600")], Some(30), 600 ; "headers win over the feed")]
+    #[test_case(&[], Some(24 * 60), 3600 ; "feed ttl is clamped")]
+    fn test_cache_ttl_from_feed(
+        input: &[(&'static str, &'static str)],
+        ttl_minutes: Option<u64>,
+        expected_secs: u64,
+    ) {
+        let hints = UpdateHints {
+            ttl_minutes,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            cache_ttl(&config(), &headers(input), &hints),
+            Duration::from_secs(expected_secs)
+        );
+    }
+
+    #[test_case(&[], 300, "public, max-age=300" ; "public by default")]
+    #[test_case(&[("cache-control", "private, max-age=10")], 300, "private, max-age=300" ; "private is kept")]
+    #[test_case(&[("cache-control", "no-store")], 0, "no-store" ; "no-store is kept")]
+    #[test_case(&[("cache-control", "no-cache")], 0, "no-cache" ; "must revalidate")]
+    fn test_response_cache_control(
+        input: &[(&'static str, &'static str)],
+        ttl_secs: u64,
+        expected: &str,
+    ) {
+        let mut map = HeaderMap::new();
+        map.typed_insert(response_cache_control(
+            &headers(input),
+            Duration::from_secs(ttl_secs),
+        ));
+
+        assert_eq!(map["cache-control"], expected);
+    }
+}
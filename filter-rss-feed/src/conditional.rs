@@ -0,0 +1,296 @@
+use std::fmt::Write as _;
+
+use bytes::Bytes;
+use headers::{ETag, HeaderMapExt, IfModifiedSince, IfNoneMatch, LastModified};
+use http::header::{
+    CACHE_CONTROL, CONTENT_LOCATION, DATE, ETAG, EXPIRES, IF_MODIFIED_SINCE, IF_NONE_MATCH,
+    LAST_MODIFIED, VARY,
+};
+use http::{HeaderMap, Response as HttpResponse, StatusCode};
+use sha2::{Digest, Sha256};
+
+use crate::response_cache::CachedResponse;
+use crate::streaming::ChannelRewrites;
+use crate::{FilterRegexes, OutputMode};
+
+/// Headers which a `304 Not Modified` response carries over from the `200`
+/// it stands in for (RFC 9110 §15.4.5).
+const NOT_MODIFIED_HEADERS: [http::HeaderName; 7] = [
+    CACHE_CONTROL,
+    CONTENT_LOCATION,
+    DATE,
+    ETAG,
+    EXPIRES,
+    LAST_MODIFIED,
+    VARY,
+];
+
+/// Hashes everything other than the upstream body which affects our output, so
+/// that changing a filter changes the `ETag` even if the feed is the same.
+pub(crate) fn filter_fingerprint(
+    filter_regexes: &FilterRegexes<'_>,
+    output_mode: OutputMode,
+    rewrites: &ChannelRewrites<'_>,
+) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+
+    hasher.update(env!("CARGO_PKG_VERSION"));
+    hasher.update(format!("{output_mode:?}"));
+
+    for regexes in [
+        filter_regexes.title_regexes,
+        filter_regexes.guid_regexes,
+        filter_regexes.link_regexes,
+    ] {
+        hasher.update([0x1e]);
+
+        for regex in regexes {
+            hasher.update(regex.as_str());
+            hasher.update([0x1f]);
+        }
+    }
+
+    for rewrite in [rewrites.self_link, rewrites.title_annotation] {
+        hasher.update([0x1e]);
+        hasher.update(rewrite.unwrap_or_default());
+    }
+
+    hasher.finalize().into()
+}
+
+/// Computes a strong `ETag` for a filtered body.
+pub(crate) fn filtered_etag(fingerprint: &[u8; 32], body: &[u8]) -> ETag {
+    let digest = Sha256::new()
+        .chain_update(fingerprint)
+        .chain_update(body)
+        .finalize();
+
+    let tag = digest[..16]
+        .iter()
+        .fold(String::with_capacity(34), |mut tag, byte| {
+            let _ = write!(tag, "{byte:02x}");
+            tag
+        });
+
+    format!("\"{tag}\"")
+        .parse()
+        .expect("a quoted hex string is a valid ETag")
+}
+
+/// The validators a client sent with its request.
+///
+/// Our `ETag`s are computed from the filtered output, so they mean nothing to
+/// the upstream server: `If-None-Match` is evaluated by us, after filtering.
+/// Our `Last-Modified` is the upstream one, so a lone `If-Modified-Since` is
+/// forwarded and the upstream server can answer it for us. It is also kept so
+/// that responses we didn't fetch, such as cached ones, can be checked.
+#[derive(Debug, Default)]
+pub(crate) struct ClientConditions {
+    if_none_match: Option<IfNoneMatch>,
+    if_modified_since: Option<IfModifiedSince>,
+}
+
+impl ClientConditions {
+    /// Takes the client's validators out of `headers`, leaving those which
+    /// should be sent upstream.
+    pub(crate) fn take_from(headers: &mut HeaderMap) -> Self {
+        let if_none_match = headers.typed_get::<IfNoneMatch>();
+        let mut if_modified_since = headers.typed_get::<IfModifiedSince>();
+
+        if headers.remove(IF_NONE_MATCH).is_some() {
+            // If-None-Match takes precedence, and If-Modified-Since must be
+            // ignored when it is present (RFC 9110 §13.1.3).
+            headers.remove(IF_MODIFIED_SINCE);
+            if_modified_since = None;
+        }
+
+        Self {
+            if_none_match,
+            if_modified_since,
+        }
+    }
+
+    /// Removes a forwarded `If-Modified-Since` from upstream request headers,
+    /// for when we need the full upstream response even if the client doesn't.
+    pub(crate) fn unconditional(headers: &mut HeaderMap) {
+        headers.remove(IF_MODIFIED_SINCE);
+    }
+
+    /// Makes an upstream request conditional on the feed having changed since
+    /// a cached filtered response was made from it, using the upstream
+    /// validators stored with it.
+    pub(crate) fn since(headers: &mut HeaderMap, cached: &CachedResponse) {
+        if let Some(etag) = &cached.upstream_etag {
+            headers.insert(IF_NONE_MATCH, etag.clone());
+        }
+        if let Some(last_modified) = cached.headers.get(LAST_MODIFIED) {
+            headers.insert(IF_MODIFIED_SINCE, last_modified.clone());
+        }
+    }
+
+    /// Turns a filtered response into a `304 Not Modified` if the client
+    /// already has it.
+    pub(crate) fn evaluate(&self, response: HttpResponse<Bytes>) -> HttpResponse<Bytes> {
+        if !response.status().is_success() {
+            return response;
+        }
+
+        let headers = response.headers();
+
+        let unchanged = match (&self.if_none_match, &self.if_modified_since) {
+            (Some(if_none_match), _) => headers
+                .typed_get::<ETag>()
+                .is_some_and(|etag| !if_none_match.precondition_passes(&etag)),
+            (None, Some(if_modified_since)) => headers
+                .typed_get::<LastModified>()
+                .is_some_and(|last_modified| !if_modified_since.is_modified(last_modified.into())),
+            (None, None) => false,
+        };
+
+        if unchanged {
+            not_modified(response)
+        } else {
+            response
+        }
+    }
+}
+
+/// Builds a `304 Not Modified` from a response, keeping only the headers a
+/// `304` is allowed to carry.
+pub(crate) fn not_modified(response: HttpResponse<Bytes>) -> HttpResponse<Bytes> {
+    let (parts, _) = response.into_parts();
+
+    let mut response = HttpResponse::new(Bytes::new());
+    *response.status_mut() = StatusCode::NOT_MODIFIED;
+    *response.extensions_mut() = parts.extensions;
+
+    for name in NOT_MODIFIED_HEADERS {
+        for value in parts.headers.get_all(&name) {
+            response.headers_mut().append(name.clone(), value.clone());
+        }
+    }
+
+    response
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use http::HeaderValue;
+    use http::header::CONTENT_TYPE;
+    use regex::Regex;
+    use test_case::test_case;
+
+    use super::*;
+
+    fn regexes(title: &[Regex]) -> FilterRegexes<'_> {
+        FilterRegexes {
+            title_regexes: title,
+            guid_regexes: &[],
+            link_regexes: &[],
+        }
+    }
+
+    fn response_with_etag(etag: &ETag) -> HttpResponse<Bytes> {
+        let mut response = HttpResponse::new(Bytes::from_static(b"<rss/>"));
+        response.headers_mut().typed_insert(etag.clone());
+        response.headers_mut().insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static("application/rss+xml"),
+        );
+        response.headers_mut().insert(
+            LAST_MODIFIED,
+            HeaderValue::from_static("Sat, 17 Oct 2026 10:00:00 GMT"),
+        );
+        response
+    }
+
+    #[test]
+    fn test_etag_depends_on_filters_and_body() {
+        let a = [Regex::new("a").unwrap()];
+        let b = [Regex::new("b").unwrap()];
+
+        let none = ChannelRewrites::default();
+        let annotated = ChannelRewrites {
+            title_annotation: Some("(filtered)"),
+            ..Default::default()
+        };
+
+        let fingerprint_a = filter_fingerprint(&regexes(&a), OutputMode::Preserve, &none);
+        let fingerprint_b = filter_fingerprint(&regexes(&b), OutputMode::Preserve, &none);
+        let reserialise_a = filter_fingerprint(&regexes(&a), OutputMode::Reserialise, &none);
+        let annotated_a = filter_fingerprint(&regexes(&a), OutputMode::Preserve, &annotated);
+
+        assert_eq!(
+            filter_fingerprint(&regexes(&a), OutputMode::Preserve, &none),
+            fingerprint_a
+        );
+        assert_ne!(fingerprint_a, fingerprint_b);
+        assert_ne!(fingerprint_a, reserialise_a);
+        assert_ne!(fingerprint_a, annotated_a);
+
+        let etag = filtered_etag(&fingerprint_a, b"body");
+        assert_eq!(filtered_etag(&fingerprint_a, b"body"), etag);
+        assert_ne!(filtered_etag(&fingerprint_a, b"other body"), etag);
+        assert_ne!(filtered_etag(&fingerprint_b, b"body"), etag);
+    }
+
+    #[test_case(Some("\"abc\""), None, None, None ; "if-none-match is never sent upstream")]
+    #[test_case(Some("\"abc\""), Some("Sat, 17 Oct 2026 10:00:00 GMT"), None, None ; "if-modified-since is ignored alongside if-none-match")]
+    #[test_case(None, Some("Sat, 17 Oct 2026 10:00:00 GMT"), None, Some("Sat, 17 Oct 2026 10:00:00 GMT") ; "lone if-modified-since is forwarded")]
+    fn test_take_from(
+        if_none_match: Option<&'static str>,
+        if_modified_since: Option<&'static str>,
+        expected_if_none_match: Option<&str>,
+        expected_if_modified_since: Option<&str>,
+    ) {
+        let mut headers = HeaderMap::new();
+        for (name, value) in [
+            (IF_NONE_MATCH, if_none_match),
+            (IF_MODIFIED_SINCE, if_modified_since),
+        ] {
+            if let Some(value) = value {
+                headers.insert(name, HeaderValue::from_static(value));
+            }
+        }
+
+        let conditions = ClientConditions::take_from(&mut headers);
+
+        assert_eq!(conditions.if_none_match.is_some(), if_none_match.is_some());
+        assert_eq!(
+            headers.get(IF_NONE_MATCH).map(|v| v.to_str().unwrap()),
+            expected_if_none_match
+        );
+        assert_eq!(
+            headers.get(IF_MODIFIED_SINCE).map(|v| v.to_str().unwrap()),
+            expected_if_modified_since
+        );
+    }
+
+    #[test_case(None, StatusCode::OK ; "no validator")]
+    #[test_case(Some(("if-none-match", "\"other\"")), StatusCode::OK ; "different etag")]
+    #[test_case(Some(("if-none-match", "\"current\"")), StatusCode::NOT_MODIFIED ; "matching etag")]
+    #[test_case(Some(("if-none-match", "W/\"current\"")), StatusCode::NOT_MODIFIED ; "weak comparison")]
+    #[test_case(Some(("if-none-match", "\"other\", \"current\"")), StatusCode::NOT_MODIFIED ; "one of several")]
+    #[test_case(Some(("if-none-match", "*")), StatusCode::NOT_MODIFIED ; "wildcard")]
+    #[test_case(Some(("if-modified-since", "Sat, 17 Oct 2026 10:00:00 GMT")), StatusCode::NOT_MODIFIED ; "not modified since")]
+    #[test_case(Some(("if-modified-since", "Fri, 16 Oct 2026 10:00:00 GMT")), StatusCode::OK ; "modified since")]
+    fn test_evaluate(condition: Option<(&'static str, &'static str)>, expected: StatusCode) {
+        let mut headers = HeaderMap::new();
+        if let Some((name, value)) = condition {
+            headers.insert(name, HeaderValue::from_static(value));
+        }
+
+        let etag: ETag = "\"current\"".parse().unwrap();
+        let response =
+            ClientConditions::take_from(&mut headers).evaluate(response_with_etag(&etag));
+
+        assert_eq!(response.status(), expected);
+
+        if expected == StatusCode::NOT_MODIFIED {
+            assert!(response.body().is_empty());
+            assert_eq!(response.headers().typed_get::<ETag>(), Some(etag));
+            assert!(response.headers().contains_key(LAST_MODIFIED));
+            assert!(!response.headers().contains_key(CONTENT_TYPE));
+        }
+    }
+}
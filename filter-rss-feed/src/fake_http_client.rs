@@ -1,17 +1,20 @@
 use async_trait::async_trait;
 use bytes::Bytes;
 use http::{
-    HeaderMap, HeaderName, HeaderValue, Request as HttpRequest, Response as HttpResponse,
-    StatusCode,
+    HeaderMap, HeaderName, HeaderValue, Method, Request as HttpRequest, Response as HttpResponse,
+    StatusCode, Uri,
 };
 use std::collections::HashMap;
 use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use thiserror::Error;
+use tower::Layer;
 
 #[cfg(any(test, feature = "testing"))]
 use derive_builder::Builder;
 
-use crate::http_client::{HttpClient, HttpClientError};
+use crate::http_client::{BodySizeLimit, HttpClient, HttpClientError, LimitedBody};
 
 /// Error types that can be simulated by the fake HTTP client.
 ///
@@ -125,6 +128,16 @@ pub struct FakeHttpClient {
     responses: HashMap<String, FakeResponse>,
     #[cfg_attr(any(test, feature = "testing"), builder(default))]
     errors: HashMap<String, FakeHttpError>,
+    /// Outcomes for successive requests to a URL, taking precedence over
+    /// `responses` and `errors`. The last one is repeated once the script
+    /// runs out.
+    #[cfg_attr(any(test, feature = "testing"), builder(default))]
+    scripts: HashMap<String, Vec<Result<FakeResponse, FakeHttpError>>>,
+    /// How long to wait before answering requests to a URL.
+    #[cfg_attr(any(test, feature = "testing"), builder(default))]
+    delays: HashMap<String, Duration>,
+    #[cfg_attr(any(test, feature = "testing"), builder(setter(skip)))]
+    requests: Mutex<HashMap<String, usize>>,
     #[cfg_attr(
         any(test, feature = "testing"),
         builder(setter(into), default = "\"MISS\".to_string()")
@@ -202,6 +215,27 @@ impl FakeHttpClientBuilder {
         self.with_errors(errors)
     }
 
+    /// Script the outcomes of successive requests to the given URL, such as
+    /// failures followed by a success.
+    pub fn with_script(
+        &mut self,
+        url: impl Into<String>,
+        script: Vec<Result<FakeResponse, FakeHttpError>>,
+    ) -> &mut Self {
+        let mut scripts = self.scripts.clone().unwrap_or_default();
+        scripts.insert(url.into(), script);
+
+        self.with_scripts(scripts)
+    }
+
+    /// Delay answering requests to the given URL, to simulate a slow server.
+    pub fn with_delay(&mut self, url: impl Into<String>, delay: Duration) -> &mut Self {
+        let mut delays = self.delays.clone().unwrap_or_default();
+        delays.insert(url.into(), delay);
+
+        self.with_delays(delays)
+    }
+
     // Convenience methods for simulating common error conditions.
 
     /// Configure a network error for the given URL.
@@ -234,43 +268,61 @@ impl FakeHttpClient {
         Self {
             responses: HashMap::new(),
             errors: HashMap::new(),
+            scripts: HashMap::new(),
+            delays: HashMap::new(),
+            requests: Mutex::new(HashMap::new()),
             cache_status: "MISS".to_string(),
         }
     }
 
-    fn convert_fake_error(&self, error: &FakeHttpError) -> HttpClientError {
-        match error {
-            FakeHttpError::Network { message } => HttpClientError::Request(message.clone()),
-            FakeHttpError::Timeout => HttpClientError::Request("Request timeout".to_string()),
-            FakeHttpError::InvalidContentType => {
-                HttpClientError::Request("Invalid content type".to_string())
-            }
-        }
-    }
-}
-
-impl Default for FakeHttpClient {
-    fn default() -> Self {
-        Self::new()
+    /// How many requests have been made for the given URL.
+    pub fn requests(&self, url: &str) -> usize {
+        self.requests
+            .lock()
+            .map(|requests| requests.get(url).copied().unwrap_or_default())
+            .unwrap_or_default()
     }
-}
 
-#[async_trait]
-#[cfg(not(target_arch = "wasm32"))]
-impl HttpClient for FakeHttpClient {
-    async fn send(
+    async fn respond(
         &self,
         request: HttpRequest<Bytes>,
     ) -> Result<HttpResponse<Bytes>, HttpClientError> {
         let url = request.uri().to_string();
 
-        // Check for configured errors first
-        if let Some(error) = self.errors.get(&url) {
-            return Err(self.convert_fake_error(error));
+        let attempt = self
+            .requests
+            .lock()
+            .map(|mut requests| {
+                let count = requests.entry(url.clone()).or_default();
+                *count += 1;
+                *count
+            })
+            .unwrap_or(1);
+
+        if let Some(delay) = self.delays.get(&url) {
+            crate::retry::sleep(*delay).await;
         }
 
+        let scripted = self
+            .scripts
+            .get(&url)
+            .and_then(|script| script.get(attempt - 1).or(script.last()));
+
+        let fake_response = match scripted {
+            Some(Err(error)) => return Err(self.convert_fake_error(error)),
+            Some(Ok(response)) => Some(response),
+            None => {
+                // Check for configured errors first
+                if let Some(error) = self.errors.get(&url) {
+                    return Err(self.convert_fake_error(error));
+                }
+
+                self.responses.get(&url)
+            }
+        };
+
         // Check for configured responses
-        if let Some(fake_response) = self.responses.get(&url) {
+        if let Some(fake_response) = fake_response {
             let mut response_builder = HttpResponse::builder().status(fake_response.status);
 
             // Add configured headers
@@ -282,7 +334,11 @@ impl HttpClient for FakeHttpClient {
             response_builder =
                 response_builder.header("x-rssfilter-cache-status", &self.cache_status);
 
-            return Ok(response_builder.body(fake_response.body.clone())?);
+            // Enforce the body size limit as a real client would
+            let mut body = LimitedBody::new(BodySizeLimit::from_request(&request));
+            body.push(&fake_response.body)?;
+
+            return Ok(response_builder.body(body.finish())?);
         }
 
         // Default 404 response for unmatched URLs
@@ -291,6 +347,33 @@ impl HttpClient for FakeHttpClient {
             .header("x-rssfilter-cache-status", &self.cache_status)
             .body(Bytes::from("Not Found"))?)
     }
+
+    fn convert_fake_error(&self, error: &FakeHttpError) -> HttpClientError {
+        match error {
+            FakeHttpError::Network { message } => HttpClientError::Connect(message.clone()),
+            FakeHttpError::Timeout => HttpClientError::Timeout(Duration::ZERO),
+            FakeHttpError::InvalidContentType => {
+                HttpClientError::Request("Invalid content type".to_string())
+            }
+        }
+    }
+}
+
+impl Default for FakeHttpClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+#[cfg(not(target_arch = "wasm32"))]
+impl HttpClient for FakeHttpClient {
+    async fn send(
+        &self,
+        request: HttpRequest<Bytes>,
+    ) -> Result<HttpResponse<Bytes>, HttpClientError> {
+        self.respond(request).await
+    }
 }
 
 #[async_trait(?Send)]
@@ -300,41 +383,95 @@ impl HttpClient for FakeHttpClient {
         &self,
         request: HttpRequest<Bytes>,
     ) -> Result<HttpResponse<Bytes>, HttpClientError> {
-        let url = request.uri().to_string();
+        self.respond(request).await
+    }
+}
 
-        // Check for configured errors first
-        if let Some(error) = self.errors.get(&url) {
-            return Err(self.convert_fake_error(error));
-        }
+/// A request seen by a [`RecordingLayer`].
+#[derive(Clone, Debug)]
+pub struct RecordedRequest {
+    pub method: Method,
+    pub uri: Uri,
+    pub headers: HeaderMap,
+}
 
-        // Check for configured responses
-        if let Some(fake_response) = self.responses.get(&url) {
-            let mut response_builder = HttpResponse::builder().status(fake_response.status);
+/// A [`Layer`] which records the requests passing through it, so that tests
+/// can check what a stack of layers actually sent. Clones share their
+/// record.
+#[derive(Clone, Debug, Default)]
+pub struct RecordingLayer {
+    requests: Arc<Mutex<Vec<RecordedRequest>>>,
+}
 
-            // Add configured headers
-            for (name, value) in &fake_response.headers {
-                response_builder = response_builder.header(name, value);
-            }
+impl RecordingLayer {
+    /// The requests recorded so far, oldest first.
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.requests
+            .lock()
+            .map(|requests| requests.clone())
+            .unwrap_or_default()
+    }
+}
 
-            // Add cache status header
-            response_builder =
-                response_builder.header("x-rssfilter-cache-status", &self.cache_status);
+impl<C> Layer<C> for RecordingLayer {
+    type Service = RecordingHttpClient<C>;
+
+    fn layer(&self, inner: C) -> Self::Service {
+        RecordingHttpClient {
+            inner,
+            requests: Arc::clone(&self.requests),
+        }
+    }
+}
+
+/// Wraps any [`HttpClient`], recording requests. See [`RecordingLayer`].
+pub struct RecordingHttpClient<C> {
+    inner: C,
+    requests: Arc<Mutex<Vec<RecordedRequest>>>,
+}
 
-            return Ok(response_builder.body(fake_response.body.clone())?);
+impl<C: HttpClient> RecordingHttpClient<C> {
+    async fn record(
+        &self,
+        request: HttpRequest<Bytes>,
+    ) -> Result<HttpResponse<Bytes>, HttpClientError> {
+        if let Ok(mut requests) = self.requests.lock() {
+            requests.push(RecordedRequest {
+                method: request.method().clone(),
+                uri: request.uri().clone(),
+                headers: request.headers().clone(),
+            });
         }
 
-        // Default 404 response for unmatched URLs
-        Ok(HttpResponse::builder()
-            .status(StatusCode::NOT_FOUND)
-            .header("x-rssfilter-cache-status", &self.cache_status)
-            .body(Bytes::from("Not Found"))?)
+        self.inner.send(request).await
+    }
+}
+
+#[async_trait]
+#[cfg(not(target_arch = "wasm32"))]
+impl<C: HttpClient> HttpClient for RecordingHttpClient<C> {
+    async fn send(
+        &self,
+        request: HttpRequest<Bytes>,
+    ) -> Result<HttpResponse<Bytes>, HttpClientError> {
+        self.record(request).await
+    }
+}
+
+#[async_trait(?Send)]
+#[cfg(target_arch = "wasm32")]
+impl<C: HttpClient> HttpClient for RecordingHttpClient<C> {
+    async fn send(
+        &self,
+        request: HttpRequest<Bytes>,
+    ) -> Result<HttpResponse<Bytes>, HttpClientError> {
+        self.record(request).await
     }
 }
 
 #[cfg(all(test, not(target_arch = "wasm32")))]
 mod tests {
     use super::*;
-    use http::Method;
 
     #[tokio::test]
     async fn test_fake_http_client_basic() {
@@ -433,7 +570,28 @@ mod tests {
 
         let result = client.send(request).await;
         assert!(result.is_err());
-        assert!(matches!(result.unwrap_err(), HttpClientError::Request(_)));
+        assert!(matches!(result.unwrap_err(), HttpClientError::Connect(_)));
+    }
+
+    #[tokio::test]
+    async fn test_fake_http_client_body_size_limit() {
+        let client = FakeHttpClientBuilder::default()
+            .with_rss_response("https://example.com/rss", "<rss>data</rss>")
+            .build()
+            .expect("Failed to build fake client");
+
+        let request = HttpRequest::builder()
+            .method(Method::GET)
+            .uri("https://example.com/rss")
+            .extension(BodySizeLimit(4))
+            .body(Bytes::new())
+            .unwrap();
+
+        let result = client.send(request).await;
+        assert!(matches!(
+            result.unwrap_err(),
+            HttpClientError::BodyTooLarge { limit: 4 }
+        ));
     }
 
     #[tokio::test]
@@ -515,7 +673,6 @@ mod tests {
 #[cfg(all(test, target_arch = "wasm32"))]
 mod wasm_tests {
     use super::*;
-    use http::Method;
     use wasm_bindgen_test::{wasm_bindgen_test, wasm_bindgen_test_configure};
 
     wasm_bindgen_test_configure!(run_in_node_experimental);
@@ -557,7 +714,7 @@ mod wasm_tests {
 
         let result = client.send(request).await;
         assert!(result.is_err());
-        assert!(matches!(result.unwrap_err(), HttpClientError::Request(_)));
+        assert!(matches!(result.unwrap_err(), HttpClientError::Connect(_)));
     }
 
     #[wasm_bindgen_test]
@@ -17,6 +17,8 @@ pub enum CfCacheStatus {
     Revalidated,
     /// Content was updated in cache
     Updating,
+    /// Stale content served because the origin couldn't be reached
+    Stale,
     /// Cache was bypassed due to configuration
     Bypass,
     /// Unknown or custom status
@@ -32,6 +34,7 @@ impl fmt::Display for CfCacheStatus {
             CfCacheStatus::Expired => write!(f, "EXPIRED"),
             CfCacheStatus::Revalidated => write!(f, "REVALIDATED"),
             CfCacheStatus::Updating => write!(f, "UPDATING"),
+            CfCacheStatus::Stale => write!(f, "STALE"),
             CfCacheStatus::Bypass => write!(f, "BYPASS"),
             CfCacheStatus::Other(s) => write!(f, "{s}"),
         }
@@ -50,6 +53,7 @@ impl FromStr for CfCacheStatus {
             "EXPIRED" => Ok(CfCacheStatus::Expired),
             "REVALIDATED" => Ok(CfCacheStatus::Revalidated),
             "UPDATING" => Ok(CfCacheStatus::Updating),
+            "STALE" => Ok(CfCacheStatus::Stale),
             "BYPASS" => Ok(CfCacheStatus::Bypass),
             _ => Ok(CfCacheStatus::Other(s.to_string())),
         }
@@ -85,6 +89,7 @@ impl Header for CfCacheStatus {
             CfCacheStatus::Expired => HeaderValue::from_static("EXPIRED"),
             CfCacheStatus::Revalidated => HeaderValue::from_static("REVALIDATED"),
             CfCacheStatus::Updating => HeaderValue::from_static("UPDATING"),
+            CfCacheStatus::Stale => HeaderValue::from_static("STALE"),
             CfCacheStatus::Bypass => HeaderValue::from_static("BYPASS"),
             CfCacheStatus::Other(s) => HeaderValue::try_from(s.as_str())
                 .unwrap_or_else(|_| HeaderValue::from_static("INVALID")),
@@ -111,6 +116,7 @@ mod tests {
     #[test_case("EXPIRED", CfCacheStatus::Expired; "expired uppercase")]
     #[test_case("REVALIDATED", CfCacheStatus::Revalidated; "revalidated uppercase")]
     #[test_case("UPDATING", CfCacheStatus::Updating; "updating uppercase")]
+    #[test_case("STALE", CfCacheStatus::Stale; "stale uppercase")]
     #[test_case("BYPASS", CfCacheStatus::Bypass; "bypass uppercase")]
     #[test_case("CUSTOM", CfCacheStatus::Other("CUSTOM".to_string()); "custom status")]
     #[test_case("unknown-status", CfCacheStatus::Other("unknown-status".to_string()); "unknown status with hyphen")]
@@ -145,6 +151,7 @@ mod tests {
     #[test_case(CfCacheStatus::Expired, "EXPIRED"; "expired encodes to uppercase")]
     #[test_case(CfCacheStatus::Revalidated, "REVALIDATED"; "revalidated encodes to uppercase")]
     #[test_case(CfCacheStatus::Updating, "UPDATING"; "updating encodes to uppercase")]
+    #[test_case(CfCacheStatus::Stale, "STALE"; "stale encodes to uppercase")]
     #[test_case(CfCacheStatus::Bypass, "BYPASS"; "bypass encodes to uppercase")]
     #[test_case(CfCacheStatus::Other("custom".to_string()), "custom"; "other preserves original case")]
     fn test_encode(status: CfCacheStatus, expected: &str) {
@@ -172,6 +179,7 @@ mod tests {
     #[test_case(CfCacheStatus::Expired, "EXPIRED"; "expired displays as uppercase")]
     #[test_case(CfCacheStatus::Revalidated, "REVALIDATED"; "revalidated displays as uppercase")]
     #[test_case(CfCacheStatus::Updating, "UPDATING"; "updating displays as uppercase")]
+    #[test_case(CfCacheStatus::Stale, "STALE"; "stale displays as uppercase")]
     #[test_case(CfCacheStatus::Bypass, "BYPASS"; "bypass displays as uppercase")]
     #[test_case(CfCacheStatus::Other("custom".to_string()), "custom"; "other displays original")]
     fn test_display(status: CfCacheStatus, expected: &str) {
@@ -193,6 +201,7 @@ mod tests {
             CfCacheStatus::Expired,
             CfCacheStatus::Revalidated,
             CfCacheStatus::Updating,
+            CfCacheStatus::Stale,
             CfCacheStatus::Bypass,
             CfCacheStatus::Other("custom-status".to_string()),
         ];
@@ -0,0 +1,69 @@
+use std::fmt;
+
+use headers::{Header, HeaderName, HeaderValue};
+
+/// Typed header for `x-rssfilter-items-removed`: how many items our filters
+/// removed from the upstream feed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RssFilterItemsRemoved(pub usize);
+
+impl fmt::Display for RssFilterItemsRemoved {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl Header for RssFilterItemsRemoved {
+    fn name() -> &'static HeaderName {
+        static NAME: HeaderName = HeaderName::from_static("x-rssfilter-items-removed");
+        &NAME
+    }
+
+    fn decode<'i, I>(values: &mut I) -> Result<Self, headers::Error>
+    where
+        I: Iterator<Item = &'i HeaderValue>,
+    {
+        let value = values.next().ok_or_else(headers::Error::invalid)?;
+        let s = value.to_str().map_err(|_| headers::Error::invalid())?;
+        s.parse().map(Self).map_err(|_| headers::Error::invalid())
+    }
+
+    fn encode<E>(&self, values: &mut E)
+    where
+        E: Extend<HeaderValue>,
+    {
+        values.extend(std::iter::once(HeaderValue::from(self.0)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use headers::HeaderMapExt;
+    use http::HeaderMap;
+    use test_case::test_case;
+
+    #[test_case("0", Some(0) ; "zero")]
+    #[test_case("42", Some(42) ; "some items")]
+    #[test_case("-1", None ; "negative")]
+    #[test_case("many", None ; "not a number")]
+    fn test_decode(input: &str, expected: Option<usize>) {
+        let header_value = HeaderValue::from_str(input).unwrap();
+        let mut values = std::iter::once(&header_value);
+
+        let result = RssFilterItemsRemoved::decode(&mut values).ok();
+        assert_eq!(result, expected.map(RssFilterItemsRemoved));
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let mut headers = HeaderMap::new();
+        headers.typed_insert(RssFilterItemsRemoved(3));
+
+        assert_eq!(headers["x-rssfilter-items-removed"], "3");
+        assert_eq!(
+            headers.typed_get::<RssFilterItemsRemoved>(),
+            Some(RssFilterItemsRemoved(3))
+        );
+    }
+}
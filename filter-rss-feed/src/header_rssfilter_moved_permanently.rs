@@ -0,0 +1,73 @@
+use std::fmt;
+
+use headers::{Header, HeaderName, HeaderValue};
+use http::Uri;
+
+/// Typed header for `x-rssfilter-moved-permanently`: where the upstream
+/// feed has permanently moved to, so that subscribers can update the URL
+/// they ask us to filter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RssFilterMovedPermanently(pub Uri);
+
+impl fmt::Display for RssFilterMovedPermanently {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl Header for RssFilterMovedPermanently {
+    fn name() -> &'static HeaderName {
+        static NAME: HeaderName = HeaderName::from_static("x-rssfilter-moved-permanently");
+        &NAME
+    }
+
+    fn decode<'i, I>(values: &mut I) -> Result<Self, headers::Error>
+    where
+        I: Iterator<Item = &'i HeaderValue>,
+    {
+        let value = values.next().ok_or_else(headers::Error::invalid)?;
+        let s = value.to_str().map_err(|_| headers::Error::invalid())?;
+        s.parse().map(Self).map_err(|_| headers::Error::invalid())
+    }
+
+    fn encode<E>(&self, values: &mut E)
+    where
+        E: Extend<HeaderValue>,
+    {
+        // A `Uri` is only ever made of visible ASCII
+        if let Ok(value) = HeaderValue::from_str(&self.0.to_string()) {
+            values.extend(std::iter::once(value));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use headers::HeaderMapExt;
+    use http::HeaderMap;
+
+    #[test]
+    fn test_roundtrip() {
+        let mut headers = HeaderMap::new();
+        let moved = RssFilterMovedPermanently(Uri::from_static("https://example.com/new?a=1"));
+        headers.typed_insert(moved.clone());
+
+        assert_eq!(
+            headers["x-rssfilter-moved-permanently"],
+            "https://example.com/new?a=1"
+        );
+        assert_eq!(
+            headers.typed_get::<RssFilterMovedPermanently>(),
+            Some(moved)
+        );
+    }
+
+    #[test]
+    fn test_decode_invalid() {
+        let header_value = HeaderValue::from_static("not a url");
+        let mut values = std::iter::once(&header_value);
+
+        assert!(RssFilterMovedPermanently::decode(&mut values).is_err());
+    }
+}
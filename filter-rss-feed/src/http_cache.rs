@@ -0,0 +1,959 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use headers::{Age, CacheControl, Date, ETag, HeaderMapExt, IfModifiedSince, IfNoneMatch};
+use headers::{LastModified, Vary};
+use http::header::{
+    AUTHORIZATION, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_RANGE, EXPIRES, IF_MATCH,
+    IF_MODIFIED_SINCE, IF_NONE_MATCH, IF_RANGE, IF_UNMODIFIED_SINCE, TRANSFER_ENCODING,
+};
+use http::{
+    HeaderMap, HeaderName, HeaderValue, Method, Request as HttpRequest, Response as HttpResponse,
+    StatusCode, Uri,
+};
+use sha2::{Digest, Sha256};
+use tower::Layer;
+use tracing::{debug, warn};
+use url::Url;
+
+use crate::bounded_cache::{BoundedCache, Weigh};
+use crate::cache_policy::{freshness_lifetime, now};
+use crate::header_cf_cache_status::CfCacheStatus;
+use crate::header_rssfilter_cache_status::RssFilterCacheStatus;
+use crate::http_client::{BodySizeLimit, CacheConfig, HttpClient, HttpClientError};
+use crate::streaming::UpdateHints;
+
+/// First line of an entry on disk, so that entries written in a format we
+/// don't understand are ignored rather than misread.
+const DISK_FORMAT: &[u8] = b"rssfilter-http-cache 1";
+
+/// Status codes which may be cached without explicit freshness information
+/// (RFC 9110 §15.1).
+const HEURISTICALLY_CACHEABLE: &[StatusCode] = &[
+    StatusCode::OK,
+    StatusCode::NON_AUTHORITATIVE_INFORMATION,
+    StatusCode::NO_CONTENT,
+    StatusCode::MULTIPLE_CHOICES,
+    StatusCode::MOVED_PERMANENTLY,
+    StatusCode::PERMANENT_REDIRECT,
+    StatusCode::NOT_FOUND,
+    StatusCode::METHOD_NOT_ALLOWED,
+    StatusCode::GONE,
+    StatusCode::URI_TOO_LONG,
+    StatusCode::NOT_IMPLEMENTED,
+];
+
+/// Headers in a 304 which must not replace the stored ones, since they
+/// describe the (empty) 304 body rather than the stored representation.
+const NOT_UPDATED_BY_304: &[HeaderName] = &[CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_RANGE];
+
+/// A stored upstream response.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Entry {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+    /// The request headers named by the response's `Vary`, which a later
+    /// request must match to be served this entry.
+    vary: Vec<(HeaderName, Option<HeaderValue>)>,
+    response_time: SystemTime,
+    /// The response's age when we received it (RFC 9111 §4.2.3).
+    initial_age: Duration,
+}
+
+impl Entry {
+    fn new(request_headers: &HeaderMap, response: &HttpResponse<Bytes>) -> Self {
+        let response_time = now();
+        let headers = response.headers().clone();
+
+        let apparent_age = headers
+            .typed_get::<Date>()
+            .and_then(|date| response_time.duration_since(date.into()).ok())
+            .unwrap_or_default();
+        let age = headers
+            .typed_get::<Age>()
+            .map(Duration::from)
+            .unwrap_or_default();
+
+        Self {
+            status: response.status(),
+            vary: vary_headers(&headers)
+                .map(|name| {
+                    let value = request_headers.get(&name).cloned();
+                    (name, value)
+                })
+                .collect(),
+            body: response.body().clone(),
+            headers,
+            response_time,
+            initial_age: apparent_age.max(age),
+        }
+    }
+
+    fn current_age(&self) -> Duration {
+        self.initial_age + now().duration_since(self.response_time).unwrap_or_default()
+    }
+
+    fn has_validators(&self) -> bool {
+        self.headers.contains_key(http::header::ETAG)
+            || self.headers.contains_key(http::header::LAST_MODIFIED)
+    }
+
+    fn matches(&self, request_headers: &HeaderMap) -> bool {
+        self.vary
+            .iter()
+            .all(|(name, value)| request_headers.get(name) == value.as_ref())
+    }
+
+    /// Builds the response to return for this entry, with its current `Age`.
+    pub(crate) fn to_response(&self) -> HttpResponse<Bytes> {
+        let mut response = HttpResponse::new(self.body.clone());
+        *response.status_mut() = self.status;
+        *response.headers_mut() = self.headers.clone();
+        response
+            .headers_mut()
+            .typed_insert(Age::from_secs(self.current_age().as_secs()));
+        response
+    }
+
+    pub(crate) fn body_len(&self) -> u64 {
+        self.body.len() as u64
+    }
+
+    /// Adds validators for this entry to a request, so that the upstream
+    /// server can answer 304 if it hasn't changed (RFC 9111 §4.3.1).
+    pub(crate) fn add_conditions<T>(&self, request: &mut HttpRequest<T>) {
+        let headers = request.headers_mut();
+
+        if let Some(etag) = self.headers.typed_get::<ETag>() {
+            headers.typed_insert(IfNoneMatch::from(etag));
+        }
+        if let Some(last_modified) = self.headers.typed_get::<LastModified>() {
+            headers.typed_insert(IfModifiedSince::from(SystemTime::from(last_modified)));
+        }
+    }
+
+    /// Updates this entry from a 304 response to a conditional request
+    /// (RFC 9111 §4.3.4).
+    fn freshen(mut self, not_modified: &HttpResponse<Bytes>) -> Self {
+        let updated = Entry::new(&HeaderMap::new(), not_modified);
+
+        for name in not_modified.headers().keys() {
+            if NOT_UPDATED_BY_304.contains(name) {
+                continue;
+            }
+
+            self.headers.remove(name);
+            for value in not_modified.headers().get_all(name) {
+                self.headers.append(name, value.clone());
+            }
+        }
+
+        self.response_time = updated.response_time;
+        self.initial_age = updated.initial_age;
+        self
+    }
+}
+
+impl Weigh for Entry {
+    fn weight(&self) -> usize {
+        let headers: usize = self
+            .headers
+            .iter()
+            .map(|(name, value)| name.as_str().len() + value.len())
+            .sum();
+
+        self.body.len() + headers
+    }
+}
+
+fn vary_headers(headers: &HeaderMap) -> impl Iterator<Item = HeaderName> + '_ {
+    headers
+        .get_all(http::header::VARY)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|name| HeaderName::from_bytes(name.trim().as_bytes()).ok())
+}
+
+/// The result of looking a request up in the cache.
+#[derive(Debug)]
+pub(crate) enum Lookup {
+    /// The request can't be answered from or stored in the cache.
+    Bypass,
+    Miss,
+    /// The entry can be returned without contacting the upstream server.
+    Fresh(Entry),
+    /// The entry must be revalidated before it can be used.
+    Stale(Entry),
+}
+
+/// An RFC 9111 shared cache of upstream responses, kept in memory and
+/// optionally in a directory so that it survives between runs.
+///
+/// In memory it holds at most [`CacheConfig::max_entries`] entries and
+/// [`CacheConfig::max_bytes`] of them, dropping the least recently used, and
+/// entries which are stale and can't be revalidated are dropped every so
+/// often.
+///
+/// Only `GET` requests are cached, and requests which are already
+/// conditional go straight to the upstream server.
+#[derive(Debug)]
+pub(crate) struct HttpCache {
+    entries: Mutex<BoundedCache<Entry>>,
+    dir: Option<PathBuf>,
+    max_heuristic_lifetime: Duration,
+}
+
+impl HttpCache {
+    pub fn new(config: &CacheConfig) -> Self {
+        Self {
+            entries: Mutex::new(BoundedCache::new(config.max_entries, config.max_bytes)),
+            dir: config.cache_dir.clone(),
+            max_heuristic_lifetime: Duration::from_secs(config.max_ttl_seconds),
+        }
+    }
+
+    pub fn lookup<T>(&self, request: &HttpRequest<T>) -> Lookup {
+        let headers = request.headers();
+
+        if request.method() != Method::GET
+            || [
+                IF_MATCH,
+                IF_MODIFIED_SINCE,
+                IF_NONE_MATCH,
+                IF_RANGE,
+                IF_UNMODIFIED_SINCE,
+            ]
+            .iter()
+            .any(|name| headers.contains_key(name))
+        {
+            return Lookup::Bypass;
+        }
+
+        let request_cache_control = headers.typed_get::<CacheControl>();
+        if request_cache_control
+            .as_ref()
+            .is_some_and(CacheControl::no_store)
+        {
+            return Lookup::Bypass;
+        }
+
+        let Some(entry) = self.get(request.uri()) else {
+            return Lookup::Miss;
+        };
+
+        if !entry.matches(headers) {
+            return Lookup::Miss;
+        }
+
+        let must_revalidate = request_cache_control.as_ref().is_some_and(|cache_control| {
+            cache_control.no_cache() || cache_control.max_age() == Some(Duration::ZERO)
+        }) || entry
+            .headers
+            .typed_get::<CacheControl>()
+            .is_some_and(|cache_control| cache_control.no_cache());
+
+        if !must_revalidate && entry.current_age() < self.freshness_lifetime(&entry) {
+            Lookup::Fresh(entry)
+        } else {
+            Lookup::Stale(entry)
+        }
+    }
+
+    /// Stores a response to a `GET` request, if it may be stored
+    /// (RFC 9111 §3).
+    pub fn store(&self, uri: &Uri, request_headers: &HeaderMap, response: &HttpResponse<Bytes>) {
+        let entry = Entry::new(request_headers, response);
+
+        if self.is_storable(request_headers, &entry) {
+            self.put(uri, entry);
+        }
+    }
+
+    /// Updates a stale entry after the upstream server has said it is still
+    /// current, returning the updated entry.
+    pub fn freshen(
+        &self,
+        uri: &Uri,
+        request_headers: &HeaderMap,
+        entry: Entry,
+        not_modified: &HttpResponse<Bytes>,
+    ) -> Entry {
+        let entry = entry.freshen(not_modified);
+
+        if self.is_storable(request_headers, &entry) {
+            self.put(uri, entry.clone());
+        } else {
+            self.invalidate(uri);
+        }
+
+        entry
+    }
+
+    /// Drops any entry for a URL, after a request which may have changed it
+    /// (RFC 9111 §4.4).
+    pub fn invalidate(&self, uri: &Uri) {
+        let key = cache_key(uri);
+
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.remove(&key);
+        }
+
+        if let Some(path) = self.path(&key) {
+            if let Err(err) = fs::remove_file(&path) {
+                if err.kind() != io::ErrorKind::NotFound {
+                    warn!(path = %path.display(), error = %err, "Failed to remove cache entry");
+                }
+            }
+        }
+    }
+
+    fn is_storable(&self, request_headers: &HeaderMap, entry: &Entry) -> bool {
+        let cache_control = entry.headers.typed_get::<CacheControl>();
+
+        if cache_control
+            .as_ref()
+            .is_some_and(|cache_control| cache_control.no_store() || cache_control.private())
+        {
+            return false;
+        }
+
+        // Responses to authenticated requests are only shared when the
+        // server explicitly allows it (RFC 9111 §3.5).
+        if request_headers.contains_key(AUTHORIZATION)
+            && !cache_control.as_ref().is_some_and(|cache_control| {
+                cache_control.public()
+                    || cache_control.s_max_age().is_some()
+                    || cache_control.must_revalidate()
+            })
+        {
+            return false;
+        }
+
+        if entry
+            .headers
+            .typed_get::<Vary>()
+            .is_some_and(|vary| vary.is_any())
+        {
+            return false;
+        }
+
+        if entry.headers.contains_key(TRANSFER_ENCODING) || entry.status.is_informational() {
+            return false;
+        }
+
+        let explicitly_cacheable = entry.headers.contains_key(EXPIRES)
+            || cache_control.as_ref().is_some_and(|cache_control| {
+                cache_control.public()
+                    || cache_control.s_max_age().is_some()
+                    || cache_control.max_age().is_some()
+            });
+        if !explicitly_cacheable && !HEURISTICALLY_CACHEABLE.contains(&entry.status) {
+            return false;
+        }
+
+        // Storing a response which can never be fresh and can't be
+        // revalidated would be pointless.
+        entry.has_validators() || !self.freshness_lifetime(entry).is_zero()
+    }
+
+    /// The entry's freshness lifetime. Without explicit freshness, a
+    /// heuristic of a tenth of the time since it was last modified is used
+    /// for status codes which allow it (RFC 9111 §4.2.2).
+    fn freshness_lifetime(&self, entry: &Entry) -> Duration {
+        if let Some(lifetime) = freshness_lifetime(&entry.headers, &UpdateHints::default()) {
+            return lifetime;
+        }
+
+        if !HEURISTICALLY_CACHEABLE.contains(&entry.status) {
+            return Duration::ZERO;
+        }
+
+        let date = entry
+            .headers
+            .typed_get::<Date>()
+            .map(SystemTime::from)
+            .unwrap_or(entry.response_time);
+
+        entry
+            .headers
+            .typed_get::<LastModified>()
+            .and_then(|last_modified| date.duration_since(last_modified.into()).ok())
+            .map(|since_modified| (since_modified / 10).min(self.max_heuristic_lifetime))
+            .unwrap_or_default()
+    }
+
+    /// Whether an entry is no use any more: it's stale, and there's nothing
+    /// to revalidate it with.
+    fn is_expired(&self, entry: &Entry) -> bool {
+        !entry.has_validators() && entry.current_age() >= self.freshness_lifetime(entry)
+    }
+
+    fn get(&self, uri: &Uri) -> Option<Entry> {
+        let key = cache_key(uri);
+
+        if let Some(entry) = self
+            .entries
+            .lock()
+            .ok()
+            .and_then(|mut entries| entries.get(&key).cloned())
+        {
+            return Some(entry);
+        }
+
+        let path = self.path(&key)?;
+        let entry = match fs::read(&path) {
+            Ok(data) => decode_entry(&data),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return None,
+            Err(err) => Err(err.to_string()),
+        };
+
+        match entry {
+            Ok(entry) => {
+                debug!(path = %path.display(), "Loaded cache entry from disk");
+                if let Ok(mut entries) = self.entries.lock() {
+                    entries.insert(key, entry.clone());
+                }
+                Some(entry)
+            }
+            Err(err) => {
+                warn!(path = %path.display(), error = %err, "Ignoring unreadable cache entry");
+                None
+            }
+        }
+    }
+
+    fn put(&self, uri: &Uri, entry: Entry) {
+        let key = cache_key(uri);
+
+        if let Some(path) = self.path(&key) {
+            if let Err(err) = write_entry(&path, &entry) {
+                warn!(path = %path.display(), error = %err, "Failed to write cache entry");
+            }
+        }
+
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.prune(|entry| self.is_expired(entry));
+            entries.insert(key, entry);
+        }
+    }
+
+    fn path(&self, key: &str) -> Option<PathBuf> {
+        self.dir.as_ref().map(|dir| dir.join(key))
+    }
+}
+
+/// Entries are keyed on the normalised URL, hashed so that it can be used as
+/// a file name.
+fn cache_key(uri: &Uri) -> String {
+    let uri = uri.to_string();
+    let normalised = match Url::parse(&uri) {
+        Ok(mut url) => {
+            url.set_fragment(None);
+            url.to_string()
+        }
+        Err(_) => uri,
+    };
+
+    Sha256::digest(normalised)
+        .iter()
+        .fold(String::new(), |mut key, byte| {
+            let _ = write!(key, "{byte:02x}");
+            key
+        })
+}
+
+fn write_entry(path: &Path, entry: &Entry) -> Result<(), HttpClientError> {
+    let dir = path
+        .parent()
+        .ok_or_else(|| HttpClientError::Cache(format!("{} has no parent", path.display())))?;
+    fs::create_dir_all(dir).map_err(|err| HttpClientError::Cache(err.to_string()))?;
+
+    // Write then rename, so that a concurrent reader never sees half an entry
+    let tmp = path.with_extension(format!("tmp{}", std::process::id()));
+    fs::write(&tmp, encode_entry(entry))
+        .and_then(|()| fs::rename(&tmp, path))
+        .map_err(|err| HttpClientError::Cache(err.to_string()))
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Entries are written as a block of `key value` lines, a blank line, and
+/// then the body. Header values can't contain newlines, so no escaping is
+/// needed.
+fn encode_entry(entry: &Entry) -> Vec<u8> {
+    let mut out = Vec::with_capacity(entry.body.len() + 1024);
+
+    let mut line = |parts: &[&[u8]]| {
+        parts.iter().for_each(|part| out.extend_from_slice(part));
+        out.push(b'\n');
+    };
+
+    line(&[DISK_FORMAT]);
+    line(&[b"status ", entry.status.as_str().as_bytes()]);
+    line(&[
+        b"response-time ",
+        unix_secs(entry.response_time).to_string().as_bytes(),
+    ]);
+    line(&[
+        b"initial-age ",
+        entry.initial_age.as_secs().to_string().as_bytes(),
+    ]);
+    for (name, value) in &entry.vary {
+        match value {
+            Some(value) => line(&[b"vary ", name.as_str().as_bytes(), b": ", value.as_bytes()]),
+            None => line(&[b"vary ", name.as_str().as_bytes()]),
+        }
+    }
+    for (name, value) in &entry.headers {
+        line(&[
+            b"header ",
+            name.as_str().as_bytes(),
+            b": ",
+            value.as_bytes(),
+        ]);
+    }
+    line(&[]);
+
+    out.extend_from_slice(&entry.body);
+    out
+}
+
+fn decode_entry(data: &[u8]) -> Result<Entry, String> {
+    let split = data
+        .windows(2)
+        .position(|window| window == b"\n\n")
+        .ok_or("missing end of headers")?;
+    let (head, body) = (&data[..split], &data[split + 2..]);
+
+    let mut lines = head.split(|&b| b == b'\n');
+    if lines.next() != Some(DISK_FORMAT) {
+        return Err("unknown format".to_string());
+    }
+
+    let mut entry = Entry {
+        status: StatusCode::OK,
+        headers: HeaderMap::new(),
+        body: Bytes::copy_from_slice(body),
+        vary: Vec::new(),
+        response_time: UNIX_EPOCH,
+        initial_age: Duration::ZERO,
+    };
+
+    let number = |value: &[u8]| -> Result<u64, String> {
+        std::str::from_utf8(value)
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .ok_or_else(|| format!("invalid number {:?}", String::from_utf8_lossy(value)))
+    };
+    let header = |value: &[u8]| -> Result<(HeaderName, Option<HeaderValue>), String> {
+        let (name, value) = match value.windows(2).position(|window| window == b": ") {
+            Some(i) => (&value[..i], Some(&value[i + 2..])),
+            None => (value, None),
+        };
+
+        Ok((
+            HeaderName::from_bytes(name).map_err(|err| err.to_string())?,
+            value
+                .map(HeaderValue::from_bytes)
+                .transpose()
+                .map_err(|err| err.to_string())?,
+        ))
+    };
+
+    for line in lines {
+        let space = line
+            .iter()
+            .position(|&b| b == b' ')
+            .ok_or("missing value")?;
+        let (key, value) = (&line[..space], &line[space + 1..]);
+
+        match key {
+            b"status" => {
+                entry.status = StatusCode::from_bytes(value).map_err(|err| err.to_string())?;
+            }
+            b"response-time" => {
+                entry.response_time = UNIX_EPOCH + Duration::from_secs(number(value)?);
+            }
+            b"initial-age" => entry.initial_age = Duration::from_secs(number(value)?),
+            b"vary" => entry.vary.push(header(value)?),
+            b"header" => match header(value)? {
+                (name, Some(value)) => {
+                    entry.headers.append(name, value);
+                }
+                (name, None) => return Err(format!("header {name} has no value")),
+            },
+            _ => return Err(format!("unknown key {:?}", String::from_utf8_lossy(key))),
+        }
+    }
+
+    Ok(entry)
+}
+
+/// A [`Layer`] wrapping clients in a [`CachingHttpClient`]. Every client it
+/// wraps shares the same cache.
+#[derive(Clone, Debug)]
+pub struct CacheLayer {
+    cache: Arc<HttpCache>,
+}
+
+impl CacheLayer {
+    pub fn new(config: &CacheConfig) -> Self {
+        Self {
+            cache: Arc::new(HttpCache::new(config)),
+        }
+    }
+}
+
+impl<C> Layer<C> for CacheLayer {
+    type Service = CachingHttpClient<C>;
+
+    fn layer(&self, inner: C) -> Self::Service {
+        CachingHttpClient {
+            inner,
+            cache: Arc::clone(&self.cache),
+        }
+    }
+}
+
+/// Wraps any [`HttpClient`], caching upstream responses as an RFC 9111 shared
+/// cache would, in memory and optionally in [`CacheConfig::cache_dir`]. The
+/// outcome is reported in `x-rssfilter-cache-status`.
+pub struct CachingHttpClient<C> {
+    inner: C,
+    cache: Arc<HttpCache>,
+}
+
+#[async_trait]
+impl<C: HttpClient> HttpClient for CachingHttpClient<C> {
+    async fn send(
+        &self,
+        request: HttpRequest<Bytes>,
+    ) -> Result<HttpResponse<Bytes>, HttpClientError> {
+        let cache = &self.cache;
+        let uri = request.uri().clone();
+        let request_headers = request.headers().clone();
+
+        let (mut response, cache_status) = match cache.lookup(&request) {
+            Lookup::Bypass => {
+                let safe = request.method().is_safe();
+                let response = self.inner.send(request).await?;
+
+                if !safe
+                    && !response.status().is_client_error()
+                    && !response.status().is_server_error()
+                {
+                    cache.invalidate(&uri);
+                }
+
+                (response, CfCacheStatus::Bypass)
+            }
+            Lookup::Miss => {
+                let response = self.inner.send(request).await?;
+                cache.store(&uri, &request_headers, &response);
+
+                (response, CfCacheStatus::Miss)
+            }
+            Lookup::Fresh(entry) => {
+                if let Some(limit) = BodySizeLimit::from_request(&request) {
+                    if entry.body_len() > limit {
+                        return Err(HttpClientError::BodyTooLarge { limit });
+                    }
+                }
+
+                (entry.to_response(), CfCacheStatus::Hit)
+            }
+            Lookup::Stale(entry) => {
+                let mut request = request;
+                entry.add_conditions(&mut request);
+
+                let response = self.inner.send(request).await?;
+
+                if response.status() == StatusCode::NOT_MODIFIED {
+                    let entry = cache.freshen(&uri, &request_headers, entry, &response);
+                    (entry.to_response(), CfCacheStatus::Revalidated)
+                } else {
+                    cache.store(&uri, &request_headers, &response);
+                    (response, CfCacheStatus::Expired)
+                }
+            }
+        };
+
+        debug!(cache_status = %cache_status, status = %response.status(), "HTTP cache lookup completed");
+
+        response
+            .headers_mut()
+            .typed_insert(RssFilterCacheStatus(cache_status));
+
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    const URL: &str = "https://example.com/feed";
+
+    fn uri() -> Uri {
+        Uri::from_static(URL)
+    }
+
+    fn request(headers: &[(&'static str, &'static str)]) -> HttpRequest<Bytes> {
+        headers
+            .iter()
+            .fold(HttpRequest::get(URL), |builder, (name, value)| {
+                builder.header(*name, *value)
+            })
+            .body(Bytes::new())
+            .unwrap()
+    }
+
+    fn response(status: u16, headers: &[(&'static str, &'static str)]) -> HttpResponse<Bytes> {
+        headers
+            .iter()
+            .fold(
+                HttpResponse::builder().status(status),
+                |builder, (name, value)| builder.header(*name, *value),
+            )
+            .body(Bytes::from_static(b"<rss/>"))
+            .unwrap()
+    }
+
+    fn cache_with(
+        request_headers: &[(&'static str, &'static str)],
+        response: HttpResponse<Bytes>,
+    ) -> HttpCache {
+        let cache = HttpCache::new(&CacheConfig::default());
+        cache.store(&uri(), request(request_headers).headers(), &response);
+        cache
+    }
+
+    #[test]
+    fn test_miss() {
+        let cache = HttpCache::new(&CacheConfig::default());
+
+        assert!(matches!(cache.lookup(&request(&[])), Lookup::Miss));
+    }
+
+    #[test_case(&[("cache-control", "max-age=300")] ; "max-age")]
+    #[test_case(&[("cache-control", "s-maxage=300, max-age=0")] ; "s-maxage wins for a shared cache")]
+    #[test_case(&[("last-modified", "Sat, 01 Jan 2000 00:00:00 GMT")] ; "heuristic from last-modified")]
+    fn test_fresh(headers: &[(&'static str, &'static str)]) {
+        let cache = cache_with(&[], response(200, headers));
+
+        assert!(matches!(cache.lookup(&request(&[])), Lookup::Fresh(_)));
+    }
+
+    #[test_case(&[("cache-control", "max-age=0"), ("etag", "\"v1\"")], &[] ; "expired")]
+    #[test_case(&[("cache-control", "max-age=300"), ("age", "400"), ("etag", "\"v1\"")], &[] ; "aged in an upstream cache")]
+    #[test_case(&[("cache-control", "no-cache, max-age=300"), ("etag", "\"v1\"")], &[] ; "response no-cache")]
+    #[test_case(&[("cache-control", "max-age=300")], &[("cache-control", "no-cache")] ; "request no-cache")]
+    #[test_case(&[("cache-control", "max-age=300")], &[("cache-control", "max-age=0")] ; "request max-age=0")]
+    fn test_stale(
+        response_headers: &[(&'static str, &'static str)],
+        request_headers: &[(&'static str, &'static str)],
+    ) {
+        let cache = cache_with(&[], response(200, response_headers));
+
+        assert!(matches!(
+            cache.lookup(&request(request_headers)),
+            Lookup::Stale(_)
+        ));
+    }
+
+    #[test_case(200, &[("cache-control", "no-store, max-age=300")] ; "no-store")]
+    #[test_case(200, &[("cache-control", "private, max-age=300")] ; "private")]
+    #[test_case(200, &[("cache-control", "max-age=300"), ("vary", "*")] ; "vary any")]
+    #[test_case(200, &[] ; "no freshness or validators")]
+    #[test_case(500, &[("last-modified", "Sat, 17 Oct 2026 10:00:00 GMT")] ; "no heuristic for errors")]
+    fn test_not_stored(status: u16, headers: &[(&'static str, &'static str)]) {
+        let cache = cache_with(&[], response(status, headers));
+
+        assert!(matches!(cache.lookup(&request(&[])), Lookup::Miss));
+    }
+
+    #[test_case(&[("cache-control", "max-age=300")], false ; "not shared by default")]
+    #[test_case(&[("cache-control", "public, max-age=300")], true ; "public")]
+    #[test_case(&[("cache-control", "s-maxage=300")], true ; "s-maxage")]
+    fn test_authorization(headers: &[(&'static str, &'static str)], stored: bool) {
+        let cache = cache_with(&[("authorization", "Bearer x")], response(200, headers));
+
+        assert_eq!(
+            matches!(cache.lookup(&request(&[])), Lookup::Fresh(_)),
+            stored
+        );
+    }
+
+    #[test_case(&[("if-none-match", "\"v1\"")] ; "conditional")]
+    #[test_case(&[("cache-control", "no-store")] ; "request no-store")]
+    fn test_bypass(request_headers: &[(&'static str, &'static str)]) {
+        let cache = cache_with(&[], response(200, &[("cache-control", "max-age=300")]));
+
+        assert!(matches!(
+            cache.lookup(&request(request_headers)),
+            Lookup::Bypass
+        ));
+    }
+
+    #[test]
+    fn test_vary() {
+        let cache = cache_with(
+            &[("accept-language", "en")],
+            response(
+                200,
+                &[
+                    ("cache-control", "max-age=300"),
+                    ("vary", "Accept-Language"),
+                ],
+            ),
+        );
+
+        assert!(matches!(
+            cache.lookup(&request(&[("accept-language", "en")])),
+            Lookup::Fresh(_)
+        ));
+        assert!(matches!(
+            cache.lookup(&request(&[("accept-language", "fr")])),
+            Lookup::Miss
+        ));
+        assert!(matches!(cache.lookup(&request(&[])), Lookup::Miss));
+    }
+
+    #[test]
+    fn test_freshen() {
+        let cache = cache_with(
+            &[],
+            response(200, &[("cache-control", "max-age=0"), ("etag", "\"v1\"")]),
+        );
+        let Lookup::Stale(entry) = cache.lookup(&request(&[])) else {
+            panic!("entry should be stale");
+        };
+
+        let mut conditional = request(&[]);
+        entry.add_conditions(&mut conditional);
+        assert_eq!(conditional.headers()["if-none-match"], "\"v1\"");
+
+        let updated = cache.freshen(
+            &uri(),
+            &HeaderMap::new(),
+            entry,
+            &response(
+                304,
+                &[("cache-control", "max-age=300"), ("content-length", "0")],
+            ),
+        );
+
+        assert_eq!(updated.status, StatusCode::OK);
+        assert_eq!(updated.body, "<rss/>");
+        assert_eq!(updated.headers["cache-control"], "max-age=300");
+        assert!(!updated.headers.contains_key("content-length"));
+        assert!(matches!(cache.lookup(&request(&[])), Lookup::Fresh(_)));
+    }
+
+    #[test]
+    fn test_invalidate() {
+        let cache = cache_with(&[], response(200, &[("cache-control", "max-age=300")]));
+
+        cache.invalidate(&uri());
+
+        assert!(matches!(cache.lookup(&request(&[])), Lookup::Miss));
+    }
+
+    #[test]
+    fn test_memory_is_bounded() {
+        let cache = HttpCache::new(&CacheConfig {
+            max_entries: 1,
+            ..Default::default()
+        });
+        let other = Uri::from_static("https://example.com/other");
+        let fresh = response(200, &[("cache-control", "max-age=300")]);
+
+        cache.store(&uri(), &HeaderMap::new(), &fresh);
+        cache.store(&other, &HeaderMap::new(), &fresh);
+
+        assert!(matches!(cache.lookup(&request(&[])), Lookup::Miss));
+        assert!(cache.get(&other).is_some());
+    }
+
+    #[test]
+    fn test_expired_entries_are_pruned() {
+        let cache = HttpCache::new(&CacheConfig::default());
+        let mut expired = response(200, &[("cache-control", "max-age=60")]);
+        expired
+            .headers_mut()
+            .typed_insert(Date::from(now() - Duration::from_secs(120)));
+        let revalidatable = response(200, &[("etag", "\"v1\""), ("cache-control", "max-age=0")]);
+        let other = Uri::from_static("https://example.com/other");
+
+        cache.store(&uri(), &HeaderMap::new(), &expired);
+        cache.store(&other, &HeaderMap::new(), &revalidatable);
+        cache
+            .entries
+            .lock()
+            .unwrap()
+            .force_prune(|entry| cache.is_expired(entry));
+
+        assert!(cache.get(&uri()).is_none());
+        assert!(cache.get(&other).is_some());
+    }
+
+    #[test]
+    fn test_disk_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = CacheConfig {
+            cache_dir: Some(dir.path().to_path_buf()),
+            ..Default::default()
+        };
+
+        HttpCache::new(&config).store(
+            &uri(),
+            request(&[("accept", "application/rss+xml")]).headers(),
+            &response(
+                200,
+                &[
+                    ("cache-control", "max-age=300"),
+                    ("vary", "accept, accept-language"),
+                    ("set-cookie", "a=1"),
+                    ("set-cookie", "b=2"),
+                ],
+            ),
+        );
+
+        // A new cache only has what was written to disk
+        let cache = HttpCache::new(&config);
+        let Lookup::Fresh(entry) = cache.lookup(&request(&[("accept", "application/rss+xml")]))
+        else {
+            panic!("entry should be loaded from disk");
+        };
+
+        assert_eq!(entry.body, "<rss/>");
+        assert_eq!(entry.headers.get_all("set-cookie").iter().count(), 2);
+        assert_eq!(decode_entry(&encode_entry(&entry)).as_ref(), Ok(&entry));
+
+        cache.invalidate(&uri());
+        assert!(matches!(
+            HttpCache::new(&config).lookup(&request(&[("accept", "application/rss+xml")])),
+            Lookup::Miss
+        ));
+    }
+
+    #[test_case(b"" ; "empty")]
+    #[test_case(b"something else 1\n\n" ; "unknown format")]
+    #[test_case(b"rssfilter-http-cache 1\nstatus abc\n\n" ; "bad status")]
+    fn test_decode_invalid(data: &[u8]) {
+        assert!(decode_entry(data).is_err());
+    }
+}
@@ -1,11 +1,18 @@
 use async_trait::async_trait;
-use bytes::Bytes;
+use bytes::{Bytes, BytesMut};
 #[cfg(not(target_arch = "wasm32"))]
 use http::{HeaderMap as HttpHeaderMap, HeaderName as HttpHeaderName};
 use http::{Request as HttpRequest, Response as HttpResponse};
+use std::path::PathBuf;
 use thiserror::Error;
 use tracing::debug;
 
+#[cfg(not(target_arch = "wasm32"))]
+use crate::http_cache::CacheLayer;
+use crate::layer::{Layer, ServiceBuilder, TraceLayer};
+use crate::rate_limit::RateLimitLayer;
+use crate::retry::{RetryConfig, RetryLayer};
+
 #[cfg(not(target_arch = "wasm32"))]
 use tracing::instrument;
 
@@ -17,6 +24,9 @@ pub enum HttpClientError {
     #[error("Request failed: {0}")]
     Request(String),
 
+    #[error("Could not connect: {0}")]
+    Connect(String),
+
     #[error("Cache error: {0}")]
     Cache(String),
 
@@ -29,6 +39,30 @@ pub enum HttpClientError {
     #[error("Body conversion error: {0}")]
     Body(String),
 
+    #[error("Request timed out after {0:?}")]
+    Timeout(std::time::Duration),
+
+    #[error("Gave up after {max} redirects")]
+    TooManyRedirects { max: usize },
+
+    #[error("Refused to follow redirect: {0}")]
+    Redirect(String),
+
+    #[error("URL not allowed: {0}")]
+    UrlPolicy(#[from] crate::url_policy::UrlPolicyError),
+
+    #[error("Response body is larger than the limit of {limit} bytes")]
+    BodyTooLarge { limit: u64 },
+
+    #[error("Too many requests to {host}; try again in {retry_after:?}")]
+    RateLimited {
+        host: String,
+        retry_after: std::time::Duration,
+    },
+
+    #[error("Rate limit store error: {0}")]
+    RateLimitStore(String),
+
     #[cfg(not(target_arch = "wasm32"))]
     #[error("Reqwest error: {0}")]
     Reqwest(#[from] reqwest::Error),
@@ -77,21 +111,109 @@ pub trait HttpClient {
     ) -> Result<HttpResponse<Bytes>, HttpClientError>;
 }
 
+/// Request extension limiting how much of the response body a client will read.
+///
+/// Clients check the limit as each chunk arrives, so a chunked or compressed
+/// response without a `Content-Length` is rejected before it is fully
+/// buffered.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BodySizeLimit(pub u64);
+
+impl BodySizeLimit {
+    pub fn from_request<T>(request: &HttpRequest<T>) -> Option<u64> {
+        request.extensions().get::<Self>().map(|limit| limit.0)
+    }
+}
+
+/// Accumulates a response body chunk by chunk, enforcing an optional
+/// [`BodySizeLimit`].
+pub(crate) struct LimitedBody {
+    buf: BytesMut,
+    limit: Option<u64>,
+}
+
+impl LimitedBody {
+    pub fn new(limit: Option<u64>) -> Self {
+        Self {
+            buf: BytesMut::new(),
+            limit,
+        }
+    }
+
+    /// Fail early when the response declares a length over the limit.
+    pub fn check_declared_length(&self, length: Option<u64>) -> Result<(), HttpClientError> {
+        match (self.limit, length) {
+            (Some(limit), Some(length)) if length > limit => {
+                Err(HttpClientError::BodyTooLarge { limit })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn push(&mut self, chunk: &[u8]) -> Result<(), HttpClientError> {
+        if let Some(limit) = self.limit {
+            if (self.buf.len() + chunk.len()) as u64 > limit {
+                return Err(HttpClientError::BodyTooLarge { limit });
+            }
+        }
+
+        self.buf.extend_from_slice(chunk);
+
+        Ok(())
+    }
+
+    pub fn finish(self) -> Bytes {
+        self.buf.freeze()
+    }
+}
+
 /// Configuration for cache behaviour
+///
+/// Cache lifetimes come from the upstream response's `Cache-Control` or
+/// `Expires`, or failing those the feed's `<ttl>` or `sy:updatePeriod`, and
+/// are clamped to `min_ttl_seconds..=max_ttl_seconds`.
 #[derive(Debug, Clone)]
 pub struct CacheConfig {
-    /// Time-to-live for cached responses in seconds. Default is 300 seconds (5 minutes)
-    #[allow(dead_code)]
+    /// Time-to-live for cached responses in seconds, when neither the response
+    /// nor the feed says. Default is 300 seconds (5 minutes)
     pub ttl_seconds: u64,
-    #[allow(dead_code)]
+    /// Shortest time-to-live, so that feeds which ask not to be cached for
+    /// long aren't fetched on every request. Default is 60 seconds
+    pub min_ttl_seconds: u64,
+    /// Longest time-to-live, so that slow feeds are still picked up. Default
+    /// is 86400 seconds (1 day)
+    pub max_ttl_seconds: u64,
     pub cache_key_prefix: String,
+    /// How long after a cached filtered response goes stale it may still be
+    /// served, while it is refreshed in the background. Default is 60 seconds
+    pub stale_while_revalidate_seconds: u64,
+    /// How long after a cached filtered response goes stale it may still be
+    /// served if the upstream server can't be reached or returns a server
+    /// error. Default is 86400 seconds (1 day)
+    pub stale_if_error_seconds: u64,
+    /// Directory the native HTTP cache keeps upstream responses in, so that
+    /// they are reused across runs. When unset they are only kept in memory.
+    /// Not used on WASM, where Cloudflare caches upstream fetches.
+    pub cache_dir: Option<PathBuf>,
+    /// Most entries each in-memory cache holds, before the least recently
+    /// used are dropped. Default is 1000
+    pub max_entries: usize,
+    /// Most bytes of responses each in-memory cache holds. Default is 64 MiB
+    pub max_bytes: usize,
 }
 
 impl Default for CacheConfig {
     fn default() -> Self {
         Self {
-            ttl_seconds: 300, // 5 minutes
+            ttl_seconds: 300,       // 5 minutes
+            min_ttl_seconds: 60,    // 1 minute
+            max_ttl_seconds: 86400, // 1 day
             cache_key_prefix: "http-cache".to_string(),
+            stale_while_revalidate_seconds: 60, // 1 minute
+            stale_if_error_seconds: 86400,      // 1 day
+            cache_dir: None,
+            max_entries: 1000,
+            max_bytes: 64 * 1024 * 1024, // 64 MiB
         }
     }
 }
@@ -132,6 +254,8 @@ pub mod reqwest_client {
     pub fn default_reqwest_client() -> Result<reqwest::Client, reqwest::Error> {
         let builder = reqwest::ClientBuilder::new()
             .user_agent("filter-rss-feed https://github.com/iainlane/filter-rss-feed")
+            // Redirects are followed by `RssFilter`, so that each hop is checked
+            .redirect(reqwest::redirect::Policy::none())
             .brotli(true)
             .deflate(true)
             .gzip(true)
@@ -174,10 +298,26 @@ pub mod reqwest_client {
             Ok(request_builder.build()?)
         }
 
+        async fn execute(
+            &self,
+            request: HttpRequest<Bytes>,
+        ) -> Result<HttpResponse<Bytes>, HttpClientError> {
+            let body_size_limit = BodySizeLimit::from_request(&request);
+            let reqwest_request = self.convert_request(request)?;
+            let reqwest_response = self.client.execute(reqwest_request).await?;
+
+            self.convert_response(reqwest_response, body_size_limit)
+                .await
+        }
+
         async fn convert_response(
             &self,
             mut resp: reqwest::Response,
+            body_size_limit: Option<u64>,
         ) -> Result<HttpResponse<Bytes>, HttpClientError> {
+            let mut body = LimitedBody::new(body_size_limit);
+            body.check_declared_length(resp.content_length())?;
+
             let status = resp.status();
             let headers = resp.headers_mut();
 
@@ -193,9 +333,11 @@ pub mod reqwest_client {
 
             response_headers.typed_insert(RssFilterCacheStatus(upstream_cf_status));
 
-            let body = resp.bytes().await?;
+            while let Some(chunk) = resp.chunk().await? {
+                body.push(&chunk)?;
+            }
 
-            Ok(response_builder.body(body)?)
+            Ok(response_builder.body(body.finish())?)
         }
     }
 
@@ -207,11 +349,7 @@ pub mod reqwest_client {
             request: HttpRequest<Bytes>,
         ) -> Result<HttpResponse<Bytes>, HttpClientError> {
             debug!("Making HTTP request via reqwest");
-
-            let reqwest_request = self.convert_request(request)?;
-            let reqwest_response = self.client.execute(reqwest_request).await?;
-
-            self.convert_response(reqwest_response).await
+            self.execute(request).await
         }
     }
 }
@@ -222,11 +360,12 @@ pub mod worker_client {
     use super::*;
     use crate::header_cf_cache_status::CfCacheStatus;
     use crate::header_rssfilter_cache_status::RssFilterCacheStatus;
+    use futures_util::StreamExt;
     use headers::HeaderMapExt;
     use http::HeaderMap;
     use std::collections::HashMap;
     use std::collections::hash_map::DefaultHasher;
-    use worker::{CfProperties, Fetch, Request as WorkerRequest, RequestInit};
+    use worker::{CfProperties, Fetch, Request as WorkerRequest, RequestInit, RequestRedirect};
 
     pub struct WorkerHttpClient {
         cache_config: CacheConfig,
@@ -260,6 +399,7 @@ pub mod worker_client {
         ) -> Result<HttpResponse<Bytes>, HttpClientError> {
             let cache_key = self.create_cache_key(&request);
             let uri = request.uri().to_string();
+            let body_size_limit = BodySizeLimit::from_request(&request);
 
             // Convert http::Request to worker::Request
             let worker_headers = worker::Headers::new();
@@ -268,17 +408,18 @@ pub mod worker_client {
                 worker_headers.set(name.as_str(), value_str)?;
             }
 
-            // Configure CloudFlare properties with caching
+            // Configure CloudFlare properties with caching. How long the
+            // filtered response is cached for is decided from the upstream
+            // response, so this cache only needs to absorb bursts of requests
+            // for the same feed.
             let mut cache_ttl_by_status = HashMap::new();
-            cache_ttl_by_status.insert("200-299".to_string(), self.cache_config.ttl_seconds as i32);
             cache_ttl_by_status.insert(
-                "300-399".to_string(),
-                (self.cache_config.ttl_seconds / 2) as i32,
-            ); // Shorter for redirects
+                "200-299".to_string(),
+                self.cache_config.min_ttl_seconds as i32,
+            );
 
             let cf_properties = CfProperties {
                 cache_everything: Some(true),
-                cache_ttl: Some(self.cache_config.ttl_seconds as i32),
                 cache_key: Some(cache_key.clone()),
                 cache_ttl_by_status: Some(cache_ttl_by_status),
                 ..Default::default()
@@ -304,7 +445,9 @@ pub mod worker_client {
             request_init
                 .with_method(method)
                 .with_headers(worker_headers)
-                .with_cf_properties(cf_properties);
+                .with_cf_properties(cf_properties)
+                // Redirects are followed by `RssFilter`, so that each hop is checked
+                .with_redirect(RequestRedirect::Manual);
 
             // Add body if present
             let body = request.into_body();
@@ -316,14 +459,35 @@ pub mod worker_client {
             let worker_request = WorkerRequest::new_with_init(&uri, &request_init)?;
 
             // Send request
-            let mut worker_response = Fetch::Request(worker_request).send().await?;
+            // Anything going wrong before there's a response is the network
+            let mut worker_response = Fetch::Request(worker_request)
+                .send()
+                .await
+                .map_err(|err| HttpClientError::Connect(err.to_string()))?;
 
             // Extract what we need before consuming the response
             let mut header_map: HeaderMap = worker_response.headers().into();
             let status = worker_response.status_code();
 
-            // Now consume the response to get the body
-            let body: Bytes = worker_response.bytes().await?.into();
+            // Now consume the response to get the body, a chunk at a time so
+            // that we can give up as soon as it's too big
+            let mut body = LimitedBody::new(body_size_limit);
+            body.check_declared_length(
+                header_map
+                    .typed_get::<headers::ContentLength>()
+                    .map(|len| len.0),
+            )?;
+
+            match worker_response.stream() {
+                Ok(mut stream) => {
+                    while let Some(chunk) = stream.next().await {
+                        body.push(&chunk?)?;
+                    }
+                }
+                Err(_) => body.push(&worker_response.bytes().await?)?,
+            }
+
+            let body = body.finish();
 
             // Check if response came from cache
             let cf_cache_status = header_map
@@ -364,17 +528,64 @@ pub mod worker_client {
     }
 }
 
+/// What the platform's HTTP client is built with. See
+/// [`create_http_client_with_options`].
+#[derive(Clone, Default)]
+pub struct HttpClientOptions {
+    pub cache: CacheConfig,
+    /// How fetches are timed out and retried. Each redirect is a fetch of
+    /// its own
+    pub retry: RetryConfig,
+    /// Limits fetches from each upstream host. It sits next to the network,
+    /// so responses from the cache don't count
+    pub rate_limit: Option<RateLimitLayer>,
+}
+
+/// Wraps the client which reaches the network in the rate limit, if there is
+/// one.
+fn rate_limited<C: HttpClient + 'static>(
+    transport: C,
+    rate_limit: Option<RateLimitLayer>,
+) -> Box<dyn HttpClient> {
+    match rate_limit {
+        Some(rate_limit) => Box::new(rate_limit.layer(transport)),
+        None => Box::new(transport),
+    }
+}
+
 // Factory functions
 pub fn create_http_client() -> Result<Box<dyn HttpClient>, HttpClientError> {
     create_http_client_with_config(CacheConfig::default())
 }
 
 pub fn create_http_client_with_config(
-    #[cfg_attr(not(target_arch = "wasm32"), allow(unused_variables))] cache_config: CacheConfig,
+    cache_config: CacheConfig,
+) -> Result<Box<dyn HttpClient>, HttpClientError> {
+    create_http_client_with_options(HttpClientOptions {
+        cache: cache_config,
+        ..Default::default()
+    })
+}
+
+/// Creates the HTTP client for the platform, with request tracing, timeouts
+/// and retries. On WASM, upstream fetches are cached by Cloudflare; otherwise
+/// reqwest is used with our own cache layer, above the retries so that
+/// cached responses are served without them.
+pub fn create_http_client_with_options(
+    options: HttpClientOptions,
 ) -> Result<Box<dyn HttpClient>, HttpClientError> {
+    let layers = ServiceBuilder::new().layer(TraceLayer);
+
     #[cfg(target_arch = "wasm32")]
     {
-        Ok(Box::new(worker_client::WorkerHttpClient::new(cache_config)))
+        Ok(Box::new(
+            layers
+                .layer(RetryLayer::new(options.retry))
+                .service(rate_limited(
+                    worker_client::WorkerHttpClient::new(options.cache),
+                    options.rate_limit,
+                )),
+        ))
     }
 
     #[cfg(not(target_arch = "wasm32"))]
@@ -382,9 +593,15 @@ pub fn create_http_client_with_config(
         let reqwest_client = reqwest_client::default_reqwest_client().map_err(|e| {
             HttpClientError::Request(format!("Failed to create reqwest client: {e}"))
         })?;
-        Ok(Box::new(reqwest_client::ReqwestHttpClient::new(
-            reqwest_client,
-        )))
+        Ok(Box::new(
+            layers
+                .layer(CacheLayer::new(&options.cache))
+                .layer(RetryLayer::new(options.retry))
+                .service(rate_limited(
+                    reqwest_client::ReqwestHttpClient::new(reqwest_client),
+                    options.rate_limit,
+                )),
+        ))
     }
 }
 
@@ -396,7 +613,11 @@ mod tests {
     fn test_cache_config_default() {
         let config = CacheConfig::default();
         assert_eq!(config.ttl_seconds, 300);
+        assert_eq!(config.min_ttl_seconds, 60);
+        assert_eq!(config.max_ttl_seconds, 86400);
         assert_eq!(config.cache_key_prefix, "http-cache");
+        assert_eq!(config.stale_while_revalidate_seconds, 60);
+        assert_eq!(config.stale_if_error_seconds, 86400);
     }
 
     #[test]
@@ -404,6 +625,7 @@ mod tests {
         let config = CacheConfig {
             ttl_seconds: 600,
             cache_key_prefix: "my-cache".to_string(),
+            ..Default::default()
         };
         assert_eq!(config.ttl_seconds, 600);
         assert_eq!(config.cache_key_prefix, "my-cache");
@@ -517,11 +739,199 @@ mod tests {
             assert!(matches!(result.unwrap_err(), HttpClientError::Reqwest(_)));
         }
 
+        #[tokio::test]
+        async fn test_reqwest_client_body_size_limit() {
+            let mut server = mockito::Server::new_async().await;
+            server
+                .mock("GET", "/chunked")
+                .with_status(OK as usize)
+                .with_chunked_body(|w| {
+                    for _ in 0..16 {
+                        w.write_all(&[b'a'; 64])?;
+                    }
+                    Ok(())
+                })
+                .create_async()
+                .await;
+
+            let client = create_http_client().unwrap();
+
+            let send = |limit: u64| {
+                let request = HttpRequest::builder()
+                    .method(Method::GET)
+                    .uri(format!("{}/chunked", server.url()))
+                    .extension(BodySizeLimit(limit))
+                    .body(Bytes::new())
+                    .unwrap();
+
+                client.send(request)
+            };
+
+            let result = send(512).await;
+            assert!(matches!(
+                result.unwrap_err(),
+                HttpClientError::BodyTooLarge { limit: 512 }
+            ));
+
+            let response = send(1024).await.unwrap();
+            assert_eq!(response.into_body().len(), 1024);
+        }
+
+        fn get(url: String) -> HttpRequest<Bytes> {
+            HttpRequest::builder()
+                .method(Method::GET)
+                .uri(url)
+                .body(Bytes::new())
+                .unwrap()
+        }
+
+        fn cache_status(response: &HttpResponse<Bytes>) -> CfCacheStatus {
+            response
+                .headers()
+                .typed_get::<RssFilterCacheStatus>()
+                .unwrap()
+                .0
+        }
+
+        #[tokio::test]
+        async fn test_reqwest_client_cache() {
+            let mut server = mockito::Server::new_async().await;
+            let fresh = server
+                .mock("GET", "/fresh")
+                .with_status(OK as usize)
+                .with_header("cache-control", "max-age=300")
+                .with_body("fresh")
+                .expect(1)
+                .create_async()
+                .await;
+
+            let client = create_http_client().unwrap();
+            let url = format!("{}/fresh", server.url());
+
+            let response = client.send(get(url.clone())).await.unwrap();
+            assert_eq!(cache_status(&response), CfCacheStatus::Miss);
+
+            let response = client.send(get(url)).await.unwrap();
+            assert_eq!(cache_status(&response), CfCacheStatus::Hit);
+            assert!(response.headers().contains_key("age"));
+            assert_eq!(response.into_body(), "fresh");
+
+            fresh.assert_async().await;
+        }
+
+        #[tokio::test]
+        async fn test_reqwest_client_cache_revalidation() {
+            let mut server = mockito::Server::new_async().await;
+            let url = format!("{}/stale", server.url());
+            let client = create_http_client().unwrap();
+
+            let first = server
+                .mock("GET", "/stale")
+                .match_header("if-none-match", mockito::Matcher::Missing)
+                .with_status(OK as usize)
+                .with_header("cache-control", "max-age=0")
+                .with_header("etag", "\"v1\"")
+                .with_body("v1")
+                .create_async()
+                .await;
+            let response = client.send(get(url.clone())).await.unwrap();
+            assert_eq!(cache_status(&response), CfCacheStatus::Miss);
+            first.remove_async().await;
+
+            let not_modified = server
+                .mock("GET", "/stale")
+                .match_header("if-none-match", "\"v1\"")
+                .with_status(StatusCode::NOT_MODIFIED.as_u16() as usize)
+                .with_header("cache-control", "max-age=0")
+                .with_header("etag", "\"v1\"")
+                .create_async()
+                .await;
+            let response = client.send(get(url.clone())).await.unwrap();
+            assert_eq!(cache_status(&response), CfCacheStatus::Revalidated);
+            assert_eq!(response.status(), OK);
+            assert_eq!(response.into_body(), "v1");
+            not_modified.remove_async().await;
+
+            server
+                .mock("GET", "/stale")
+                .match_header("if-none-match", "\"v1\"")
+                .with_status(OK as usize)
+                .with_header("etag", "\"v2\"")
+                .with_body("v2")
+                .create_async()
+                .await;
+            let response = client.send(get(url)).await.unwrap();
+            assert_eq!(cache_status(&response), CfCacheStatus::Expired);
+            assert_eq!(response.into_body(), "v2");
+        }
+
+        #[tokio::test]
+        async fn test_reqwest_client_disk_cache() {
+            let dir = tempfile::tempdir().unwrap();
+            let config = || CacheConfig {
+                cache_dir: Some(dir.path().to_path_buf()),
+                ..Default::default()
+            };
+
+            let mut server = mockito::Server::new_async().await;
+            let mock = server
+                .mock("GET", "/feed")
+                .with_status(OK as usize)
+                .with_header("cache-control", "max-age=300")
+                .with_body("<rss/>")
+                .expect(1)
+                .create_async()
+                .await;
+            let url = format!("{}/feed", server.url());
+
+            let response = create_http_client_with_config(config())
+                .unwrap()
+                .send(get(url.clone()))
+                .await
+                .unwrap();
+            assert_eq!(cache_status(&response), CfCacheStatus::Miss);
+
+            // A new client, as on the next run of the CLI
+            let response = create_http_client_with_config(config())
+                .unwrap()
+                .send(get(url))
+                .await
+                .unwrap();
+            assert_eq!(cache_status(&response), CfCacheStatus::Hit);
+            assert_eq!(response.into_body(), "<rss/>");
+
+            mock.assert_async().await;
+        }
+
+        #[tokio::test]
+        async fn test_reqwest_client_cache_body_size_limit() {
+            let mut server = mockito::Server::new_async().await;
+            server
+                .mock("GET", "/big")
+                .with_status(OK as usize)
+                .with_header("cache-control", "max-age=300")
+                .with_body([b'a'; 1024])
+                .create_async()
+                .await;
+
+            let client = create_http_client().unwrap();
+            let url = format!("{}/big", server.url());
+            client.send(get(url.clone())).await.unwrap();
+
+            let mut request = get(url);
+            request.extensions_mut().insert(BodySizeLimit(512));
+            assert!(matches!(
+                client.send(request).await.unwrap_err(),
+                HttpClientError::BodyTooLarge { limit: 512 }
+            ));
+        }
+
         #[tokio::test]
         async fn test_custom_cache_config() {
             let config = CacheConfig {
                 ttl_seconds: 600,
                 cache_key_prefix: "test-cache".to_string(),
+                ..Default::default()
             };
 
             let mut server = mockito::Server::new_async().await;
@@ -577,6 +987,7 @@ mod wasm_tests {
         let config = CacheConfig {
             ttl_seconds: 600,
             cache_key_prefix: "test-cache".to_string(),
+            ..Default::default()
         };
 
         let client = create_http_client_with_config(config);
@@ -797,7 +1208,7 @@ mod wasm_tests {
 
         let result = fake_client.send(request).await;
         assert!(result.is_err());
-        assert!(matches!(result.unwrap_err(), HttpClientError::Request(_)));
+        assert!(matches!(result.unwrap_err(), HttpClientError::Connect(_)));
     }
 
     #[wasm_bindgen_test]
@@ -0,0 +1,360 @@
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use http::{HeaderName, HeaderValue, Request as HttpRequest, Response as HttpResponse};
+use tracing::{debug, warn};
+use web_time::Instant;
+
+use crate::http_client::{HttpClient, HttpClientError};
+
+pub use tower::{Layer, Service, ServiceBuilder};
+
+// Borrowed, boxed and shared clients are clients too, so that layers can wrap them
+#[async_trait]
+#[cfg(not(target_arch = "wasm32"))]
+impl<C: HttpClient + ?Sized> HttpClient for Box<C> {
+    async fn send(
+        &self,
+        request: HttpRequest<Bytes>,
+    ) -> Result<HttpResponse<Bytes>, HttpClientError> {
+        (**self).send(request).await
+    }
+}
+
+#[async_trait(?Send)]
+#[cfg(target_arch = "wasm32")]
+impl<C: HttpClient + ?Sized> HttpClient for Box<C> {
+    async fn send(
+        &self,
+        request: HttpRequest<Bytes>,
+    ) -> Result<HttpResponse<Bytes>, HttpClientError> {
+        (**self).send(request).await
+    }
+}
+
+#[async_trait]
+#[cfg(not(target_arch = "wasm32"))]
+impl<C: HttpClient + ?Sized> HttpClient for &C {
+    async fn send(
+        &self,
+        request: HttpRequest<Bytes>,
+    ) -> Result<HttpResponse<Bytes>, HttpClientError> {
+        (**self).send(request).await
+    }
+}
+
+#[async_trait(?Send)]
+#[cfg(target_arch = "wasm32")]
+impl<C: HttpClient + ?Sized> HttpClient for &C {
+    async fn send(
+        &self,
+        request: HttpRequest<Bytes>,
+    ) -> Result<HttpResponse<Bytes>, HttpClientError> {
+        (**self).send(request).await
+    }
+}
+
+#[async_trait]
+#[cfg(not(target_arch = "wasm32"))]
+impl<C: HttpClient + ?Sized> HttpClient for Arc<C> {
+    async fn send(
+        &self,
+        request: HttpRequest<Bytes>,
+    ) -> Result<HttpResponse<Bytes>, HttpClientError> {
+        (**self).send(request).await
+    }
+}
+
+#[async_trait(?Send)]
+#[cfg(target_arch = "wasm32")]
+impl<C: HttpClient + ?Sized> HttpClient for Arc<C> {
+    async fn send(
+        &self,
+        request: HttpRequest<Bytes>,
+    ) -> Result<HttpResponse<Bytes>, HttpClientError> {
+        (**self).send(request).await
+    }
+}
+
+/// A [`Layer`] which logs each request with its outcome and how long it took.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TraceLayer;
+
+impl<C> Layer<C> for TraceLayer {
+    type Service = TracingHttpClient<C>;
+
+    fn layer(&self, inner: C) -> Self::Service {
+        TracingHttpClient { inner }
+    }
+}
+
+/// Wraps any [`HttpClient`], logging each request. See [`TraceLayer`].
+pub struct TracingHttpClient<C> {
+    inner: C,
+}
+
+impl<C: HttpClient> TracingHttpClient<C> {
+    async fn trace(
+        &self,
+        request: HttpRequest<Bytes>,
+    ) -> Result<HttpResponse<Bytes>, HttpClientError> {
+        let method = request.method().clone();
+        let uri = request.uri().clone();
+        let start = Instant::now();
+
+        let result = self.inner.send(request).await;
+        let elapsed = start.elapsed();
+
+        match &result {
+            Ok(response) => {
+                debug!(%method, %uri, status = %response.status(), ?elapsed, "HTTP request completed");
+            }
+            Err(err) => warn!(%method, %uri, error = %err, ?elapsed, "HTTP request failed"),
+        }
+
+        result
+    }
+}
+
+#[async_trait]
+#[cfg(not(target_arch = "wasm32"))]
+impl<C: HttpClient> HttpClient for TracingHttpClient<C> {
+    async fn send(
+        &self,
+        request: HttpRequest<Bytes>,
+    ) -> Result<HttpResponse<Bytes>, HttpClientError> {
+        self.trace(request).await
+    }
+}
+
+#[async_trait(?Send)]
+#[cfg(target_arch = "wasm32")]
+impl<C: HttpClient> HttpClient for TracingHttpClient<C> {
+    async fn send(
+        &self,
+        request: HttpRequest<Bytes>,
+    ) -> Result<HttpResponse<Bytes>, HttpClientError> {
+        self.trace(request).await
+    }
+}
+
+/// A [`Layer`] which adds a header to every request which doesn't already
+/// have it, such as a `User-Agent`.
+#[derive(Clone, Debug)]
+pub struct SetHeaderLayer {
+    name: HeaderName,
+    value: HeaderValue,
+}
+
+impl SetHeaderLayer {
+    pub fn new(name: HeaderName, value: HeaderValue) -> Self {
+        Self { name, value }
+    }
+}
+
+impl<C> Layer<C> for SetHeaderLayer {
+    type Service = SetHeaderHttpClient<C>;
+
+    fn layer(&self, inner: C) -> Self::Service {
+        SetHeaderHttpClient {
+            inner,
+            name: self.name.clone(),
+            value: self.value.clone(),
+        }
+    }
+}
+
+/// Wraps any [`HttpClient`], adding a header to requests. See
+/// [`SetHeaderLayer`].
+pub struct SetHeaderHttpClient<C> {
+    inner: C,
+    name: HeaderName,
+    value: HeaderValue,
+}
+
+impl<C: HttpClient> SetHeaderHttpClient<C> {
+    async fn send_with_header(
+        &self,
+        mut request: HttpRequest<Bytes>,
+    ) -> Result<HttpResponse<Bytes>, HttpClientError> {
+        request
+            .headers_mut()
+            .entry(&self.name)
+            .or_insert_with(|| self.value.clone());
+
+        self.inner.send(request).await
+    }
+}
+
+#[async_trait]
+#[cfg(not(target_arch = "wasm32"))]
+impl<C: HttpClient> HttpClient for SetHeaderHttpClient<C> {
+    async fn send(
+        &self,
+        request: HttpRequest<Bytes>,
+    ) -> Result<HttpResponse<Bytes>, HttpClientError> {
+        self.send_with_header(request).await
+    }
+}
+
+#[async_trait(?Send)]
+#[cfg(target_arch = "wasm32")]
+impl<C: HttpClient> HttpClient for SetHeaderHttpClient<C> {
+    async fn send(
+        &self,
+        request: HttpRequest<Bytes>,
+    ) -> Result<HttpResponse<Bytes>, HttpClientError> {
+        self.send_with_header(request).await
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+type ServiceFuture =
+    futures_util::future::BoxFuture<'static, Result<HttpResponse<Bytes>, HttpClientError>>;
+
+#[cfg(target_arch = "wasm32")]
+type ServiceFuture =
+    futures_util::future::LocalBoxFuture<'static, Result<HttpResponse<Bytes>, HttpClientError>>;
+
+/// Adapts an [`HttpClient`] to a [`tower::Service`], so that `tower`
+/// middleware can be used with it.
+pub struct HttpClientService<C: ?Sized> {
+    client: Arc<C>,
+}
+
+impl<C> HttpClientService<C> {
+    pub fn new(client: C) -> Self {
+        Self {
+            client: Arc::new(client),
+        }
+    }
+}
+
+impl<C: ?Sized> From<Arc<C>> for HttpClientService<C> {
+    fn from(client: Arc<C>) -> Self {
+        Self { client }
+    }
+}
+
+impl<C: ?Sized> Clone for HttpClientService<C> {
+    fn clone(&self) -> Self {
+        Self {
+            client: Arc::clone(&self.client),
+        }
+    }
+}
+
+impl<C: HttpClient + ?Sized + 'static> Service<HttpRequest<Bytes>> for HttpClientService<C> {
+    type Response = HttpResponse<Bytes>;
+    type Error = HttpClientError;
+    type Future = ServiceFuture;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: HttpRequest<Bytes>) -> Self::Future {
+        let client = Arc::clone(&self.client);
+        Box::pin(async move { client.send(request).await })
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use crate::fake_http_client::{FakeHttpClientBuilder, RecordingLayer};
+    use crate::retry::{RetryConfig, RetryLayer};
+    use http::StatusCode;
+    use http::header::USER_AGENT;
+    use std::future::poll_fn;
+    use std::time::Duration;
+
+    const URL: &str = "https://example.com/feed";
+
+    fn get() -> HttpRequest<Bytes> {
+        HttpRequest::builder().uri(URL).body(Bytes::new()).unwrap()
+    }
+
+    fn user_agent_layer() -> SetHeaderLayer {
+        SetHeaderLayer::new(USER_AGENT, HeaderValue::from_static("rssfilter-test"))
+    }
+
+    #[tokio::test]
+    async fn test_layers_are_applied_outside_in() {
+        let recording = RecordingLayer::default();
+        let fake = FakeHttpClientBuilder::default()
+            .with_status_response(URL, StatusCode::SERVICE_UNAVAILABLE, "")
+            .build()
+            .unwrap();
+
+        let client = ServiceBuilder::new()
+            .layer(TraceLayer)
+            .layer(RetryLayer::new(RetryConfig {
+                initial_backoff: Duration::from_millis(1),
+                ..Default::default()
+            }))
+            .layer(user_agent_layer())
+            .layer(recording.clone())
+            .service(fake);
+
+        let response = client.send(get()).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        // Each retry passes through the layers beneath the retry layer
+        let requests = recording.requests();
+        assert_eq!(requests.len(), 3);
+        assert!(
+            requests
+                .iter()
+                .all(|request| request.headers[USER_AGENT] == "rssfilter-test")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_set_header_keeps_existing_value() {
+        let recording = RecordingLayer::default();
+        let client = ServiceBuilder::new()
+            .layer(user_agent_layer())
+            .layer(recording.clone())
+            .service(FakeHttpClientBuilder::default().build().unwrap());
+
+        let mut request = get();
+        request
+            .headers_mut()
+            .insert(USER_AGENT, HeaderValue::from_static("custom"));
+        client.send(request).await.unwrap();
+
+        assert_eq!(recording.requests()[0].headers[USER_AGENT], "custom");
+    }
+
+    #[tokio::test]
+    async fn test_boxed_clients_can_be_layered() {
+        let boxed: Box<dyn HttpClient> = Box::new(
+            FakeHttpClientBuilder::default()
+                .with_rss_response(URL, "<rss/>")
+                .build()
+                .unwrap(),
+        );
+
+        let client = TraceLayer.layer(boxed);
+
+        assert_eq!(client.send(get()).await.unwrap().into_body(), "<rss/>");
+    }
+
+    #[tokio::test]
+    async fn test_tower_service() {
+        let fake = FakeHttpClientBuilder::default()
+            .with_rss_response(URL, "<rss/>")
+            .build()
+            .unwrap();
+        let mut service = HttpClientService::new(fake);
+
+        poll_fn(|cx| service.poll_ready(cx)).await.unwrap();
+        let response = service.call(get()).await.unwrap();
+
+        assert_eq!(response.into_body(), "<rss/>");
+    }
+}
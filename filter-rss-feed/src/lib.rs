@@ -1,6 +1,24 @@
+mod bounded_cache;
+mod cache_policy;
+mod conditional;
 mod header_cf_cache_status;
 mod header_rssfilter_cache_status;
+mod header_rssfilter_items_removed;
+mod header_rssfilter_moved_permanently;
+#[cfg(not(target_arch = "wasm32"))]
+mod http_cache;
 mod http_client;
+mod layer;
+mod opml;
+mod params;
+mod rate_limit;
+mod redirect;
+mod response_cache;
+mod response_headers;
+mod retry;
+mod signing;
+mod streaming;
+mod url_policy;
 
 /// Mock HTTP client for testing RSS filtering without external dependencies.
 ///
@@ -11,20 +29,72 @@ mod http_client;
 pub mod fake_http_client;
 
 use bytes::Bytes;
-use headers::{ContentLength, ContentType, HeaderMapExt};
-use http::{HeaderMap, Method, Request as HttpRequest, Response as HttpResponse};
+use headers::{Age, CacheControl, ContentLength, ContentType, Date, HeaderMapExt};
+use http::header::{AGE, AUTHORIZATION, COOKIE, ETAG, EXPIRES, LAST_MODIFIED, WARNING};
+use http::{
+    HeaderMap, HeaderValue, Method, Request as HttpRequest, Response as HttpResponse, StatusCode,
+};
 use regex::Regex;
-use rss::{Channel, Item};
+use rss::Channel;
 use std::error::Error as StdError;
+use std::time::{Duration, SystemTime};
 use thiserror::Error;
-use tracing::{debug, info, instrument};
-
-use http_client::{HttpClient, HttpClientError};
+use tracing::{debug, info, instrument, warn};
+
+use cache_policy::{cache_ttl, now, response_cache_control};
+use conditional::{ClientConditions, filter_fingerprint, filtered_etag, not_modified};
+use http_client::BodySizeLimit;
+use redirect::send_following_redirects;
+use response_cache::{UpstreamETag, cache_key};
+use streaming::{ChannelRewrites, FilteredFeed, ItemFields, UpdateHints, filter_document};
+
+pub use header_cf_cache_status::CfCacheStatus;
+pub use header_rssfilter_cache_status::RssFilterCacheStatus;
+pub use header_rssfilter_items_removed::RssFilterItemsRemoved;
+pub use header_rssfilter_moved_permanently::RssFilterMovedPermanently;
+#[cfg(not(target_arch = "wasm32"))]
+pub use http_cache::{CacheLayer, CachingHttpClient};
+pub use http_client::{
+    CacheConfig, HttpClient, HttpClientError, HttpClientOptions, create_http_client,
+    create_http_client_with_config, create_http_client_with_options,
+};
+pub use layer::{
+    HttpClientService, Layer, Service, ServiceBuilder, SetHeaderHttpClient, SetHeaderLayer,
+    TraceLayer, TracingHttpClient,
+};
+pub use opml::{
+    FilterProfile, OpmlError, RewrittenOpml, filter_subscriptions, unfilter_subscriptions,
+    unfiltered_url,
+};
+pub use params::{
+    FEED_PARAMS, GUID_FILTER_PARAM, LINK_FILTER_PARAM, TITLE_FILTER_PARAM, URL_PARAM,
+};
+#[cfg(target_arch = "wasm32")]
+pub use rate_limit::durable_object::{DurableObjectRateLimitStore, RateLimitBucket};
+pub use rate_limit::{
+    InMemoryRateLimitStore, RateLimit, RateLimitDecision, RateLimitLayer, RateLimitStore,
+    RateLimitedHttpClient, create_rate_limit_store,
+};
+pub use redirect::{RedirectConfig, RedirectLayer, Redirected, RedirectingHttpClient};
+pub use response_cache::{
+    CachedResponse, InMemoryResponseCache, ResponseCache, create_response_cache,
+    create_response_cache_with_config,
+};
+pub use response_headers::filter_response_headers;
+pub use retry::{RetryConfig, RetryLayer, RetryingHttpClient};
+pub use signing::{SIGNATURE_PARAM, SignatureError, UrlSigner};
+pub use url_policy::{UrlPolicy, UrlPolicyError, UrlPolicyHttpClient, UrlPolicyLayer};
 
 pub type BoxError = Box<dyn StdError + Send + Sync>;
 
-/// The maximum size of the RSS feed we'll accept, to prevent excessive memory usage.
-static MAX_RSS_SIZE: u64 = 10 * 1024 * 1024; // 10MB limit
+/// The default maximum size of the RSS feed we'll accept, to prevent excessive memory usage.
+pub const DEFAULT_MAX_FEED_BYTES: u64 = 10 * 1024 * 1024; // 10MB limit
+
+/// The default maximum number of items we'll process in one feed.
+pub const DEFAULT_MAX_FEED_ITEMS: usize = 10_000;
+
+/// The default maximum depth of nested elements in a feed document.
+pub const DEFAULT_MAX_FEED_DEPTH: usize = 32;
 
 #[derive(Error, Debug)]
 pub enum RssError {
@@ -37,6 +107,12 @@ pub enum RssError {
     #[error("RSS feed is too large (max {max_size} bytes)")]
     FeedTooLarge { max_size: u64 },
 
+    #[error("RSS feed has too many items (max {max_items})")]
+    TooManyItems { max_items: usize },
+
+    #[error("RSS feed is nested too deeply (max depth {max_depth})")]
+    NestingTooDeep { max_depth: usize },
+
     #[error("Invalid content type: {content_type}. Expected XML or RSS content")]
     InvalidContentType { content_type: String },
 
@@ -50,15 +126,13 @@ pub enum RssError {
     UTF8(#[from] std::string::FromUtf8Error),
 }
 /// Validate response size to prevent memory issues
-fn validate_response_size(resp: &HttpResponse<Bytes>) -> Result<(), RssError> {
+fn validate_response_size(resp: &HttpResponse<Bytes>, max_size: u64) -> Result<(), RssError> {
     if resp
         .headers()
         .typed_get::<ContentLength>()
-        .is_some_and(|len| len.0 > MAX_RSS_SIZE)
+        .is_some_and(|len| len.0 > max_size)
     {
-        return Err(RssError::FeedTooLarge {
-            max_size: MAX_RSS_SIZE,
-        });
+        return Err(RssError::FeedTooLarge { max_size });
     }
 
     Ok(())
@@ -92,9 +166,230 @@ pub struct FilterRegexes<'a> {
     pub link_regexes: &'a [Regex],
 }
 
+/// An item of a feed, as the filters see it.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FeedItem {
+    pub title: Option<String>,
+    pub guid: Option<String>,
+    pub link: Option<String>,
+}
+
+impl From<&ItemFields<'_>> for FeedItem {
+    fn from(item: &ItemFields<'_>) -> Self {
+        Self {
+            title: item.title().map(str::to_string),
+            guid: item.guid().map(str::to_string),
+            link: item.link().map(str::to_string),
+        }
+    }
+}
+
+/// Which items of a feed filtering keeps and which it removes, each in the
+/// order they appear in the feed.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FilterReport {
+    pub kept: Vec<FeedItem>,
+    pub removed: Vec<FeedItem>,
+}
+
+/// A field of an item which filters can match.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ItemField {
+    Title,
+    Guid,
+    Link,
+}
+
+impl ItemField {
+    const ALL: [ItemField; 3] = [ItemField::Title, ItemField::Guid, ItemField::Link];
+
+    /// The field's name, as in the filters' query parameters.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ItemField::Title => "title",
+            ItemField::Guid => "guid",
+            ItemField::Link => "link",
+        }
+    }
+}
+
+/// An item of a feed and the fields of it which the filters match. Filtering
+/// removes it if any do.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PreviewItem {
+    pub item: FeedItem,
+    pub matched: Vec<ItemField>,
+}
+
+impl PreviewItem {
+    pub fn is_removed(&self) -> bool {
+        !self.matched.is_empty()
+    }
+}
+
+/// How the filtered feed is written back out.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputMode {
+    /// Parse the feed into an `rss::Channel`, remove items and write the
+    /// channel back out. Anything the `rss` crate doesn't model is lost, and
+    /// the whole channel is held in memory alongside the upstream body.
+    Reserialise,
+
+    /// Stream through the upstream document item by item with a pull parser,
+    /// removing filtered `<item>` elements and leaving everything else
+    /// byte-for-byte as it was.
+    #[default]
+    Preserve,
+}
+
+/// Limits applied to upstream feeds, so that one request can't use unbounded
+/// memory or CPU. Each breach is reported as a distinct [`RssError`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FeedLimits {
+    /// Maximum size of the decompressed response body, in bytes.
+    pub max_body_bytes: u64,
+    /// Maximum number of items in the feed, kept or not.
+    pub max_items: usize,
+    /// Maximum depth of nested elements, counting the root element as 1.
+    pub max_depth: usize,
+}
+
+impl Default for FeedLimits {
+    fn default() -> Self {
+        Self {
+            max_body_bytes: DEFAULT_MAX_FEED_BYTES,
+            max_items: DEFAULT_MAX_FEED_ITEMS,
+            max_depth: DEFAULT_MAX_FEED_DEPTH,
+        }
+    }
+}
+
+/// Configuration for an [`RssFilter`].
+#[derive(Clone, Debug, Default)]
+pub struct RssFilterConfig {
+    pub output_mode: OutputMode,
+    pub limits: FeedLimits,
+    pub cache: CacheConfig,
+    pub redirect: RedirectConfig,
+    /// Appended to the channel's title, after a space, so that the filtered
+    /// feed can be told apart from the original. For example `(filtered)`
+    pub title_annotation: Option<String>,
+}
+
 pub struct RssFilter<'a> {
     filter_regexes: &'a FilterRegexes<'a>,
     http_client: Box<dyn HttpClient>,
+    response_cache: Option<Box<dyn ResponseCache>>,
+    self_url: Option<String>,
+    config: RssFilterConfig,
+}
+
+/// Requests with credentials may get a response meant only for that user,
+/// which mustn't be served to anyone else.
+fn is_cacheable_request(headers: &HeaderMap) -> bool {
+    !headers.contains_key(AUTHORIZATION) && !headers.contains_key(COOKIE)
+}
+
+fn is_cacheable_response(response: &HttpResponse<Bytes>) -> bool {
+    response.status() == StatusCode::OK
+        && !response
+            .headers()
+            .typed_get::<CacheControl>()
+            .is_some_and(|cache_control| cache_control.no_store() || cache_control.private())
+}
+
+/// Response extension set when a stale filtered response has been served
+/// from the cache on the understanding that it will be refreshed. The caller
+/// should call [`RssFilter::revalidate`] once the response is on its way.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NeedsRevalidation;
+
+const RESPONSE_IS_STALE: HeaderValue = HeaderValue::from_static("110 - \"Response is Stale\"");
+const REVALIDATION_FAILED: HeaderValue = HeaderValue::from_static("111 - \"Revalidation Failed\"");
+
+/// How long ago a cached filtered response stopped being fresh, or zero if
+/// it is still fresh. Our own `Cache-Control` and `Date` are always set on
+/// filtered responses.
+fn staleness(response: &HttpResponse<Bytes>) -> Duration {
+    let headers = response.headers();
+
+    let age = headers
+        .typed_get::<Date>()
+        .and_then(|date| now().duration_since(SystemTime::from(date)).ok())
+        .unwrap_or_default();
+    let lifetime = headers
+        .typed_get::<CacheControl>()
+        .and_then(|cache_control| cache_control.max_age())
+        .unwrap_or_default();
+
+    age.saturating_sub(lifetime)
+}
+
+/// Sets `Age` on a response served from the response cache.
+fn with_age(mut response: HttpResponse<Bytes>) -> HttpResponse<Bytes> {
+    let headers = response.headers_mut();
+
+    if let Some(date) = headers.typed_get::<Date>() {
+        let age = now()
+            .duration_since(SystemTime::from(date))
+            .unwrap_or_default();
+        headers.typed_insert(Age::from_secs(age.as_secs()));
+    }
+
+    response
+}
+
+/// A cached filtered response made new as of now, after the upstream server
+/// said the feed hasn't changed. Validators sent with the `304` replace the
+/// stored ones (RFC 9111 §4.3.4).
+fn refreshed(
+    mut response: HttpResponse<Bytes>,
+    not_modified: &HttpResponse<Bytes>,
+) -> HttpResponse<Bytes> {
+    let headers = response.headers_mut();
+    headers.remove(AGE);
+    headers.typed_insert(Date::from(now()));
+    if let Some(last_modified) = not_modified.headers().get(LAST_MODIFIED) {
+        headers.insert(LAST_MODIFIED, last_modified.clone());
+    }
+
+    if let Some(etag) = not_modified.headers().get(ETAG) {
+        response.extensions_mut().insert(UpstreamETag(etag.clone()));
+    }
+
+    response
+}
+
+/// Where the feed was fetched from in the end, if that isn't where we asked
+/// for it.
+fn final_url(extensions: &http::Extensions) -> Option<String> {
+    extensions
+        .get::<Redirected>()
+        .map(|redirected| redirected.final_url.to_string())
+}
+
+/// Points a parsed channel's `atom:link rel="self"` at `href`.
+fn rewrite_self_link(channel: &mut Channel, href: &str) {
+    let links = channel
+        .extensions
+        .get_mut("atom")
+        .and_then(|atom| atom.get_mut("link"));
+
+    for link in links.into_iter().flatten() {
+        if link.attrs.get("rel").is_some_and(|rel| rel == "self") {
+            link.attrs.insert("href".to_string(), href.to_string());
+        }
+    }
+}
+
+fn with_cache_status(
+    mut response: HttpResponse<Bytes>,
+    status: CfCacheStatus,
+) -> HttpResponse<Bytes> {
+    response
+        .headers_mut()
+        .typed_insert(RssFilterCacheStatus(status));
+    response
 }
 
 impl<'a> RssFilter<'a> {
@@ -115,8 +410,50 @@ impl<'a> RssFilter<'a> {
         Self {
             filter_regexes,
             http_client,
+            response_cache: None,
+            self_url: None,
+            config: RssFilterConfig::default(),
         }
     }
+
+    /// Point the channel's `atom:link rel="self"` at `url`, the URL the
+    /// filtered feed is served from. Otherwise readers and WebSub hubs which
+    /// follow the self link move subscribers back to the unfiltered feed.
+    pub fn with_self_url(mut self, url: impl Into<String>) -> Self {
+        self.self_url = Some(url.into());
+        self
+    }
+
+    /// The changes to make to the channel. The self link points at our own
+    /// URL if we have one, or else at where a redirected feed ended up.
+    fn channel_rewrites<'r>(&'r self, final_url: Option<&'r str>) -> ChannelRewrites<'r> {
+        ChannelRewrites {
+            self_link: self.self_url.as_deref().or(final_url),
+            title_annotation: self.config.title_annotation.as_deref(),
+        }
+    }
+
+    fn fingerprint(&self) -> [u8; 32] {
+        filter_fingerprint(
+            self.filter_regexes,
+            self.config.output_mode,
+            &self.channel_rewrites(None),
+        )
+    }
+
+    /// Replace the default configuration.
+    pub fn with_config(mut self, config: RssFilterConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Cache filtered responses, so that repeated requests for the same feed
+    /// and filters are served without fetching or filtering it again.
+    pub fn with_response_cache(mut self, response_cache: Box<dyn ResponseCache>) -> Self {
+        self.response_cache = Some(response_cache);
+        self
+    }
+
     #[instrument(skip(self))]
     pub async fn fetch(
         &self,
@@ -125,7 +462,10 @@ impl<'a> RssFilter<'a> {
     ) -> Result<HttpResponse<Bytes>, RssError> {
         debug!("Requesting URL: {}", url);
 
-        let mut request_builder = HttpRequest::builder().method(Method::GET).uri(url);
+        let mut request_builder = HttpRequest::builder()
+            .method(Method::GET)
+            .uri(url)
+            .extension(BodySizeLimit(self.config.limits.max_body_bytes));
 
         request_builder = headers
             .iter()
@@ -135,9 +475,18 @@ impl<'a> RssFilter<'a> {
 
         let request = request_builder.body(Bytes::new())?;
 
-        let response = self.http_client.send(request).await?;
+        // Each hop goes through any URL policy
+        let response =
+            send_following_redirects(self.http_client.as_ref(), request, &self.config.redirect)
+                .await
+                .map_err(|err| match err {
+                    HttpClientError::BodyTooLarge { limit } => {
+                        RssError::FeedTooLarge { max_size: limit }
+                    }
+                    err => err.into(),
+                })?;
 
-        validate_response_size(&response)?;
+        validate_response_size(&response, self.config.limits.max_body_bytes)?;
 
         Ok(response)
     }
@@ -146,41 +495,34 @@ impl<'a> RssFilter<'a> {
         value.is_some_and(|v| regexes.iter().any(|r| r.is_match(v)))
     }
 
-    #[instrument(skip(self, channel))]
-    fn filter(&self, mut channel: Channel) -> Result<Bytes, RssError> {
-        info!("Filtering items from RSS feed");
-
-        let n_items_at_start = channel.items.len();
-
-        type ItemGetter = fn(&Item) -> Option<&str>;
+    /// Whether any of our regexes for `field` match the item's.
+    fn field_matches(&self, field: ItemField, item: &ItemFields<'_>) -> bool {
+        let (regexes, value) = match field {
+            ItemField::Title => (self.filter_regexes.title_regexes, item.title()),
+            ItemField::Guid => (self.filter_regexes.guid_regexes, item.guid()),
+            ItemField::Link => (self.filter_regexes.link_regexes, item.link()),
+        };
 
-        let filter_regexes: &[(&[Regex], ItemGetter)] = &[
-            (self.filter_regexes.title_regexes, |item: &Item| {
-                item.title()
-            }),
-            (self.filter_regexes.guid_regexes, |item: &Item| {
-                item.guid().map(|guid| guid.value())
-            }),
-            (self.filter_regexes.link_regexes, |item: &Item| item.link()),
-        ];
+        self.filter_out(regexes, value)
+    }
 
-        channel.items.retain(|item| {
-            !filter_regexes.iter().any(|(regexes, getter)| {
-                let filter = self.filter_out(regexes, getter(item));
+    /// Whether any of our regexes match the item, meaning it should be
+    /// removed from the feed.
+    fn should_remove(&self, item: &ItemFields<'_>) -> bool {
+        let filter = ItemField::ALL
+            .iter()
+            .any(|field| self.field_matches(*field, item));
 
-                if filter {
-                    debug!(item = item.link(), "Filtering out item");
-                }
+        if filter {
+            debug!(item = item.link(), "Filtering out item");
+        }
 
-                filter
-            })
-        });
+        filter
+    }
 
-        let n_items_at_end = channel.items.len();
+    fn log_filtered(channel_url: Option<&str>, n_items_at_start: usize, n_items_at_end: usize) {
         let n_items_filtered = n_items_at_start - n_items_at_end;
 
-        let channel_url = channel.link();
-
         if n_items_filtered > 0 {
             info!(
                 channel_url,
@@ -189,26 +531,115 @@ impl<'a> RssFilter<'a> {
         } else {
             info!(channel_url, "No items filtered from RSS feed");
         }
+    }
+
+    #[instrument(skip(self, channel))]
+    fn filter(
+        &self,
+        mut channel: Channel,
+        rewrites: &ChannelRewrites<'_>,
+    ) -> Result<FilteredFeed, RssError> {
+        info!("Filtering items from RSS feed");
+
+        if let Some(self_link) = rewrites.self_link {
+            rewrite_self_link(&mut channel, self_link);
+        }
+
+        if let Some(annotation) = rewrites.title_annotation {
+            // An empty title becomes just the annotation
+            let title = format!("{} {annotation}", channel.title());
+            channel.set_title(title.trim_start());
+        }
+
+        let n_items_at_start = channel.items.len();
+
+        channel
+            .items
+            .retain(|item| !self.should_remove(&ItemFields::from(item)));
+
+        let n_items_at_end = channel.items.len();
+        Self::log_filtered(Some(channel.link()), n_items_at_start, n_items_at_end);
 
         let mut buf = Vec::new();
         channel.pretty_write_to(&mut buf, b' ', 2)?;
 
-        Ok(Bytes::from(buf))
+        Ok(FilteredFeed {
+            body: Bytes::from(buf),
+            n_items: n_items_at_start,
+            n_removed: n_items_at_start - n_items_at_end,
+            update_hints: UpdateHints::default(),
+        })
+    }
+
+    #[instrument(skip(self, content))]
+    fn filter_preserving(
+        &self,
+        content: &Bytes,
+        rewrites: &ChannelRewrites<'_>,
+    ) -> Result<FilteredFeed, RssError> {
+        info!("Filtering items from RSS feed, preserving the original document");
+
+        let feed = filter_document(content, &self.config.limits, rewrites, |item| {
+            self.should_remove(item)
+        })?;
+
+        Self::log_filtered(None, feed.n_items, feed.n_items - feed.n_removed);
+
+        Ok(feed)
     }
 
     #[instrument(skip(self, response), fields(status = %response.status()))]
     pub async fn filter_response(&self, response: HttpResponse<Bytes>) -> Result<Bytes, RssError> {
+        let (parts, content) = response.into_parts();
+        let final_url = final_url(&parts.extensions);
+        let rewrites = self.channel_rewrites(final_url.as_deref());
+
+        Ok(self.filter_content(content, &rewrites)?.body)
+    }
+
+    fn filter_content(
+        &self,
+        content: Bytes,
+        rewrites: &ChannelRewrites<'_>,
+    ) -> Result<FilteredFeed, RssError> {
         debug!("Received response");
-        let content = response.into_body();
-        let channel = Channel::read_from(&content[..])?;
 
-        self.filter(channel)
+        match self.config.output_mode {
+            OutputMode::Reserialise => {
+                // Check the limits with a streaming pass, which doesn't copy
+                // anything when no items are removed, before building the
+                // whole channel in memory. This also finds the feed's update
+                // hints.
+                let checked = filter_document(
+                    &content,
+                    &self.config.limits,
+                    &ChannelRewrites::default(),
+                    |_| false,
+                )?;
+                let feed = self.filter(Channel::read_from(&content[..])?, rewrites)?;
+
+                Ok(FilteredFeed {
+                    update_hints: checked.update_hints,
+                    ..feed
+                })
+            }
+            OutputMode::Preserve => self.filter_preserving(&content, rewrites),
+        }
     }
 
+    /// Filters a successful upstream response. Headers which no longer
+    /// describe the body are dropped or recomputed, following
+    /// [`filter_response_headers`]. An upstream `304 Not Modified` is passed
+    /// on without its `ETag`, and other responses are returned as they are.
     pub async fn try_filter_response(
         &self,
-        response: HttpResponse<Bytes>,
+        mut response: HttpResponse<Bytes>,
     ) -> Result<HttpResponse<Bytes>, RssError> {
+        if response.status() == StatusCode::NOT_MODIFIED {
+            response.headers_mut().remove(ETAG);
+            return Ok(not_modified(response));
+        }
+
         if !response.status().is_success() {
             return Ok(response);
         }
@@ -218,22 +649,138 @@ impl<'a> RssFilter<'a> {
         let status_code = response.status();
         debug!(status = status_code.as_str(), "Received response",);
 
-        let response_builder = HttpResponse::builder().status(status_code.as_u16());
-        let response_builder = response
-            .headers()
-            .clone()
-            .iter()
-            .fold(response_builder, |builder, (key, value)| {
-                builder.header(key.as_str(), value)
-            });
+        let (parts, content) = response.into_parts();
+        let final_url = final_url(&parts.extensions);
+        let rewrites = self.channel_rewrites(final_url.as_deref());
+        let feed = self.filter_content(content, &rewrites)?;
+
+        let fingerprint = self.fingerprint();
+
+        // Our `Cache-Control` replaces the upstream freshness information,
+        // and the response is new as of now
+        let ttl = cache_ttl(&self.config.cache, &parts.headers, &feed.update_hints);
+
+        let mut headers = filter_response_headers(&parts.headers);
+        headers.remove(AGE);
+        headers.remove(EXPIRES);
+        headers.typed_insert(response_cache_control(&parts.headers, ttl));
+        headers.typed_insert(Date::from(now()));
+        headers.typed_insert(ContentLength(feed.body.len() as u64));
+        headers.typed_insert(filtered_etag(&fingerprint, &feed.body));
+        headers.typed_insert(RssFilterItemsRemoved(feed.n_removed));
+
+        if let Some(moved_to) = parts
+            .extensions
+            .get::<Redirected>()
+            .and_then(|redirected| redirected.moved_permanently_to.clone())
+        {
+            headers.typed_insert(RssFilterMovedPermanently(moved_to));
+        }
 
-        let filtered_body = self.filter_response(response).await?;
-        let resp_out = response_builder.body(filtered_body)?;
+        let mut resp_out = HttpResponse::new(feed.body);
+        *resp_out.status_mut() = status_code;
+        *resp_out.headers_mut() = headers;
+        if let Some(etag) = parts.headers.get(ETAG) {
+            resp_out.extensions_mut().insert(UpstreamETag(etag.clone()));
+        }
 
         Ok(resp_out)
     }
 
+    /// Reports which items of a successful upstream response filtering would
+    /// keep and which it would remove, instead of filtering it. The same
+    /// limits apply.
+    pub fn report_response(
+        &self,
+        response: &HttpResponse<Bytes>,
+    ) -> Result<FilterReport, RssError> {
+        validate_content_type(response)?;
+
+        let mut report = FilterReport::default();
+
+        // Nothing is removed from the document, so it isn't copied
+        filter_document(
+            response.body(),
+            &self.config.limits,
+            &ChannelRewrites::default(),
+            |item| {
+                let list = if self.should_remove(item) {
+                    &mut report.removed
+                } else {
+                    &mut report.kept
+                };
+                list.push(FeedItem::from(item));
+
+                false
+            },
+        )?;
+
+        Ok(report)
+    }
+
+    /// Lists the items of a successful upstream response in the order they
+    /// appear, each with the fields our regexes match, for showing what
+    /// filtering would do as the filters are written. The same limits apply.
+    pub fn preview_response(
+        &self,
+        response: &HttpResponse<Bytes>,
+    ) -> Result<Vec<PreviewItem>, RssError> {
+        validate_content_type(response)?;
+
+        let mut items = Vec::new();
+
+        filter_document(
+            response.body(),
+            &self.config.limits,
+            &ChannelRewrites::default(),
+            |item| {
+                items.push(PreviewItem {
+                    item: FeedItem::from(item),
+                    matched: ItemField::ALL
+                        .into_iter()
+                        .filter(|field| self.field_matches(*field, item))
+                        .collect(),
+                });
+
+                false
+            },
+        )?;
+
+        Ok(items)
+    }
+
+    /// Fetches and filters a feed on behalf of a client. The client's
+    /// conditional request headers are evaluated against our own `ETag`, so
+    /// that it gets a `304 Not Modified` if it already has the filtered feed.
+    ///
+    /// If there is a response cache, the outcome is reported in
+    /// [`RssFilterCacheStatus`].
     pub async fn fetch_and_filter_with_headers(
+        &self,
+        url: &str,
+        mut headers: HeaderMap,
+    ) -> Result<HttpResponse<Bytes>, RssError> {
+        let conditions = ClientConditions::take_from(&mut headers);
+
+        let response = match &self.response_cache {
+            Some(response_cache) if is_cacheable_request(&headers) => {
+                self.fetch_and_filter_cached(response_cache.as_ref(), url, headers)
+                    .await?
+            }
+            Some(_) => {
+                let mut response = self.fetch_and_filter_uncached(url, headers).await?;
+                response
+                    .headers_mut()
+                    .typed_insert(RssFilterCacheStatus(CfCacheStatus::Bypass));
+                response
+            }
+            None => self.fetch_and_filter_uncached(url, headers).await?,
+        };
+
+        Ok(conditions.evaluate(response))
+    }
+
+    async fn fetch_and_filter_uncached(
         &self,
         url: &str,
         headers: HeaderMap,
@@ -243,6 +790,169 @@ impl<'a> RssFilter<'a> {
         self.try_filter_response(response).await
     }
 
+    #[instrument(skip(self, response_cache, headers))]
+    async fn fetch_and_filter_cached(
+        &self,
+        response_cache: &dyn ResponseCache,
+        url: &str,
+        mut headers: HeaderMap,
+    ) -> Result<HttpResponse<Bytes>, RssError> {
+        let key = self.cache_key(url);
+        let cache = &self.config.cache;
+
+        let cached = match response_cache.get(&key).await {
+            Ok(cached) => cached,
+            Err(err) => {
+                warn!(key, %err, "Failed to read from response cache");
+                None
+            }
+        };
+
+        let stale = match cached.clone() {
+            Some(cached) => {
+                let response = HttpResponse::from(cached);
+                let staleness = staleness(&response);
+
+                if staleness.is_zero() {
+                    debug!(key, "Serving filtered response from cache");
+                    return Ok(with_cache_status(with_age(response), CfCacheStatus::Hit));
+                }
+
+                if staleness < Duration::from_secs(cache.stale_while_revalidate_seconds) {
+                    debug!(key, "Serving stale filtered response while it is refreshed");
+
+                    let mut response =
+                        with_cache_status(with_age(response), CfCacheStatus::Updating);
+                    response.headers_mut().append(WARNING, RESPONSE_IS_STALE);
+                    response.extensions_mut().insert(NeedsRevalidation);
+                    return Ok(response);
+                }
+
+                (staleness < Duration::from_secs(cache.stale_if_error_seconds)).then_some(response)
+            }
+            None => None,
+        };
+
+        // We need the whole feed to cache it, even if the client already has it
+        ClientConditions::unconditional(&mut headers);
+
+        let result = self
+            .fetch_filter_and_store(response_cache, &key, url, headers, cached)
+            .await;
+
+        let upstream_failed = match &result {
+            Err(RssError::HttpClient(_)) => true,
+            Ok(response) => response.status().is_server_error(),
+            Err(_) => false,
+        };
+
+        match stale {
+            Some(stale) if upstream_failed => {
+                warn!(
+                    key,
+                    "Upstream request failed, serving stale filtered response"
+                );
+
+                let mut response = with_cache_status(with_age(stale), CfCacheStatus::Stale);
+                let headers = response.headers_mut();
+                headers.append(WARNING, RESPONSE_IS_STALE);
+                headers.append(WARNING, REVALIDATION_FAILED);
+                Ok(response)
+            }
+            Some(_) => Ok(with_cache_status(result?, CfCacheStatus::Expired)),
+            None => Ok(with_cache_status(result?, CfCacheStatus::Miss)),
+        }
+    }
+
+    fn cache_key(&self, url: &str) -> String {
+        let fingerprint = self.fingerprint();
+        cache_key(&self.config.cache.cache_key_prefix, url, &fingerprint)
+    }
+
+    /// Fetches and filters a feed, storing the result in the response cache
+    /// if it may be cached. Stale entries are kept for as long as they might
+    /// still be served.
+    ///
+    /// If there is an existing entry, the upstream request is made
+    /// conditional on the feed having changed, and a `304 Not Modified`
+    /// refreshes the entry instead.
+    async fn fetch_filter_and_store(
+        &self,
+        response_cache: &dyn ResponseCache,
+        key: &str,
+        url: &str,
+        mut headers: HeaderMap,
+        cached: Option<CachedResponse>,
+    ) -> Result<HttpResponse<Bytes>, RssError> {
+        if let Some(cached) = &cached {
+            ClientConditions::since(&mut headers, cached);
+        }
+
+        let response = self.fetch(url, headers).await?;
+        let response = match cached {
+            Some(cached) if response.status() == StatusCode::NOT_MODIFIED => {
+                debug!(
+                    key,
+                    "Feed not modified upstream, refreshing filtered response"
+                );
+                refreshed(HttpResponse::from(cached), &response)
+            }
+            _ => self.try_filter_response(response).await?,
+        };
+
+        let ttl = response
+            .headers()
+            .typed_get::<CacheControl>()
+            .and_then(|cache_control| cache_control.max_age());
+
+        if let Some(ttl) = ttl.filter(|_| is_cacheable_response(&response)) {
+            let stale_for = Duration::from_secs(
+                self.config
+                    .cache
+                    .stale_while_revalidate_seconds
+                    .max(self.config.cache.stale_if_error_seconds),
+            );
+
+            if let Err(err) = response_cache
+                .put(key, CachedResponse::from(&response), ttl + stale_for)
+                .await
+            {
+                warn!(key, %err, "Failed to write to response cache");
+            }
+        }
+
+        Ok(response)
+    }
+
+    /// Refreshes the cached filtered response for a feed. Callers do this
+    /// after sending a response marked with [`NeedsRevalidation`], ideally
+    /// once the response has been sent.
+    #[instrument(skip(self, headers))]
+    pub async fn revalidate(&self, url: &str, mut headers: HeaderMap) -> Result<(), RssError> {
+        let Some(response_cache) = &self.response_cache else {
+            return Ok(());
+        };
+
+        ClientConditions::take_from(&mut headers);
+        if !is_cacheable_request(&headers) {
+            return Ok(());
+        }
+        ClientConditions::unconditional(&mut headers);
+
+        let key = self.cache_key(url);
+        let cached = response_cache.get(&key).await.unwrap_or_else(|err| {
+            warn!(key, %err, "Failed to read from response cache");
+            None
+        });
+        let response = self
+            .fetch_filter_and_store(response_cache.as_ref(), &key, url, headers, cached)
+            .await?;
+
+        debug!(key, status = %response.status(), "Revalidated filtered response");
+
+        Ok(())
+    }
+
     pub async fn fetch_and_filter(&self, url: &str) -> Result<HttpResponse<Bytes>, RssError> {
         self.fetch_and_filter_with_headers(url, HeaderMap::new())
             .await
@@ -253,7 +963,7 @@ impl<'a> RssFilter<'a> {
 mod tests {
     use std::env;
     use std::io::Cursor;
-    use std::sync::LazyLock;
+    use std::sync::{Arc, LazyLock};
 
     use super::*;
 
@@ -343,6 +1053,753 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_preserve_output_mode() -> Result<(), BoxError> {
+        init_tracing();
+
+        let feed = r#"<rss version="2.0" xmlns:itunes="http://www.itunes.com/dtds/podcast-1.0.dtd">
+  <channel>
+    <title>Podcast</title>
+    <itunes:explicit>false</itunes:explicit>
+    <item><title>Keep</title><itunes:episode>1</itunes:episode></item>
+    <item><title>Drop</title><itunes:episode>2</itunes:episode></item>
+  </channel>
+</rss>"#;
+
+        let http_client = fake_http_client::FakeHttpClientBuilder::default()
+            .with_rss_response("https://example.com/feed", feed)
+            .build()?;
+
+        let filter_regexes = FilterRegexes {
+            title_regexes: &[Regex::new("^Drop$")?],
+            guid_regexes: &[],
+            link_regexes: &[],
+        };
+
+        let rss_filter = RssFilter::new_with_http_client(&filter_regexes, Box::new(http_client))
+            .with_config(RssFilterConfig {
+                output_mode: OutputMode::Preserve,
+                ..Default::default()
+            });
+
+        let body = rss_filter
+            .fetch_and_filter("https://example.com/feed")
+            .await?
+            .into_body();
+        let body = std::str::from_utf8(&body)?;
+
+        assert!(body.contains("<itunes:explicit>false</itunes:explicit>"));
+        assert!(
+            body.contains("<item><title>Keep</title><itunes:episode>1</itunes:episode></item>")
+        );
+        assert!(!body.contains("Drop"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_report_response() -> Result<(), BoxError> {
+        let server = serve_test_rss_feed(&["1", "2", "3"]).await?;
+        let filter_regexes = FilterRegexes {
+            title_regexes: &[Regex::new("^Test Item 2$")?],
+            guid_regexes: &[],
+            link_regexes: &[],
+        };
+        let rss_filter = RssFilter::new(&filter_regexes)?;
+
+        let response = rss_filter.fetch(&server.url(), HeaderMap::new()).await?;
+        let report = rss_filter.report_response(&response)?;
+
+        let titles = |items: &[FeedItem]| {
+            items
+                .iter()
+                .map(|item| item.title.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            titles(&report.kept),
+            [
+                Some("Test Item 1".to_string()),
+                Some("Test Item 3".to_string())
+            ]
+        );
+        assert_eq!(titles(&report.removed), [Some("Test Item 2".to_string())]);
+        assert!(report.removed[0].guid.is_some());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_preview_response() -> Result<(), BoxError> {
+        let server = serve_test_rss_feed(&["1", "2", "3"]).await?;
+        let filter_regexes = FilterRegexes {
+            title_regexes: &[Regex::new("^Test Item 2$")?],
+            guid_regexes: &[],
+            link_regexes: &[Regex::new("test[23]$")?],
+        };
+        let rss_filter = RssFilter::new(&filter_regexes)?;
+
+        let response = rss_filter.fetch(&server.url(), HeaderMap::new()).await?;
+        let items = rss_filter.preview_response(&response)?;
+
+        let previewed = items
+            .iter()
+            .map(|item| (item.item.title.clone().unwrap(), item.matched.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            previewed,
+            [
+                ("Test Item 1".to_string(), vec![]),
+                (
+                    "Test Item 2".to_string(),
+                    vec![ItemField::Title, ItemField::Link]
+                ),
+                ("Test Item 3".to_string(), vec![ItemField::Link]),
+            ]
+        );
+        assert!(!items[0].is_removed());
+        assert!(items[2].is_removed());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_response_headers() -> Result<(), BoxError> {
+        init_tracing();
+
+        let feed = r#"<rss version="2.0"><channel><title>Feed</title><item><title>Keep</title></item><item><title>Drop</title></item></channel></rss>"#;
+
+        let http_client = fake_http_client::FakeHttpClientBuilder::default()
+            .with_response(
+                "https://example.com/feed",
+                fake_http_client::FakeResponseBuilder::rss(feed)
+                    .with_header("content-length", feed.len().to_string())
+                    .with_header("content-encoding", "gzip")
+                    .with_header("transfer-encoding", "chunked")
+                    .with_header("cache-control", "max-age=60")
+                    .build()?,
+            )
+            .build()?;
+
+        let filter_regexes = FilterRegexes {
+            title_regexes: &[Regex::new("^Drop$")?],
+            guid_regexes: &[],
+            link_regexes: &[],
+        };
+
+        let rss_filter = RssFilter::new_with_http_client(&filter_regexes, Box::new(http_client));
+
+        let response = rss_filter
+            .fetch_and_filter("https://example.com/feed")
+            .await?;
+        let headers = response.headers();
+
+        assert_eq!(
+            headers.typed_get::<ContentLength>(),
+            Some(ContentLength(response.body().len() as u64))
+        );
+        assert_eq!(
+            headers.typed_get::<RssFilterItemsRemoved>(),
+            Some(RssFilterItemsRemoved(1))
+        );
+        assert_eq!(headers["cache-control"], "public, max-age=60");
+        assert!(headers.contains_key("date"));
+        assert!(!headers.contains_key("content-encoding"));
+        assert!(!headers.contains_key("transfer-encoding"));
+
+        Ok(())
+    }
+
+    #[test_case(OutputMode::Preserve ; "preserve")]
+    #[test_case(OutputMode::Reserialise ; "reserialise")]
+    #[tokio::test]
+    async fn test_self_url_and_title_annotation(output_mode: OutputMode) -> Result<(), BoxError> {
+        init_tracing();
+
+        let feed = r#"<rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom"><channel><title>Feed</title><link>https://example.com/</link><description>Feed</description><atom:link href="https://example.com/feed" rel="self"/></channel></rss>"#;
+
+        let http_client = fake_http_client::FakeHttpClientBuilder::default()
+            .with_response(
+                "https://example.com/feed",
+                fake_http_client::FakeResponse::new(StatusCode::FOUND, "")
+                    .with_header("location", "/new"),
+            )
+            .with_rss_response("https://example.com/new", feed)
+            .build()?;
+
+        let filter_regexes = FilterRegexes {
+            title_regexes: &[],
+            guid_regexes: &[],
+            link_regexes: &[],
+        };
+
+        let rss_filter = RssFilter::new_with_http_client(&filter_regexes, Box::new(http_client))
+            .with_config(RssFilterConfig {
+                output_mode,
+                title_annotation: Some("(filtered)".to_string()),
+                ..Default::default()
+            })
+            .with_self_url("https://rssfilter.example.com/?url=https%3A%2F%2Fexample.com%2Ffeed");
+
+        let response = rss_filter
+            .fetch_and_filter("https://example.com/feed")
+            .await?;
+        let channel = Channel::read_from(&response.body()[..])?;
+
+        assert_eq!(channel.title(), "Feed (filtered)");
+        // Our own URL wins over the one the feed was redirected to
+        assert_eq!(
+            channel.extensions()["atom"]["link"][0].attrs()["href"],
+            "https://rssfilter.example.com/?url=https%3A%2F%2Fexample.com%2Ffeed"
+        );
+
+        Ok(())
+    }
+
+    #[test_case(OutputMode::Preserve ; "preserve")]
+    #[test_case(OutputMode::Reserialise ; "reserialise")]
+    #[tokio::test]
+    async fn test_title_annotation_of_untitled_feed(
+        output_mode: OutputMode,
+    ) -> Result<(), BoxError> {
+        init_tracing();
+
+        let feed = r#"<rss version="2.0"><channel><title></title><link>https://example.com/</link><description>Feed</description></channel></rss>"#;
+
+        let http_client = fake_http_client::FakeHttpClientBuilder::default()
+            .with_rss_response("https://example.com/feed", feed)
+            .build()?;
+
+        let filter_regexes = FilterRegexes {
+            title_regexes: &[],
+            guid_regexes: &[],
+            link_regexes: &[],
+        };
+
+        let rss_filter = RssFilter::new_with_http_client(&filter_regexes, Box::new(http_client))
+            .with_config(RssFilterConfig {
+                output_mode,
+                title_annotation: Some("(filtered)".to_string()),
+                ..Default::default()
+            });
+
+        let response = rss_filter
+            .fetch_and_filter("https://example.com/feed")
+            .await?;
+        let channel = Channel::read_from(&response.body()[..])?;
+
+        assert_eq!(channel.title(), "(filtered)");
+
+        Ok(())
+    }
+
+    #[test_case(StatusCode::MOVED_PERMANENTLY, Some("https://example.com/new") ; "moved permanently")]
+    #[test_case(StatusCode::FOUND, None ; "found")]
+    #[tokio::test]
+    async fn test_redirected_feed(
+        status: StatusCode,
+        moved_to: Option<&str>,
+    ) -> Result<(), BoxError> {
+        init_tracing();
+
+        let feed = r#"<rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom"><channel><title>Feed</title><atom:link href="https://example.com/feed" rel="self"/></channel></rss>"#;
+
+        let http_client = fake_http_client::FakeHttpClientBuilder::default()
+            .with_response(
+                "https://example.com/feed",
+                fake_http_client::FakeResponse::new(status, "").with_header("location", "/new"),
+            )
+            .with_rss_response("https://example.com/new", feed)
+            .build()?;
+
+        let filter_regexes = FilterRegexes {
+            title_regexes: &[],
+            guid_regexes: &[],
+            link_regexes: &[],
+        };
+
+        let rss_filter = RssFilter::new_with_http_client(&filter_regexes, Box::new(http_client));
+
+        let response = rss_filter
+            .fetch_and_filter("https://example.com/feed")
+            .await?;
+
+        assert_eq!(
+            response
+                .headers()
+                .typed_get::<RssFilterMovedPermanently>()
+                .map(|moved| moved.to_string()),
+            moved_to.map(str::to_string)
+        );
+        assert!(
+            String::from_utf8_lossy(response.body())
+                .contains(r#"<atom:link href="https://example.com/new" rel="self"/>"#)
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_response_cache() -> Result<(), BoxError> {
+        init_tracing();
+
+        let feed = r#"<rss version="2.0"><channel><title>Feed</title><item><title>Keep</title></item><item><title>Drop</title></item></channel></rss>"#;
+
+        let http_client = fake_http_client::FakeHttpClientBuilder::default()
+            .with_rss_response("https://example.com/feed", feed)
+            .with_response(
+                "https://example.com/private",
+                fake_http_client::FakeResponseBuilder::rss(feed)
+                    .with_header("cache-control", "private")
+                    .build()?,
+            )
+            .build()?;
+
+        let filter_regexes = FilterRegexes {
+            title_regexes: &[Regex::new("^Drop$")?],
+            guid_regexes: &[],
+            link_regexes: &[],
+        };
+
+        let rss_filter = RssFilter::new_with_http_client(&filter_regexes, Box::new(http_client))
+            .with_response_cache(Box::new(InMemoryResponseCache::new()));
+
+        let cache_status = |response: &HttpResponse<Bytes>| {
+            response
+                .headers()
+                .typed_get::<RssFilterCacheStatus>()
+                .map(|status| status.0)
+        };
+
+        let first = rss_filter
+            .fetch_and_filter("https://example.com/feed")
+            .await?;
+        assert_eq!(cache_status(&first), Some(CfCacheStatus::Miss));
+
+        let second = rss_filter
+            .fetch_and_filter("https://EXAMPLE.com/feed#fragment")
+            .await?;
+        assert_eq!(cache_status(&second), Some(CfCacheStatus::Hit));
+        assert!(second.headers().contains_key("age"));
+        assert_eq!(second.body(), first.body());
+        assert_eq!(second.headers()[ETAG], first.headers()[ETAG]);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(http::header::IF_NONE_MATCH, first.headers()[ETAG].clone());
+        let revalidated = rss_filter
+            .fetch_and_filter_with_headers("https://example.com/feed", headers)
+            .await?;
+        assert_eq!(revalidated.status(), StatusCode::NOT_MODIFIED);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            http::header::AUTHORIZATION,
+            http::HeaderValue::from_static("Bearer secret"),
+        );
+        let authorised = rss_filter
+            .fetch_and_filter_with_headers("https://example.com/feed", headers)
+            .await?;
+        assert_eq!(cache_status(&authorised), Some(CfCacheStatus::Bypass));
+
+        for _ in 0..2 {
+            let private = rss_filter
+                .fetch_and_filter("https://example.com/private")
+                .await?;
+            assert_eq!(cache_status(&private), Some(CfCacheStatus::Miss));
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_stale_responses() -> Result<(), BoxError> {
+        init_tracing();
+
+        let feed = r#"<rss version="2.0"><channel><title>Feed</title><item><title>Fresh</title></item></channel></rss>"#;
+
+        let unavailable = || {
+            fake_http_client::FakeResponseBuilder::default()
+                .with_status(StatusCode::SERVICE_UNAVAILABLE)
+                .with_body(Bytes::new())
+                .build()
+        };
+        let http_client = fake_http_client::FakeHttpClientBuilder::default()
+            .with_rss_response("https://example.com/feed", feed)
+            .with_response("https://example.com/down", unavailable()?)
+            .with_response("https://example.com/long-down", unavailable()?)
+            .build()?;
+
+        let filter_regexes = FilterRegexes {
+            title_regexes: &[],
+            guid_regexes: &[],
+            link_regexes: &[],
+        };
+
+        // Entries which went stale `stale_for` ago
+        let response_cache = InMemoryResponseCache::new();
+        let fingerprint = filter_fingerprint(
+            &filter_regexes,
+            OutputMode::Preserve,
+            &ChannelRewrites::default(),
+        );
+        let config = RssFilterConfig {
+            cache: CacheConfig {
+                stale_while_revalidate_seconds: 60,
+                stale_if_error_seconds: 600,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        for (path, stale_for) in [
+            ("updating", 30),
+            ("down", 300),
+            ("feed", 300),
+            ("long-down", 3000),
+        ] {
+            let mut headers = HeaderMap::new();
+            headers.typed_insert(CacheControl::new().with_max_age(Duration::from_secs(60)));
+            headers.typed_insert(Date::from(now() - Duration::from_secs(60 + stale_for)));
+
+            response_cache
+                .put(
+                    &cache_key(
+                        &config.cache.cache_key_prefix,
+                        &format!("https://example.com/{path}"),
+                        &fingerprint,
+                    ),
+                    CachedResponse {
+                        status: StatusCode::OK,
+                        headers,
+                        body: Bytes::from_static(b"<rss>stale</rss>"),
+                        upstream_etag: None,
+                    },
+                    Duration::from_secs(3600),
+                )
+                .await?;
+        }
+
+        let rss_filter = RssFilter::new_with_http_client(&filter_regexes, Box::new(http_client))
+            .with_config(config)
+            .with_response_cache(Box::new(response_cache));
+
+        let cache_status = |response: &HttpResponse<Bytes>| {
+            response
+                .headers()
+                .typed_get::<RssFilterCacheStatus>()
+                .map(|status| status.0)
+        };
+        let warnings = |response: &HttpResponse<Bytes>| {
+            response
+                .headers()
+                .get_all(WARNING)
+                .iter()
+                .map(|value| value.to_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        // Within stale-while-revalidate: served stale, to be refreshed later
+        let updating = rss_filter
+            .fetch_and_filter("https://example.com/updating")
+            .await?;
+        assert_eq!(cache_status(&updating), Some(CfCacheStatus::Updating));
+        assert_eq!(updating.body(), "<rss>stale</rss>");
+        assert_eq!(warnings(&updating), [r#"110 - "Response is Stale""#]);
+        assert_eq!(
+            updating.extensions().get::<NeedsRevalidation>(),
+            Some(&NeedsRevalidation)
+        );
+
+        // Within stale-if-error, and the upstream server is down
+        let down = rss_filter
+            .fetch_and_filter("https://example.com/down")
+            .await?;
+        assert_eq!(down.status(), StatusCode::OK);
+        assert_eq!(cache_status(&down), Some(CfCacheStatus::Stale));
+        assert_eq!(down.body(), "<rss>stale</rss>");
+        assert_eq!(
+            warnings(&down),
+            [
+                r#"110 - "Response is Stale""#,
+                r#"111 - "Revalidation Failed""#
+            ]
+        );
+
+        // Past stale-if-error, the upstream error is passed on
+        let long_down = rss_filter
+            .fetch_and_filter("https://example.com/long-down")
+            .await?;
+        assert_eq!(long_down.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(cache_status(&long_down), Some(CfCacheStatus::Miss));
+
+        // Within stale-if-error, and the upstream server is fine
+        let expired = rss_filter
+            .fetch_and_filter("https://example.com/feed")
+            .await?;
+        assert_eq!(cache_status(&expired), Some(CfCacheStatus::Expired));
+        assert!(expired.extensions().get::<NeedsRevalidation>().is_none());
+        assert_ne!(expired.body(), "<rss>stale</rss>");
+
+        let hit = rss_filter
+            .fetch_and_filter("https://example.com/feed")
+            .await?;
+        assert_eq!(cache_status(&hit), Some(CfCacheStatus::Hit));
+        assert!(warnings(&hit).is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_revalidate() -> Result<(), BoxError> {
+        init_tracing();
+
+        let feed = r#"<rss version="2.0"><channel><title>Feed</title><item><title>Item</title></item></channel></rss>"#;
+
+        let http_client = fake_http_client::FakeHttpClientBuilder::default()
+            .with_rss_response("https://example.com/feed", feed)
+            .build()?;
+
+        let filter_regexes = FilterRegexes {
+            title_regexes: &[],
+            guid_regexes: &[],
+            link_regexes: &[],
+        };
+
+        let rss_filter = RssFilter::new_with_http_client(&filter_regexes, Box::new(http_client))
+            .with_response_cache(Box::new(InMemoryResponseCache::new()));
+
+        rss_filter
+            .revalidate("https://example.com/feed", HeaderMap::new())
+            .await?;
+
+        let response = rss_filter
+            .fetch_and_filter("https://example.com/feed")
+            .await?;
+        assert_eq!(
+            response.headers().typed_get::<RssFilterCacheStatus>(),
+            Some(RssFilterCacheStatus(CfCacheStatus::Hit))
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_refresh_with_conditional_requests() -> Result<(), BoxError> {
+        init_tracing();
+
+        let feed = r#"<rss version="2.0"><channel><title>Feed</title><item><title>Item</title></item></channel></rss>"#;
+        let last_modified = "Sat, 17 Oct 2026 10:00:00 GMT";
+
+        let mut server = mockito::Server::new_async().await;
+        let changed = server
+            .mock("GET", "/feed")
+            .match_header("if-none-match", mockito::Matcher::Missing)
+            .with_header("content-type", "application/rss+xml")
+            .with_header("etag", "\"upstream\"")
+            .with_header("last-modified", last_modified)
+            .with_body(feed)
+            .expect(1)
+            .create_async()
+            .await;
+        let unchanged = server
+            .mock("GET", "/feed")
+            .match_header("if-none-match", "\"upstream\"")
+            .match_header("if-modified-since", last_modified)
+            .with_status(304)
+            .with_header("etag", "\"upstream-2\"")
+            .expect(1)
+            .create_async()
+            .await;
+
+        let filter_regexes = FilterRegexes {
+            title_regexes: &[],
+            guid_regexes: &[],
+            link_regexes: &[],
+        };
+
+        let response_cache = Arc::new(InMemoryResponseCache::new());
+        let rss_filter = RssFilter::new(&filter_regexes)?
+            .with_config(RssFilterConfig {
+                cache: CacheConfig {
+                    stale_while_revalidate_seconds: 0,
+                    stale_if_error_seconds: 600,
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .with_response_cache(Box::new(Arc::clone(&response_cache)));
+
+        let url = format!("{}/feed", server.url());
+        let key = rss_filter.cache_key(&url);
+
+        let first = rss_filter.fetch_and_filter(&url).await?;
+        assert_eq!(
+            first.headers().typed_get::<RssFilterCacheStatus>(),
+            Some(RssFilterCacheStatus(CfCacheStatus::Miss))
+        );
+        assert_ne!(first.headers()[ETAG], "\"upstream\"");
+
+        // The upstream `ETag` is kept for refreshing, and the entry expires
+        let mut cached = response_cache
+            .get(&key)
+            .await?
+            .expect("entry should be cached");
+        assert_eq!(
+            cached.upstream_etag,
+            Some(HeaderValue::from_static("\"upstream\""))
+        );
+        cached
+            .headers
+            .typed_insert(CacheControl::new().with_max_age(Duration::from_secs(60)));
+        cached
+            .headers
+            .typed_insert(Date::from(now() - Duration::from_secs(360)));
+        response_cache
+            .put(&key, cached, Duration::from_secs(3600))
+            .await?;
+
+        let refreshed = rss_filter.fetch_and_filter(&url).await?;
+        assert_eq!(refreshed.status(), StatusCode::OK);
+        assert_eq!(
+            refreshed.headers().typed_get::<RssFilterCacheStatus>(),
+            Some(RssFilterCacheStatus(CfCacheStatus::Expired))
+        );
+        assert_eq!(refreshed.body(), first.body());
+        assert_eq!(refreshed.headers()[ETAG], first.headers()[ETAG]);
+
+        let hit = rss_filter.fetch_and_filter(&url).await?;
+        assert_eq!(
+            hit.headers().typed_get::<RssFilterCacheStatus>(),
+            Some(RssFilterCacheStatus(CfCacheStatus::Hit))
+        );
+        assert_eq!(
+            response_cache
+                .get(&key)
+                .await?
+                .and_then(|cached| cached.upstream_etag),
+            Some(HeaderValue::from_static("\"upstream-2\""))
+        );
+
+        changed.assert_async().await;
+        unchanged.assert_async().await;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_conditional_requests() -> Result<(), BoxError> {
+        init_tracing();
+
+        let feed = r#"<rss version="2.0"><channel><title>Feed</title><item><title>Item</title></item></channel></rss>"#;
+        let last_modified = "Sat, 17 Oct 2026 10:00:00 GMT";
+
+        let http_client = fake_http_client::FakeHttpClientBuilder::default()
+            .with_response(
+                "https://example.com/feed",
+                fake_http_client::FakeResponseBuilder::rss(feed)
+                    .with_header("etag", "\"upstream\"")
+                    .with_header("last-modified", last_modified)
+                    .build()?,
+            )
+            .with_response(
+                "https://example.com/unchanged",
+                fake_http_client::FakeResponseBuilder::default()
+                    .with_status(StatusCode::NOT_MODIFIED)
+                    .with_header("etag", "\"upstream\"")
+                    .with_header("last-modified", last_modified)
+                    .with_body(Bytes::new())
+                    .build()?,
+            )
+            .build()?;
+
+        let filter_regexes = FilterRegexes {
+            title_regexes: &[],
+            guid_regexes: &[],
+            link_regexes: &[],
+        };
+
+        let rss_filter = RssFilter::new_with_http_client(&filter_regexes, Box::new(http_client));
+
+        let response = rss_filter
+            .fetch_and_filter("https://example.com/feed")
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["last-modified"], last_modified);
+
+        let etag = response.headers()[ETAG].clone();
+        assert_ne!(etag, "\"upstream\"");
+
+        let mut headers = HeaderMap::new();
+        headers.insert(http::header::IF_NONE_MATCH, etag.clone());
+        let response = rss_filter
+            .fetch_and_filter_with_headers("https://example.com/feed", headers)
+            .await?;
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers()[ETAG], etag);
+        assert!(response.body().is_empty());
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            http::header::IF_NONE_MATCH,
+            http::HeaderValue::from_static("\"upstream\""),
+        );
+        let response = rss_filter
+            .fetch_and_filter_with_headers("https://example.com/feed", headers)
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = rss_filter
+            .fetch_and_filter("https://example.com/unchanged")
+            .await?;
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert!(!response.headers().contains_key(ETAG));
+        assert_eq!(response.headers()["last-modified"], last_modified);
+
+        Ok(())
+    }
+
+    #[test_case(FeedLimits { max_body_bytes: 64, ..Default::default() }, |err| matches!(err, RssError::FeedTooLarge { max_size: 64 }) ; "body size")]
+    #[test_case(FeedLimits { max_items: 2, ..Default::default() }, |err| matches!(err, RssError::TooManyItems { max_items: 2 }) ; "item count")]
+    #[test_case(FeedLimits { max_depth: 3, ..Default::default() }, |err| matches!(err, RssError::NestingTooDeep { max_depth: 3 }) ; "nesting depth")]
+    #[tokio::test]
+    async fn test_feed_limits(limits: FeedLimits, expected: fn(&RssError) -> bool) {
+        init_tracing();
+
+        let items = "<item><title>Item</title></item>".repeat(3);
+        let feed =
+            format!("<rss version=\"2.0\"><channel><title>Feed</title>{items}</channel></rss>");
+
+        let filter_regexes = FilterRegexes {
+            title_regexes: &[],
+            guid_regexes: &[],
+            link_regexes: &[],
+        };
+
+        for output_mode in [OutputMode::Preserve, OutputMode::Reserialise] {
+            let http_client = fake_http_client::FakeHttpClientBuilder::default()
+                .with_rss_response("https://example.com/feed", feed.clone())
+                .build()
+                .expect("Failed to build fake client");
+
+            let rss_filter =
+                RssFilter::new_with_http_client(&filter_regexes, Box::new(http_client))
+                    .with_config(RssFilterConfig {
+                        output_mode,
+                        limits,
+                        ..Default::default()
+                    });
+
+            let err = rss_filter
+                .fetch_and_filter("https://example.com/feed")
+                .await
+                .expect_err("Expected the limit to be breached");
+
+            assert!(
+                expected(&err),
+                "unexpected error for {output_mode:?}: {err:?}"
+            );
+        }
+    }
+
     #[tokio::test]
     async fn test_server_error() -> Result<(), BoxError> {
         init_tracing();
@@ -435,13 +1892,13 @@ mod tests {
         let headers = response_builder
             .headers_mut()
             .expect("Failed to get headers");
-        headers.typed_insert(ContentLength(MAX_RSS_SIZE * 2));
+        headers.typed_insert(ContentLength(DEFAULT_MAX_FEED_BYTES * 2));
 
         let response = response_builder
             .body(Bytes::new())
             .expect("Failed to build response");
 
-        let result = validate_response_size(&response);
+        let result = validate_response_size(&response, DEFAULT_MAX_FEED_BYTES);
         assert!(result.is_err());
         assert!(matches!(result.unwrap_err(), RssError::FeedTooLarge { .. }));
     }
@@ -460,7 +1917,7 @@ mod tests {
             .body(Bytes::new())
             .expect("Failed to build response");
 
-        let result = validate_response_size(&response);
+        let result = validate_response_size(&response, DEFAULT_MAX_FEED_BYTES);
         assert!(result.is_ok());
     }
 }
@@ -0,0 +1,388 @@
+use bytes::Bytes;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::{Reader, Writer, XmlVersion};
+use thiserror::Error;
+use url::Url;
+
+use crate::params::{GUID_FILTER_PARAM, LINK_FILTER_PARAM, TITLE_FILTER_PARAM, URL_PARAM};
+use crate::signing::UrlSigner;
+
+#[derive(Error, Debug)]
+pub enum OpmlError {
+    #[error("The subscription list could not be parsed: {0}")]
+    Parse(#[from] quick_xml::Error),
+
+    #[error("The subscription list is not an OPML document")]
+    NotOpml,
+}
+
+impl From<quick_xml::events::attributes::AttrError> for OpmlError {
+    fn from(err: quick_xml::events::attributes::AttrError) -> Self {
+        OpmlError::Parse(err.into())
+    }
+}
+
+/// The filters to apply to every feed in a subscription list, each given as
+/// the regex of a filter URL's query parameter.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FilterProfile {
+    pub title_filter_regexes: Vec<String>,
+    pub guid_filter_regexes: Vec<String>,
+    pub link_filter_regexes: Vec<String>,
+}
+
+impl FilterProfile {
+    pub fn is_empty(&self) -> bool {
+        self.query_pairs().next().is_none()
+    }
+
+    fn query_pairs(&self) -> impl Iterator<Item = (&'static str, &str)> {
+        [
+            (TITLE_FILTER_PARAM, &self.title_filter_regexes),
+            (GUID_FILTER_PARAM, &self.guid_filter_regexes),
+            (LINK_FILTER_PARAM, &self.link_filter_regexes),
+        ]
+        .into_iter()
+        .flat_map(|(name, regexes)| regexes.iter().map(move |regex| (name, regex.as_str())))
+    }
+
+    /// The URL at which the service at `service` serves `feed_url` with
+    /// these filters.
+    pub fn filtered_url(&self, service: &Url, feed_url: &str) -> Url {
+        let mut url = service.clone();
+        url.set_query(None);
+        url.query_pairs_mut()
+            .append_pair(URL_PARAM, feed_url)
+            .extend_pairs(self.query_pairs());
+
+        url
+    }
+}
+
+/// The feed which `url` filters, if it's a filtered feed from the service at
+/// `service`. The service is the same over `http` and `https`, since
+/// subscription lists often have the one and the service redirects to the
+/// other.
+pub fn unfiltered_url(service: &Url, url: &str) -> Option<String> {
+    let url = Url::parse(url).ok()?;
+
+    let same_service = matches!(url.scheme(), "http" | "https")
+        && url.host() == service.host()
+        && url.port() == service.port()
+        && url.path() == service.path();
+    if !same_service {
+        return None;
+    }
+
+    url.query_pairs()
+        .find_map(|(name, value)| (name == URL_PARAM).then(|| value.into_owned()))
+}
+
+/// A subscription list with its feeds' URLs rewritten.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RewrittenOpml {
+    pub opml: Bytes,
+    /// How many feeds' URLs were changed.
+    pub n_rewritten: usize,
+}
+
+/// Points every feed of an OPML subscription list at the service at
+/// `service`, filtered with `profile`. Feeds which are already filtered by
+/// the service get `profile`'s filters instead of their own. Services which
+/// only filter signed URLs need a `signer` with their key.
+pub fn filter_subscriptions(
+    opml: &[u8],
+    service: &Url,
+    profile: &FilterProfile,
+    signer: Option<&UrlSigner>,
+) -> Result<RewrittenOpml, OpmlError> {
+    rewrite_feed_urls(opml, |xml_url| {
+        let feed_url = unfiltered_url(service, xml_url);
+        let filtered = profile.filtered_url(service, feed_url.as_deref().unwrap_or(xml_url));
+
+        match signer {
+            Some(signer) => Some(signer.sign(&filtered).into()),
+            None => Some(filtered.into()),
+        }
+    })
+}
+
+/// Undoes [`filter_subscriptions`]: every feed of an OPML subscription list
+/// which is filtered by the service at `service` is pointed back at the feed
+/// itself.
+pub fn unfilter_subscriptions(opml: &[u8], service: &Url) -> Result<RewrittenOpml, OpmlError> {
+    rewrite_feed_urls(opml, |xml_url| unfiltered_url(service, xml_url))
+}
+
+/// Writes an `<outline>` back out with its `xmlUrl` replaced.
+fn rewrite_outline(
+    element: &BytesStart<'_>,
+    xml_url: &str,
+    empty: bool,
+) -> Result<Vec<u8>, OpmlError> {
+    let mut rewritten = BytesStart::new("outline");
+
+    for attribute in element.attributes() {
+        let attribute = attribute?;
+
+        if attribute.key.as_ref() == b"xmlUrl" {
+            rewritten.push_attribute(("xmlUrl", xml_url));
+        } else {
+            rewritten.push_attribute(attribute);
+        }
+    }
+
+    let mut writer = Writer::new(Vec::new());
+    writer
+        .write_event(if empty {
+            Event::Empty(rewritten)
+        } else {
+            Event::Start(rewritten)
+        })
+        .map_err(quick_xml::Error::from)?;
+
+    Ok(writer.into_inner())
+}
+
+/// Streams through an OPML document, replacing the `xmlUrl` of each
+/// `<outline>` for which `rewrite` returns a new one. As with feeds, the rest
+/// of the document is copied through untouched.
+fn rewrite_feed_urls<F>(opml: &[u8], mut rewrite: F) -> Result<RewrittenOpml, OpmlError>
+where
+    F: FnMut(&str) -> Option<String>,
+{
+    let mut reader = Reader::from_reader(opml);
+
+    let mut body = Vec::with_capacity(opml.len());
+    let mut copied_to = 0;
+    let mut seen_root = false;
+    let mut n_rewritten = 0;
+
+    loop {
+        let event_start = reader.buffer_position() as usize;
+
+        let (element, empty) = match reader.read_event()? {
+            Event::Start(element) if !seen_root || element.name().as_ref() == b"outline" => {
+                (element, false)
+            }
+            Event::Empty(element) if seen_root && element.name().as_ref() == b"outline" => {
+                (element, true)
+            }
+            Event::Empty(_) if !seen_root => return Err(OpmlError::NotOpml),
+            Event::Eof => break,
+            _ => continue,
+        };
+
+        if !seen_root {
+            if element.name().as_ref() != b"opml" {
+                return Err(OpmlError::NotOpml);
+            }
+            seen_root = true;
+            continue;
+        }
+
+        let Some(xml_url) = element.try_get_attribute("xmlUrl")? else {
+            continue;
+        };
+        let Some(new_url) = rewrite(
+            &xml_url.decoded_and_normalized_value(XmlVersion::Implicit1_0, reader.decoder())?,
+        ) else {
+            continue;
+        };
+
+        body.extend_from_slice(&opml[copied_to..event_start]);
+        body.extend_from_slice(&rewrite_outline(&element, &new_url, empty)?);
+        copied_to = reader.buffer_position() as usize;
+        n_rewritten += 1;
+    }
+
+    if !seen_root {
+        return Err(OpmlError::NotOpml);
+    }
+
+    body.extend_from_slice(&opml[copied_to..]);
+
+    Ok(RewrittenOpml {
+        opml: body.into(),
+        n_rewritten,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use matches::assert_matches;
+    use test_case::test_case;
+
+    const SERVICE: &str = "https://rssfilter.example.com/";
+
+    const OPML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<opml version="2.0">
+  <head><title>Subscriptions</title></head>
+  <body>
+    <outline text="News">
+      <outline type="rss" text="Example" xmlUrl="https://example.com/feed?a=1&amp;b=2" htmlUrl="https://example.com/"/>
+      <outline type="rss" text="Other" xmlUrl="https://other.example.com/rss.xml"></outline>
+    </outline>
+  </body>
+</opml>
+"#;
+
+    fn service() -> Url {
+        Url::parse(SERVICE).unwrap()
+    }
+
+    fn profile() -> FilterProfile {
+        FilterProfile {
+            title_filter_regexes: vec!["^Ad:".to_string(), "^Sponsored:".to_string()],
+            link_filter_regexes: vec!["/ads/".to_string()],
+            ..Default::default()
+        }
+    }
+
+    fn xml_urls(opml: &[u8]) -> Vec<String> {
+        let mut reader = Reader::from_reader(opml);
+        let mut urls = Vec::new();
+
+        loop {
+            match reader.read_event().unwrap() {
+                Event::Start(element) | Event::Empty(element) => {
+                    if let Some(url) = element.try_get_attribute("xmlUrl").unwrap() {
+                        urls.push(
+                            url.decoded_and_normalized_value(
+                                XmlVersion::Implicit1_0,
+                                reader.decoder(),
+                            )
+                            .unwrap()
+                            .into_owned(),
+                        );
+                    }
+                }
+                Event::Eof => return urls,
+                _ => {}
+            }
+        }
+    }
+
+    #[test]
+    fn test_filtered_url() {
+        assert_eq!(
+            profile()
+                .filtered_url(&service(), "https://example.com/feed?a=1&b=2")
+                .as_str(),
+            "https://rssfilter.example.com/?url=https%3A%2F%2Fexample.com%2Ffeed%3Fa%3D1%26b%3D2&title_filter_regex=%5EAd%3A&title_filter_regex=%5ESponsored%3A&link_filter_regex=%2Fads%2F"
+        );
+    }
+
+    #[test_case("https://rssfilter.example.com/?url=https%3A%2F%2Fexample.com%2Ffeed", Some("https://example.com/feed"); "filtered")]
+    #[test_case("https://rssfilter.example.com/api/v1/filter?url=https%3A%2F%2Fexample.com%2Ffeed", None; "other path")]
+    #[test_case("http://rssfilter.example.com/?url=https%3A%2F%2Fexample.com%2Ffeed", Some("https://example.com/feed"); "http")]
+    #[test_case("https://rssfilter.example.com:443/?url=https%3A%2F%2Fexample.com%2Ffeed", Some("https://example.com/feed"); "default port")]
+    #[test_case("https://rssfilter.example.com:8443/?url=https%3A%2F%2Fexample.com%2Ffeed", None; "other port")]
+    #[test_case("ftp://rssfilter.example.com/?url=https%3A%2F%2Fexample.com%2Ffeed", None; "other scheme")]
+    #[test_case("https://other.example.com/?url=https%3A%2F%2Fexample.com%2Ffeed", None; "other host")]
+    #[test_case("https://rssfilter.example.com/?title_filter_regex=x", None; "no url")]
+    #[test_case("not a url", None; "invalid")]
+    fn test_unfiltered_url(url: &str, expected: Option<&str>) {
+        assert_eq!(unfiltered_url(&service(), url).as_deref(), expected);
+    }
+
+    #[test]
+    fn test_filter_subscriptions() {
+        let rewritten =
+            filter_subscriptions(OPML.as_bytes(), &service(), &profile(), None).unwrap();
+
+        assert_eq!(rewritten.n_rewritten, 2);
+        assert_eq!(
+            xml_urls(&rewritten.opml),
+            [
+                profile()
+                    .filtered_url(&service(), "https://example.com/feed?a=1&b=2")
+                    .to_string(),
+                profile()
+                    .filtered_url(&service(), "https://other.example.com/rss.xml")
+                    .to_string(),
+            ]
+        );
+
+        // Everything else is as it was
+        let opml = std::str::from_utf8(&rewritten.opml).unwrap();
+        assert!(opml.starts_with(r#"<?xml version="1.0" encoding="UTF-8"?>"#));
+        assert!(opml.contains(r#"htmlUrl="https://example.com/"/>"#));
+        assert!(opml.contains(r#"xmlUrl="https://rssfilter.example.com/?url=https%3A%2F%2Fother.example.com%2Frss.xml&amp;"#));
+        assert!(opml.contains("</outline>\n    </outline>"));
+    }
+
+    #[test]
+    fn test_filter_subscriptions_signed() {
+        let signer = UrlSigner::new("shared secret");
+        let rewritten =
+            filter_subscriptions(OPML.as_bytes(), &service(), &profile(), Some(&signer)).unwrap();
+
+        for url in xml_urls(&rewritten.opml) {
+            assert_eq!(signer.verify(&Url::parse(&url).unwrap()), Ok(()));
+        }
+
+        // Refiltering replaces the signatures rather than adding to them
+        let refiltered =
+            filter_subscriptions(&rewritten.opml, &service(), &profile(), Some(&signer)).unwrap();
+        assert_eq!(xml_urls(&refiltered.opml), xml_urls(&rewritten.opml));
+    }
+
+    #[test]
+    fn test_unfilter_subscriptions() {
+        let filtered = filter_subscriptions(OPML.as_bytes(), &service(), &profile(), None).unwrap();
+        let unfiltered = unfilter_subscriptions(&filtered.opml, &service()).unwrap();
+
+        assert_eq!(unfiltered.n_rewritten, 2);
+        assert_eq!(xml_urls(&unfiltered.opml), xml_urls(OPML.as_bytes()));
+    }
+
+    #[test]
+    fn test_refilter_subscriptions() {
+        let filtered = filter_subscriptions(OPML.as_bytes(), &service(), &profile(), None).unwrap();
+        let profile = FilterProfile {
+            guid_filter_regexes: vec!["^ad-".to_string()],
+            ..Default::default()
+        };
+        let refiltered = filter_subscriptions(&filtered.opml, &service(), &profile, None).unwrap();
+
+        assert_eq!(
+            xml_urls(&refiltered.opml)[0],
+            profile
+                .filtered_url(&service(), "https://example.com/feed?a=1&b=2")
+                .as_str()
+        );
+    }
+
+    #[test]
+    fn test_unfilter_leaves_other_feeds() {
+        let unfiltered = unfilter_subscriptions(OPML.as_bytes(), &service()).unwrap();
+
+        assert_eq!(unfiltered.n_rewritten, 0);
+        assert_eq!(unfiltered.opml, OPML.as_bytes());
+    }
+
+    #[test_case("<rss version=\"2.0\"><channel/></rss>"; "rss")]
+    #[test_case("<opml/>"; "empty root")]
+    #[test_case(""; "empty")]
+    fn test_not_opml(document: &str) {
+        assert_matches!(
+            filter_subscriptions(document.as_bytes(), &service(), &profile(), None),
+            Err(OpmlError::NotOpml)
+        );
+    }
+
+    #[test]
+    fn test_malformed() {
+        assert_matches!(
+            filter_subscriptions(
+                b"<opml><body><outline xmlUrl=x></body></opml>",
+                &service(),
+                &profile(),
+                None
+            ),
+            Err(OpmlError::Parse(_))
+        );
+    }
+}
@@ -0,0 +1,20 @@
+/// The query parameter giving the feed to filter.
+pub const URL_PARAM: &str = "url";
+
+/// The query parameter giving a regex for the titles of items to remove.
+pub const TITLE_FILTER_PARAM: &str = "title_filter_regex";
+
+/// The query parameter giving a regex for the GUIDs of items to remove.
+pub const GUID_FILTER_PARAM: &str = "guid_filter_regex";
+
+/// The query parameter giving a regex for the links of items to remove.
+pub const LINK_FILTER_PARAM: &str = "link_filter_regex";
+
+/// The query parameters which make up a filtered feed's URL: everything which
+/// says what is fetched and how it's filtered.
+pub const FEED_PARAMS: &[&str] = &[
+    URL_PARAM,
+    TITLE_FILTER_PARAM,
+    GUID_FILTER_PARAM,
+    LINK_FILTER_PARAM,
+];
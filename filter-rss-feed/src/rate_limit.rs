@@ -0,0 +1,721 @@
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use headers::HeaderMapExt;
+use http::{Request as HttpRequest, Response as HttpResponse};
+use lru::LruCache;
+use tower::Layer;
+use tracing::warn;
+use web_time::Instant;
+
+use crate::header_cf_cache_status::CfCacheStatus;
+use crate::header_rssfilter_cache_status::RssFilterCacheStatus;
+use crate::http_client::{HttpClient, HttpClientError};
+
+/// Most buckets the in-memory store holds. Past this the least recently used
+/// is dropped, which is usually one that has long since refilled.
+const MAX_BUCKETS: usize = 10_000;
+
+/// A token bucket: up to `burst` requests may be made at once, and then
+/// `per_minute` a minute, spread evenly.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RateLimit {
+    pub per_minute: u32,
+    pub burst: u32,
+}
+
+impl RateLimit {
+    /// Tokens added to the bucket per second.
+    fn refill_rate(&self) -> f64 {
+        f64::from(self.per_minute) / 60.0
+    }
+
+    /// How many tokens the bucket holds when full. There's always room for
+    /// one, or nothing could ever be allowed.
+    fn capacity(&self) -> f64 {
+        f64::from(self.burst.max(1))
+    }
+
+    /// Refills a bucket holding `tokens` for the `elapsed` time since it was
+    /// last used, then takes a token from it if there is one.
+    fn take_from(&self, tokens: &mut f64, elapsed: Duration) -> RateLimitDecision {
+        *tokens = (*tokens + elapsed.as_secs_f64() * self.refill_rate()).min(self.capacity());
+
+        if *tokens >= 1.0 {
+            *tokens -= 1.0;
+            return RateLimitDecision::Allowed;
+        }
+
+        let rate = self.refill_rate();
+        let retry_after = if rate > 0.0 {
+            Duration::from_secs_f64((1.0 - *tokens) / rate)
+        } else {
+            Duration::MAX
+        };
+
+        RateLimitDecision::Limited { retry_after }
+    }
+
+    /// Puts a token back into a bucket holding `tokens`.
+    fn give_back_to(&self, tokens: &mut f64) {
+        *tokens = (*tokens + 1.0).min(self.capacity());
+    }
+}
+
+/// Whether a request may go ahead.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RateLimitDecision {
+    Allowed,
+    /// The bucket is empty, and will have a token again after `retry_after`.
+    Limited {
+        retry_after: Duration,
+    },
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn full(limit: &RateLimit, now: Instant) -> Self {
+        Self {
+            tokens: limit.capacity(),
+            updated: now,
+        }
+    }
+
+    /// Refills the bucket for the time since it was last used, then takes a
+    /// token from it if there is one.
+    fn take(&mut self, limit: &RateLimit, now: Instant) -> RateLimitDecision {
+        let elapsed = now.saturating_duration_since(self.updated);
+        self.updated = now;
+
+        limit.take_from(&mut self.tokens, elapsed)
+    }
+}
+
+/// Where rate limits' buckets are kept, by key.
+///
+/// Errors are reported so they can be logged, but callers let the request
+/// go ahead: failing to count a request is better than refusing every one.
+#[async_trait]
+#[cfg(not(target_arch = "wasm32"))]
+pub trait RateLimitStore: Send + Sync {
+    /// Takes a token from the bucket for `key`, which starts full.
+    async fn take(
+        &self,
+        key: &str,
+        limit: &RateLimit,
+    ) -> Result<RateLimitDecision, HttpClientError>;
+
+    /// Puts back a token taken for a request which didn't need it after all.
+    async fn give_back(&self, key: &str, limit: &RateLimit) -> Result<(), HttpClientError>;
+}
+
+#[async_trait(?Send)]
+#[cfg(target_arch = "wasm32")]
+pub trait RateLimitStore {
+    /// Takes a token from the bucket for `key`, which starts full.
+    async fn take(
+        &self,
+        key: &str,
+        limit: &RateLimit,
+    ) -> Result<RateLimitDecision, HttpClientError>;
+
+    /// Puts back a token taken for a request which didn't need it after all.
+    async fn give_back(&self, key: &str, limit: &RateLimit) -> Result<(), HttpClientError>;
+}
+
+/// A [`RateLimitStore`] held in process memory, so each process counts
+/// requests on its own. It holds a bounded number of buckets, forgetting the
+/// least recently used, so clients can't make it grow by using many
+/// addresses.
+#[derive(Debug)]
+pub struct InMemoryRateLimitStore {
+    buckets: Mutex<LruCache<String, Bucket>>,
+}
+
+impl Default for InMemoryRateLimitStore {
+    fn default() -> Self {
+        Self::with_max_buckets(MAX_BUCKETS)
+    }
+}
+
+impl InMemoryRateLimitStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn with_max_buckets(max_buckets: usize) -> Self {
+        Self {
+            buckets: Mutex::new(LruCache::new(
+                NonZeroUsize::new(max_buckets).unwrap_or(NonZeroUsize::MIN),
+            )),
+        }
+    }
+
+    fn take_at(
+        &self,
+        key: &str,
+        limit: &RateLimit,
+        now: Instant,
+    ) -> Result<RateLimitDecision, HttpClientError> {
+        let mut buckets = self
+            .buckets
+            .lock()
+            .map_err(|err| HttpClientError::RateLimitStore(err.to_string()))?;
+
+        let bucket = buckets.get_or_insert_mut(key.to_string(), || Bucket::full(limit, now));
+
+        Ok(bucket.take(limit, now))
+    }
+
+    fn give_back_to(&self, key: &str, limit: &RateLimit) -> Result<(), HttpClientError> {
+        let mut buckets = self
+            .buckets
+            .lock()
+            .map_err(|err| HttpClientError::RateLimitStore(err.to_string()))?;
+
+        if let Some(bucket) = buckets.peek_mut(key) {
+            limit.give_back_to(&mut bucket.tokens);
+        }
+
+        Ok(())
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.buckets.lock().map_or(0, |buckets| buckets.len())
+    }
+}
+
+#[async_trait]
+#[cfg(not(target_arch = "wasm32"))]
+impl RateLimitStore for InMemoryRateLimitStore {
+    async fn take(
+        &self,
+        key: &str,
+        limit: &RateLimit,
+    ) -> Result<RateLimitDecision, HttpClientError> {
+        self.take_at(key, limit, Instant::now())
+    }
+
+    async fn give_back(&self, key: &str, limit: &RateLimit) -> Result<(), HttpClientError> {
+        self.give_back_to(key, limit)
+    }
+}
+
+#[async_trait(?Send)]
+#[cfg(target_arch = "wasm32")]
+impl RateLimitStore for InMemoryRateLimitStore {
+    async fn take(
+        &self,
+        key: &str,
+        limit: &RateLimit,
+    ) -> Result<RateLimitDecision, HttpClientError> {
+        self.take_at(key, limit, Instant::now())
+    }
+
+    async fn give_back(&self, key: &str, limit: &RateLimit) -> Result<(), HttpClientError> {
+        self.give_back_to(key, limit)
+    }
+}
+
+// A store shared between clients is a store too
+#[async_trait]
+#[cfg(not(target_arch = "wasm32"))]
+impl<S: RateLimitStore + ?Sized> RateLimitStore for Arc<S> {
+    async fn take(
+        &self,
+        key: &str,
+        limit: &RateLimit,
+    ) -> Result<RateLimitDecision, HttpClientError> {
+        (**self).take(key, limit).await
+    }
+
+    async fn give_back(&self, key: &str, limit: &RateLimit) -> Result<(), HttpClientError> {
+        (**self).give_back(key, limit).await
+    }
+}
+
+#[async_trait(?Send)]
+#[cfg(target_arch = "wasm32")]
+impl<S: RateLimitStore + ?Sized> RateLimitStore for Arc<S> {
+    async fn take(
+        &self,
+        key: &str,
+        limit: &RateLimit,
+    ) -> Result<RateLimitDecision, HttpClientError> {
+        (**self).take(key, limit).await
+    }
+
+    async fn give_back(&self, key: &str, limit: &RateLimit) -> Result<(), HttpClientError> {
+        (**self).give_back(key, limit).await
+    }
+}
+
+/// Creates an in-memory rate limit store, so limits are per process. That
+/// suits tests and the native server; the worker makes a new backend for each
+/// request, and passes a [`durable_object::DurableObjectRateLimitStore`] so
+/// that every isolate counts against the same buckets.
+pub fn create_rate_limit_store() -> Box<dyn RateLimitStore> {
+    Box::new(InMemoryRateLimitStore::new())
+}
+
+#[cfg(target_arch = "wasm32")]
+pub mod durable_object {
+    use serde::{Deserialize, Serialize};
+    use worker::{
+        Date, DurableObject, Env, ObjectNamespace, Request, Response, State, durable_object,
+    };
+
+    use super::*;
+
+    /// Where each bucket object keeps its bucket.
+    const BUCKET_KEY: &str = "bucket";
+
+    /// Buckets which never refill are still forgotten after this long.
+    const MAX_IDLE: Duration = Duration::from_secs(24 * 60 * 60);
+
+    #[derive(Serialize, Deserialize)]
+    struct StoredBucket {
+        tokens: f64,
+        updated_ms: u64,
+    }
+
+    fn limit_from(req: &Request) -> worker::Result<RateLimit> {
+        let url = req.url()?;
+        let param = |name: &str| {
+            url.query_pairs()
+                .find(|(key, _)| key == name)
+                .and_then(|(_, value)| value.parse().ok())
+                .ok_or_else(|| worker::Error::RustError(format!("Missing {name}")))
+        };
+
+        Ok(RateLimit {
+            per_minute: param("per_minute")?,
+            burst: param("burst")?,
+        })
+    }
+
+    /// A Durable Object holding one rate limit bucket, so that every isolate
+    /// counts against the same one. Bind it as `RATE_LIMIT_BUCKETS`.
+    ///
+    /// `/take` takes a token, answering `429` with the milliseconds to wait
+    /// when there isn't one, and `/give-back` puts one back. Both
+    /// are given the limit as `per_minute` and `burst` query parameters. Once
+    /// the bucket would be full again it's deleted, as a missing bucket
+    /// starts full.
+    #[durable_object(alarm)]
+    pub struct RateLimitBucket {
+        state: State,
+    }
+
+    impl DurableObject for RateLimitBucket {
+        fn new(state: State, _env: Env) -> Self {
+            Self { state }
+        }
+
+        async fn fetch(&self, req: Request) -> worker::Result<Response> {
+            let limit = limit_from(&req)?;
+            let storage = self.state.storage();
+            let now = Date::now().as_millis();
+            let stored = storage.get::<StoredBucket>(BUCKET_KEY).await?;
+
+            let (mut tokens, elapsed) = match &stored {
+                Some(bucket) => (
+                    bucket.tokens,
+                    Duration::from_millis(now.saturating_sub(bucket.updated_ms)),
+                ),
+                None => (limit.capacity(), Duration::ZERO),
+            };
+
+            let decision = match req.path().as_str() {
+                "/take" => limit.take_from(&mut tokens, elapsed),
+                // A bucket which has been forgotten is already full
+                "/give-back" if stored.is_none() => return Response::empty(),
+                "/give-back" => {
+                    limit.give_back_to(&mut tokens);
+                    RateLimitDecision::Allowed
+                }
+                _ => return Response::error("Not Found", 404),
+            };
+
+            storage
+                .put(
+                    BUCKET_KEY,
+                    StoredBucket {
+                        tokens,
+                        updated_ms: now,
+                    },
+                )
+                .await?;
+
+            let rate = limit.refill_rate();
+            let until_full = if rate > 0.0 {
+                Duration::from_secs_f64((limit.capacity() - tokens).max(0.0) / rate).min(MAX_IDLE)
+            } else {
+                MAX_IDLE
+            };
+            storage.set_alarm(until_full).await?;
+
+            match decision {
+                RateLimitDecision::Allowed => Response::empty(),
+                RateLimitDecision::Limited { retry_after } => {
+                    let millis = retry_after.as_millis().min(u128::from(u64::MAX));
+                    Ok(Response::ok(millis.to_string())?.with_status(429))
+                }
+            }
+        }
+
+        async fn alarm(&self) -> worker::Result<Response> {
+            self.state.storage().delete_all().await?;
+
+            Response::empty()
+        }
+    }
+
+    /// A [`RateLimitStore`] keeping each bucket in a [`RateLimitBucket`]
+    /// Durable Object, so limits hold across every isolate and data centre.
+    pub struct DurableObjectRateLimitStore {
+        namespace: ObjectNamespace,
+    }
+
+    impl DurableObjectRateLimitStore {
+        pub fn new(namespace: ObjectNamespace) -> Self {
+            Self { namespace }
+        }
+
+        async fn call(
+            &self,
+            key: &str,
+            limit: &RateLimit,
+            path: &str,
+        ) -> Result<RateLimitDecision, HttpClientError> {
+            let url = format!(
+                "https://rate-limit{path}?per_minute={}&burst={}",
+                limit.per_minute, limit.burst
+            );
+            let stub = self.namespace.id_from_name(key)?.get_stub()?;
+            let mut response = stub.fetch_with_str(&url).await?;
+
+            match response.status_code() {
+                429 => {
+                    let body = response.text().await?;
+                    let millis = body.trim().parse().map_err(|_| {
+                        HttpClientError::RateLimitStore(format!(
+                            "Invalid retry delay from rate limit bucket: {body}"
+                        ))
+                    })?;
+
+                    Ok(RateLimitDecision::Limited {
+                        retry_after: Duration::from_millis(millis),
+                    })
+                }
+                200..=299 => Ok(RateLimitDecision::Allowed),
+                status => Err(HttpClientError::RateLimitStore(format!(
+                    "Rate limit bucket answered {status}"
+                ))),
+            }
+        }
+    }
+
+    #[async_trait(?Send)]
+    impl RateLimitStore for DurableObjectRateLimitStore {
+        async fn take(
+            &self,
+            key: &str,
+            limit: &RateLimit,
+        ) -> Result<RateLimitDecision, HttpClientError> {
+            self.call(key, limit, "/take").await
+        }
+
+        async fn give_back(&self, key: &str, limit: &RateLimit) -> Result<(), HttpClientError> {
+            self.call(key, limit, "/give-back").await.map(|_| ())
+        }
+    }
+}
+
+/// A [`Layer`] which limits how often each upstream host is fetched from, so
+/// that one popular feed can't be used to hammer its server. Every request
+/// which reaches the layer counts, including each redirect and retry, so it
+/// belongs below any cache. Responses which say they came from a cache, such
+/// as Cloudflare's in front of `fetch` or a CDN in front of the feed, didn't
+/// reach the feed's server, so their token is given back.
+#[derive(Clone)]
+pub struct RateLimitLayer {
+    store: Arc<dyn RateLimitStore>,
+    limit: RateLimit,
+}
+
+impl RateLimitLayer {
+    pub fn new(store: Arc<dyn RateLimitStore>, limit: RateLimit) -> Self {
+        Self { store, limit }
+    }
+}
+
+impl<C> Layer<C> for RateLimitLayer {
+    type Service = RateLimitedHttpClient<C>;
+
+    fn layer(&self, inner: C) -> Self::Service {
+        RateLimitedHttpClient {
+            inner,
+            store: Arc::clone(&self.store),
+            limit: self.limit,
+        }
+    }
+}
+
+/// Whether a response was answered from a cache, without reaching the
+/// upstream server.
+fn from_cache(response: &HttpResponse<Bytes>) -> bool {
+    matches!(
+        response.headers().typed_get::<RssFilterCacheStatus>(),
+        Some(RssFilterCacheStatus(
+            CfCacheStatus::Hit | CfCacheStatus::Stale | CfCacheStatus::Updating
+        ))
+    )
+}
+
+/// Wraps any [`HttpClient`], refusing requests to hosts which have been
+/// fetched from too often. See [`RateLimitLayer`].
+pub struct RateLimitedHttpClient<C> {
+    inner: C,
+    store: Arc<dyn RateLimitStore>,
+    limit: RateLimit,
+}
+
+impl<C: HttpClient> RateLimitedHttpClient<C> {
+    async fn send_limited(
+        &self,
+        request: HttpRequest<Bytes>,
+    ) -> Result<HttpResponse<Bytes>, HttpClientError> {
+        // Requests without a host can't go anywhere, and fail further in
+        let Some(host) = request.uri().host().map(str::to_ascii_lowercase) else {
+            return self.inner.send(request).await;
+        };
+
+        let key = format!("upstream:{host}");
+        match self.store.take(&key, &self.limit).await {
+            Ok(RateLimitDecision::Allowed) => {}
+            Ok(RateLimitDecision::Limited { retry_after }) => {
+                return Err(HttpClientError::RateLimited { host, retry_after });
+            }
+            Err(err) => warn!(%err, host, "Failed to check the upstream rate limit"),
+        }
+
+        let response = self.inner.send(request).await?;
+
+        if from_cache(&response) {
+            if let Err(err) = self.store.give_back(&key, &self.limit).await {
+                warn!(%err, host, "Failed to give back an upstream rate limit token");
+            }
+        }
+
+        Ok(response)
+    }
+}
+
+#[async_trait]
+#[cfg(not(target_arch = "wasm32"))]
+impl<C: HttpClient> HttpClient for RateLimitedHttpClient<C> {
+    async fn send(
+        &self,
+        request: HttpRequest<Bytes>,
+    ) -> Result<HttpResponse<Bytes>, HttpClientError> {
+        self.send_limited(request).await
+    }
+}
+
+#[async_trait(?Send)]
+#[cfg(target_arch = "wasm32")]
+impl<C: HttpClient> HttpClient for RateLimitedHttpClient<C> {
+    async fn send(
+        &self,
+        request: HttpRequest<Bytes>,
+    ) -> Result<HttpResponse<Bytes>, HttpClientError> {
+        self.send_limited(request).await
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use crate::fake_http_client::{FakeHttpClientBuilder, FakeResponse};
+    use http::StatusCode;
+    use matches::assert_matches;
+
+    const LIMIT: RateLimit = RateLimit {
+        per_minute: 60,
+        burst: 2,
+    };
+
+    #[test]
+    fn test_burst_then_refill() {
+        let store = InMemoryRateLimitStore::new();
+        let start = Instant::now();
+
+        assert_eq!(
+            store.take_at("a", &LIMIT, start).unwrap(),
+            RateLimitDecision::Allowed
+        );
+        assert_eq!(
+            store.take_at("a", &LIMIT, start).unwrap(),
+            RateLimitDecision::Allowed
+        );
+        assert_eq!(
+            store.take_at("a", &LIMIT, start).unwrap(),
+            RateLimitDecision::Limited {
+                retry_after: Duration::from_secs(1)
+            }
+        );
+
+        // Half a token has come back, so there's half as long to wait
+        let later = start + Duration::from_millis(500);
+        assert_eq!(
+            store.take_at("a", &LIMIT, later).unwrap(),
+            RateLimitDecision::Limited {
+                retry_after: Duration::from_millis(500)
+            }
+        );
+
+        let later = start + Duration::from_secs(1);
+        assert_eq!(
+            store.take_at("a", &LIMIT, later).unwrap(),
+            RateLimitDecision::Allowed
+        );
+    }
+
+    #[test]
+    fn test_keys_are_separate() {
+        let store = InMemoryRateLimitStore::new();
+        let now = Instant::now();
+        let limit = RateLimit {
+            per_minute: 1,
+            burst: 1,
+        };
+
+        assert_eq!(
+            store.take_at("a", &limit, now).unwrap(),
+            RateLimitDecision::Allowed
+        );
+        assert_matches!(
+            store.take_at("a", &limit, now).unwrap(),
+            RateLimitDecision::Limited { .. }
+        );
+        assert_eq!(
+            store.take_at("b", &limit, now).unwrap(),
+            RateLimitDecision::Allowed
+        );
+    }
+
+    #[test]
+    fn test_refill_stops_at_burst() {
+        let store = InMemoryRateLimitStore::new();
+        let start = Instant::now();
+        store.take_at("a", &LIMIT, start).unwrap();
+
+        // An hour's rest is only worth a full bucket
+        let later = start + Duration::from_secs(3600);
+        for _ in 0..LIMIT.burst {
+            assert_eq!(
+                store.take_at("a", &LIMIT, later).unwrap(),
+                RateLimitDecision::Allowed
+            );
+        }
+        assert_matches!(
+            store.take_at("a", &LIMIT, later).unwrap(),
+            RateLimitDecision::Limited { .. }
+        );
+    }
+
+    #[test]
+    fn test_buckets_are_bounded() {
+        let store = InMemoryRateLimitStore::with_max_buckets(100);
+        let now = Instant::now();
+        let limit = RateLimit {
+            per_minute: 1,
+            burst: 1,
+        };
+
+        store.take_at("busy", &limit, now).unwrap();
+        for n in 0..1000 {
+            store.take_at(&n.to_string(), &limit, now).unwrap();
+            // Still counted, however many others come and go
+            if n % 10 == 0 {
+                assert_matches!(
+                    store.take_at("busy", &limit, now).unwrap(),
+                    RateLimitDecision::Limited { .. }
+                );
+            }
+        }
+
+        assert_eq!(store.len(), 100);
+    }
+
+    #[tokio::test]
+    async fn test_layer() {
+        let limit = RateLimit {
+            per_minute: 1,
+            burst: 1,
+        };
+        let client = RateLimitLayer::new(Arc::new(InMemoryRateLimitStore::new()), limit).layer(
+            FakeHttpClientBuilder::default()
+                .with_rss_response("https://example.com/feed", "<rss/>")
+                .with_rss_response("https://EXAMPLE.com/other", "<rss/>")
+                .with_rss_response("https://example.org/feed", "<rss/>")
+                .build()
+                .unwrap(),
+        );
+
+        let send = |uri: &'static str| {
+            client.send(HttpRequest::builder().uri(uri).body(Bytes::new()).unwrap())
+        };
+
+        assert!(send("https://example.com/feed").await.is_ok());
+        assert_matches!(
+            send("https://EXAMPLE.com/other").await,
+            Err(HttpClientError::RateLimited { host, .. }) if host == "example.com"
+        );
+        assert!(send("https://example.org/feed").await.is_ok());
+        assert_eq!(client.inner.requests("https://EXAMPLE.com/other"), 0);
+    }
+
+    #[tokio::test]
+    async fn test_cached_responses_are_given_back() {
+        let limit = RateLimit {
+            per_minute: 1,
+            burst: 1,
+        };
+        let client = RateLimitLayer::new(Arc::new(InMemoryRateLimitStore::new()), limit).layer(
+            FakeHttpClientBuilder::default()
+                .with_response(
+                    "https://example.com/cached",
+                    FakeResponse::new(StatusCode::OK, "<rss/>")
+                        .with_header("x-rssfilter-cache-status", "HIT"),
+                )
+                .with_rss_response("https://example.com/feed", "<rss/>")
+                .build()
+                .unwrap(),
+        );
+
+        let send = |uri: &'static str| {
+            client.send(HttpRequest::builder().uri(uri).body(Bytes::new()).unwrap())
+        };
+
+        // Cache hits didn't reach the host, so don't use up its only token
+        for _ in 0..3 {
+            assert!(send("https://example.com/cached").await.is_ok());
+        }
+        assert!(send("https://example.com/feed").await.is_ok());
+        assert_matches!(
+            send("https://example.com/feed").await,
+            Err(HttpClientError::RateLimited { .. })
+        );
+    }
+}
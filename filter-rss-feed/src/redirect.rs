@@ -0,0 +1,441 @@
+use async_trait::async_trait;
+use bytes::Bytes;
+use http::header::{AUTHORIZATION, COOKIE, LOCATION, PROXY_AUTHORIZATION};
+use http::{Method, Request as HttpRequest, Response as HttpResponse, StatusCode, Uri};
+use tower::Layer;
+use tracing::debug;
+use url::Url;
+
+use crate::http_client::{HttpClient, HttpClientError};
+use crate::retry::copy_request;
+
+/// How redirects from upstream servers are followed.
+///
+/// The platform clients don't follow redirects themselves, so that each hop
+/// goes back through every layer, including any [`UrlPolicyLayer`]. Only
+/// `GET` and `HEAD` requests are redirected, and redirects from `https` to
+/// `http` are always refused.
+///
+/// [`UrlPolicyLayer`]: crate::UrlPolicyLayer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RedirectConfig {
+    /// Most redirects to follow for one request. `0` returns redirects as
+    /// they are. Default is 10
+    pub max_redirects: usize,
+    /// Whether redirects must keep the scheme of the URL they came from,
+    /// refusing upgrades from `http` to `https` too. Default is false
+    pub same_scheme_only: bool,
+}
+
+impl Default for RedirectConfig {
+    fn default() -> Self {
+        Self {
+            max_redirects: 10,
+            same_scheme_only: false,
+        }
+    }
+}
+
+/// Response extension recording that a request was redirected.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Redirected {
+    /// The URL the response actually came from.
+    pub final_url: Uri,
+    /// Where the feed has permanently moved to: the furthest URL reached by
+    /// unbroken `301` and `308` redirects from the one requested.
+    pub moved_permanently_to: Option<Uri>,
+}
+
+fn is_redirect(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::MOVED_PERMANENTLY
+            | StatusCode::FOUND
+            | StatusCode::SEE_OTHER
+            | StatusCode::TEMPORARY_REDIRECT
+            | StatusCode::PERMANENT_REDIRECT
+    )
+}
+
+fn is_permanent(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::MOVED_PERMANENTLY | StatusCode::PERMANENT_REDIRECT
+    )
+}
+
+/// Where a redirect response points, resolved against the URL it came from.
+/// `None` if it doesn't say, in which case it is passed on as it is.
+fn next_url(current: &Uri, response: &HttpResponse<Bytes>) -> Option<Result<Url, HttpClientError>> {
+    let location = response.headers().get(LOCATION)?;
+
+    Some(
+        location
+            .to_str()
+            .map_err(HttpClientError::from)
+            .and_then(|location| {
+                Url::parse(&current.to_string())
+                    .and_then(|current| current.join(location))
+                    .map_err(|err| {
+                        HttpClientError::Redirect(format!("invalid Location {location:?}: {err}"))
+                    })
+            }),
+    )
+}
+
+fn check_scheme(from: &Uri, to: &Url, config: &RedirectConfig) -> Result<(), HttpClientError> {
+    let from = from.scheme_str().unwrap_or_default();
+    let to = to.scheme();
+
+    if from == to {
+        return Ok(());
+    }
+
+    if config.same_scheme_only || (from == "https" && to == "http") {
+        return Err(HttpClientError::Redirect(format!(
+            "redirect from {from} to {to} is not allowed"
+        )));
+    }
+
+    Ok(())
+}
+
+/// Whether two URLs share a scheme, host and port, so that credentials for
+/// one may be sent to the other.
+fn same_origin(a: &Uri, b: &Uri) -> bool {
+    a.scheme() == b.scheme() && a.host() == b.host() && a.port_u16() == b.port_u16()
+}
+
+/// Sends a request, following redirects as `config` says. Each hop is sent
+/// through `client`, so it is checked and retried as any other request is.
+pub(crate) async fn send_following_redirects<C: HttpClient + ?Sized>(
+    client: &C,
+    request: HttpRequest<Bytes>,
+    config: &RedirectConfig,
+) -> Result<HttpResponse<Bytes>, HttpClientError> {
+    let follow = matches!(*request.method(), Method::GET | Method::HEAD);
+    let requested = request.uri().clone();
+
+    let mut current = requested.clone();
+    let mut moved_permanently_to = None;
+    let mut all_permanent = true;
+    let mut request = request;
+    let mut redirects = 0;
+
+    loop {
+        let mut response = client.send(copy_request(&request)).await?;
+
+        let next = if follow && is_redirect(response.status()) {
+            next_url(&current, &response).transpose()?
+        } else {
+            None
+        };
+
+        let Some(next) = next else {
+            if current != requested {
+                response.extensions_mut().insert(Redirected {
+                    final_url: current,
+                    moved_permanently_to,
+                });
+            }
+
+            return Ok(response);
+        };
+
+        if redirects >= config.max_redirects {
+            if config.max_redirects == 0 {
+                return Ok(response);
+            }
+
+            return Err(HttpClientError::TooManyRedirects {
+                max: config.max_redirects,
+            });
+        }
+
+        check_scheme(&current, &next, config)?;
+        let next: Uri = next
+            .as_str()
+            .parse()
+            .map_err(|err: http::uri::InvalidUri| {
+                HttpClientError::Redirect(format!("invalid Location {next}: {err}"))
+            })?;
+
+        debug!(from = %current, to = %next, status = %response.status(), "Following redirect");
+
+        all_permanent &= is_permanent(response.status());
+        if all_permanent {
+            moved_permanently_to = Some(next.clone());
+        }
+
+        if !same_origin(&current, &next) {
+            let headers = request.headers_mut();
+            headers.remove(AUTHORIZATION);
+            headers.remove(COOKIE);
+            headers.remove(PROXY_AUTHORIZATION);
+        }
+
+        *request.uri_mut() = next.clone();
+        current = next;
+        redirects += 1;
+    }
+}
+
+/// A [`Layer`] wrapping clients in a [`RedirectingHttpClient`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RedirectLayer {
+    config: RedirectConfig,
+}
+
+impl RedirectLayer {
+    pub fn new(config: RedirectConfig) -> Self {
+        Self { config }
+    }
+}
+
+impl<C> Layer<C> for RedirectLayer {
+    type Service = RedirectingHttpClient<C>;
+
+    fn layer(&self, inner: C) -> Self::Service {
+        RedirectingHttpClient {
+            inner,
+            config: self.config,
+        }
+    }
+}
+
+/// Wraps any [`HttpClient`], following redirects as [`RedirectConfig`] says
+/// and marking redirected responses with [`Redirected`].
+pub struct RedirectingHttpClient<C> {
+    inner: C,
+    config: RedirectConfig,
+}
+
+#[async_trait]
+#[cfg(not(target_arch = "wasm32"))]
+impl<C: HttpClient> HttpClient for RedirectingHttpClient<C> {
+    async fn send(
+        &self,
+        request: HttpRequest<Bytes>,
+    ) -> Result<HttpResponse<Bytes>, HttpClientError> {
+        send_following_redirects(&self.inner, request, &self.config).await
+    }
+}
+
+#[async_trait(?Send)]
+#[cfg(target_arch = "wasm32")]
+impl<C: HttpClient> HttpClient for RedirectingHttpClient<C> {
+    async fn send(
+        &self,
+        request: HttpRequest<Bytes>,
+    ) -> Result<HttpResponse<Bytes>, HttpClientError> {
+        send_following_redirects(&self.inner, request, &self.config).await
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use crate::fake_http_client::{
+        FakeHttpClient, FakeHttpClientBuilder, FakeResponse, RecordingLayer,
+    };
+    use crate::url_policy::{UrlPolicy, UrlPolicyError, UrlPolicyLayer};
+    use matches::assert_matches;
+    use test_case::test_case;
+
+    const START: &str = "https://example.com/feed";
+
+    fn redirect(status: StatusCode, location: &str) -> FakeResponse {
+        FakeResponse::new(status, "").with_header("location", location)
+    }
+
+    fn get(url: &str) -> HttpRequest<Bytes> {
+        HttpRequest::builder().uri(url).body(Bytes::new()).unwrap()
+    }
+
+    fn redirecting(fake: &mut FakeHttpClientBuilder) -> RedirectingHttpClient<FakeHttpClient> {
+        RedirectLayer::default().layer(fake.build().unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_follows_redirects() {
+        let client = redirecting(
+            FakeHttpClientBuilder::default()
+                .with_response(START, redirect(StatusCode::MOVED_PERMANENTLY, "/new"))
+                .with_response(
+                    "https://example.com/new",
+                    redirect(StatusCode::FOUND, "https://cdn.example.com/feed"),
+                )
+                .with_rss_response("https://cdn.example.com/feed", "<rss/>"),
+        );
+
+        let response = client.send(get(START)).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.body(), "<rss/>");
+        assert_eq!(
+            response.extensions().get::<Redirected>(),
+            Some(&Redirected {
+                final_url: Uri::from_static("https://cdn.example.com/feed"),
+                moved_permanently_to: Some(Uri::from_static("https://example.com/new")),
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_not_redirected() {
+        let client =
+            redirecting(FakeHttpClientBuilder::default().with_rss_response(START, "<rss/>"));
+
+        let response = client.send(get(START)).await.unwrap();
+
+        assert!(response.extensions().get::<Redirected>().is_none());
+    }
+
+    #[test_case(StatusCode::MOVED_PERMANENTLY, StatusCode::PERMANENT_REDIRECT, Some("https://example.com/c") ; "all permanent")]
+    #[test_case(StatusCode::FOUND, StatusCode::PERMANENT_REDIRECT, None ; "temporary first")]
+    #[test_case(StatusCode::PERMANENT_REDIRECT, StatusCode::TEMPORARY_REDIRECT, Some("https://example.com/b") ; "temporary second")]
+    #[tokio::test]
+    async fn test_moved_permanently(first: StatusCode, second: StatusCode, expected: Option<&str>) {
+        let client = redirecting(
+            FakeHttpClientBuilder::default()
+                .with_response(START, redirect(first, "/b"))
+                .with_response("https://example.com/b", redirect(second, "/c"))
+                .with_rss_response("https://example.com/c", "<rss/>"),
+        );
+
+        let response = client.send(get(START)).await.unwrap();
+        let redirected = response.extensions().get::<Redirected>().unwrap();
+
+        assert_eq!(
+            redirected.moved_permanently_to,
+            expected.map(|uri| uri.parse::<Uri>().unwrap())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_too_many_redirects() {
+        let client = redirecting(
+            FakeHttpClientBuilder::default()
+                .with_response(START, redirect(StatusCode::FOUND, START)),
+        );
+
+        let result = client.send(get(START)).await;
+
+        assert_matches!(result, Err(HttpClientError::TooManyRedirects { max: 10 }));
+        assert_eq!(client.inner.requests(START), 11);
+    }
+
+    #[tokio::test]
+    async fn test_redirects_disabled() {
+        let client = RedirectLayer::new(RedirectConfig {
+            max_redirects: 0,
+            ..Default::default()
+        })
+        .layer(
+            FakeHttpClientBuilder::default()
+                .with_response(START, redirect(StatusCode::FOUND, "/new"))
+                .build()
+                .unwrap(),
+        );
+
+        let response = client.send(get(START)).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::FOUND);
+    }
+
+    #[test_case("https://example.com/feed", "http://example.com/new", false, false ; "downgrade is refused")]
+    #[test_case("http://example.com/feed", "https://example.com/new", false, true ; "upgrade is allowed")]
+    #[test_case("http://example.com/feed", "https://example.com/new", true, false ; "upgrade is refused for same scheme only")]
+    #[tokio::test]
+    async fn test_scheme_changes(from: &str, to: &str, same_scheme_only: bool, allowed: bool) {
+        let client = RedirectLayer::new(RedirectConfig {
+            same_scheme_only,
+            ..Default::default()
+        })
+        .layer(
+            FakeHttpClientBuilder::default()
+                .with_response(from, redirect(StatusCode::FOUND, to))
+                .with_rss_response(to, "<rss/>")
+                .build()
+                .unwrap(),
+        );
+
+        let result = client.send(get(from)).await;
+
+        if allowed {
+            assert_eq!(result.unwrap().status(), StatusCode::OK);
+        } else {
+            assert_matches!(result, Err(HttpClientError::Redirect(_)));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_each_hop_is_checked() {
+        let fake = FakeHttpClientBuilder::default()
+            .with_response(
+                START,
+                redirect(
+                    StatusCode::FOUND,
+                    "https://169.254.169.254/latest/meta-data",
+                ),
+            )
+            .build()
+            .unwrap();
+        let client =
+            RedirectLayer::default().layer(UrlPolicyLayer::new(UrlPolicy::default()).layer(fake));
+
+        let result = client.send(get(START)).await;
+
+        assert_matches!(
+            result,
+            Err(HttpClientError::UrlPolicy(UrlPolicyError::PrivateAddress(
+                _
+            )))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_credentials_are_dropped_across_origins() {
+        let recording = RecordingLayer::default();
+        let client = RedirectLayer::default().layer(
+            recording.layer(
+                FakeHttpClientBuilder::default()
+                    .with_response(START, redirect(StatusCode::FOUND, "/same"))
+                    .with_response(
+                        "https://example.com/same",
+                        redirect(StatusCode::FOUND, "https://other.example.com/feed"),
+                    )
+                    .with_rss_response("https://other.example.com/feed", "<rss/>")
+                    .build()
+                    .unwrap(),
+            ),
+        );
+
+        let mut request = get(START);
+        request
+            .headers_mut()
+            .insert(AUTHORIZATION, "Bearer secret".parse().unwrap());
+        client.send(request).await.unwrap();
+
+        let authorised: Vec<_> = recording
+            .requests()
+            .iter()
+            .map(|request| request.headers.contains_key(AUTHORIZATION))
+            .collect();
+        assert_eq!(authorised, [true, true, false]);
+    }
+
+    #[tokio::test]
+    async fn test_only_get_and_head_are_redirected() {
+        let client = redirecting(
+            FakeHttpClientBuilder::default()
+                .with_response(START, redirect(StatusCode::TEMPORARY_REDIRECT, "/new")),
+        );
+
+        let mut request = get(START);
+        *request.method_mut() = Method::POST;
+        let response = client.send(request).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT);
+    }
+}
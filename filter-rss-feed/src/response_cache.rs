@@ -0,0 +1,503 @@
+use std::fmt::Write as _;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use http::header::SET_COOKIE;
+use http::{HeaderMap, HeaderValue, Response as HttpResponse, StatusCode};
+use sha2::{Digest, Sha256};
+use url::Url;
+use web_time::Instant;
+
+use crate::bounded_cache::{BoundedCache, Weigh};
+use crate::http_client::{CacheConfig, HttpClientError};
+
+/// Builds the key a filtered response is cached under, from the upstream URL
+/// and the fingerprint of the filters applied to it.
+///
+/// The URL is normalised first, so that trivially different spellings of the
+/// same feed (case of the scheme and host, default ports, fragments) share an
+/// entry.
+pub(crate) fn cache_key(prefix: &str, upstream_url: &str, fingerprint: &[u8; 32]) -> String {
+    let normalised = match Url::parse(upstream_url) {
+        Ok(mut url) => {
+            url.set_fragment(None);
+            url.to_string()
+        }
+        Err(_) => upstream_url.to_string(),
+    };
+
+    let digest = Sha256::new()
+        .chain_update(normalised)
+        .chain_update(fingerprint)
+        .finalize();
+
+    // The Workers Cache API needs keys to be URLs
+    digest.iter().fold(
+        format!("https://rssfilter.cache/{prefix}/filtered/"),
+        |mut key, byte| {
+            let _ = write!(key, "{byte:02x}");
+            key
+        },
+    )
+}
+
+/// Response extension holding the upstream `ETag` of the feed a filtered
+/// response was made from. Clients only ever see our own `ETag`, but we need
+/// the upstream one to refresh the filtered response with a conditional
+/// request.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct UpstreamETag(pub(crate) HeaderValue);
+
+/// A filtered response, as stored in a [`ResponseCache`]. Entries are
+/// shared by every client, so cookies are never stored or replayed.
+#[derive(Clone, Debug)]
+pub struct CachedResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+    /// The upstream `ETag` of the feed, if it had one.
+    pub upstream_etag: Option<HeaderValue>,
+}
+
+impl From<&HttpResponse<Bytes>> for CachedResponse {
+    fn from(response: &HttpResponse<Bytes>) -> Self {
+        let mut headers = response.headers().clone();
+        headers.remove(SET_COOKIE);
+
+        Self {
+            status: response.status(),
+            headers,
+            body: response.body().clone(),
+            upstream_etag: response
+                .extensions()
+                .get::<UpstreamETag>()
+                .map(|etag| etag.0.clone()),
+        }
+    }
+}
+
+impl From<CachedResponse> for HttpResponse<Bytes> {
+    fn from(cached: CachedResponse) -> Self {
+        let mut response = HttpResponse::new(cached.body);
+        *response.status_mut() = cached.status;
+        *response.headers_mut() = cached.headers;
+        response.headers_mut().remove(SET_COOKIE);
+        if let Some(etag) = cached.upstream_etag {
+            response.extensions_mut().insert(UpstreamETag(etag));
+        }
+        response
+    }
+}
+
+impl Weigh for CachedResponse {
+    fn weight(&self) -> usize {
+        let headers: usize = self
+            .headers
+            .iter()
+            .map(|(name, value)| name.as_str().len() + value.len())
+            .sum();
+        let upstream_etag = self.upstream_etag.as_ref().map_or(0, HeaderValue::len);
+
+        self.body.len() + headers + upstream_etag
+    }
+}
+
+/// A cache of filtered responses, so that repeated requests for the same feed
+/// and filters don't need to fetch and filter it again.
+///
+/// Errors are reported so they can be logged, but callers treat them as a
+/// miss: the cache is an optimisation, and the feed can always be fetched.
+#[async_trait]
+#[cfg(not(target_arch = "wasm32"))]
+pub trait ResponseCache: Send + Sync {
+    async fn get(&self, key: &str) -> Result<Option<CachedResponse>, HttpClientError>;
+
+    async fn put(
+        &self,
+        key: &str,
+        response: CachedResponse,
+        ttl: Duration,
+    ) -> Result<(), HttpClientError>;
+}
+
+#[async_trait(?Send)]
+#[cfg(target_arch = "wasm32")]
+pub trait ResponseCache {
+    async fn get(&self, key: &str) -> Result<Option<CachedResponse>, HttpClientError>;
+
+    async fn put(
+        &self,
+        key: &str,
+        response: CachedResponse,
+        ttl: Duration,
+    ) -> Result<(), HttpClientError>;
+}
+
+/// An entry in an [`InMemoryResponseCache`].
+#[derive(Debug)]
+struct Stored {
+    expires_at: Instant,
+    response: CachedResponse,
+}
+
+impl Weigh for Stored {
+    fn weight(&self) -> usize {
+        self.response.weight()
+    }
+}
+
+/// A [`ResponseCache`] held in process memory, bounded by
+/// [`CacheConfig::max_entries`] and [`CacheConfig::max_bytes`]. Expired
+/// entries are dropped when they are looked up, and every so often when
+/// others are stored.
+#[derive(Debug)]
+pub struct InMemoryResponseCache {
+    entries: Mutex<BoundedCache<Stored>>,
+}
+
+impl Default for InMemoryResponseCache {
+    fn default() -> Self {
+        Self::with_config(&CacheConfig::default())
+    }
+}
+
+impl InMemoryResponseCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A cache with the limits in `config`.
+    pub fn with_config(config: &CacheConfig) -> Self {
+        Self {
+            entries: Mutex::new(BoundedCache::new(config.max_entries, config.max_bytes)),
+        }
+    }
+
+    fn get_entry(&self, key: &str) -> Result<Option<CachedResponse>, HttpClientError> {
+        let mut entries = self
+            .entries
+            .lock()
+            .map_err(|err| HttpClientError::Cache(err.to_string()))?;
+
+        match entries.get(key) {
+            Some(stored) if stored.expires_at > Instant::now() => Ok(Some(stored.response.clone())),
+            Some(_) => {
+                entries.remove(key);
+                Ok(None)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn put_entry(
+        &self,
+        key: &str,
+        response: CachedResponse,
+        ttl: Duration,
+    ) -> Result<(), HttpClientError> {
+        let mut entries = self
+            .entries
+            .lock()
+            .map_err(|err| HttpClientError::Cache(err.to_string()))?;
+
+        let now = Instant::now();
+        entries.prune(|stored| stored.expires_at <= now);
+        entries.insert(
+            key.to_string(),
+            Stored {
+                expires_at: now + ttl,
+                response,
+            },
+        );
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+#[cfg(not(target_arch = "wasm32"))]
+impl ResponseCache for InMemoryResponseCache {
+    async fn get(&self, key: &str) -> Result<Option<CachedResponse>, HttpClientError> {
+        self.get_entry(key)
+    }
+
+    async fn put(
+        &self,
+        key: &str,
+        response: CachedResponse,
+        ttl: Duration,
+    ) -> Result<(), HttpClientError> {
+        self.put_entry(key, response, ttl)
+    }
+}
+
+#[async_trait(?Send)]
+#[cfg(target_arch = "wasm32")]
+impl ResponseCache for InMemoryResponseCache {
+    async fn get(&self, key: &str) -> Result<Option<CachedResponse>, HttpClientError> {
+        self.get_entry(key)
+    }
+
+    async fn put(
+        &self,
+        key: &str,
+        response: CachedResponse,
+        ttl: Duration,
+    ) -> Result<(), HttpClientError> {
+        self.put_entry(key, response, ttl)
+    }
+}
+
+// A cache shared between filters is a cache too
+#[async_trait]
+#[cfg(not(target_arch = "wasm32"))]
+impl<C: ResponseCache + ?Sized> ResponseCache for Arc<C> {
+    async fn get(&self, key: &str) -> Result<Option<CachedResponse>, HttpClientError> {
+        (**self).get(key).await
+    }
+
+    async fn put(
+        &self,
+        key: &str,
+        response: CachedResponse,
+        ttl: Duration,
+    ) -> Result<(), HttpClientError> {
+        (**self).put(key, response, ttl).await
+    }
+}
+
+#[async_trait(?Send)]
+#[cfg(target_arch = "wasm32")]
+impl<C: ResponseCache + ?Sized> ResponseCache for Arc<C> {
+    async fn get(&self, key: &str) -> Result<Option<CachedResponse>, HttpClientError> {
+        (**self).get(key).await
+    }
+
+    async fn put(
+        &self,
+        key: &str,
+        response: CachedResponse,
+        ttl: Duration,
+    ) -> Result<(), HttpClientError> {
+        (**self).put(key, response, ttl).await
+    }
+}
+
+// WASM implementation using the Workers Cache API
+#[cfg(target_arch = "wasm32")]
+pub mod worker_cache {
+    use http::HeaderValue;
+    use http::header::CACHE_CONTROL;
+    use worker::{Cache, Headers, Response as WorkerResponse};
+
+    use super::*;
+
+    /// The upstream `Cache-Control` is kept here while the entry is stored,
+    /// since the Cache API takes the entry's lifetime from `Cache-Control`.
+    const UPSTREAM_CACHE_CONTROL: &str = "x-rssfilter-upstream-cache-control";
+
+    /// The upstream `ETag` is kept here while the entry is stored, and never
+    /// served.
+    const UPSTREAM_ETAG: &str = "x-rssfilter-upstream-etag";
+
+    /// A [`ResponseCache`] backed by the Workers Cache API, which is local to
+    /// each Cloudflare data centre.
+    pub struct WorkerResponseCache {
+        cache: Cache,
+    }
+
+    impl Default for WorkerResponseCache {
+        fn default() -> Self {
+            Self {
+                cache: Cache::default(),
+            }
+        }
+    }
+
+    #[async_trait(?Send)]
+    impl ResponseCache for WorkerResponseCache {
+        async fn get(&self, key: &str) -> Result<Option<CachedResponse>, HttpClientError> {
+            let Some(mut response) = self.cache.get(key, false).await? else {
+                return Ok(None);
+            };
+
+            let mut headers: HeaderMap = response.headers().into();
+            headers.remove(CACHE_CONTROL);
+            if let Some(cache_control) = headers.remove(UPSTREAM_CACHE_CONTROL) {
+                headers.insert(CACHE_CONTROL, cache_control);
+            }
+            let upstream_etag = headers.remove(UPSTREAM_ETAG);
+
+            Ok(Some(CachedResponse {
+                status: StatusCode::from_u16(response.status_code())
+                    .map_err(|err| HttpClientError::Cache(err.to_string()))?,
+                headers,
+                body: response.bytes().await?.into(),
+                upstream_etag,
+            }))
+        }
+
+        async fn put(
+            &self,
+            key: &str,
+            response: CachedResponse,
+            ttl: Duration,
+        ) -> Result<(), HttpClientError> {
+            let mut headers = response.headers;
+            if let Some(cache_control) = headers.remove(CACHE_CONTROL) {
+                headers.insert(UPSTREAM_CACHE_CONTROL, cache_control);
+            }
+            if let Some(etag) = response.upstream_etag {
+                headers.insert(UPSTREAM_ETAG, etag);
+            }
+            headers.insert(
+                CACHE_CONTROL,
+                HeaderValue::from_str(&format!("max-age={}", ttl.as_secs()))?,
+            );
+
+            let worker_response = WorkerResponse::from_bytes(response.body.to_vec())?
+                .with_status(response.status.as_u16())
+                .with_headers(Headers::from(&headers));
+
+            self.cache.put(key, worker_response).await?;
+
+            Ok(())
+        }
+    }
+}
+
+/// Creates the response cache for the platform: the Workers Cache API on
+/// WASM, and a new in-memory cache otherwise.
+pub fn create_response_cache() -> Box<dyn ResponseCache> {
+    create_response_cache_with_config(&CacheConfig::default())
+}
+
+/// Like [`create_response_cache`], with the in-memory cache holding no more
+/// than `config` allows. Cloudflare manages the size of its own cache.
+#[cfg_attr(target_arch = "wasm32", allow(unused_variables))]
+pub fn create_response_cache_with_config(config: &CacheConfig) -> Box<dyn ResponseCache> {
+    #[cfg(target_arch = "wasm32")]
+    {
+        Box::new(worker_cache::WorkerResponseCache::default())
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        Box::new(InMemoryResponseCache::with_config(config))
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    const FINGERPRINT: [u8; 32] = [7; 32];
+
+    #[test_case("https://example.com/feed", "HTTPS://Example.COM:443/feed" ; "scheme, host and default port")]
+    #[test_case("https://example.com/feed", "https://example.com/feed#latest" ; "fragment")]
+    fn test_cache_key_normalises_url(a: &str, b: &str) {
+        assert_eq!(
+            cache_key("http-cache", a, &FINGERPRINT),
+            cache_key("http-cache", b, &FINGERPRINT)
+        );
+    }
+
+    #[test]
+    fn test_cache_key_distinguishes() {
+        let key = cache_key("http-cache", "https://example.com/feed", &FINGERPRINT);
+
+        assert!(key.starts_with("https://rssfilter.cache/http-cache/filtered/"));
+        assert_ne!(
+            key,
+            cache_key("http-cache", "https://example.com/other", &FINGERPRINT)
+        );
+        assert_ne!(
+            key,
+            cache_key(
+                "http-cache",
+                "https://example.com/feed?page=2",
+                &FINGERPRINT
+            )
+        );
+        assert_ne!(
+            key,
+            cache_key("http-cache", "https://example.com/feed", &[8; 32])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_cache() -> Result<(), HttpClientError> {
+        let cache = InMemoryResponseCache::new();
+        let response = CachedResponse {
+            status: StatusCode::OK,
+            headers: HeaderMap::new(),
+            body: Bytes::from_static(b"<rss/>"),
+            upstream_etag: None,
+        };
+
+        assert!(cache.get("key").await?.is_none());
+
+        cache
+            .put("key", response.clone(), Duration::from_secs(60))
+            .await?;
+        let cached = cache.get("key").await?.expect("entry should be cached");
+        assert_eq!(cached.body, response.body);
+
+        cache.put("expired", response, Duration::ZERO).await?;
+        assert!(cache.get("expired").await?.is_none());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_cache_is_bounded() -> Result<(), HttpClientError> {
+        let cache = InMemoryResponseCache::with_config(&CacheConfig {
+            max_entries: 2,
+            ..Default::default()
+        });
+        let response = CachedResponse {
+            status: StatusCode::OK,
+            headers: HeaderMap::new(),
+            body: Bytes::from_static(b"<rss/>"),
+            upstream_etag: None,
+        };
+        let ttl = Duration::from_secs(60);
+
+        cache.put("a", response.clone(), ttl).await?;
+        cache.put("b", response.clone(), ttl).await?;
+        cache.get("a").await?;
+        cache.put("c", response, ttl).await?;
+
+        assert!(cache.get("a").await?.is_some());
+        assert!(cache.get("b").await?.is_none());
+        assert!(cache.get("c").await?.is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cookies_are_not_cached() {
+        let response = HttpResponse::builder()
+            .header(SET_COOKIE, "session=first-client")
+            .header("content-type", "application/rss+xml")
+            .body(Bytes::from_static(b"<rss/>"))
+            .unwrap();
+
+        let cached = CachedResponse::from(&response);
+        assert!(!cached.headers.contains_key(SET_COOKIE));
+        assert!(cached.headers.contains_key("content-type"));
+
+        let mut stored = cached;
+        stored
+            .headers
+            .insert(SET_COOKIE, "session=stored".parse().unwrap());
+        assert!(
+            !HttpResponse::from(stored)
+                .headers()
+                .contains_key(SET_COOKIE)
+        );
+    }
+}
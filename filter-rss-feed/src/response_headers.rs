@@ -0,0 +1,153 @@
+use http::HeaderValue;
+use http::header::{
+    ACCEPT_RANGES, CONNECTION, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_RANGE, ETAG, HeaderMap,
+    HeaderName, PROXY_AUTHENTICATE, SET_COOKIE, TE, TRAILER, TRANSFER_ENCODING, UPGRADE,
+};
+use std::borrow::Borrow;
+use std::collections::HashSet;
+use std::sync::LazyLock;
+
+/// Hop-by-hop headers, which describe a single connection rather than the
+/// response, and must not be forwarded (RFC 9110 §7.6.1).
+static HOP_BY_HOP_HEADERS: LazyLock<HashSet<HeaderName>> = LazyLock::new(|| {
+    [
+        CONNECTION,
+        HeaderName::from_static("keep-alive"),
+        PROXY_AUTHENTICATE,
+        HeaderName::from_static("proxy-connection"),
+        TE,
+        TRAILER,
+        TRANSFER_ENCODING,
+        UPGRADE,
+    ]
+    .into_iter()
+    .collect()
+});
+
+/// Headers which describe the upstream body, and are wrong once we have
+/// removed items from it. The length and `ETag` are recomputed by the caller;
+/// the rest don't apply to our body at all. The body we have is always
+/// decoded, so `Content-Encoding` goes too.
+static REPRESENTATION_HEADERS: LazyLock<HashSet<HeaderName>> = LazyLock::new(|| {
+    [
+        ACCEPT_RANGES,
+        CONTENT_ENCODING,
+        CONTENT_LENGTH,
+        HeaderName::from_static("content-md5"),
+        CONTENT_RANGE,
+        HeaderName::from_static("content-digest"),
+        HeaderName::from_static("digest"),
+        ETAG,
+        HeaderName::from_static("repr-digest"),
+    ]
+    .into_iter()
+    .collect()
+});
+
+/// Headers which would act on our origin rather than the feed's. Filtered
+/// feeds are served from our own domain, so the upstream server's cookies
+/// would be set on it, for every client which fetches the feed.
+static ORIGIN_HEADERS: LazyLock<HashSet<HeaderName>> =
+    LazyLock::new(|| [SET_COOKIE].into_iter().collect());
+
+/// Headers named in `Connection` are hop-by-hop for this response too.
+fn connection_headers(headers: &HeaderMap) -> HashSet<HeaderName> {
+    headers
+        .get_all(CONNECTION)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|name| HeaderName::from_bytes(name.trim().as_bytes()).ok())
+        .collect()
+}
+
+/// Filters the upstream response's headers down to those which still
+/// describe the filtered body. The upstream response is decoded and has items
+/// removed, so its length, encoding, digests and validators no longer apply,
+/// and connection-specific headers and cookies are never forwarded. Callers
+/// should set `Content-Length` and `ETag` for the new body.
+pub fn filter_response_headers<I, K, V>(headers: I) -> HeaderMap
+where
+    I: IntoIterator<Item = (K, V)>,
+    K: Borrow<HeaderName>,
+    V: Borrow<HeaderValue>,
+{
+    let headers: HeaderMap = headers
+        .into_iter()
+        .map(|(key, value)| (key.borrow().clone(), value.borrow().clone()))
+        .collect();
+
+    let connection_headers = connection_headers(&headers);
+
+    headers
+        .iter()
+        .filter(|(key, _)| {
+            !HOP_BY_HOP_HEADERS.contains(*key)
+                && !REPRESENTATION_HEADERS.contains(*key)
+                && !ORIGIN_HEADERS.contains(*key)
+                && !connection_headers.contains(*key)
+        })
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect()
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use http::header::{CACHE_CONTROL, CONTENT_TYPE, LAST_MODIFIED, LINK};
+    use test_case::test_case;
+
+    fn header_map(headers: &[(&'static str, &'static str)]) -> HeaderMap {
+        headers
+            .iter()
+            .fold(HeaderMap::new(), |mut map, (key, value)| {
+                map.append(
+                    HeaderName::from_static(key),
+                    HeaderValue::from_static(value),
+                );
+                map
+            })
+    }
+
+    #[test_case(&[("content-type", "application/rss+xml")], &[("content-type", "application/rss+xml")] ; "content type is kept")]
+    #[test_case(&[("content-length", "1234"), ("content-encoding", "gzip"), ("content-md5", "Q2hlY2sgSW50ZWdyaXR5IQ==")], &[] ; "representation headers are stripped")]
+    #[test_case(&[("etag", "\"upstream\""), ("last-modified", "Sat, 17 Oct 2026 10:00:00 GMT")], &[("last-modified", "Sat, 17 Oct 2026 10:00:00 GMT")] ; "etag is stripped but last-modified is kept")]
+    #[test_case(&[("transfer-encoding", "chunked"), ("keep-alive", "timeout=5"), ("upgrade", "h2c")], &[] ; "hop-by-hop headers are stripped")]
+    #[test_case(&[("connection", "x-upstream-hop"), ("x-upstream-hop", "1"), ("x-other", "2")], &[("x-other", "2")] ; "headers named in connection are stripped")]
+    #[test_case(&[("accept-ranges", "bytes"), ("content-range", "bytes 0-99/1234")], &[] ; "ranges no longer apply")]
+    #[test_case(&[("set-cookie", "session=upstream"), ("x-other", "2")], &[("x-other", "2")] ; "cookies are stripped")]
+    fn test_filter_response_headers(
+        input: &[(&'static str, &'static str)],
+        expected: &[(&'static str, &'static str)],
+    ) {
+        assert_eq!(
+            filter_response_headers(&header_map(input)),
+            header_map(expected)
+        );
+    }
+
+    #[test]
+    fn test_repeated_headers_are_kept() {
+        let mut headers = HeaderMap::new();
+        headers.append(
+            LINK,
+            HeaderValue::from_static("<https://a.example>; rel=hub"),
+        );
+        headers.append(
+            LINK,
+            HeaderValue::from_static("<https://b.example>; rel=hub"),
+        );
+        headers.insert(CACHE_CONTROL, HeaderValue::from_static("max-age=300"));
+        headers.insert(CONTENT_LENGTH, HeaderValue::from_static("10"));
+        headers.insert(LAST_MODIFIED, HeaderValue::from_static("yesterday"));
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("text/xml"));
+
+        let filtered = filter_response_headers(&headers);
+
+        assert_eq!(filtered.get_all(LINK).iter().count(), 2);
+        assert!(filtered.contains_key(CACHE_CONTROL));
+        assert!(filtered.contains_key(LAST_MODIFIED));
+        assert!(filtered.contains_key(CONTENT_TYPE));
+        assert!(!filtered.contains_key(CONTENT_LENGTH));
+    }
+}
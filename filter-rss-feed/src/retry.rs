@@ -0,0 +1,394 @@
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::pin::pin;
+use std::time::{Duration, SystemTime};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures_util::future::{Either, select};
+use headers::{Date, Header};
+use http::header::RETRY_AFTER;
+use http::{Method, Request as HttpRequest, Response as HttpResponse, StatusCode};
+use tower::Layer;
+use tracing::{debug, warn};
+
+use crate::cache_policy::now;
+use crate::http_client::{HttpClient, HttpClientError};
+
+/// How requests to upstream servers are timed out and retried.
+///
+/// Only `GET` and `HEAD` requests are retried, after connection errors,
+/// timeouts, and `502`, `503` and `504` responses.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetryConfig {
+    /// Most attempts to make, including the first. `1` disables retries.
+    /// Default is 3
+    pub max_attempts: u32,
+    /// Time allowed for each attempt, including reading the body. Default is
+    /// 15 seconds
+    pub timeout: Duration,
+    /// Backoff before the first retry, doubling for each one after. The
+    /// actual wait is jittered between half and all of it. Default is 250ms
+    pub initial_backoff: Duration,
+    /// Longest backoff between attempts. Default is 5 seconds
+    pub max_backoff: Duration,
+    /// Longest `Retry-After` we will wait for. Responses asking for longer
+    /// are returned as they are. Default is 10 seconds
+    pub max_retry_after: Duration,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            timeout: Duration::from_secs(15),
+            initial_backoff: Duration::from_millis(250),
+            max_backoff: Duration::from_secs(5),
+            max_retry_after: Duration::from_secs(10),
+        }
+    }
+}
+
+impl RetryConfig {
+    /// The backoff before retry number `retry`, counting from 1.
+    fn backoff(&self, retry: u32) -> Duration {
+        let exponential = self
+            .initial_backoff
+            .saturating_mul(2u32.saturating_pow(retry.saturating_sub(1)))
+            .min(self.max_backoff);
+
+        exponential / 2 + jitter(exponential / 2)
+    }
+}
+
+/// A random duration up to `max`. This doesn't need to be unpredictable, only
+/// different between clients, so that they don't all retry in step.
+fn jitter(max: Duration) -> Duration {
+    let random = RandomState::new().hash_one(now());
+
+    max.mul_f64(random as f64 / u64::MAX as f64)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) async fn sleep(duration: Duration) {
+    tokio::time::sleep(duration).await;
+}
+
+#[cfg(target_arch = "wasm32")]
+pub(crate) async fn sleep(duration: Duration) {
+    worker::Delay::from(duration).await;
+}
+
+/// Whether an error could go away by itself: the server couldn't be reached,
+/// or took too long. Anything else would only fail again.
+fn is_transient_error(err: &HttpClientError) -> bool {
+    match err {
+        HttpClientError::Timeout(_) | HttpClientError::Connect(_) => true,
+        #[cfg(not(target_arch = "wasm32"))]
+        HttpClientError::Reqwest(err) => err.is_connect() || err.is_timeout(),
+        _ => false,
+    }
+}
+
+fn is_transient_status(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+/// How long a response asks us to wait before retrying, either as a number
+/// of seconds or an HTTP date.
+fn retry_after(response: &HttpResponse<Bytes>) -> Option<Duration> {
+    let value = response.headers().get(RETRY_AFTER)?;
+
+    if let Some(seconds) = value.to_str().ok().and_then(|s| s.trim().parse().ok()) {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = Date::decode(&mut std::iter::once(value)).ok()?;
+    Some(
+        SystemTime::from(date)
+            .duration_since(now())
+            .unwrap_or_default(),
+    )
+}
+
+/// Copies a request so that it can be sent again. Only bodiless requests are
+/// retried, but the body is cheap to copy anyway.
+pub(crate) fn copy_request(request: &HttpRequest<Bytes>) -> HttpRequest<Bytes> {
+    let mut copy = HttpRequest::new(request.body().clone());
+    *copy.method_mut() = request.method().clone();
+    *copy.uri_mut() = request.uri().clone();
+    *copy.version_mut() = request.version();
+    *copy.headers_mut() = request.headers().clone();
+    *copy.extensions_mut() = request.extensions().clone();
+    copy
+}
+
+async fn send_with_timeout<C: HttpClient + ?Sized>(
+    client: &C,
+    request: HttpRequest<Bytes>,
+    timeout: Duration,
+) -> Result<HttpResponse<Bytes>, HttpClientError> {
+    match select(pin!(client.send(request)), pin!(sleep(timeout))).await {
+        Either::Left((result, _)) => result,
+        Either::Right(((), _)) => Err(HttpClientError::Timeout(timeout)),
+    }
+}
+
+/// Sends a request, timing out each attempt and retrying as `config` says.
+pub(crate) async fn send_with_retry<C: HttpClient + ?Sized>(
+    client: &C,
+    request: HttpRequest<Bytes>,
+    config: &RetryConfig,
+) -> Result<HttpResponse<Bytes>, HttpClientError> {
+    let retryable = matches!(*request.method(), Method::GET | Method::HEAD);
+    let max_attempts = if retryable {
+        config.max_attempts.max(1)
+    } else {
+        1
+    };
+
+    let mut attempt = 1;
+    loop {
+        let result = send_with_timeout(client, copy_request(&request), config.timeout).await;
+
+        if attempt >= max_attempts {
+            return result;
+        }
+
+        let delay = match &result {
+            Ok(response) if is_transient_status(response.status()) => match retry_after(response) {
+                Some(delay) if delay > config.max_retry_after => {
+                    debug!(?delay, "Upstream asked us to retry later than we will wait");
+                    return result;
+                }
+                Some(delay) => delay,
+                None => config.backoff(attempt),
+            },
+            Err(err) if is_transient_error(err) => config.backoff(attempt),
+            _ => return result,
+        };
+
+        warn!(
+            uri = %request.uri(),
+            attempt,
+            ?delay,
+            status = result.as_ref().ok().map(|response| response.status().as_u16()),
+            error = result.as_ref().err().map(ToString::to_string),
+            "Upstream request failed, retrying"
+        );
+
+        sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+/// Wraps any [`HttpClient`], timing out requests and retrying them after
+/// transient failures as [`RetryConfig`] says.
+pub struct RetryingHttpClient<C> {
+    inner: C,
+    config: RetryConfig,
+}
+
+impl<C> RetryingHttpClient<C> {
+    pub fn new(inner: C, config: RetryConfig) -> Self {
+        Self { inner, config }
+    }
+}
+
+/// A [`Layer`] wrapping clients in a [`RetryingHttpClient`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RetryLayer {
+    config: RetryConfig,
+}
+
+impl RetryLayer {
+    pub fn new(config: RetryConfig) -> Self {
+        Self { config }
+    }
+}
+
+impl<C> Layer<C> for RetryLayer {
+    type Service = RetryingHttpClient<C>;
+
+    fn layer(&self, inner: C) -> Self::Service {
+        RetryingHttpClient::new(inner, self.config)
+    }
+}
+
+#[async_trait]
+#[cfg(not(target_arch = "wasm32"))]
+impl<C: HttpClient> HttpClient for RetryingHttpClient<C> {
+    async fn send(
+        &self,
+        request: HttpRequest<Bytes>,
+    ) -> Result<HttpResponse<Bytes>, HttpClientError> {
+        send_with_retry(&self.inner, request, &self.config).await
+    }
+}
+
+#[async_trait(?Send)]
+#[cfg(target_arch = "wasm32")]
+impl<C: HttpClient> HttpClient for RetryingHttpClient<C> {
+    async fn send(
+        &self,
+        request: HttpRequest<Bytes>,
+    ) -> Result<HttpResponse<Bytes>, HttpClientError> {
+        send_with_retry(&self.inner, request, &self.config).await
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use crate::fake_http_client::{FakeHttpClient, FakeHttpClientBuilder, FakeHttpError};
+    use crate::fake_http_client::{FakeResponse, FakeResponseBuilder};
+    use test_case::test_case;
+
+    const URL: &str = "https://example.com/feed";
+
+    fn config() -> RetryConfig {
+        RetryConfig {
+            timeout: Duration::from_millis(200),
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(4),
+            ..Default::default()
+        }
+    }
+
+    fn request(method: Method) -> HttpRequest<Bytes> {
+        HttpRequest::builder()
+            .method(method)
+            .uri(URL)
+            .body(Bytes::new())
+            .unwrap()
+    }
+
+    fn status(status: StatusCode) -> Result<FakeResponse, FakeHttpError> {
+        Ok(FakeResponse::new(status, ""))
+    }
+
+    fn network_error() -> Result<FakeResponse, FakeHttpError> {
+        Err(FakeHttpError::Network {
+            message: "connection refused".to_string(),
+        })
+    }
+
+    fn retrying(fake: &mut FakeHttpClientBuilder) -> RetryingHttpClient<FakeHttpClient> {
+        RetryingHttpClient::new(fake.build().unwrap(), config())
+    }
+
+    #[test_case(vec![status(StatusCode::BAD_GATEWAY), status(StatusCode::OK)], StatusCode::OK, 2 ; "502 then ok")]
+    #[test_case(vec![status(StatusCode::SERVICE_UNAVAILABLE), status(StatusCode::GATEWAY_TIMEOUT), status(StatusCode::OK)], StatusCode::OK, 3 ; "503 and 504 then ok")]
+    #[test_case(vec![network_error(), status(StatusCode::OK)], StatusCode::OK, 2 ; "connection error then ok")]
+    #[test_case(vec![status(StatusCode::SERVICE_UNAVAILABLE)], StatusCode::SERVICE_UNAVAILABLE, 3 ; "gives up after max attempts")]
+    #[test_case(vec![status(StatusCode::INTERNAL_SERVER_ERROR)], StatusCode::INTERNAL_SERVER_ERROR, 1 ; "500 is not retried")]
+    #[test_case(vec![status(StatusCode::NOT_FOUND)], StatusCode::NOT_FOUND, 1 ; "404 is not retried")]
+    #[tokio::test]
+    async fn test_retry(
+        script: Vec<Result<FakeResponse, FakeHttpError>>,
+        expected: StatusCode,
+        attempts: usize,
+    ) {
+        let client = retrying(FakeHttpClientBuilder::default().with_script(URL, script));
+
+        let response = client.send(request(Method::GET)).await.unwrap();
+
+        assert_eq!(response.status(), expected);
+        assert_eq!(client.inner.requests(URL), attempts);
+    }
+
+    #[tokio::test]
+    async fn test_retry_exhausted_error() {
+        let client =
+            retrying(FakeHttpClientBuilder::default().with_script(URL, vec![network_error()]));
+
+        let result = client.send(request(Method::GET)).await;
+
+        assert!(matches!(result, Err(HttpClientError::Connect(_))));
+        assert_eq!(client.inner.requests(URL), 3);
+    }
+
+    #[tokio::test]
+    async fn test_other_errors_are_not_retried() {
+        let client = retrying(
+            FakeHttpClientBuilder::default()
+                .with_script(URL, vec![Err(FakeHttpError::InvalidContentType)]),
+        );
+
+        let result = client.send(request(Method::GET)).await;
+
+        assert!(matches!(result, Err(HttpClientError::Request(_))));
+        assert_eq!(client.inner.requests(URL), 1);
+    }
+
+    #[tokio::test]
+    async fn test_only_get_and_head_are_retried() {
+        let client = retrying(
+            FakeHttpClientBuilder::default()
+                .with_script(URL, vec![status(StatusCode::BAD_GATEWAY)]),
+        );
+
+        let response = client.send(request(Method::POST)).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+        assert_eq!(client.inner.requests(URL), 1);
+    }
+
+    #[tokio::test]
+    async fn test_timeout() {
+        let client = retrying(
+            FakeHttpClientBuilder::default()
+                .with_rss_response(URL, "<rss/>")
+                .with_delay(URL, Duration::from_secs(10)),
+        );
+
+        let result = client.send(request(Method::GET)).await;
+
+        assert!(matches!(
+            result,
+            Err(HttpClientError::Timeout(timeout)) if timeout == config().timeout
+        ));
+        assert_eq!(client.inner.requests(URL), 3);
+    }
+
+    #[test_case("0", 2 ; "retry after seconds")]
+    #[test_case("3600", 1 ; "retry after too long")]
+    #[test_case("Thu, 01 Jan 1970 00:00:00 GMT", 2 ; "retry after a date in the past")]
+    #[tokio::test]
+    async fn test_retry_after(retry_after: &str, attempts: usize) {
+        let unavailable = FakeResponseBuilder::default()
+            .with_status(StatusCode::SERVICE_UNAVAILABLE)
+            .with_header("retry-after", retry_after)
+            .with_body(Bytes::new())
+            .build()
+            .unwrap();
+        let client = retrying(
+            FakeHttpClientBuilder::default()
+                .with_script(URL, vec![Ok(unavailable), status(StatusCode::OK)]),
+        );
+
+        client.send(request(Method::GET)).await.unwrap();
+
+        assert_eq!(client.inner.requests(URL), attempts);
+    }
+
+    #[test_case(1, 2 ; "first retry")]
+    #[test_case(3, 8 ; "doubles")]
+    #[test_case(10, 10 ; "capped")]
+    fn test_backoff(retry: u32, exponential_ms: u64) {
+        let config = RetryConfig {
+            initial_backoff: Duration::from_millis(2),
+            max_backoff: Duration::from_millis(10),
+            ..Default::default()
+        };
+        let exponential = Duration::from_millis(exponential_ms);
+
+        let backoff = config.backoff(retry);
+
+        assert!(backoff >= exponential / 2);
+        assert!(backoff <= exponential);
+    }
+}
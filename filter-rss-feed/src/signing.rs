@@ -0,0 +1,204 @@
+use std::fmt;
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use thiserror::Error;
+use url::Url;
+
+use crate::params::FEED_PARAMS;
+
+/// The query parameter a filtered feed's signature is given in.
+pub const SIGNATURE_PARAM: &str = "sig";
+
+type HmacSha256 = Hmac<Sha256>;
+
+#[derive(Clone, Debug, Error, PartialEq, Eq)]
+pub enum SignatureError {
+    #[error("The URL must be signed: the sig parameter is missing")]
+    Missing,
+
+    #[error("The URL's signature doesn't match its parameters")]
+    Invalid,
+}
+
+/// Signs filtered feeds' URLs, and checks their signatures, so that a URL
+/// which has been shared can't be changed to fetch or filter something else.
+///
+/// The signature is an HMAC-SHA256 of the [`FEED_PARAMS`], in hex. They're
+/// sorted first, so it doesn't matter what order they're in, and other
+/// parameters aren't covered.
+#[derive(Clone)]
+pub struct UrlSigner {
+    key: Vec<u8>,
+}
+
+// The key is secret, so it's kept out of logs
+impl fmt::Debug for UrlSigner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UrlSigner").finish_non_exhaustive()
+    }
+}
+
+impl UrlSigner {
+    pub fn new(key: impl Into<Vec<u8>>) -> Self {
+        Self { key: key.into() }
+    }
+
+    /// The HMAC of `url`'s signed parameters, ready to be finalised or
+    /// verified.
+    fn mac(&self, url: &Url) -> HmacSha256 {
+        let mut pairs: Vec<_> = url
+            .query_pairs()
+            .filter(|(name, _)| FEED_PARAMS.contains(&name.as_ref()))
+            .collect();
+        pairs.sort();
+
+        let message = url::form_urlencoded::Serializer::new(String::new())
+            .extend_pairs(pairs)
+            .finish();
+
+        let mut mac = HmacSha256::new_from_slice(&self.key).expect("HMAC takes keys of any size");
+        mac.update(message.as_bytes());
+
+        mac
+    }
+
+    /// `url` with a `sig` parameter signing it, in place of any it had.
+    pub fn sign(&self, url: &Url) -> Url {
+        let signature = hex::encode(self.mac(url).finalize().into_bytes());
+
+        let pairs: Vec<_> = url
+            .query_pairs()
+            .filter(|(name, _)| name != SIGNATURE_PARAM)
+            .map(|(name, value)| (name.into_owned(), value.into_owned()))
+            .collect();
+
+        let mut signed = url.clone();
+        signed
+            .query_pairs_mut()
+            .clear()
+            .extend_pairs(pairs)
+            .append_pair(SIGNATURE_PARAM, &signature);
+
+        signed
+    }
+
+    /// Checks that `url` has a `sig` parameter which signs it.
+    pub fn verify(&self, url: &Url) -> Result<(), SignatureError> {
+        let signature = url
+            .query_pairs()
+            .find_map(|(name, value)| (name == SIGNATURE_PARAM).then_some(value))
+            .ok_or(SignatureError::Missing)?;
+        let signature = hex::decode(signature.as_bytes()).map_err(|_| SignatureError::Invalid)?;
+
+        self.mac(url)
+            .verify_slice(&signature)
+            .map_err(|_| SignatureError::Invalid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    fn signer() -> UrlSigner {
+        UrlSigner::new("shared secret")
+    }
+
+    fn url(query: &str) -> Url {
+        Url::parse(&format!("https://rssfilter.example.com/?{query}")).unwrap()
+    }
+
+    #[test]
+    fn test_signature() {
+        // The HMAC-SHA256 of `title_filter_regex=x&url=a`, so URLs signed
+        // before stay valid
+        let signed = signer().sign(&url("title_filter_regex=x&url=a"));
+
+        assert_eq!(
+            signed
+                .query_pairs()
+                .find(|(name, _)| name == "sig")
+                .unwrap()
+                .1,
+            "1da64cd85f2d7159fc45494cdea44e25ee691eaa23d2b10571e6ac8daef208c9"
+        );
+    }
+
+    #[test]
+    fn test_sign_and_verify() {
+        let signed = signer().sign(&url(
+            "url=https%3A%2F%2Fexample.com%2Ffeed&title_filter_regex=%5EAd%3A",
+        ));
+
+        assert!(signed.query().unwrap().contains("&sig="));
+        assert_eq!(signer().verify(&signed), Ok(()));
+        assert_eq!(
+            UrlSigner::new("other secret").verify(&signed),
+            Err(SignatureError::Invalid)
+        );
+    }
+
+    #[test]
+    fn test_resign() {
+        let signed = signer().sign(&url("url=a&sig=00"));
+
+        assert_eq!(
+            signed
+                .query_pairs()
+                .filter(|(name, _)| name == "sig")
+                .count(),
+            1
+        );
+        assert_eq!(signer().verify(&signed), Ok(()));
+    }
+
+    #[test_case("url=b&title_filter_regex=x" ; "other feed")]
+    #[test_case("url=a&title_filter_regex=y" ; "other filter")]
+    #[test_case("url=a&title_filter_regex=x&link_filter_regex=z" ; "added filter")]
+    #[test_case("url=a" ; "removed filter")]
+    #[test_case("url=a&guid_filter_regex=x" ; "moved filter")]
+    fn test_tampered(query: &str) {
+        let signed = signer().sign(&url("url=a&title_filter_regex=x"));
+        let sig = signed
+            .query_pairs()
+            .find(|(name, _)| name == "sig")
+            .unwrap()
+            .1
+            .into_owned();
+
+        assert_eq!(
+            signer().verify(&url(&format!("{query}&sig={sig}"))),
+            Err(SignatureError::Invalid)
+        );
+    }
+
+    #[test]
+    fn test_order_and_other_params_ignored() {
+        let signed = signer().sign(&url("url=a&title_filter_regex=x&title_filter_regex=y"));
+        let sig = signed
+            .query_pairs()
+            .find(|(name, _)| name == "sig")
+            .unwrap()
+            .1
+            .into_owned();
+
+        let reordered = url(&format!(
+            "sig={sig}&title_filter_regex=y&utm_source=feed&url=a&title_filter_regex=x"
+        ));
+        assert_eq!(signer().verify(&reordered), Ok(()));
+    }
+
+    #[test_case("url=a" => Err(SignatureError::Missing); "missing")]
+    #[test_case("url=a&sig=zz" => Err(SignatureError::Invalid); "not hex")]
+    #[test_case("url=a&sig=00" => Err(SignatureError::Invalid); "short")]
+    fn test_bad_signature(query: &str) -> Result<(), SignatureError> {
+        signer().verify(&url(query))
+    }
+
+    #[test]
+    fn test_debug_hides_key() {
+        assert!(!format!("{:?}", signer()).contains("shared secret"));
+    }
+}
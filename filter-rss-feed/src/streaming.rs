@@ -0,0 +1,715 @@
+use std::borrow::Cow;
+use std::time::Duration;
+
+use bytes::Bytes;
+
+use quick_xml::encoding::EncodingError;
+use quick_xml::escape::{escape, resolve_predefined_entity};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::{Reader, Writer};
+use rss::Item;
+
+use crate::{FeedLimits, RssError};
+
+impl From<quick_xml::Error> for RssError {
+    fn from(err: quick_xml::Error) -> Self {
+        RssError::RSSParse(err.into())
+    }
+}
+
+impl From<EncodingError> for RssError {
+    fn from(err: EncodingError) -> Self {
+        RssError::RSSParse(err.into())
+    }
+}
+
+/// The parts of an item which filters are matched against.
+///
+/// These are borrowed from an [`rss::Item`] when we have parsed the whole
+/// channel, or owned when they have been pulled out of the document as we
+/// stream through it.
+#[derive(Debug, Default)]
+pub(crate) struct ItemFields<'a> {
+    pub title: Option<Cow<'a, str>>,
+    pub guid: Option<Cow<'a, str>>,
+    pub link: Option<Cow<'a, str>>,
+}
+
+impl ItemFields<'_> {
+    pub fn title(&self) -> Option<&str> {
+        self.title.as_deref()
+    }
+
+    pub fn guid(&self) -> Option<&str> {
+        self.guid.as_deref()
+    }
+
+    pub fn link(&self) -> Option<&str> {
+        self.link.as_deref()
+    }
+}
+
+impl<'a> From<&'a Item> for ItemFields<'a> {
+    fn from(item: &'a Item) -> Self {
+        Self {
+            title: item.title().map(Cow::Borrowed),
+            guid: item.guid().map(|guid| Cow::Borrowed(guid.value())),
+            link: item.link().map(Cow::Borrowed),
+        }
+    }
+}
+
+/// A filtered feed, and how many items it had before and after filtering.
+#[derive(Debug)]
+pub(crate) struct FilteredFeed {
+    pub body: Bytes,
+    pub n_items: usize,
+    pub n_removed: usize,
+    pub update_hints: UpdateHints,
+}
+
+/// What the feed says about how often it changes: RSS 2.0's `<ttl>`, and the
+/// RSS 1.0 syndication module's `<sy:updatePeriod>` and
+/// `<sy:updateFrequency>`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct UpdateHints {
+    pub ttl_minutes: Option<u64>,
+    pub update_period: Option<String>,
+    pub update_frequency: Option<u64>,
+}
+
+/// Longest interval a feed is taken at its word for: a year, its longest
+/// `sy:updatePeriod`.
+const MAX_UPDATE_INTERVAL_SECS: u64 = 365 * 24 * 60 * 60;
+
+impl UpdateHints {
+    /// How long the feed can be cached for, if it says, up to a year.
+    pub fn interval(&self) -> Option<Duration> {
+        if let Some(minutes) = self.ttl_minutes {
+            let secs = minutes.saturating_mul(60).min(MAX_UPDATE_INTERVAL_SECS);
+            return Some(Duration::from_secs(secs));
+        }
+
+        let period = match self.update_period.as_deref()? {
+            "hourly" => 60 * 60,
+            "daily" => 24 * 60 * 60,
+            "weekly" => 7 * 24 * 60 * 60,
+            "monthly" => 30 * 24 * 60 * 60,
+            "yearly" => MAX_UPDATE_INTERVAL_SECS,
+            _ => return None,
+        };
+
+        // The feed is updated `updateFrequency` times per `updatePeriod`
+        let frequency = self.update_frequency.unwrap_or(1).max(1);
+
+        Some(Duration::from_secs(period / frequency))
+    }
+
+    fn record(&mut self, name: &[u8], value: &str) {
+        let value = value.trim();
+
+        match name {
+            b"ttl" => self.ttl_minutes = value.parse().ok(),
+            b"sy:updatePeriod" => self.update_period = Some(value.to_ascii_lowercase()),
+            b"sy:updateFrequency" => self.update_frequency = value.parse().ok(),
+            _ => {}
+        }
+    }
+}
+
+/// Changes made to the channel itself as the document is streamed through.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct ChannelRewrites<'a> {
+    /// Replaces the `href` of the channel's `atom:link rel="self"`.
+    pub self_link: Option<&'a str>,
+    /// Appended to the channel's `<title>`, after a space.
+    pub title_annotation: Option<&'a str>,
+}
+
+/// Whether an element is an Atom `<link rel="self">`. Namespaces aren't
+/// resolved, so any prefixed `link` will do: RSS's own `<link>` has none.
+fn is_self_link(element: &BytesStart<'_>) -> bool {
+    let name = element.name();
+
+    name.prefix().is_some()
+        && name.local_name().as_ref() == b"link"
+        && element
+            .attributes()
+            .flatten()
+            .any(|attribute| attribute.key.as_ref() == b"rel" && &*attribute.value == b"self")
+}
+
+/// Writes `element` back out with its `href` replaced.
+fn rewrite_href(element: &BytesStart<'_>, href: &str, empty: bool) -> Result<Vec<u8>, RssError> {
+    let name = String::from_utf8_lossy(element.name().as_ref()).into_owned();
+    let mut rewritten = BytesStart::new(name);
+
+    for attribute in element.attributes() {
+        let attribute = attribute.map_err(quick_xml::Error::from)?;
+
+        if attribute.key.as_ref() == b"href" {
+            rewritten.push_attribute(("href", href));
+        } else {
+            rewritten.push_attribute(attribute);
+        }
+    }
+
+    let mut writer = Writer::new(Vec::new());
+    writer.write_event(if empty {
+        Event::Empty(rewritten)
+    } else {
+        Event::Start(rewritten)
+    })?;
+
+    Ok(writer.into_inner())
+}
+
+#[derive(Clone, Copy)]
+enum ItemField {
+    Title,
+    Guid,
+    Link,
+}
+
+impl ItemField {
+    fn from_name(name: &[u8]) -> Option<Self> {
+        match name {
+            b"title" => Some(Self::Title),
+            b"guid" => Some(Self::Guid),
+            b"link" => Some(Self::Link),
+            _ => None,
+        }
+    }
+}
+
+fn check_depth(depth: usize, limits: &FeedLimits) -> Result<(), RssError> {
+    if depth > limits.max_depth {
+        return Err(RssError::NestingTooDeep {
+            max_depth: limits.max_depth,
+        });
+    }
+
+    Ok(())
+}
+
+/// Reads the contents of an `<item>`, up to and including its end tag, and
+/// collects the fields we filter on. Only direct children are considered, the
+/// same as `rss::Item` does. `item_depth` is the depth of the `<item>` itself.
+fn read_item<'a>(
+    reader: &mut Reader<&'a [u8]>,
+    item_depth: usize,
+    limits: &FeedLimits,
+) -> Result<ItemFields<'a>, RssError> {
+    let mut fields = ItemFields::default();
+    let mut depth = 0usize;
+    let mut current = None;
+    let mut text = String::new();
+
+    loop {
+        match reader.read_event()? {
+            Event::Start(element) => {
+                if depth == 0 {
+                    current = ItemField::from_name(element.name().as_ref());
+                    text.clear();
+                }
+                depth += 1;
+                check_depth(item_depth + depth, limits)?;
+            }
+            Event::End(_) if depth == 0 => return Ok(fields),
+            Event::End(_) => {
+                depth -= 1;
+
+                if depth > 0 {
+                    continue;
+                }
+
+                let value = Some(text.trim().to_owned())
+                    .filter(|v| !v.is_empty())
+                    .map(Cow::Owned);
+
+                match current.take() {
+                    Some(ItemField::Title) => fields.title = value,
+                    Some(ItemField::Guid) => fields.guid = value,
+                    Some(ItemField::Link) => fields.link = value,
+                    None => {}
+                }
+            }
+            Event::Text(element) if depth == 1 && current.is_some() => {
+                text.push_str(&element.decode()?);
+            }
+            Event::CData(element) if depth == 1 && current.is_some() => {
+                text.push_str(&element.decode()?);
+            }
+            Event::GeneralRef(gref) if depth == 1 && current.is_some() => {
+                let entity = gref.decode()?;
+
+                if let Some(resolved) = resolve_predefined_entity(&entity) {
+                    text.push_str(resolved);
+                } else if let Some(ch) = gref.resolve_char_ref()? {
+                    text.push(ch);
+                } else {
+                    text.push('&');
+                    text.push_str(&entity);
+                    text.push(';');
+                }
+            }
+            Event::Eof => return Err(rss::Error::Eof.into()),
+            _ => {}
+        }
+    }
+}
+
+/// Returns the end of `input[from..to]` once any trailing whitespace is
+/// dropped. Used so that removing an item also removes the indentation in
+/// front of it, rather than leaving blank lines behind.
+fn trim_trailing_whitespace(input: &[u8], from: usize, to: usize) -> usize {
+    input[from..to]
+        .iter()
+        .rposition(|b| !b.is_ascii_whitespace())
+        .map_or(from, |pos| from + pos + 1)
+}
+
+/// Streams through an RSS document with a pull parser, removing each
+/// `<item>` for which `remove` returns `true` and applying `rewrites` to the
+/// channel.
+///
+/// Only the bytes making up removed items and rewritten elements are
+/// changed; everything else, including namespaces and extension elements
+/// which `rss::Channel` does not model, is copied through untouched. Only one
+/// item's fields are held at a time, and if nothing is changed the input is
+/// returned without copying.
+///
+/// The item count and nesting depth in `limits` are enforced as we go.
+pub(crate) fn filter_document<F>(
+    input: &Bytes,
+    limits: &FeedLimits,
+    rewrites: &ChannelRewrites<'_>,
+    mut remove: F,
+) -> Result<FilteredFeed, RssError>
+where
+    F: FnMut(&ItemFields<'_>) -> bool,
+{
+    let mut reader = Reader::from_reader(&input[..]);
+
+    let mut body = Vec::new();
+    let mut edited = false;
+    let mut copied_to = 0;
+    let mut depth = 0;
+    let mut seen_root = false;
+    let mut seen_channel = false;
+    let mut channel_depth = None;
+    let mut update_hints = UpdateHints::default();
+    let mut n_items = 0;
+    let mut n_removed = 0;
+
+    loop {
+        let event_start = reader.buffer_position() as usize;
+
+        match reader.read_event()? {
+            Event::Start(element) => {
+                depth += 1;
+                check_depth(depth, limits)?;
+
+                match element.name().as_ref() {
+                    b"rss" | b"rdf:RDF" if !seen_root => seen_root = true,
+                    _ if !seen_root => return Err(rss::Error::InvalidStartTag.into()),
+                    b"channel" => {
+                        seen_channel = true;
+                        channel_depth = Some(depth);
+                    }
+                    name @ (b"ttl" | b"sy:updatePeriod" | b"sy:updateFrequency")
+                        if channel_depth.is_some_and(|channel| depth == channel + 1) =>
+                    {
+                        let text = reader.read_text(element.name())?;
+                        update_hints.record(name, &text.decode()?);
+                        depth -= 1;
+                    }
+                    b"title"
+                        if rewrites.title_annotation.is_some()
+                            && channel_depth.is_some_and(|channel| depth == channel + 1) =>
+                    {
+                        let open_end = reader.buffer_position() as usize;
+                        reader.read_to_end(element.name())?;
+                        depth -= 1;
+
+                        // Insert the annotation just before `</title>`
+                        let event_end = reader.buffer_position() as usize;
+                        let close = input[event_start..event_end]
+                            .windows(2)
+                            .rposition(|window| window == b"</")
+                            .map_or(event_end, |pos| event_start + pos);
+
+                        let annotation = rewrites.title_annotation.unwrap_or_default();
+                        edited = true;
+                        body.extend_from_slice(&input[copied_to..close]);
+                        // An empty title becomes just the annotation
+                        if !input[open_end..close].iter().all(u8::is_ascii_whitespace) {
+                            body.push(b' ');
+                        }
+                        body.extend_from_slice(escape(annotation).as_bytes());
+                        copied_to = close;
+                    }
+                    b"item" => {
+                        n_items += 1;
+
+                        if n_items > limits.max_items {
+                            return Err(RssError::TooManyItems {
+                                max_items: limits.max_items,
+                            });
+                        }
+
+                        let fields = read_item(&mut reader, depth, limits)?;
+                        let event_end = reader.buffer_position() as usize;
+
+                        depth -= 1;
+
+                        if remove(&fields) {
+                            if !edited {
+                                body.reserve(input.len());
+                                edited = true;
+                            }
+
+                            let keep_until =
+                                trim_trailing_whitespace(input, copied_to, event_start);
+                            body.extend_from_slice(&input[copied_to..keep_until]);
+                            copied_to = event_end;
+                            n_removed += 1;
+                        }
+                    }
+                    _ if channel_depth.is_some_and(|channel| depth == channel + 1)
+                        && is_self_link(&element) =>
+                    {
+                        if let Some(self_link) = rewrites.self_link {
+                            let rewritten = rewrite_href(&element, self_link, false)?;
+                            edited = true;
+                            body.extend_from_slice(&input[copied_to..event_start]);
+                            body.extend_from_slice(&rewritten);
+                            copied_to = reader.buffer_position() as usize;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Event::Empty(element) if channel_depth == Some(depth) && is_self_link(&element) => {
+                if let Some(self_link) = rewrites.self_link {
+                    let rewritten = rewrite_href(&element, self_link, true)?;
+                    edited = true;
+                    body.extend_from_slice(&input[copied_to..event_start]);
+                    body.extend_from_slice(&rewritten);
+                    copied_to = reader.buffer_position() as usize;
+                }
+            }
+            Event::End(_) => {
+                if channel_depth == Some(depth) {
+                    channel_depth = None;
+                }
+                depth -= 1;
+            }
+            Event::Empty(_) if !seen_root => return Err(rss::Error::InvalidStartTag.into()),
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    if !seen_channel {
+        return Err(rss::Error::Eof.into());
+    }
+
+    let body = if !edited {
+        input.clone()
+    } else {
+        body.extend_from_slice(&input[copied_to..]);
+        Bytes::from(body)
+    };
+
+    Ok(FilteredFeed {
+        body,
+        n_items,
+        n_removed,
+        update_hints,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use matches::assert_matches;
+    use test_case::test_case;
+
+    const PODCAST_FEED: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0" xmlns:itunes="http://www.itunes.com/dtds/podcast-1.0.dtd" xmlns:podcast="https://podcastindex.org/namespace/1.0">
+  <channel>
+    <title>Example Podcast</title>
+    <link>https://example.com/</link>
+    <itunes:author>Someone</itunes:author>
+    <podcast:locked owner="someone@example.com">yes</podcast:locked>
+    <item>
+      <title>Episode 1</title>
+      <guid isPermaLink="false">ep-1</guid>
+      <itunes:duration>01:02:03</itunes:duration>
+    </item>
+    <item>
+      <title><![CDATA[Episode 2 & friends]]></title>
+      <link>https://example.com/ep2</link>
+      <podcast:transcript url="https://example.com/ep2.vtt" type="text/vtt"/>
+    </item>
+  </channel>
+</rss>
+"#;
+
+    fn titles(feed: &FilteredFeed) -> Vec<String> {
+        let channel = rss::Channel::read_from(&feed.body[..]).expect("output should parse");
+        channel
+            .items()
+            .iter()
+            .filter_map(|item| item.title().map(str::to_owned))
+            .collect()
+    }
+
+    #[test]
+    fn test_nothing_removed_is_byte_for_byte() {
+        let feed = filter_document(
+            &Bytes::from_static(PODCAST_FEED.as_bytes()),
+            &FeedLimits::default(),
+            &ChannelRewrites::default(),
+            |_| false,
+        )
+        .unwrap();
+
+        assert_eq!(feed.body, PODCAST_FEED.as_bytes());
+        assert_eq!(
+            feed.body.as_ptr(),
+            PODCAST_FEED.as_ptr(),
+            "body should not be copied"
+        );
+        assert_eq!(feed.n_items, 2);
+        assert_eq!(feed.n_removed, 0);
+    }
+
+    #[test]
+    fn test_removed_item_leaves_the_rest_untouched() {
+        let feed = filter_document(
+            &Bytes::from_static(PODCAST_FEED.as_bytes()),
+            &FeedLimits::default(),
+            &ChannelRewrites::default(),
+            |item| item.guid() == Some("ep-1"),
+        )
+        .unwrap();
+
+        let expected = PODCAST_FEED.replace(
+            r#"
+    <item>
+      <title>Episode 1</title>
+      <guid isPermaLink="false">ep-1</guid>
+      <itunes:duration>01:02:03</itunes:duration>
+    </item>"#,
+            "",
+        );
+
+        assert_eq!(std::str::from_utf8(&feed.body).unwrap(), expected);
+        assert_eq!(feed.n_removed, 1);
+        assert_eq!(titles(&feed), vec!["Episode 2 & friends"]);
+    }
+
+    #[test_case(|item: &ItemFields| item.title() == Some("Episode 2 & friends") ; "cdata title")]
+    #[test_case(|item: &ItemFields| item.link() == Some("https://example.com/ep2") ; "link")]
+    fn test_fields_are_extracted(remove: fn(&ItemFields) -> bool) {
+        let feed = filter_document(
+            &Bytes::from_static(PODCAST_FEED.as_bytes()),
+            &FeedLimits::default(),
+            &ChannelRewrites::default(),
+            remove,
+        )
+        .unwrap();
+
+        assert_eq!(titles(&feed), vec!["Episode 1"]);
+    }
+
+    #[test]
+    fn test_entities_in_fields_are_resolved() {
+        let input = r#"<rss version="2.0"><channel><title>t</title>
+<item><title>Fish &amp; Chips &#169;</title></item>
+</channel></rss>"#;
+
+        let feed = filter_document(
+            &Bytes::copy_from_slice(input.as_bytes()),
+            &FeedLimits::default(),
+            &ChannelRewrites::default(),
+            |item| item.title() == Some("Fish & Chips ©"),
+        )
+        .unwrap();
+
+        assert_eq!(feed.n_removed, 1);
+    }
+
+    #[test]
+    fn test_rss_1_0_items_outside_channel() {
+        let input = r#"<rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#" xmlns="http://purl.org/rss/1.0/">
+  <channel rdf:about="https://example.com/"><title>t</title></channel>
+  <item rdf:about="https://example.com/1"><title>One</title></item>
+  <item rdf:about="https://example.com/2"><title>Two</title></item>
+</rdf:RDF>"#;
+
+        let feed = filter_document(
+            &Bytes::copy_from_slice(input.as_bytes()),
+            &FeedLimits::default(),
+            &ChannelRewrites::default(),
+            |item| item.title() == Some("One"),
+        )
+        .unwrap();
+
+        assert_eq!(feed.n_items, 2);
+        assert_eq!(feed.n_removed, 1);
+        assert!(!std::str::from_utf8(&feed.body).unwrap().contains("One"));
+    }
+
+    #[test_case(r#"<atom:link href="https://old.example.com/feed" rel="self" type="application/rss+xml"/>"#, r#"<atom:link href="https://new.example.com/feed?a=1&amp;b=2" rel="self" type="application/rss+xml"/>"# ; "empty element")]
+    #[test_case(r#"<atom:link rel="self" href="https://old.example.com/feed"></atom:link>"#, r#"<atom:link rel="self" href="https://new.example.com/feed?a=1&amp;b=2"></atom:link>"# ; "start and end")]
+    #[test_case(r#"<atom:link rel="hub" href="https://hub.example.com/"/>"#, r#"<atom:link rel="hub" href="https://hub.example.com/"/>"# ; "other rel is kept")]
+    #[test_case(r#"<link>https://old.example.com/</link>"#, r#"<link>https://old.example.com/</link>"# ; "rss link is kept")]
+    fn test_self_link_rewrite(link: &str, expected: &str) {
+        let feed = format!(
+            r#"<rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom"><channel>{link}<item><title>One</title><atom:link rel="self" href="https://item.example.com/"/></item></channel></rss>"#
+        );
+        let rewrites = ChannelRewrites {
+            self_link: Some("https://new.example.com/feed?a=1&b=2"),
+            ..Default::default()
+        };
+
+        let result = filter_document(
+            &Bytes::from(feed.clone()),
+            &FeedLimits::default(),
+            &rewrites,
+            |_| false,
+        )
+        .unwrap();
+
+        assert_eq!(
+            std::str::from_utf8(&result.body).unwrap(),
+            feed.replace(link, expected)
+        );
+    }
+
+    #[test_case("<title>Feed</title>", "<title>Feed (filtered &amp; tidied)</title>" ; "text")]
+    #[test_case("<title><![CDATA[Feed & more]]></title>", "<title><![CDATA[Feed & more]]> (filtered &amp; tidied)</title>" ; "cdata")]
+    #[test_case("<title>Feed &amp; more</title >", "<title>Feed &amp; more (filtered &amp; tidied)</title >" ; "entity and spaced end tag")]
+    #[test_case("<title></title>", "<title>(filtered &amp; tidied)</title>" ; "empty")]
+    fn test_title_annotation(title: &str, expected: &str) {
+        let feed = format!(
+            r#"<rss version="2.0"><channel>{title}<image><title>Logo</title></image><item><title>One</title></item></channel></rss>"#
+        );
+        let rewrites = ChannelRewrites {
+            title_annotation: Some("(filtered & tidied)"),
+            ..Default::default()
+        };
+
+        let result = filter_document(
+            &Bytes::from(feed.clone()),
+            &FeedLimits::default(),
+            &rewrites,
+            |_| false,
+        )
+        .unwrap();
+
+        assert_eq!(
+            std::str::from_utf8(&result.body).unwrap(),
+            feed.replace(title, expected)
+        );
+    }
+
+    #[test_case("<root><item>not rss</item></root>" ; "wrong root element")]
+    #[test_case("<feed/>" ; "empty root element")]
+    fn test_invalid_start_tag(input: &str) {
+        let result = filter_document(
+            &Bytes::copy_from_slice(input.as_bytes()),
+            &FeedLimits::default(),
+            &ChannelRewrites::default(),
+            |_| false,
+        );
+
+        assert_matches!(result, Err(RssError::RSSParse(rss::Error::InvalidStartTag)));
+    }
+
+    #[test]
+    fn test_too_many_items() {
+        let limits = FeedLimits {
+            max_items: 1,
+            ..Default::default()
+        };
+
+        let result = filter_document(
+            &Bytes::from_static(PODCAST_FEED.as_bytes()),
+            &limits,
+            &ChannelRewrites::default(),
+            |_| false,
+        );
+
+        assert_matches!(result, Err(RssError::TooManyItems { max_items: 1 }));
+    }
+
+    #[test_case(3, true ; "item children are too deep")]
+    #[test_case(4, false ; "item children are at the limit")]
+    fn test_nesting_too_deep(max_depth: usize, too_deep: bool) {
+        let limits = FeedLimits {
+            max_depth,
+            ..Default::default()
+        };
+
+        let result = filter_document(
+            &Bytes::from_static(PODCAST_FEED.as_bytes()),
+            &limits,
+            &ChannelRewrites::default(),
+            |_| false,
+        );
+
+        if too_deep {
+            assert_matches!(result, Err(RssError::NestingTooDeep { max_depth: 3 }));
+        } else {
+            assert!(result.is_ok());
+        }
+    }
+
+    #[test_case("" ; "empty document")]
+    #[test_case("<rss version=\"2.0\"></rss>" ; "no channel")]
+    #[test_case("<rss version=\"2.0\"><channel><item><title>x</title>" ; "truncated item")]
+    fn test_incomplete_document(input: &str) {
+        assert!(
+            filter_document(
+                &Bytes::copy_from_slice(input.as_bytes()),
+                &FeedLimits::default(),
+                &ChannelRewrites::default(),
+                |_| false
+            )
+            .is_err()
+        );
+    }
+
+    #[test_case("<ttl>60</ttl>", Some(60 * 60) ; "ttl in minutes")]
+    #[test_case("<sy:updatePeriod>daily</sy:updatePeriod>", Some(24 * 60 * 60) ; "update period")]
+    #[test_case("<sy:updatePeriod> Hourly </sy:updatePeriod><sy:updateFrequency>4</sy:updateFrequency>", Some(15 * 60) ; "update period and frequency")]
+    #[test_case("<ttl>5</ttl><sy:updatePeriod>daily</sy:updatePeriod>", Some(5 * 60) ; "ttl takes precedence")]
+    #[test_case("<ttl>18446744073709551615</ttl>", Some(365 * 24 * 60 * 60) ; "huge ttl is capped")]
+    #[test_case("<ttl>1000000</ttl>", Some(365 * 24 * 60 * 60) ; "ttl over a year is capped")]
+    #[test_case("<sy:updatePeriod>fortnightly</sy:updatePeriod>", None ; "unknown period")]
+    #[test_case("<item><ttl>60</ttl></item>", None ; "only channel children count")]
+    #[test_case("", None ; "no hints")]
+    fn test_update_hints(channel: &str, expected_secs: Option<u64>) {
+        let input = format!(
+            r#"<rss version="2.0" xmlns:sy="http://purl.org/rss/1.0/modules/syndication/"><channel><title>Feed</title>{channel}<item><title>Item</title></item></channel></rss>"#
+        );
+
+        let feed = filter_document(
+            &Bytes::from(input),
+            &FeedLimits::default(),
+            &ChannelRewrites::default(),
+            |_| false,
+        )
+        .expect("valid document");
+
+        assert_eq!(
+            feed.update_hints.interval(),
+            expected_secs.map(Duration::from_secs)
+        );
+        assert_eq!(feed.n_items, 1 + channel.matches("<item>").count());
+    }
+}
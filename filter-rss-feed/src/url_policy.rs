@@ -0,0 +1,366 @@
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use http::{Request as HttpRequest, Response as HttpResponse};
+use thiserror::Error;
+use tower::Layer;
+use url::{Host, Url};
+
+use crate::http_client::{HttpClient, HttpClientError};
+
+#[derive(Clone, Debug, Error, PartialEq, Eq)]
+pub enum UrlPolicyError {
+    #[error("The URL could not be parsed: {0}")]
+    Invalid(#[from] url::ParseError),
+
+    #[error("Only http and https URLs can be fetched, not {0}")]
+    UnsupportedScheme(String),
+
+    #[error("The URL has no host")]
+    NoHost,
+
+    #[error("{0} is a private, loopback, link-local or reserved address")]
+    PrivateAddress(String),
+
+    #[error("{0} is this service; feeds can't be fetched through it recursively")]
+    SelfReference(String),
+
+    #[error("{0} is not allowed to be fetched")]
+    DeniedHost(String),
+
+    #[error("{0} is not in the list of hosts which may be fetched")]
+    HostNotAllowed(String),
+}
+
+/// Which upstream URLs may be fetched, so that a public deployment can't be
+/// used to reach internal services or itself.
+///
+/// Host entries match the host itself and any of its subdomains, ignoring
+/// case. Only IP literals are checked against private ranges: hostnames
+/// aren't resolved here, so operators relying on this in front of a private
+/// network should also deny its hostnames.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct UrlPolicy {
+    /// When not empty, only these hosts may be fetched
+    pub allowed_hosts: Vec<String>,
+    /// Hosts which may never be fetched. These win over `allowed_hosts`
+    pub denied_hosts: Vec<String>,
+    /// Hostnames this service is reachable on. Fetching them would make the
+    /// service call itself, possibly without end
+    pub self_hosts: Vec<String>,
+    /// Whether loopback, private, link-local and reserved addresses,
+    /// including IPv6 addresses which reach them, and `localhost`, may be
+    /// fetched. Default is false
+    pub allow_private_addresses: bool,
+}
+
+impl UrlPolicy {
+    /// A copy of this policy which also treats `host` as ourselves.
+    pub fn with_self_host(mut self, host: Option<&str>) -> Self {
+        self.self_hosts.extend(host.map(str::to_string));
+        self
+    }
+
+    /// Checks a URL which is about to be fetched.
+    pub fn check(&self, url: &Url) -> Result<(), UrlPolicyError> {
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(UrlPolicyError::UnsupportedScheme(url.scheme().to_string()));
+        }
+
+        let host = url.host().ok_or(UrlPolicyError::NoHost)?;
+        let name = match &host {
+            Host::Domain(domain) => domain.trim_end_matches('.').to_ascii_lowercase(),
+            Host::Ipv4(ip) => ip.to_string(),
+            Host::Ipv6(ip) => ip.to_string(),
+        };
+
+        if !self.allow_private_addresses && is_private(&host) {
+            return Err(UrlPolicyError::PrivateAddress(name));
+        }
+
+        if matches_any(&self.self_hosts, &name) {
+            return Err(UrlPolicyError::SelfReference(name));
+        }
+
+        if matches_any(&self.denied_hosts, &name) {
+            return Err(UrlPolicyError::DeniedHost(name));
+        }
+
+        if !self.allowed_hosts.is_empty() && !matches_any(&self.allowed_hosts, &name) {
+            return Err(UrlPolicyError::HostNotAllowed(name));
+        }
+
+        Ok(())
+    }
+
+    /// Parses and checks a URL which is about to be fetched.
+    pub fn check_str(&self, url: &str) -> Result<Url, UrlPolicyError> {
+        let url = Url::parse(url)?;
+        self.check(&url)?;
+        Ok(url)
+    }
+}
+
+fn matches_any(patterns: &[String], host: &str) -> bool {
+    patterns.iter().any(|pattern| {
+        let pattern = pattern.trim().trim_end_matches('.').to_ascii_lowercase();
+
+        !pattern.is_empty()
+            && (host == pattern
+                || host
+                    .strip_suffix(&pattern)
+                    .is_some_and(|prefix| prefix.ends_with('.')))
+    })
+}
+
+fn is_private(host: &Host<&str>) -> bool {
+    match host {
+        Host::Domain(domain) => {
+            let domain = domain.trim_end_matches('.').to_ascii_lowercase();
+            domain == "localhost" || domain.ends_with(".localhost")
+        }
+        Host::Ipv4(ip) => is_private_ipv4(ip),
+        Host::Ipv6(ip) => is_private_ipv6(ip),
+    }
+}
+
+fn is_private_ipv4(ip: &Ipv4Addr) -> bool {
+    let [a, b, c, _] = ip.octets();
+
+    ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        // "This network", 0.0.0.0/8
+        || a == 0
+        // Carrier-grade NAT, 100.64.0.0/10
+        || (a == 100 && (b & 0xc0) == 64)
+        // IETF protocol assignments, 192.0.0.0/24
+        || (a == 192 && b == 0 && c == 0)
+        // Benchmarking, 198.18.0.0/15
+        || (a == 198 && (b & 0xfe) == 18)
+        // Reserved, 240.0.0.0/4, which takes in the broadcast address
+        || a >= 240
+}
+
+fn is_private_ipv6(ip: &Ipv6Addr) -> bool {
+    // IPv4-mapped `::ffff:a.b.c.d` and IPv4-compatible `::a.b.c.d`, which
+    // takes in `::` and `::1`
+    if let Some(ipv4) = ip.to_ipv4() {
+        return is_private_ipv4(&ipv4);
+    }
+
+    let segments = ip.segments();
+    let embedded = |high: u16, low: u16| {
+        let [a, b] = high.to_be_bytes();
+        let [c, d] = low.to_be_bytes();
+        Ipv4Addr::new(a, b, c, d)
+    };
+
+    // NAT64, 64:ff9b::/96, which reaches the IPv4 address in its last 32 bits
+    if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+        return is_private_ipv4(&embedded(segments[6], segments[7]));
+    }
+
+    // 6to4, 2002::/16, which reaches the IPv4 address in its next 32 bits
+    if segments[0] == 0x2002 {
+        return is_private_ipv4(&embedded(segments[1], segments[2]));
+    }
+
+    ip.is_unique_local()
+        || ip.is_unicast_link_local()
+        // Site-local, fec0::/10, deprecated but still routed by some networks
+        || (segments[0] & 0xffc0) == 0xfec0
+}
+
+/// A [`Layer`] which refuses to send requests the [`UrlPolicy`] doesn't
+/// allow, so that every fetch is checked however it was made.
+#[derive(Clone, Debug, Default)]
+pub struct UrlPolicyLayer {
+    policy: UrlPolicy,
+}
+
+impl UrlPolicyLayer {
+    pub fn new(policy: UrlPolicy) -> Self {
+        Self { policy }
+    }
+}
+
+impl<C> Layer<C> for UrlPolicyLayer {
+    type Service = UrlPolicyHttpClient<C>;
+
+    fn layer(&self, inner: C) -> Self::Service {
+        UrlPolicyHttpClient {
+            inner,
+            policy: self.policy.clone(),
+        }
+    }
+}
+
+/// Wraps any [`HttpClient`], checking requests against a [`UrlPolicy`]. See
+/// [`UrlPolicyLayer`].
+pub struct UrlPolicyHttpClient<C> {
+    inner: C,
+    policy: UrlPolicy,
+}
+
+impl<C: HttpClient> UrlPolicyHttpClient<C> {
+    async fn send_checked(
+        &self,
+        request: HttpRequest<Bytes>,
+    ) -> Result<HttpResponse<Bytes>, HttpClientError> {
+        self.policy.check_str(&request.uri().to_string())?;
+        self.inner.send(request).await
+    }
+}
+
+#[async_trait]
+#[cfg(not(target_arch = "wasm32"))]
+impl<C: HttpClient> HttpClient for UrlPolicyHttpClient<C> {
+    async fn send(
+        &self,
+        request: HttpRequest<Bytes>,
+    ) -> Result<HttpResponse<Bytes>, HttpClientError> {
+        self.send_checked(request).await
+    }
+}
+
+#[async_trait(?Send)]
+#[cfg(target_arch = "wasm32")]
+impl<C: HttpClient> HttpClient for UrlPolicyHttpClient<C> {
+    async fn send(
+        &self,
+        request: HttpRequest<Bytes>,
+    ) -> Result<HttpResponse<Bytes>, HttpClientError> {
+        self.send_checked(request).await
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use crate::fake_http_client::FakeHttpClientBuilder;
+    use matches::assert_matches;
+    use test_case::test_case;
+
+    fn hosts(hosts: &[&str]) -> Vec<String> {
+        hosts.iter().map(|host| host.to_string()).collect()
+    }
+
+    #[test_case("https://example.com/feed" ; "https")]
+    #[test_case("http://example.com:8080/feed" ; "http with a port")]
+    #[test_case("https://93.184.215.14/feed" ; "public ipv4")]
+    #[test_case("https://[2606:2800:21f:cb07:6820:80da:af6b:8b2c]/feed" ; "public ipv6")]
+    #[test_case("https://localhost.example.com/feed" ; "localhost as a subdomain")]
+    fn test_allowed(url: &str) {
+        assert_eq!(UrlPolicy::default().check_str(url).map(|_| ()), Ok(()));
+    }
+
+    #[test_case("file:///etc/passwd" ; "file")]
+    #[test_case("ftp://example.com/feed" ; "ftp")]
+    #[test_case("gopher://example.com/feed" ; "gopher")]
+    fn test_unsupported_scheme(url: &str) {
+        assert_matches!(
+            UrlPolicy::default().check_str(url),
+            Err(UrlPolicyError::UnsupportedScheme(_))
+        );
+    }
+
+    #[test_case("http://localhost/feed" ; "localhost")]
+    #[test_case("http://LOCALHOST./feed" ; "localhost with a trailing dot")]
+    #[test_case("http://app.localhost/feed" ; "localhost subdomain")]
+    #[test_case("http://127.0.0.1:8080/feed" ; "loopback")]
+    #[test_case("http://2130706433/feed" ; "loopback as a number")]
+    #[test_case("http://0.0.0.0/feed" ; "unspecified")]
+    #[test_case("http://10.1.2.3/feed" ; "10/8")]
+    #[test_case("http://172.16.0.1/feed" ; "172.16/12")]
+    #[test_case("http://192.168.1.1/feed" ; "192.168/16")]
+    #[test_case("http://169.254.169.254/latest/meta-data" ; "link-local metadata service")]
+    #[test_case("http://100.64.0.1/feed" ; "carrier-grade nat")]
+    #[test_case("http://[::1]/feed" ; "ipv6 loopback")]
+    #[test_case("http://[fd00::1]/feed" ; "ipv6 unique local")]
+    #[test_case("http://[fe80::1]/feed" ; "ipv6 link-local")]
+    #[test_case("http://[::ffff:127.0.0.1]/feed" ; "ipv4-mapped loopback")]
+    #[test_case("http://0.1.2.3/feed" ; "this network")]
+    #[test_case("http://192.0.0.8/feed" ; "ietf protocol assignments")]
+    #[test_case("http://198.19.0.1/feed" ; "benchmarking")]
+    #[test_case("http://240.0.0.1/feed" ; "reserved")]
+    #[test_case("http://255.255.255.255/feed" ; "broadcast")]
+    #[test_case("http://[::127.0.0.1]/feed" ; "ipv4-compatible loopback")]
+    #[test_case("http://[64:ff9b::10.0.0.1]/feed" ; "nat64 private")]
+    #[test_case("http://[2002:a9fe:a9fe::1]/feed" ; "6to4 link-local")]
+    #[test_case("http://[fec0::1]/feed" ; "ipv6 site-local")]
+    fn test_private_addresses(url: &str) {
+        assert_matches!(
+            UrlPolicy::default().check_str(url),
+            Err(UrlPolicyError::PrivateAddress(_))
+        );
+
+        let permissive = UrlPolicy {
+            allow_private_addresses: true,
+            ..Default::default()
+        };
+        assert_eq!(permissive.check_str(url).map(|_| ()), Ok(()));
+    }
+
+    #[test_case("http://8.8.8.8/feed" ; "ipv4")]
+    #[test_case("http://192.0.2.1/feed" ; "not protocol assignments")]
+    #[test_case("http://[2001:4860:4860::8888]/feed" ; "ipv6")]
+    #[test_case("http://[64:ff9b::8.8.8.8]/feed" ; "nat64 public")]
+    #[test_case("http://[2002:808:808::1]/feed" ; "6to4 public")]
+    fn test_public_addresses(url: &str) {
+        assert_eq!(UrlPolicy::default().check_str(url).map(|_| ()), Ok(()));
+    }
+
+    #[test_case("https://rssfilter.example.com/?url=x", true ; "own host")]
+    #[test_case("https://RSSFILTER.example.com./?url=x", true ; "self with different case")]
+    #[test_case("https://www.rssfilter.example.com/", true ; "subdomain of self")]
+    #[test_case("https://notrssfilter.example.com/", false ; "similar host")]
+    fn test_self_reference(url: &str, rejected: bool) {
+        let policy = UrlPolicy::default().with_self_host(Some("rssfilter.example.com"));
+
+        assert_eq!(
+            matches!(policy.check_str(url), Err(UrlPolicyError::SelfReference(_))),
+            rejected
+        );
+    }
+
+    #[test_case(&[], &[], "https://example.com/" => Ok(()) ; "no lists")]
+    #[test_case(&["example.com"], &[], "https://feeds.example.com/" => Ok(()) ; "allowed subdomain")]
+    #[test_case(&["example.com"], &[], "https://example.org/" => Err(UrlPolicyError::HostNotAllowed("example.org".to_string())) ; "not allowed")]
+    #[test_case(&[], &["example.com"], "https://feeds.example.com/" => Err(UrlPolicyError::DeniedHost("feeds.example.com".to_string())) ; "denied subdomain")]
+    #[test_case(&["example.com"], &["private.example.com"], "https://private.example.com/" => Err(UrlPolicyError::DeniedHost("private.example.com".to_string())) ; "deny wins")]
+    fn test_host_lists(allowed: &[&str], denied: &[&str], url: &str) -> Result<(), UrlPolicyError> {
+        let policy = UrlPolicy {
+            allowed_hosts: hosts(allowed),
+            denied_hosts: hosts(denied),
+            ..Default::default()
+        };
+
+        policy.check_str(url).map(|_| ())
+    }
+
+    #[tokio::test]
+    async fn test_layer() {
+        let client = UrlPolicyLayer::new(UrlPolicy::default()).layer(
+            FakeHttpClientBuilder::default()
+                .with_rss_response("http://127.0.0.1/feed", "<rss/>")
+                .build()
+                .unwrap(),
+        );
+
+        let request = HttpRequest::builder()
+            .uri("http://127.0.0.1/feed")
+            .body(Bytes::new())
+            .unwrap();
+        let result = client.send(request).await;
+
+        assert_matches!(
+            result,
+            Err(HttpClientError::UrlPolicy(UrlPolicyError::PrivateAddress(
+                _
+            )))
+        );
+        assert_eq!(client.inner.requests("http://127.0.0.1/feed"), 0);
+    }
+}
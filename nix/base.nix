@@ -109,7 +109,14 @@
       rustfmtBin = lib.getExe' rustfmtNightly "rustfmt";
       wasmTestRunnerBin = lib.getExe' wasmTestRunner "wasm-bindgen-test-runner";
 
-      src = craneLib.cleanCargoSource inputs.self.outPath;
+      # The handler embeds the web UI, which isn't a Cargo source
+      src = lib.cleanSourceWith {
+        src = inputs.self.outPath;
+        filter = path: type:
+          (craneLib.filterCargoSources path type)
+          || (lib.hasSuffix ".html" path);
+        name = "source";
+      };
 
       commonArgs = {
         inherit (config.rssfilter) src;
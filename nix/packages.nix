@@ -1,4 +1,4 @@
-_: {
+{inputs, ...}: {
   perSystem = {config, ...}: let
     inherit
       (config.rssfilter)
@@ -7,15 +7,26 @@ _: {
       cargoArtifactsNative
       ;
 
+    # Reported by the server's `/version`
+    gitRevision = inputs.self.rev or inputs.self.dirtyRev or "";
+
     rssfilter = craneLib.buildPackage (commonArgs
       // {
         cargoArtifacts = cargoArtifactsNative;
         cargoExtraArgs = "--locked -p rssfilter-cli";
         meta.mainProgram = "rssfilter";
       });
+
+    rssfilter-server = craneLib.buildPackage (commonArgs
+      // {
+        cargoArtifacts = cargoArtifactsNative;
+        cargoExtraArgs = "--locked -p rssfilter-server";
+        env.RSSFILTER_GIT_REVISION = gitRevision;
+        meta.mainProgram = "rssfilter-server";
+      });
   in {
     packages = {
-      inherit rssfilter;
+      inherit rssfilter rssfilter-server;
       default = rssfilter;
     };
   };
@@ -1,27 +1,215 @@
-use clap::Parser;
+use clap::{Args, Parser, Subcommand};
 use log::info;
 use regex::Regex;
 use std::env;
 use std::error::Error;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use url::Url;
 
-use filter_rss_feed::{FilterRegexes, RssFilter};
+use filter_rss_feed::{
+    CacheConfig, FilterProfile, FilterRegexes, OutputMode, RssFilter, RssFilterConfig, UrlSigner,
+    create_http_client_with_config, filter_subscriptions, unfilter_subscriptions,
+};
+
+/// The instance subscription lists are pointed at unless told otherwise.
+const DEFAULT_SERVICE_URL: &str = "https://rssfilter.orangesquash.org.uk/";
+
+/// The environment variable `sign-url` and `filter-opml` read the signing key
+/// from, which is the same one the service is configured with.
+const SIGNING_KEY_VAR: &str = "URL_SIGNING_KEY";
 
 #[derive(Parser, Debug)]
-#[command(name = "rss_filter", version)]
+#[command(
+    name = "rss_filter",
+    version,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 struct Opt {
+    /// Remove items whose title matches.
     #[arg(short, long)]
     title_filter_regex: Option<String>,
 
+    /// Remove items whose GUID matches.
     #[arg(short, long)]
     guid_filter_regex: Option<String>,
 
+    /// Remove items whose link matches.
     #[arg(short, long)]
     link_filter_regex: Option<String>,
 
+    /// Log debugging information.
     #[arg(short, long)]
     debug: bool,
 
-    url: String,
+    /// Parse the whole feed and write it out again, instead of only removing
+    /// the filtered items from the publisher's document.
+    #[arg(short, long)]
+    reserialise: bool,
+
+    /// Keep fetched feeds in this directory, and reuse them while the
+    /// publisher says they are fresh.
+    #[arg(long)]
+    cache_dir: Option<PathBuf>,
+
+    /// The feed to filter.
+    #[arg(required = true)]
+    url: Option<String>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Rewrite an OPML subscription list so that every feed in it is filtered
+    /// by rssfilter. Feeds which already are get these filters instead. When
+    /// the URL_SIGNING_KEY environment variable is set, the feeds' URLs are
+    /// signed with it, for instances which only filter signed URLs.
+    FilterOpml {
+        #[command(flatten)]
+        filters: FilterArgs,
+
+        #[command(flatten)]
+        opml: OpmlArgs,
+    },
+
+    /// Rewrite an OPML subscription list so that the feeds in it which are
+    /// filtered by rssfilter aren't any more.
+    UnfilterOpml {
+        #[command(flatten)]
+        opml: OpmlArgs,
+    },
+
+    /// Print the URL at which rssfilter serves a feed with these filters,
+    /// signed with the key in the URL_SIGNING_KEY environment variable, for
+    /// instances which only filter signed URLs.
+    SignUrl {
+        #[command(flatten)]
+        filters: FilterArgs,
+
+        /// The rssfilter instance which filters the feed.
+        #[arg(long, default_value = DEFAULT_SERVICE_URL)]
+        service_url: Url,
+
+        /// The feed to filter.
+        url: String,
+    },
+}
+
+#[derive(Args, Debug)]
+struct FilterArgs {
+    /// Remove items whose title matches. Can be given more than once.
+    #[arg(short, long)]
+    title_filter_regex: Vec<String>,
+
+    /// Remove items whose GUID matches. Can be given more than once.
+    #[arg(short, long)]
+    guid_filter_regex: Vec<String>,
+
+    /// Remove items whose link matches. Can be given more than once.
+    #[arg(short, long)]
+    link_filter_regex: Vec<String>,
+}
+
+impl FilterArgs {
+    fn into_profile(self) -> Result<FilterProfile, Box<dyn Error + Send + Sync>> {
+        let profile = FilterProfile {
+            title_filter_regexes: self.title_filter_regex,
+            guid_filter_regexes: self.guid_filter_regex,
+            link_filter_regexes: self.link_filter_regex,
+        };
+        if profile.is_empty() {
+            return Err("At least one filter must be given".into());
+        }
+
+        // Catch mistakes now, rather than when a feed reader fetches the
+        // filtered feed
+        for regex in profile
+            .title_filter_regexes
+            .iter()
+            .chain(&profile.guid_filter_regexes)
+            .chain(&profile.link_filter_regexes)
+        {
+            Regex::new(regex)?;
+        }
+
+        Ok(profile)
+    }
+}
+
+#[derive(Args, Debug)]
+struct OpmlArgs {
+    /// The rssfilter instance which filters the feeds.
+    #[arg(long, default_value = DEFAULT_SERVICE_URL)]
+    service_url: Url,
+
+    /// The subscription list, or `-` to read it from stdin. The rewritten
+    /// list is written to stdout.
+    input: PathBuf,
+}
+
+fn read_input(path: &Path) -> io::Result<Vec<u8>> {
+    if path == Path::new("-") {
+        let mut input = Vec::new();
+        io::stdin().read_to_end(&mut input)?;
+        return Ok(input);
+    }
+
+    fs::read(path)
+}
+
+/// The signer for the key in [`SIGNING_KEY_VAR`], if it's set.
+fn url_signer() -> Option<UrlSigner> {
+    env::var(SIGNING_KEY_VAR)
+        .ok()
+        .map(|key| key.trim().to_string())
+        .filter(|key| !key.is_empty())
+        .map(UrlSigner::new)
+}
+
+fn sign_url(
+    filters: FilterArgs,
+    service_url: &Url,
+    feed_url: &str,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let signer = url_signer()
+        .ok_or_else(|| format!("{SIGNING_KEY_VAR} must be set to the service's signing key"))?;
+    Url::parse(feed_url)?;
+
+    let url = filters.into_profile()?.filtered_url(service_url, feed_url);
+    println!("{}", signer.sign(&url));
+
+    Ok(())
+}
+
+fn run_command(command: Command) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let rewritten = match command {
+        Command::FilterOpml { filters, opml } => {
+            let profile = filters.into_profile()?;
+            filter_subscriptions(
+                &read_input(&opml.input)?,
+                &opml.service_url,
+                &profile,
+                url_signer().as_ref(),
+            )?
+        }
+        Command::UnfilterOpml { opml } => {
+            unfilter_subscriptions(&read_input(&opml.input)?, &opml.service_url)?
+        }
+        Command::SignUrl {
+            filters,
+            service_url,
+            url,
+        } => return sign_url(filters, &service_url, &url),
+    };
+
+    io::stdout().write_all(&rewritten.opml)?;
+    info!("Rewrote {} feeds", rewritten.n_rewritten);
+
+    Ok(())
 }
 
 pub async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
@@ -32,6 +220,10 @@ pub async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
     }
     env_logger::init();
 
+    if let Some(command) = opt.command {
+        return run_command(command);
+    }
+
     info!("Starting RSS filter application");
 
     let title_regexes = opt
@@ -59,9 +251,28 @@ pub async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
         link_regexes: &link_regexes.unwrap_or(vec![]),
     };
 
-    let rss_filter = RssFilter::new(&filter_regexes)?;
+    let output_mode = if opt.reserialise {
+        OutputMode::Reserialise
+    } else {
+        OutputMode::Preserve
+    };
+
+    let cache = CacheConfig {
+        cache_dir: opt.cache_dir,
+        ..Default::default()
+    };
+
+    let http_client = create_http_client_with_config(cache.clone())?;
+    let rss_filter = RssFilter::new_with_http_client(&filter_regexes, http_client).with_config(
+        RssFilterConfig {
+            output_mode,
+            cache,
+            ..Default::default()
+        },
+    );
 
-    let filtered = rss_filter.fetch_and_filter(&opt.url).await?.into_body();
+    let url = opt.url.ok_or("A feed URL must be given")?;
+    let filtered = rss_filter.fetch_and_filter(&url).await?.into_body();
 
     let s = std::str::from_utf8(&filtered)?;
     println!("{s}");
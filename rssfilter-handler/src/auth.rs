@@ -0,0 +1,249 @@
+use std::str::FromStr;
+
+use bytes::Bytes;
+use http::header::AUTHORIZATION;
+use http::uri::PathAndQuery;
+use http::{Request, Uri};
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+use thiserror::Error;
+
+/// The query parameter an API key can be given in, for clients such as feed
+/// readers which can't send headers.
+const KEY_PARAM: &str = "key";
+
+#[derive(Debug, Error)]
+pub enum AuthError {
+    #[error("An API key is required: send it as a bearer token or in the key parameter")]
+    MissingKey,
+
+    #[error("The API key is not valid")]
+    InvalidKey,
+}
+
+/// An API key which may use the service, known by its `id` in traces. Only
+/// the key's SHA-256 is configured, so the configuration never holds the
+/// key itself.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ApiKey {
+    pub id: String,
+    pub sha256: [u8; 32],
+}
+
+impl FromStr for ApiKey {
+    type Err = String;
+
+    /// Parses `id:sha256`, where the hash is in hex.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (id, hash) = s
+            .split_once(':')
+            .ok_or_else(|| "expected id:sha256".to_string())?;
+        let id = id.trim();
+        if id.is_empty() {
+            return Err("the key has no id".to_string());
+        }
+
+        let mut sha256 = [0; 32];
+        hex::decode_to_slice(hash.trim(), &mut sha256)
+            .map_err(|_| format!("the hash for {id} isn't 64 hex digits"))?;
+
+        Ok(Self {
+            id: id.to_string(),
+            sha256,
+        })
+    }
+}
+
+/// Who may use the service.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AuthConfig {
+    /// Keys which may be used. When `None`, anyone may use the service
+    pub api_keys: Option<Vec<ApiKey>>,
+}
+
+/// Request extension with the id of the API key a request was made with.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ApiKeyId(pub String);
+
+/// The bearer token in `Authorization`, if there is one.
+fn bearer_token<B>(req: &Request<B>) -> Option<&str> {
+    let value = req.headers().get(AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, token) = value.split_once(' ')?;
+
+    scheme.eq_ignore_ascii_case("bearer").then(|| token.trim())
+}
+
+/// The value of the key parameter in `uri`'s query, if there is one.
+fn key_param(uri: &Uri) -> Option<String> {
+    url::form_urlencoded::parse(uri.query()?.as_bytes())
+        .find_map(|(name, value)| (name == KEY_PARAM).then(|| value.into_owned()))
+}
+
+/// `uri` without the key parameter. The rest of the query is left as it was
+/// sent, rather than encoded again.
+fn without_key_param(uri: &Uri) -> Uri {
+    let Some(query) = uri.query() else {
+        return uri.clone();
+    };
+
+    let query = query
+        .split('&')
+        .filter(|pair| {
+            url::form_urlencoded::parse(pair.as_bytes())
+                .next()
+                .is_none_or(|(name, _)| name != KEY_PARAM)
+        })
+        .collect::<Vec<_>>()
+        .join("&");
+
+    let path_and_query = if query.is_empty() {
+        uri.path().to_string()
+    } else {
+        format!("{}?{query}", uri.path())
+    };
+
+    let mut parts = uri.clone().into_parts();
+    parts.path_and_query = PathAndQuery::try_from(path_and_query).ok();
+
+    Uri::from_parts(parts).unwrap_or_else(|_| uri.clone())
+}
+
+/// The configured key which `key` is, if any. Every key is compared in
+/// constant time, so that how long this takes says nothing about them.
+fn find_key<'a>(api_keys: &'a [ApiKey], key: &str) -> Option<&'a ApiKey> {
+    let sha256: [u8; 32] = Sha256::digest(key.as_bytes()).into();
+
+    api_keys.iter().fold(None, |found, api_key| {
+        let matches = bool::from(api_key.sha256.ct_eq(&sha256));
+        found.or(matches.then_some(api_key))
+    })
+}
+
+/// Checks that a request carries one of the configured API keys, as a bearer
+/// token or in the key parameter, and marks it with the key's [`ApiKeyId`].
+///
+/// The key is then taken out of the request, so that nothing after this can
+/// log it or send it upstream: the key parameter always, and `Authorization`
+/// when it held the key. Without keys configured, requests are left alone.
+pub(crate) fn authenticate(req: &mut Request<Bytes>, config: &AuthConfig) -> Result<(), AuthError> {
+    let Some(api_keys) = &config.api_keys else {
+        return Ok(());
+    };
+
+    let bearer = bearer_token(req).map(str::to_string);
+    let key = bearer.clone().or_else(|| key_param(req.uri()));
+
+    if bearer.is_some() {
+        req.headers_mut().remove(AUTHORIZATION);
+    }
+    *req.uri_mut() = without_key_param(req.uri());
+
+    let key = key.ok_or(AuthError::MissingKey)?;
+    let api_key = find_key(api_keys, &key).ok_or(AuthError::InvalidKey)?;
+
+    req.extensions_mut().insert(ApiKeyId(api_key.id.clone()));
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use matches::assert_matches;
+    use test_case::test_case;
+
+    // The SHA-256 of "secret"
+    const SECRET_SHA256: &str = "2bb80d537b1da3e38bd30361aa855686bde0eacd7162fef6a25fe97bf527a25b";
+
+    fn config() -> AuthConfig {
+        AuthConfig {
+            api_keys: Some(vec![
+                format!("other:{}", "0".repeat(64)).parse().unwrap(),
+                format!("team:{SECRET_SHA256}").parse().unwrap(),
+            ]),
+        }
+    }
+
+    fn request(uri: &str, authorization: Option<&str>) -> Request<Bytes> {
+        let mut builder = Request::builder().uri(uri);
+        if let Some(authorization) = authorization {
+            builder = builder.header(AUTHORIZATION, authorization);
+        }
+        builder.body(Bytes::new()).unwrap()
+    }
+
+    #[test_case("team:2BB80D537B1DA3E38BD30361AA855686BDE0EACD7162FEF6A25FE97BF527A25B" => true; "upper case")]
+    #[test_case(" team : 2bb80d537b1da3e38bd30361aa855686bde0eacd7162fef6a25fe97bf527a25b" => true; "spaces")]
+    #[test_case("2bb80d537b1da3e38bd30361aa855686bde0eacd7162fef6a25fe97bf527a25b" => false; "no id")]
+    #[test_case(":2bb80d537b1da3e38bd30361aa855686bde0eacd7162fef6a25fe97bf527a25b" => false; "empty id")]
+    #[test_case("team:2bb80d" => false; "short")]
+    #[test_case("team:zbb80d537b1da3e38bd30361aa855686bde0eacd7162fef6a25fe97bf527a25b" => false; "not hex")]
+    #[test_case("team:2bb80d537b1da3e38bd30361aa855686bde0eacd7162fef6a25fe97bf527a2é" => false; "not ascii")]
+    fn test_parse_api_key(s: &str) -> bool {
+        s.parse::<ApiKey>().is_ok()
+    }
+
+    #[test_case(None, "https://rssfilter.example.com/?url=x&key=secret"; "parameter")]
+    #[test_case(Some("Bearer secret"), "https://rssfilter.example.com/?url=x"; "bearer")]
+    #[test_case(Some("bearer  secret "), "https://rssfilter.example.com/?url=x"; "bearer in lower case")]
+    fn test_authenticate(authorization: Option<&str>, uri: &str) {
+        let mut req = request(uri, authorization);
+
+        authenticate(&mut req, &config()).unwrap();
+
+        assert_eq!(
+            req.extensions().get::<ApiKeyId>(),
+            Some(&ApiKeyId("team".to_string()))
+        );
+        assert_eq!(req.uri(), "https://rssfilter.example.com/?url=x");
+        assert!(!req.headers().contains_key(AUTHORIZATION));
+    }
+
+    #[test_case(None, "https://rssfilter.example.com/?url=x" => matches Err(AuthError::MissingKey); "no key")]
+    #[test_case(Some("Basic dXNlcjpwYXNz"), "https://rssfilter.example.com/" => matches Err(AuthError::MissingKey); "basic")]
+    #[test_case(Some("Bearer wrong"), "https://rssfilter.example.com/" => matches Err(AuthError::InvalidKey); "wrong bearer")]
+    #[test_case(None, "https://rssfilter.example.com/?key=wrong" => matches Err(AuthError::InvalidKey); "wrong parameter")]
+    fn test_refused(authorization: Option<&str>, uri: &str) -> Result<(), AuthError> {
+        let mut req = request(uri, authorization);
+        let result = authenticate(&mut req, &config());
+
+        // Even a wrong key isn't kept
+        assert_eq!(key_param(req.uri()), None);
+        result
+    }
+
+    #[test]
+    fn test_no_valid_keys() {
+        let config = AuthConfig {
+            api_keys: Some(Vec::new()),
+        };
+        let mut req = request("https://rssfilter.example.com/?key=secret", None);
+
+        assert_matches!(authenticate(&mut req, &config), Err(AuthError::InvalidKey));
+    }
+
+    #[test]
+    fn test_no_keys_configured() {
+        let mut req = request(
+            "https://rssfilter.example.com/?url=x&key=anything",
+            Some("Bearer upstream"),
+        );
+
+        assert_matches!(authenticate(&mut req, &AuthConfig::default()), Ok(()));
+        assert_eq!(
+            req.uri(),
+            "https://rssfilter.example.com/?url=x&key=anything"
+        );
+        assert_eq!(req.headers()[AUTHORIZATION], "Bearer upstream");
+        assert_eq!(req.extensions().get::<ApiKeyId>(), None);
+    }
+
+    #[test_case("https://rssfilter.example.com/?key=a", "https://rssfilter.example.com/"; "only key")]
+    #[test_case("https://rssfilter.example.com/?url=x&key=a&title_filter_regex=%5EAd+", "https://rssfilter.example.com/?url=x&title_filter_regex=%5EAd+"; "rest untouched")]
+    #[test_case("https://rssfilter.example.com/?url=x&k%65y=a", "https://rssfilter.example.com/?url=x"; "encoded name")]
+    #[test_case("https://rssfilter.example.com/?keys=a", "https://rssfilter.example.com/?keys=a"; "similar name")]
+    #[test_case("/?url=x&key=a", "/?url=x"; "relative")]
+    fn test_without_key_param(uri: &str, expected: &str) {
+        assert_eq!(without_key_param(&uri.parse().unwrap()), expected);
+    }
+}
@@ -0,0 +1,64 @@
+use std::sync::Arc;
+
+use filter_rss_feed::{
+    HttpClient, HttpClientOptions, RateLimitLayer, RateLimitStore, ResponseCache, RssError,
+    create_http_client, create_http_client_with_options, create_rate_limit_store,
+    create_response_cache, create_response_cache_with_config,
+};
+
+use crate::Config;
+
+/// Where feeds are fetched from, filtered responses are cached, and requests
+/// are counted against rate limits.
+///
+/// The worker makes a new one for each request, since nothing outlives a
+/// request there. Long-running servers make one and share it, so that
+/// connections and cached feeds are reused between requests.
+#[derive(Clone)]
+pub struct Backend {
+    pub http_client: Arc<dyn HttpClient>,
+    pub response_cache: Arc<dyn ResponseCache>,
+    pub rate_limits: Arc<dyn RateLimitStore>,
+}
+
+impl Backend {
+    /// The platform's HTTP client, response cache and rate limit store: see
+    /// [`create_http_client`], [`create_response_cache`] and
+    /// [`create_rate_limit_store`].
+    pub fn new() -> Result<Self, RssError> {
+        Ok(Self {
+            http_client: Arc::from(create_http_client()?),
+            response_cache: Arc::from(create_response_cache()),
+            rate_limits: Arc::from(create_rate_limit_store()),
+        })
+    }
+
+    /// Like [`Backend::new`], but with the caches configured from `config`
+    /// and requests counted in `rate_limits`, including the HTTP client's
+    /// fetches against the upstream rate limit.
+    /// The limit sits below the cache, so feeds which are already cached can
+    /// still be filtered when their host has been fetched from too often.
+    ///
+    /// The native server passes [`create_rate_limit_store`]'s in-memory
+    /// store, which it keeps for as long as it runs. The worker passes one
+    /// backed by a Durable Object, so that limits hold across isolates.
+    pub fn with_config(
+        config: &Config,
+        rate_limits: Arc<dyn RateLimitStore>,
+    ) -> Result<Self, RssError> {
+        let http_client = create_http_client_with_options(HttpClientOptions {
+            cache: config.cache.clone(),
+            retry: config.retry,
+            rate_limit: config
+                .rate_limit
+                .upstream
+                .map(|limit| RateLimitLayer::new(Arc::clone(&rate_limits), limit)),
+        })?;
+
+        Ok(Self {
+            http_client: Arc::from(http_client),
+            response_cache: Arc::from(create_response_cache_with_config(&config.cache)),
+            rate_limits,
+        })
+    }
+}
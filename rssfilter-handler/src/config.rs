@@ -0,0 +1,371 @@
+use std::str::FromStr;
+use std::time::Duration;
+
+use filter_rss_feed::{
+    CacheConfig, FeedLimits, RateLimit, RedirectConfig, RetryConfig, UrlPolicy, UrlSigner,
+};
+use rssfilter_telemetry::WorkerConfig;
+use tracing::warn;
+
+use crate::{ApiKey, AuthConfig, CorsConfig, RateLimitConfig};
+
+/// Everything the worker reads from its environment.
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    pub telemetry: WorkerConfig,
+    pub limits: FeedLimits,
+    pub cache: CacheConfig,
+    pub retry: RetryConfig,
+    pub redirect: RedirectConfig,
+    pub url_policy: UrlPolicy,
+    pub keep_upstream_self_link: bool,
+    pub title_annotation: Option<String>,
+    pub cors: CorsConfig,
+    pub rate_limit: RateLimitConfig,
+    pub auth: AuthConfig,
+    /// When set, filtered feeds' URLs must be signed with it
+    pub url_signer: Option<UrlSigner>,
+}
+
+impl Config {
+    /// Builds the config from the worker's environment variables:
+    ///
+    /// - `LOG_FORMAT` and `RUST_LOG`: see [`WorkerConfig`]
+    /// - `MAX_FEED_BYTES`: maximum decompressed size of an upstream feed
+    /// - `MAX_FEED_ITEMS`: maximum number of items in an upstream feed
+    /// - `MAX_FEED_DEPTH`: maximum element nesting depth of an upstream feed
+    /// - `CACHE_DEFAULT_TTL`: seconds to cache feeds which don't say for
+    /// - `CACHE_MIN_TTL` and `CACHE_MAX_TTL`: bounds, in seconds, on how long
+    ///   feeds are cached for
+    /// - `CACHE_STALE_WHILE_REVALIDATE`: seconds a stale feed may be served
+    ///   while it is refreshed in the background
+    /// - `CACHE_STALE_IF_ERROR`: seconds a stale feed may be served while the
+    ///   upstream server is failing
+    /// - `CACHE_MAX_ENTRIES` and `CACHE_MAX_BYTES`: most responses, and bytes
+    ///   of them, each in-memory cache holds
+    /// - `UPSTREAM_TIMEOUT`: seconds to wait for each attempt at fetching a feed
+    /// - `UPSTREAM_MAX_ATTEMPTS`: how many times to try fetching a feed
+    /// - `MAX_REDIRECTS`: how many redirects to follow when fetching a feed
+    /// - `REDIRECT_SAME_SCHEME_ONLY`: `true` to refuse redirects which change
+    ///   the scheme, including from `http` to `https`
+    /// - `ALLOWED_HOSTS`: comma-separated hosts which feeds may be fetched
+    ///   from. When unset, any public host may be
+    /// - `DENIED_HOSTS`: comma-separated hosts which feeds may not be fetched
+    ///   from
+    /// - `SELF_HOSTS`: comma-separated hostnames the worker is reachable on,
+    ///   besides the one each request is made to, so that it can't be made
+    ///   to fetch from itself
+    /// - `ALLOW_PRIVATE_ADDRESSES`: `true` to allow fetching from loopback,
+    ///   private and link-local addresses
+    /// - `KEEP_UPSTREAM_SELF_LINK`: `true` to leave the feed's
+    ///   `atom:link rel="self"` alone, rather than pointing it at the worker
+    /// - `TITLE_ANNOTATION`: text to append to filtered feeds' titles, such as
+    ///   `(filtered)`
+    /// - `CORS_ALLOWED_ORIGINS`: comma-separated origins, such as
+    ///   `https://reader.example.com`, whose scripts may read our responses,
+    ///   or `*` for any. When unset, no CORS headers are sent
+    /// - `RATE_LIMIT_CLIENT_PER_MINUTE`: requests each client may make a
+    ///   minute. When unset or 0, clients aren't limited
+    /// - `RATE_LIMIT_CLIENT_BURST`: requests each client may make at once,
+    ///   which defaults to the per-minute limit
+    /// - `RATE_LIMIT_UPSTREAM_PER_MINUTE` and `RATE_LIMIT_UPSTREAM_BURST`: the
+    ///   same, for fetches from each upstream host
+    /// - `API_KEYS`: comma-separated `id:sha256` pairs, each naming an API key
+    ///   by the hex SHA-256 of the key. When set, feeds can only be filtered
+    ///   with one of these keys, and invalid entries are ignored. Set it as a
+    ///   secret
+    /// - `URL_SIGNING_KEY`: when set, feeds can only be filtered with URLs
+    ///   signed with this key, such as by `rssfilter sign-url`. Set it as a
+    ///   secret
+    ///
+    /// Switches may be `true`, `yes` or `1`, or `false`, `no` or `0`. Values
+    /// which are unset or can't be parsed fall back to their defaults.
+    pub fn from_vars(var: impl Fn(&str) -> Option<String>) -> Self {
+        let defaults = FeedLimits::default();
+        let cache_defaults = CacheConfig::default();
+        let retry_defaults = RetryConfig::default();
+        let redirect_defaults = RedirectConfig::default();
+
+        Self {
+            telemetry: WorkerConfig {
+                log_format: var("LOG_FORMAT"),
+                rust_log: var("RUST_LOG"),
+            },
+            limits: FeedLimits {
+                max_body_bytes: parse_var(&var, "MAX_FEED_BYTES", defaults.max_body_bytes),
+                max_items: parse_var(&var, "MAX_FEED_ITEMS", defaults.max_items),
+                max_depth: parse_var(&var, "MAX_FEED_DEPTH", defaults.max_depth),
+            },
+            cache: CacheConfig {
+                ttl_seconds: parse_var(&var, "CACHE_DEFAULT_TTL", cache_defaults.ttl_seconds),
+                min_ttl_seconds: parse_var(&var, "CACHE_MIN_TTL", cache_defaults.min_ttl_seconds),
+                max_ttl_seconds: parse_var(&var, "CACHE_MAX_TTL", cache_defaults.max_ttl_seconds),
+                stale_while_revalidate_seconds: parse_var(
+                    &var,
+                    "CACHE_STALE_WHILE_REVALIDATE",
+                    cache_defaults.stale_while_revalidate_seconds,
+                ),
+                stale_if_error_seconds: parse_var(
+                    &var,
+                    "CACHE_STALE_IF_ERROR",
+                    cache_defaults.stale_if_error_seconds,
+                ),
+                max_entries: parse_var(&var, "CACHE_MAX_ENTRIES", cache_defaults.max_entries),
+                max_bytes: parse_var(&var, "CACHE_MAX_BYTES", cache_defaults.max_bytes),
+                ..cache_defaults
+            },
+            retry: RetryConfig {
+                timeout: Duration::from_secs(parse_var(
+                    &var,
+                    "UPSTREAM_TIMEOUT",
+                    retry_defaults.timeout.as_secs(),
+                )),
+                max_attempts: parse_var(&var, "UPSTREAM_MAX_ATTEMPTS", retry_defaults.max_attempts),
+                ..retry_defaults
+            },
+            redirect: RedirectConfig {
+                max_redirects: parse_var(&var, "MAX_REDIRECTS", redirect_defaults.max_redirects),
+                same_scheme_only: parse_bool(
+                    &var,
+                    "REDIRECT_SAME_SCHEME_ONLY",
+                    redirect_defaults.same_scheme_only,
+                ),
+            },
+            url_policy: UrlPolicy {
+                allowed_hosts: parse_list(&var, "ALLOWED_HOSTS"),
+                denied_hosts: parse_list(&var, "DENIED_HOSTS"),
+                self_hosts: parse_list(&var, "SELF_HOSTS"),
+                allow_private_addresses: parse_bool(&var, "ALLOW_PRIVATE_ADDRESSES", false),
+            },
+            keep_upstream_self_link: parse_bool(&var, "KEEP_UPSTREAM_SELF_LINK", false),
+            title_annotation: var("TITLE_ANNOTATION")
+                .map(|annotation| annotation.trim().to_string())
+                .filter(|annotation| !annotation.is_empty()),
+            cors: CorsConfig {
+                allowed_origins: parse_list(&var, "CORS_ALLOWED_ORIGINS"),
+            },
+            rate_limit: RateLimitConfig {
+                client: parse_rate_limit(&var, "RATE_LIMIT_CLIENT"),
+                upstream: parse_rate_limit(&var, "RATE_LIMIT_UPSTREAM"),
+            },
+            auth: AuthConfig {
+                api_keys: parse_api_keys(&var),
+            },
+            url_signer: var("URL_SIGNING_KEY")
+                .map(|key| key.trim().to_string())
+                .filter(|key| !key.is_empty())
+                .map(UrlSigner::new),
+        }
+    }
+}
+
+fn parse_var<T: FromStr>(var: impl Fn(&str) -> Option<String>, name: &str, default: T) -> T {
+    let Some(value) = var(name) else {
+        return default;
+    };
+
+    value.trim().parse().unwrap_or_else(|_| {
+        warn!(name, value, "Ignoring invalid value, using the default");
+        default
+    })
+}
+
+fn parse_bool(var: impl Fn(&str) -> Option<String>, name: &str, default: bool) -> bool {
+    let Some(value) = var(name) else {
+        return default;
+    };
+
+    match value.trim().to_ascii_lowercase().as_str() {
+        "true" | "yes" | "1" => true,
+        "false" | "no" | "0" => false,
+        _ => {
+            warn!(name, value, "Ignoring invalid value, using the default");
+            default
+        }
+    }
+}
+
+fn parse_list(var: impl Fn(&str) -> Option<String>, name: &str) -> Vec<String> {
+    var(name)
+        .map(|value| {
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// The rate limit set by `{prefix}_PER_MINUTE` and `{prefix}_BURST`, if
+/// there's one.
+fn parse_rate_limit(var: impl Fn(&str) -> Option<String>, prefix: &str) -> Option<RateLimit> {
+    let per_minute = parse_var(&var, &format!("{prefix}_PER_MINUTE"), 0);
+    if per_minute == 0 {
+        return None;
+    }
+
+    Some(RateLimit {
+        per_minute,
+        burst: parse_var(&var, &format!("{prefix}_BURST"), per_minute),
+    })
+}
+
+/// The keys in `API_KEYS`, if it's set. Keys which can't be parsed are left
+/// out, so if none can, nobody can use the service, rather than everybody.
+fn parse_api_keys(var: impl Fn(&str) -> Option<String>) -> Option<Vec<ApiKey>> {
+    let entries = parse_list(&var, "API_KEYS");
+    if entries.is_empty() {
+        return None;
+    }
+
+    let keys = entries
+        .iter()
+        .filter_map(|entry| {
+            entry
+                .parse()
+                .map_err(|err: String| warn!(err, "Ignoring invalid API key"))
+                .ok()
+        })
+        .collect();
+
+    Some(keys)
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use std::collections::HashMap;
+
+    use filter_rss_feed::DEFAULT_MAX_FEED_DEPTH;
+    use test_case::test_case;
+
+    use super::*;
+
+    fn config_from(vars: &[(&str, &str)]) -> Config {
+        let vars: HashMap<_, _> = vars.iter().copied().collect();
+        Config::from_vars(|name| vars.get(name).map(|value| value.to_string()))
+    }
+
+    #[test]
+    fn test_defaults() {
+        let config = config_from(&[]);
+
+        assert_eq!(config.limits, FeedLimits::default());
+        assert_eq!(config.telemetry.log_format, None);
+        assert_eq!(config.telemetry.rust_log, None);
+        assert_eq!(config.url_policy, UrlPolicy::default());
+        assert_eq!(config.redirect, RedirectConfig::default());
+        assert!(!config.keep_upstream_self_link);
+        assert_eq!(config.title_annotation, None);
+        assert_eq!(config.cors, CorsConfig::default());
+        assert_eq!(config.rate_limit, RateLimitConfig::default());
+        assert_eq!(config.auth, AuthConfig::default());
+        assert!(config.url_signer.is_none());
+    }
+
+    #[test_case("TRUE", true ; "upper case true")]
+    #[test_case(" yes ", true ; "yes")]
+    #[test_case("1", true ; "one")]
+    #[test_case("No", false ; "no")]
+    #[test_case("0", false ; "zero")]
+    #[test_case("off", true ; "invalid")]
+    fn test_parse_bool(value: &str, expected: bool) {
+        assert_eq!(
+            parse_bool(|_| Some(value.to_string()), "SWITCH", true),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_from_vars() {
+        let config = config_from(&[
+            ("LOG_FORMAT", "json"),
+            ("RUST_LOG", "debug"),
+            ("MAX_FEED_BYTES", "1024"),
+            ("MAX_FEED_ITEMS", " 50 "),
+            ("MAX_FEED_DEPTH", "-1"),
+            ("CACHE_MIN_TTL", "120"),
+            ("CACHE_STALE_IF_ERROR", "3600"),
+            ("CACHE_MAX_ENTRIES", "200"),
+            ("UPSTREAM_TIMEOUT", "5"),
+            ("DENIED_HOSTS", "internal.example.com, ,corp.example.com"),
+            ("ALLOW_PRIVATE_ADDRESSES", "true"),
+            ("MAX_REDIRECTS", "3"),
+            ("REDIRECT_SAME_SCHEME_ONLY", "yes"),
+            ("KEEP_UPSTREAM_SELF_LINK", "1"),
+            ("TITLE_ANNOTATION", " (filtered) "),
+            (
+                "CORS_ALLOWED_ORIGINS",
+                "https://reader.example.com, https://app.example.org",
+            ),
+            ("RATE_LIMIT_CLIENT_PER_MINUTE", "30"),
+            ("RATE_LIMIT_UPSTREAM_PER_MINUTE", "0"),
+            ("RATE_LIMIT_UPSTREAM_BURST", "5"),
+            (
+                "API_KEYS",
+                "team:2bb80d537b1da3e38bd30361aa855686bde0eacd7162fef6a25fe97bf527a25b, broken",
+            ),
+            ("URL_SIGNING_KEY", " shared secret "),
+        ]);
+
+        assert_eq!(config.telemetry.log_format.as_deref(), Some("json"));
+        assert_eq!(config.telemetry.rust_log.as_deref(), Some("debug"));
+        assert_eq!(config.limits.max_body_bytes, 1024);
+        assert_eq!(config.limits.max_items, 50);
+        assert_eq!(config.limits.max_depth, DEFAULT_MAX_FEED_DEPTH);
+        assert_eq!(config.cache.min_ttl_seconds, 120);
+        assert_eq!(config.cache.stale_if_error_seconds, 3600);
+        assert_eq!(
+            config.cache.max_ttl_seconds,
+            CacheConfig::default().max_ttl_seconds
+        );
+        assert_eq!(config.cache.max_entries, 200);
+        assert_eq!(config.cache.max_bytes, CacheConfig::default().max_bytes);
+        assert_eq!(config.retry.timeout, Duration::from_secs(5));
+        assert_eq!(
+            config.url_policy.denied_hosts,
+            ["internal.example.com", "corp.example.com"]
+        );
+        assert!(config.url_policy.allowed_hosts.is_empty());
+        assert!(config.url_policy.allow_private_addresses);
+        assert_eq!(
+            config.retry.max_attempts,
+            RetryConfig::default().max_attempts
+        );
+        assert_eq!(config.redirect.max_redirects, 3);
+        assert!(config.redirect.same_scheme_only);
+        assert!(config.keep_upstream_self_link);
+        assert_eq!(config.title_annotation.as_deref(), Some("(filtered)"));
+        assert_eq!(
+            config.cors.allowed_origins,
+            ["https://reader.example.com", "https://app.example.org"]
+        );
+        assert_eq!(
+            config.rate_limit.client,
+            Some(RateLimit {
+                per_minute: 30,
+                burst: 30
+            })
+        );
+        assert_eq!(config.rate_limit.upstream, None);
+        let api_keys = config.auth.api_keys.unwrap();
+        assert_eq!(
+            api_keys
+                .iter()
+                .map(|key| key.id.as_str())
+                .collect::<Vec<_>>(),
+            ["team"]
+        );
+        let url = url::Url::parse("https://rssfilter.example.com/?url=a").unwrap();
+        let signed = UrlSigner::new("shared secret").sign(&url);
+        assert_eq!(config.url_signer.unwrap().verify(&signed), Ok(()));
+    }
+
+    #[test]
+    fn test_empty_signing_key() {
+        let config = config_from(&[("URL_SIGNING_KEY", "  ")]);
+
+        assert!(config.url_signer.is_none());
+    }
+}
@@ -0,0 +1,255 @@
+use bytes::Bytes;
+use http::header::{
+    ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_ALLOW_ORIGIN,
+    ACCESS_CONTROL_EXPOSE_HEADERS, ACCESS_CONTROL_MAX_AGE, ACCESS_CONTROL_REQUEST_METHOD, ALLOW,
+    ORIGIN, VARY,
+};
+use http::{HeaderMap, HeaderValue, Method, Response, StatusCode};
+
+/// Lists `methods` for `Allow` and `Access-Control-Allow-Methods`.
+pub(crate) fn allow_header(methods: &[Method]) -> HeaderValue {
+    let methods = methods
+        .iter()
+        .map(Method::as_str)
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    HeaderValue::from_str(&methods).expect("Methods are valid header values")
+}
+
+/// Response headers which scripts may read besides the CORS-safelisted ones,
+/// so that web readers can revalidate and see what was filtered.
+const EXPOSED_HEADERS: &str =
+    "etag, x-rssfilter-items-removed, x-rssfilter-cache-status, x-rssfilter-moved-permanently";
+
+/// Request headers which scripts may send: API keys, the type of posted
+/// filter definitions, and our `ETag` to revalidate with.
+const ALLOWED_HEADERS: &str = "Authorization, Content-Type, If-None-Match";
+
+/// How long browsers may cache a preflight response for, in seconds.
+const PREFLIGHT_MAX_AGE: u32 = 86400;
+
+/// Which web pages may call us from another origin.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CorsConfig {
+    /// Origins such as `https://reader.example.com` which may read our
+    /// responses, or `*` for any origin. When empty, no CORS headers are
+    /// sent, and browsers keep responses from other origins' scripts.
+    pub allowed_origins: Vec<String>,
+}
+
+impl CorsConfig {
+    fn allows_any_origin(&self) -> bool {
+        self.allowed_origins.iter().any(|origin| origin == "*")
+    }
+
+    /// The `Access-Control-Allow-Origin` to answer a request from `origin`
+    /// with, if it's allowed.
+    fn allow_origin(&self, origin: &HeaderValue) -> Option<HeaderValue> {
+        if self.allows_any_origin() {
+            return Some(HeaderValue::from_static("*"));
+        }
+
+        let origin_str = origin.to_str().ok()?;
+
+        self.allowed_origins
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(origin_str))
+            .then(|| origin.clone())
+    }
+}
+
+/// Adds the CORS headers for a request with the given `Origin` to a response.
+/// This is done for errors too, so that scripts can see why they failed.
+pub(crate) fn add_cors_headers(
+    origin: Option<&HeaderValue>,
+    response: &mut Response<Bytes>,
+    config: &CorsConfig,
+) {
+    if config.allowed_origins.is_empty() {
+        return;
+    }
+
+    let headers = response.headers_mut();
+
+    // Unless every origin gets the same answer, caches must keep them apart
+    if !config.allows_any_origin() {
+        headers.append(VARY, HeaderValue::from_static("origin"));
+    }
+
+    // With any origin allowed, every response is the same, whether or not
+    // the request came from a browser
+    let allow_origin = match origin {
+        Some(origin) => config.allow_origin(origin),
+        None if config.allows_any_origin() => Some(HeaderValue::from_static("*")),
+        None => None,
+    };
+    let Some(allow_origin) = allow_origin else {
+        return;
+    };
+
+    headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, allow_origin);
+    headers.insert(
+        ACCESS_CONTROL_EXPOSE_HEADERS,
+        HeaderValue::from_static(EXPOSED_HEADERS),
+    );
+}
+
+/// Answers an `OPTIONS` request to a resource which allows `methods`. For a
+/// CORS preflight from an allowed origin, this says which methods and headers
+/// the real request may use; [`add_cors_headers`] adds the rest.
+pub(crate) fn preflight(
+    request_headers: &HeaderMap,
+    config: &CorsConfig,
+    methods: &[Method],
+) -> Response<Bytes> {
+    let mut response = Response::builder()
+        .status(StatusCode::NO_CONTENT)
+        .header(ALLOW, allow_header(methods))
+        .body(Bytes::new())
+        .unwrap();
+
+    let origin_allowed = request_headers
+        .get(ORIGIN)
+        .and_then(|origin| config.allow_origin(origin))
+        .is_some();
+    let method_allowed = request_headers
+        .get(ACCESS_CONTROL_REQUEST_METHOD)
+        .and_then(|method| Method::from_bytes(method.as_bytes()).ok())
+        .is_some_and(|method| method != Method::OPTIONS && methods.contains(&method));
+
+    if !origin_allowed || !method_allowed {
+        return response;
+    }
+
+    let headers = response.headers_mut();
+    headers.insert(ACCESS_CONTROL_ALLOW_METHODS, allow_header(methods));
+    headers.insert(ACCESS_CONTROL_MAX_AGE, PREFLIGHT_MAX_AGE.into());
+    headers.insert(
+        ACCESS_CONTROL_ALLOW_HEADERS,
+        HeaderValue::from_static(ALLOWED_HEADERS),
+    );
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::header::ACCESS_CONTROL_REQUEST_HEADERS;
+    use test_case::test_case;
+
+    const METHODS: &[Method] = &[Method::GET, Method::HEAD, Method::OPTIONS];
+
+    fn config(allowed_origins: &[&str]) -> CorsConfig {
+        CorsConfig {
+            allowed_origins: allowed_origins.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    fn response_for(origin: Option<&str>, config: &CorsConfig) -> Response<Bytes> {
+        let origin = origin.map(|origin| HeaderValue::from_str(origin).unwrap());
+        let mut response = Response::new(Bytes::new());
+        add_cors_headers(origin.as_ref(), &mut response, config);
+        response
+    }
+
+    #[test_case(&[], Some("https://reader.example.com"), None; "disabled")]
+    #[test_case(&["*"], Some("https://reader.example.com"), Some("*"); "any origin")]
+    #[test_case(&["*"], None, Some("*"); "any origin without origin header")]
+    #[test_case(
+        &["https://reader.example.com"],
+        Some("https://Reader.example.com"),
+        Some("https://Reader.example.com");
+        "listed origin"
+    )]
+    #[test_case(
+        &["https://reader.example.com"],
+        Some("https://other.example.com"),
+        None;
+        "unlisted origin"
+    )]
+    #[test_case(&["https://reader.example.com"], None, None; "no origin header")]
+    fn test_allow_origin(allowed: &[&str], origin: Option<&str>, expected: Option<&str>) {
+        let response = response_for(origin, &config(allowed));
+
+        assert_eq!(
+            response
+                .headers()
+                .get(ACCESS_CONTROL_ALLOW_ORIGIN)
+                .map(|value| value.to_str().unwrap()),
+            expected
+        );
+        assert_eq!(
+            response
+                .headers()
+                .contains_key(ACCESS_CONTROL_EXPOSE_HEADERS),
+            expected.is_some()
+        );
+    }
+
+    #[test_case(&[], false; "disabled")]
+    #[test_case(&["*"], false; "any origin")]
+    #[test_case(&["https://reader.example.com"], true; "listed origins")]
+    fn test_vary(allowed: &[&str], varies: bool) {
+        let response = response_for(Some("https://other.example.com"), &config(allowed));
+
+        assert_eq!(response.headers().contains_key(VARY), varies);
+    }
+
+    #[test]
+    fn test_preflight() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            ORIGIN,
+            HeaderValue::from_static("https://reader.example.com"),
+        );
+        headers.insert(
+            ACCESS_CONTROL_REQUEST_METHOD,
+            HeaderValue::from_static("GET"),
+        );
+        headers.insert(
+            ACCESS_CONTROL_REQUEST_HEADERS,
+            HeaderValue::from_static("if-none-match, x-forwarded-for"),
+        );
+
+        let response = preflight(&headers, &config(&["https://reader.example.com"]), METHODS);
+
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert_eq!(response.headers()[ALLOW], "GET, HEAD, OPTIONS");
+        assert_eq!(
+            response.headers()[ACCESS_CONTROL_ALLOW_METHODS],
+            "GET, HEAD, OPTIONS"
+        );
+        assert_eq!(
+            response.headers()[ACCESS_CONTROL_ALLOW_HEADERS],
+            "Authorization, Content-Type, If-None-Match"
+        );
+        assert_eq!(response.headers()[ACCESS_CONTROL_MAX_AGE], "86400");
+    }
+
+    #[test_case(&[], "GET"; "cors disabled")]
+    #[test_case(&["https://other.example.com"], "GET"; "origin not allowed")]
+    #[test_case(&["*"], "POST"; "method not allowed")]
+    fn test_preflight_refused(allowed: &[&str], method: &'static str) {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            ORIGIN,
+            HeaderValue::from_static("https://reader.example.com"),
+        );
+        headers.insert(
+            ACCESS_CONTROL_REQUEST_METHOD,
+            HeaderValue::from_static(method),
+        );
+
+        let response = preflight(&headers, &config(allowed), METHODS);
+
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert_eq!(response.headers()[ALLOW], "GET, HEAD, OPTIONS");
+        assert!(
+            !response
+                .headers()
+                .contains_key(ACCESS_CONTROL_ALLOW_METHODS)
+        );
+    }
+}
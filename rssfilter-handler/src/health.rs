@@ -0,0 +1,122 @@
+use bytes::Bytes;
+use http::header::{CACHE_CONTROL, CONTENT_TYPE};
+use http::{Response, StatusCode};
+use serde_json::json;
+use tracing::error;
+
+use crate::{Config, RssHandlerError};
+
+/// The commit we were built from, if the build was told. The deploy script
+/// and the nix packages set `RSSFILTER_GIT_REVISION`, possibly to nothing.
+fn git_revision() -> Option<&'static str> {
+    option_env!("RSSFILTER_GIT_REVISION").filter(|revision| !revision.is_empty())
+}
+
+fn text_response(status: StatusCode, body: impl Into<Bytes>) -> Response<Bytes> {
+    Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "text/plain")
+        .header(CACHE_CONTROL, "no-store")
+        .body(body.into())
+        .unwrap()
+}
+
+/// Answers `/healthz`: we're up. Nothing is fetched, so this says nothing
+/// about any feed.
+pub(crate) fn healthz() -> Response<Bytes> {
+    text_response(StatusCode::OK, "ok")
+}
+
+/// Answers `/readyz` with whether telemetry was initialised, as every filter
+/// request fails without it. Why it wasn't is logged rather than shown, as it
+/// may name the collector.
+pub(crate) fn readyz(telemetry: &Result<(), RssHandlerError>) -> Response<Bytes> {
+    match telemetry {
+        Ok(()) => text_response(StatusCode::OK, "ready"),
+        Err(err) => {
+            error!(%err, "Not ready: telemetry was not initialised");
+            text_response(StatusCode::SERVICE_UNAVAILABLE, "not ready")
+        }
+    }
+}
+
+/// The optional parts of the service which `config` turns on, for clients to
+/// check before relying on them.
+fn enabled_features(config: &Config) -> Vec<&'static str> {
+    [
+        ("cors", !config.cors.allowed_origins.is_empty()),
+        ("self_link", !config.keep_upstream_self_link),
+        ("title_annotation", config.title_annotation.is_some()),
+        ("client_rate_limit", config.rate_limit.client.is_some()),
+        ("upstream_rate_limit", config.rate_limit.upstream.is_some()),
+        ("api_keys", config.auth.api_keys.is_some()),
+        ("signed_urls", config.url_signer.is_some()),
+    ]
+    .into_iter()
+    .filter_map(|(feature, enabled)| enabled.then_some(feature))
+    .collect()
+}
+
+/// Answers `/version` with what is running, as JSON.
+pub(crate) fn version(config: &Config) -> Response<Bytes> {
+    let body = json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "git_revision": git_revision(),
+        "features": enabled_features(config),
+    });
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "application/json")
+        .body(body.to_string().into())
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CorsConfig;
+    use rssfilter_telemetry::TracingError;
+    use serde_json::Value;
+
+    #[test]
+    fn test_healthz() {
+        let response = healthz();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[CACHE_CONTROL], "no-store");
+        assert_eq!(response.body(), "ok");
+    }
+
+    #[test]
+    fn test_readyz() {
+        assert_eq!(readyz(&Ok(())).status(), StatusCode::OK);
+
+        let failed = Err(RssHandlerError::Tracing(TracingError::OtlpError(
+            "no collector".to_string(),
+        )));
+        let response = readyz(&failed);
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.body(), "not ready");
+    }
+
+    #[test]
+    fn test_version() {
+        let config = Config {
+            cors: CorsConfig {
+                allowed_origins: vec!["*".to_string()],
+            },
+            keep_upstream_self_link: true,
+            ..Default::default()
+        };
+
+        let response = version(&config);
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[CONTENT_TYPE], "application/json");
+
+        let body: Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body["version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(body["git_revision"].as_str(), git_revision());
+        assert_eq!(body["features"], json!(["cors"]));
+    }
+}
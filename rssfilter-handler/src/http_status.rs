@@ -12,9 +12,14 @@ macro_rules! status_code {
 status_code! {
   BAD_GATEWAY => BAD_GATEWAY,
   BAD_REQUEST => BAD_REQUEST,
+  FORBIDDEN => FORBIDDEN,
+  GATEWAY_TIMEOUT => GATEWAY_TIMEOUT,
   INTERNAL_SERVER_ERROR => INTERNAL_SERVER_ERROR,
   NOT_FOUND => NOT_FOUND,
   METHOD_NOT_ALLOWED => METHOD_NOT_ALLOWED,
   PAYLOAD_TOO_LARGE => PAYLOAD_TOO_LARGE,
+  TOO_MANY_REQUESTS => TOO_MANY_REQUESTS,
+  UNAUTHORIZED => UNAUTHORIZED,
+  UNPROCESSABLE_ENTITY => UNPROCESSABLE_ENTITY,
   UNSUPPORTED_MEDIA_TYPE => UNSUPPORTED_MEDIA_TYPE,
 }
@@ -0,0 +1,3148 @@
+use bytes::Bytes;
+use http::header::{
+    ALLOW, CONTENT_LENGTH, CONTENT_TYPE, IF_MATCH, IF_MODIFIED_SINCE, IF_NONE_MATCH,
+    IF_UNMODIFIED_SINCE, ORIGIN, RETRY_AFTER, WWW_AUTHENTICATE,
+};
+use http::{HeaderMap, HeaderValue, Method, Request, Response, StatusCode, Uri};
+use opentelemetry_http::HeaderExtractor;
+use regex::Regex;
+use rssfilter_telemetry::TracingError;
+use serde_json::json;
+use std::borrow::Cow;
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+use tracing::{Instrument, debug, info, instrument, warn};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use url::{ParseError, Url};
+use urlencoding::decode;
+use uuid::Uuid;
+use web_time::Instant;
+
+use filter_rss_feed::{
+    FEED_PARAMS, FeedItem, FilterProfile, FilterRegexes, GUID_FILTER_PARAM, HttpClientError,
+    LINK_FILTER_PARAM, Layer, NeedsRevalidation, OpmlError, RssError, RssFilter, RssFilterConfig,
+    SIGNATURE_PARAM, SignatureError, TITLE_FILTER_PARAM, URL_PARAM, UrlPolicy, UrlPolicyError,
+    UrlPolicyLayer, UrlSigner, filter_subscriptions, unfilter_subscriptions,
+};
+
+#[cfg(all(test, target_arch = "wasm32"))]
+use filter_rss_feed::fake_http_client::FakeHttpClientBuilder;
+use rssfilter_telemetry::WorkerConfig;
+
+mod auth;
+use auth::authenticate;
+pub use auth::{ApiKey, ApiKeyId, AuthConfig, AuthError};
+
+mod backend;
+pub use backend::Backend;
+
+mod config;
+pub use config::Config;
+
+mod cors;
+pub use cors::CorsConfig;
+use cors::{add_cors_headers, allow_header, preflight};
+
+mod filter;
+use filter::filter_request_headers;
+
+mod health;
+use health::{healthz, readyz, version};
+
+mod http_status;
+use http_status::*;
+
+mod post_body;
+use post_body::{PostBody, parse_post_body};
+
+mod problem;
+use problem::ErrorFormat;
+
+mod rate_limit;
+pub use rate_limit::{ClientAddr, RateLimitConfig};
+use rate_limit::{check_client, retry_after_header, retry_after_seconds};
+
+mod ui;
+use ui::ui;
+
+/// Where the JSON API filters feeds.
+const API_FILTER_PATH: &str = "/api/v1/filter";
+
+/// Where the JSON API previews filters as they're written.
+const API_PREVIEW_PATH: &str = "/api/v1/preview";
+
+/// Where OPML subscription lists are rewritten so that every feed is
+/// filtered.
+const API_OPML_FILTER_PATH: &str = "/api/v1/opml/filter";
+
+/// Where OPML subscription lists are rewritten so that no feed is filtered.
+const API_OPML_UNFILTER_PATH: &str = "/api/v1/opml/unfilter";
+
+#[derive(Debug, Error)]
+pub enum RequestValidationError {
+    #[error("Not Found")]
+    NotFound,
+    #[error("Method Not Allowed")]
+    MethodNotAllowed,
+}
+
+impl From<RequestValidationError> for Response<Bytes> {
+    fn from(err: RequestValidationError) -> Response<Bytes> {
+        let (status_code, message) = match err {
+            RequestValidationError::NotFound => (*NOT_FOUND, "Not Found"),
+            RequestValidationError::MethodNotAllowed => (*METHOD_NOT_ALLOWED, "Method Not Allowed"),
+        };
+
+        Response::builder()
+            .status(status_code)
+            .header("content-type", "text/plain")
+            .body(Bytes::from(message))
+            .unwrap()
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ValidationError {
+    #[error("the parameter {name} could not be decoded: {source}")]
+    MalformedParameter {
+        name: &'static str,
+        #[source]
+        source: std::string::FromUtf8Error,
+    },
+
+    #[error("the regex for {name} is invalid: {source}")]
+    InvalidRegex {
+        name: &'static str,
+        #[source]
+        source: regex::Error,
+    },
+
+    #[error(
+        "A url and at least one of title_filter_regex, guid_filter_regex, or link_filter_regex must be provided"
+    )]
+    NoParametersProvided,
+
+    #[error(
+        "At least one of title_filter_regex, guid_filter_regex, or link_filter_regex must be provided"
+    )]
+    NoFiltersProvided,
+
+    #[error("The provided URL is malformed: {source}")]
+    UrlParseError {
+        #[source]
+        source: ParseError,
+    },
+
+    #[error("A URL must be provided")]
+    NoUrlProvided,
+
+    #[error("The feed URL is not allowed: {0}")]
+    UrlPolicy(#[from] UrlPolicyError),
+
+    #[error("The request body is invalid: {0}")]
+    InvalidBody(String),
+
+    #[error(
+        "Can't filter a request body of type {content_type:?}: send a JSON or form filter definition, or an RSS or Atom feed"
+    )]
+    UnsupportedBody { content_type: String },
+
+    #[error("{0}")]
+    InvalidOpml(#[from] OpmlError),
+
+    #[error("{0}")]
+    Signature(#[from] SignatureError),
+}
+
+#[derive(Debug, Error)]
+pub enum ProcessingError {
+    #[error("RSS processing failed: {0}")]
+    Rss(#[from] RssError),
+
+    #[error("HTTP request building failed: {source}")]
+    RequestBuild {
+        #[source]
+        source: http::Error,
+    },
+
+    #[error("The feed's server responded with {status}")]
+    UpstreamStatus { status: StatusCode },
+}
+
+#[derive(Debug, Error)]
+pub enum RssHandlerError {
+    #[error("Request validation failed: {0}")]
+    Validation(#[from] ValidationError),
+
+    #[error("RSS processing failed: {0}")]
+    Processing(#[from] ProcessingError),
+
+    #[error("Tracing error: {0}")]
+    Tracing(#[from] TracingError),
+
+    #[error(
+        "Too many requests; try again in {} seconds",
+        retry_after_seconds(*retry_after)
+    )]
+    RateLimited { retry_after: Duration },
+
+    #[error("{0}")]
+    Auth(#[from] AuthError),
+}
+
+impl RssHandlerError {
+    /// How long to wait before trying again, for errors which say.
+    fn retry_after(&self) -> Option<Duration> {
+        match self {
+            RssHandlerError::RateLimited { retry_after }
+            | RssHandlerError::Processing(ProcessingError::Rss(RssError::HttpClient(
+                HttpClientError::RateLimited { retry_after, .. },
+            ))) => Some(*retry_after),
+            _ => None,
+        }
+    }
+}
+
+// Manual conversions for cases where we can't use #[from]
+impl From<ParseError> for ValidationError {
+    fn from(value: ParseError) -> Self {
+        ValidationError::UrlParseError { source: value }
+    }
+}
+
+impl From<http::Error> for ProcessingError {
+    fn from(value: http::Error) -> Self {
+        ProcessingError::RequestBuild { source: value }
+    }
+}
+
+impl From<RssError> for RssHandlerError {
+    fn from(value: RssError) -> Self {
+        RssHandlerError::Processing(ProcessingError::Rss(value))
+    }
+}
+
+impl From<&RssHandlerError> for Response<Bytes> {
+    fn from(err: &RssHandlerError) -> Response<Bytes> {
+        let message: Bytes = err.to_string().into();
+
+        let status_code = match err {
+            RssHandlerError::Processing(processing_err) => match processing_err {
+                ProcessingError::RequestBuild { .. } => *BAD_GATEWAY,
+                ProcessingError::UpstreamStatus { .. } => *BAD_GATEWAY,
+                ProcessingError::Rss(rss_err) => match rss_err {
+                    RssError::Http { .. } => *BAD_GATEWAY,
+                    RssError::FeedTooLarge { .. } => *PAYLOAD_TOO_LARGE,
+                    RssError::TooManyItems { .. } => *PAYLOAD_TOO_LARGE,
+                    RssError::NestingTooDeep { .. } => *UNPROCESSABLE_ENTITY,
+                    RssError::HttpClient(HttpClientError::Timeout(_)) => *GATEWAY_TIMEOUT,
+                    RssError::HttpClient(HttpClientError::UrlPolicy(_)) => *FORBIDDEN,
+                    RssError::HttpClient(HttpClientError::RateLimited { .. }) => *TOO_MANY_REQUESTS,
+                    RssError::HttpClient { .. } => *BAD_GATEWAY,
+                    RssError::InvalidContentType { .. } => *UNSUPPORTED_MEDIA_TYPE,
+                    RssError::IO { .. } => *INTERNAL_SERVER_ERROR,
+                    RssError::RSSParse { .. } => *BAD_REQUEST,
+                    RssError::UTF8 { .. } => *INTERNAL_SERVER_ERROR,
+                },
+            },
+            RssHandlerError::Tracing { .. } => *INTERNAL_SERVER_ERROR,
+            RssHandlerError::RateLimited { .. } => *TOO_MANY_REQUESTS,
+            RssHandlerError::Auth(AuthError::MissingKey) => *UNAUTHORIZED,
+            RssHandlerError::Auth(AuthError::InvalidKey) => *FORBIDDEN,
+            RssHandlerError::Validation(ValidationError::UrlPolicy(
+                UrlPolicyError::Invalid(_) | UrlPolicyError::NoHost,
+            )) => *BAD_REQUEST,
+            RssHandlerError::Validation(ValidationError::UrlPolicy(_)) => *FORBIDDEN,
+            RssHandlerError::Validation(ValidationError::Signature(_)) => *FORBIDDEN,
+            RssHandlerError::Validation(ValidationError::UnsupportedBody { .. }) => {
+                *UNSUPPORTED_MEDIA_TYPE
+            }
+            RssHandlerError::Validation { .. } => *BAD_REQUEST,
+        };
+
+        let mut response = Response::builder()
+            .status(status_code)
+            .header("Content-Type", "text/plain")
+            .body(message)
+            .unwrap();
+
+        if let Some(retry_after) = err.retry_after() {
+            response
+                .headers_mut()
+                .insert(RETRY_AFTER, retry_after_header(retry_after));
+        }
+
+        if let RssHandlerError::Auth(AuthError::MissingKey) = err {
+            response
+                .headers_mut()
+                .insert(WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+        }
+
+        response
+    }
+}
+
+impl From<RssHandlerError> for Response<Bytes> {
+    fn from(err: RssHandlerError) -> Response<Bytes> {
+        (&err).into()
+    }
+}
+
+struct RegexParams {
+    title_regexes: Vec<Regex>,
+    guid_regexes: Vec<Regex>,
+    link_regexes: Vec<Regex>,
+}
+
+impl RegexParams {
+    fn is_empty(&self) -> bool {
+        [&self.title_regexes, &self.guid_regexes, &self.link_regexes]
+            .iter()
+            .all(|regexes| regexes.is_empty())
+    }
+}
+
+impl std::fmt::Debug for RegexParams {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let regexes_to_str = |regexes: &Vec<Regex>| {
+            regexes
+                .iter()
+                .map(|r| r.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+
+        write!(
+            f,
+            "title: [{}], guid: [{}], link: [{}]",
+            regexes_to_str(&self.title_regexes),
+            regexes_to_str(&self.guid_regexes),
+            regexes_to_str(&self.link_regexes)
+        )
+    }
+}
+
+#[derive(Debug)]
+pub struct Params<'a> {
+    regex_params: RegexParams,
+    url: Cow<'a, str>,
+}
+
+impl<'a> From<&'a RegexParams> for FilterRegexes<'a> {
+    fn from(params: &'a RegexParams) -> Self {
+        FilterRegexes {
+            title_regexes: &params.title_regexes,
+            guid_regexes: &params.guid_regexes,
+            link_regexes: &params.link_regexes,
+        }
+    }
+}
+
+/// What a request is for, from its path.
+#[derive(Debug, PartialEq, Eq)]
+enum Route {
+    /// "/": filter a feed
+    Filter,
+    /// [`API_FILTER_PATH`]: say what filtering a feed keeps and removes
+    ApiFilter,
+    /// [`API_PREVIEW_PATH`]: list a feed's items and what the filters match
+    ApiPreview,
+    /// "/ui": a page for writing filters
+    Ui,
+    /// [`API_OPML_FILTER_PATH`]: filter every feed of a subscription list
+    ApiOpmlFilter,
+    /// [`API_OPML_UNFILTER_PATH`]: stop filtering the feeds of a subscription
+    /// list
+    ApiOpmlUnfilter,
+    /// "/healthz": are we up?
+    Healthz,
+    /// "/readyz": can we filter feeds?
+    Readyz,
+    /// "/version": what's running?
+    Version,
+}
+
+impl Route {
+    fn from_path(path: &str) -> Option<Self> {
+        match path {
+            "/" => Some(Route::Filter),
+            API_FILTER_PATH => Some(Route::ApiFilter),
+            API_PREVIEW_PATH => Some(Route::ApiPreview),
+            "/ui" => Some(Route::Ui),
+            API_OPML_FILTER_PATH => Some(Route::ApiOpmlFilter),
+            API_OPML_UNFILTER_PATH => Some(Route::ApiOpmlUnfilter),
+            "/healthz" => Some(Route::Healthz),
+            "/readyz" => Some(Route::Readyz),
+            "/version" => Some(Route::Version),
+            _ => None,
+        }
+    }
+
+    /// The methods the route answers. Filter definitions and feeds can be
+    /// posted to "/", and subscription lists must be posted.
+    fn methods(&self) -> &'static [Method] {
+        match self {
+            Route::Filter => &[Method::GET, Method::HEAD, Method::OPTIONS, Method::POST],
+            Route::ApiOpmlFilter | Route::ApiOpmlUnfilter => &[Method::OPTIONS, Method::POST],
+            _ => &[Method::GET, Method::HEAD, Method::OPTIONS],
+        }
+    }
+}
+
+/// Validate request method and path
+fn validate_request<T>(req: &Request<T>) -> Result<Route, RequestValidationError> {
+    let route = Route::from_path(req.uri().path()).ok_or(RequestValidationError::NotFound)?;
+
+    if !route.methods().contains(req.method()) {
+        return Err(RequestValidationError::MethodNotAllowed);
+    }
+
+    Ok(route)
+}
+
+/// The response to a `HEAD` request: the headers of the `GET` response,
+/// including how long its body would have been, without the body.
+fn without_body(response: Response<Bytes>) -> Response<Bytes> {
+    let (mut parts, body) = response.into_parts();
+
+    if !body.is_empty() {
+        parts.headers.insert(CONTENT_LENGTH, body.len().into());
+    }
+
+    Response::from_parts(parts, Bytes::new())
+}
+
+/// Validate content type to ensure we're processing RSS/XML
+/// Log request metrics for observability
+fn log_request_metrics(url: &str, status: StatusCode, duration_ms: Duration) {
+    info!(
+        url = url,
+        status = status.to_string(),
+        duration_ms = duration_ms.as_millis(),
+        "Request completed"
+    );
+}
+
+#[instrument]
+fn decode_and_compile_regex(url: &Url, key: &'static str) -> Result<Vec<Regex>, ValidationError> {
+    url.query_pairs()
+        .filter(|(k, _)| k == key)
+        .map(|(_, value)| {
+            let value_string = value.to_string();
+            let decoded =
+                decode(&value_string).map_err(|err| ValidationError::MalformedParameter {
+                    name: key,
+                    source: err,
+                })?;
+            Regex::new(&decoded).map_err(|err| ValidationError::InvalidRegex {
+                name: key,
+                source: err,
+            })
+        })
+        .collect()
+}
+
+fn parse_regex_params(url: &Url) -> Result<RegexParams, ValidationError> {
+    Ok(RegexParams {
+        title_regexes: decode_and_compile_regex(url, TITLE_FILTER_PARAM)?,
+        guid_regexes: decode_and_compile_regex(url, GUID_FILTER_PARAM)?,
+        link_regexes: decode_and_compile_regex(url, LINK_FILTER_PARAM)?,
+    })
+}
+
+fn feed_url_param(url: &Url) -> Option<Cow<'_, str>> {
+    url.query_pairs()
+        .find_map(|(k, v)| (k == URL_PARAM).then_some(v))
+}
+
+/// Checks that `url` is signed, when filtered feeds' URLs must be.
+fn check_signature(url: &Url, signer: Option<&UrlSigner>) -> Result<(), ValidationError> {
+    match signer {
+        Some(signer) => Ok(signer.verify(url)?),
+        None => Ok(()),
+    }
+}
+
+#[instrument]
+fn validate_parameters<'a>(
+    url: &'a Url,
+    policy: &UrlPolicy,
+    signer: Option<&UrlSigner>,
+) -> Result<Params<'a>, ValidationError> {
+    let regex_params = parse_regex_params(url)?;
+    let feed_url = feed_url_param(url);
+
+    let any_filters_provided = !regex_params.is_empty();
+    let url_provided = feed_url.is_some();
+
+    match (any_filters_provided, url_provided) {
+        (false, false) => return Err(ValidationError::NoParametersProvided),
+        (false, true) => return Err(ValidationError::NoFiltersProvided),
+        (true, false) => return Err(ValidationError::NoUrlProvided),
+        _ => {}
+    }
+
+    check_signature(url, signer)?;
+
+    let feed_url = feed_url.unwrap();
+    policy.check_str(&feed_url)?;
+
+    Ok(Params {
+        regex_params,
+        url: feed_url,
+    })
+}
+
+/// Handles the incoming request for the RSS filter. The query string parameters
+/// are used to filter the RSS feed. Each item in the RSS feed is checked against
+/// the provided regexes. If any one of the regex matches, the item is filtered
+/// out.
+///
+/// The following query string parameters are supported:
+/// - `title_filter_regex`: A regex to filter the title of the item.
+/// - `guid_filter_regex`: A regex to filter the guid of the item.
+/// - `link_filter_regex`: A regex to filter the link of the item.
+///
+/// At least one of `title_filter_regex`, `guid_filter_regex`, or
+/// `link_filter_regex` must be provided. Each can be given multiple times.
+///
+/// The `url` query string parameter is required and is the URL of the RSS feed.
+///
+/// The response will be the filtered RSS feed.
+///
+/// # Example
+/// Given the following RSS feed:
+/// ```xml
+/// <rss version="2.0">
+///   <channel>
+///     <title>Example Feed</title>
+///     <link>http://example.com/</link>
+///     <description>Example feed</description>
+///     <item>
+///       <title>Item 1</title>
+///       <link>http://example.com/item1</link>
+///       <guid>1</guid>
+///     </item>
+///     <item>
+///       <title>Item 2</title>
+///       <link>http://example.com/item2</link>
+///       <guid>2</guid>
+///     </item>
+///   </channel>
+/// </rss>
+/// ```
+///
+/// and the following query string parameters:
+/// - `title_filter_regex=Item 1`
+/// - `url=http://example.com/rss`
+///
+/// The response will be:
+/// ```xml
+/// <rss version="2.0">
+///   <channel>
+///     <title>Example Feed</title>
+///     <link>http://example.com/</link>
+///     <description>Example feed</description>
+///     <item>
+///       <title>Item 2</title>
+///       <link>http://example.com/item2</link>
+///       <guid>2</guid>
+///     </item>
+///   </channel>
+/// </rss>
+/// ```
+///
+/// The `Item 1` item was filtered out because it matched the `title_filter_regex`.
+#[instrument(skip(req, config, backend), fields(request_id))]
+async fn rss_handler(
+    req: Request<Bytes>,
+    config: &Config,
+    backend: &Backend,
+) -> Result<Response<Bytes>, RssHandlerError> {
+    let start_time = Instant::now();
+
+    let uri = req.uri();
+    let url = uri.to_string().parse().map_err(ValidationError::from)?;
+    let policy = url_policy(&url, config);
+    let params = validate_parameters(&url, &policy, config.url_signer.as_ref())?;
+    let feed_url = &params.url;
+
+    let filter_regexes: FilterRegexes = (&params.regex_params).into();
+
+    debug!(
+        regexes = ?&params.regex_params,
+        url = feed_url.as_ref(),
+        "Filtering RSS feed"
+    );
+
+    let rss_filter = create_rss_filter(&filter_regexes, config, policy, &url, backend);
+
+    let headers = req.headers();
+
+    let resp = rss_filter
+        .fetch_and_filter_with_headers(feed_url, filter_request_headers(headers))
+        .await?;
+
+    let duration = start_time.elapsed();
+    log_request_metrics(feed_url, resp.status(), duration);
+
+    let mut resp = resp;
+    if resp.extensions().get::<NeedsRevalidation>().is_some() {
+        resp.extensions_mut().insert(Revalidate {
+            uri: uri.clone(),
+            headers: headers.clone(),
+        });
+    }
+
+    Ok(resp)
+}
+
+/// Answers a `POST` to "/". A filter definition is handled as the `GET` it
+/// stands for, and a feed is filtered with the filters in the query.
+async fn post_handler(
+    req: Request<Bytes>,
+    config: &Config,
+    backend: &Backend,
+) -> Result<Response<Bytes>, RssHandlerError> {
+    match parse_post_body(req.headers(), req.body())? {
+        PostBody::Definition(params) => {
+            rss_handler(definition_request(req, params)?, config, backend).await
+        }
+        PostBody::Feed(media_type) => feed_handler(req, media_type, config, backend).await,
+    }
+}
+
+/// The `GET` request which a posted filter definition stands for: the same
+/// request, with the definition's parameters added to the query and no body.
+fn definition_request(
+    req: Request<Bytes>,
+    params: Vec<(String, String)>,
+) -> Result<Request<Bytes>, ValidationError> {
+    let mut url: Url = req.uri().to_string().parse()?;
+    url.query_pairs_mut().extend_pairs(params);
+
+    let (mut parts, _) = req.into_parts();
+    parts.method = Method::GET;
+    parts.uri = url
+        .as_str()
+        .parse()
+        .map_err(|err: http::uri::InvalidUri| ValidationError::InvalidBody(err.to_string()))?;
+
+    // The request headers are passed upstream, where there's no body
+    parts.headers.remove(CONTENT_TYPE);
+    parts.headers.remove(CONTENT_LENGTH);
+
+    Ok(Request::from_parts(parts, Bytes::new()))
+}
+
+/// Filters a feed which was posted, of type `media_type`, with the filters in
+/// the query. Nothing is fetched or cached.
+#[instrument(skip(req, config, backend), fields(request_id))]
+async fn feed_handler(
+    req: Request<Bytes>,
+    media_type: &'static str,
+    config: &Config,
+    backend: &Backend,
+) -> Result<Response<Bytes>, RssHandlerError> {
+    let url = req
+        .uri()
+        .to_string()
+        .parse()
+        .map_err(ValidationError::from)?;
+    let regex_params = parse_regex_params(&url)?;
+    if regex_params.is_empty() {
+        return Err(ValidationError::NoFiltersProvided.into());
+    }
+
+    let feed = req.into_body();
+    let max_size = config.limits.max_body_bytes;
+    if feed.len() as u64 > max_size {
+        return Err(RssError::FeedTooLarge { max_size }.into());
+    }
+
+    let filter_regexes: FilterRegexes = (&regex_params).into();
+
+    // There's nothing to fetch, but a filter needs a client
+    let rss_filter = RssFilter::new_with_http_client(
+        &filter_regexes,
+        Box::new(Arc::clone(&backend.http_client)),
+    )
+    .with_config(filter_config(config));
+
+    let feed = Response::builder()
+        .header(CONTENT_TYPE, media_type)
+        .body(feed)
+        .map_err(ProcessingError::from)?;
+
+    Ok(rss_filter.try_filter_response(feed).await?)
+}
+
+/// Answers the JSON API: which items of the feed filtering keeps and which it
+/// removes, rather than the filtered feed.
+#[instrument(skip(req, config, backend), fields(request_id))]
+async fn api_filter_handler(
+    req: Request<Bytes>,
+    config: &Config,
+    backend: &Backend,
+) -> Result<Response<Bytes>, RssHandlerError> {
+    let url = req
+        .uri()
+        .to_string()
+        .parse()
+        .map_err(ValidationError::from)?;
+    let policy = url_policy(&url, config);
+    let params = validate_parameters(&url, &policy, config.url_signer.as_ref())?;
+    let filter_regexes: FilterRegexes = (&params.regex_params).into();
+    let rss_filter = create_rss_filter(&filter_regexes, config, policy, &url, backend);
+
+    let response = fetch_for_api(&rss_filter, &params.url, req.headers()).await?;
+    let report = rss_filter.report_response(&response)?;
+
+    let items = |items: &[FeedItem]| {
+        items
+            .iter()
+            .map(|item| json!({ "title": item.title, "guid": item.guid, "link": item.link }))
+            .collect::<Vec<_>>()
+    };
+    let body = json!({
+        "url": params.url,
+        "kept": items(&report.kept),
+        "removed": items(&report.removed),
+    });
+
+    Ok(Response::builder()
+        .header(CONTENT_TYPE, "application/json")
+        .body(body.to_string().into())
+        .map_err(ProcessingError::from)?)
+}
+
+/// Answers the preview API: every item of the feed, in order, with which of
+/// its fields the filters match. Unlike elsewhere, no filters are needed, so
+/// that the feed can be shown before any are written.
+#[instrument(skip(req, config, backend), fields(request_id))]
+async fn api_preview_handler(
+    req: Request<Bytes>,
+    config: &Config,
+    backend: &Backend,
+) -> Result<Response<Bytes>, RssHandlerError> {
+    let url = req
+        .uri()
+        .to_string()
+        .parse()
+        .map_err(ValidationError::from)?;
+    let policy = url_policy(&url, config);
+    let regex_params = parse_regex_params(&url)?;
+    let feed_url = feed_url_param(&url).ok_or(ValidationError::NoUrlProvided)?;
+    // Previews aren't feeds anyone can subscribe to, so they needn't be
+    // signed, and filters can be edited where only signed URLs are filtered
+    policy.check_str(&feed_url).map_err(ValidationError::from)?;
+
+    let filter_regexes: FilterRegexes = (&regex_params).into();
+    let rss_filter = create_rss_filter(&filter_regexes, config, policy, &url, backend);
+
+    let response = fetch_for_api(&rss_filter, &feed_url, req.headers()).await?;
+    let items = rss_filter
+        .preview_response(&response)?
+        .iter()
+        .map(|preview| {
+            json!({
+                "title": preview.item.title,
+                "guid": preview.item.guid,
+                "link": preview.item.link,
+                "removed": preview.is_removed(),
+                "matched": preview
+                    .matched
+                    .iter()
+                    .map(|field| field.as_str())
+                    .collect::<Vec<_>>(),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    // The subscription URL keeps any signature the preview was asked for
+    // with, which only holds while the filters are left alone, unless we can
+    // sign it ourselves
+    let subscription = (!regex_params.is_empty()).then(|| match request_signer(&req, config) {
+        Some(signer) => signer.sign(&subscription_url(&url)),
+        None => subscription_url(&url),
+    });
+    let needs_signature = match (&subscription, &config.url_signer) {
+        (Some(subscription), Some(signer)) => signer.verify(subscription).is_err(),
+        _ => false,
+    };
+
+    let body = json!({
+        "url": feed_url,
+        "subscription_url": subscription.map(String::from),
+        "needs_signature": needs_signature,
+        "items": items,
+    });
+
+    Ok(Response::builder()
+        .header(CONTENT_TYPE, "application/json")
+        .body(body.to_string().into())
+        .map_err(ProcessingError::from)?)
+}
+
+/// The URL at which we filter feeds, for a request made to `url`.
+fn service_url(url: &Url) -> Url {
+    let mut service = url.clone();
+    service.set_path("/");
+    service.set_query(None);
+
+    service
+}
+
+/// The URL of the filtered feed which an API request to `url` is about: its
+/// feed parameters and any signature, at "/".
+fn subscription_url(url: &Url) -> Url {
+    let mut subscription = service_url(url);
+    subscription.query_pairs_mut().extend_pairs(
+        url.query_pairs()
+            .filter(|(name, _)| FEED_PARAMS.contains(&name.as_ref()) || name == SIGNATURE_PARAM),
+    );
+
+    subscription
+}
+
+/// The filters in the query of `url`, to apply to a subscription list.
+fn filter_profile(url: &Url) -> FilterProfile {
+    let regexes = |name: &str| {
+        url.query_pairs()
+            .filter(|(key, _)| key == name)
+            .map(|(_, value)| value.into_owned())
+            .collect()
+    };
+
+    FilterProfile {
+        title_filter_regexes: regexes(TITLE_FILTER_PARAM),
+        guid_filter_regexes: regexes(GUID_FILTER_PARAM),
+        link_filter_regexes: regexes(LINK_FILTER_PARAM),
+    }
+}
+
+/// The signer for URLs we hand out in answer to `req`: only requests made
+/// with an API key get signed URLs, as otherwise anyone could have any feed
+/// signed, and signing would stop nobody repointing a shared link.
+fn request_signer<'a>(req: &Request<Bytes>, config: &'a Config) -> Option<&'a UrlSigner> {
+    config
+        .url_signer
+        .as_ref()
+        .filter(|_| req.extensions().get::<ApiKeyId>().is_some())
+}
+
+/// Rewrites a posted OPML subscription list so that every feed in it is
+/// filtered here with the filters in the query, or, with `unfilter`, so that
+/// none of them are. Nothing is fetched. When we only filter signed URLs,
+/// the feeds' URLs are signed for requests made with an API key; see
+/// [`request_signer`].
+#[instrument(skip(req, config), fields(request_id))]
+async fn opml_handler(
+    req: Request<Bytes>,
+    config: &Config,
+    unfilter: bool,
+) -> Result<Response<Bytes>, RssHandlerError> {
+    let url = req
+        .uri()
+        .to_string()
+        .parse()
+        .map_err(ValidationError::from)?;
+    let service = service_url(&url);
+
+    let rewritten = if unfilter {
+        unfilter_subscriptions(req.body(), &service)
+    } else {
+        // Check the filters now, rather than when each feed is fetched
+        if parse_regex_params(&url)?.is_empty() {
+            return Err(ValidationError::NoFiltersProvided.into());
+        }
+
+        filter_subscriptions(
+            req.body(),
+            &service,
+            &filter_profile(&url),
+            request_signer(&req, config),
+        )
+    }
+    .map_err(ValidationError::from)?;
+
+    debug!(
+        n_rewritten = rewritten.n_rewritten,
+        "Rewrote subscription list"
+    );
+
+    Ok(Response::builder()
+        .header(CONTENT_TYPE, "text/x-opml")
+        .body(rewritten.opml)
+        .map_err(ProcessingError::from)?)
+}
+
+/// Fetches the whole of a feed for the JSON API, which reports on it rather
+/// than passing it on, so anything but success is an error.
+async fn fetch_for_api(
+    rss_filter: &RssFilter<'_>,
+    feed_url: &str,
+    request_headers: &HeaderMap,
+) -> Result<Response<Bytes>, RssHandlerError> {
+    // The client's conditions are about our responses, not the feed, which
+    // we need all of
+    let mut headers = filter_request_headers(request_headers);
+    for name in [
+        IF_MATCH,
+        IF_MODIFIED_SINCE,
+        IF_NONE_MATCH,
+        IF_UNMODIFIED_SINCE,
+    ] {
+        headers.remove(name);
+    }
+
+    let response = rss_filter.fetch(feed_url, headers).await?;
+    if !response.status().is_success() {
+        return Err(ProcessingError::UpstreamStatus {
+            status: response.status(),
+        }
+        .into());
+    }
+
+    Ok(response)
+}
+
+/// The URL policy for a request: the configured one, treating the host the
+/// request was made to as ourselves.
+fn url_policy(url: &Url, config: &Config) -> UrlPolicy {
+    config.url_policy.clone().with_self_host(url.host_str())
+}
+
+fn filter_config(config: &Config) -> RssFilterConfig {
+    RssFilterConfig {
+        limits: config.limits,
+        cache: config.cache.clone(),
+        redirect: config.redirect,
+        title_annotation: config.title_annotation.clone(),
+        ..Default::default()
+    }
+}
+
+/// Creates the filter for a request to `url`, which filtered feeds link to as
+/// their own URL unless configured not to.
+fn create_rss_filter<'a>(
+    filter_regexes: &'a FilterRegexes<'a>,
+    config: &Config,
+    policy: UrlPolicy,
+    url: &Url,
+    backend: &Backend,
+) -> RssFilter<'a> {
+    // Fetches the policy refuses never reach the cache or the upstream rate
+    // limit, which the backend's HTTP client has below it
+    let http_client = UrlPolicyLayer::new(policy).layer(Arc::clone(&backend.http_client));
+
+    let rss_filter = RssFilter::new_with_http_client(filter_regexes, Box::new(http_client))
+        .with_config(filter_config(config))
+        .with_response_cache(Box::new(Arc::clone(&backend.response_cache)));
+
+    if config.keep_upstream_self_link {
+        return rss_filter;
+    }
+
+    // Only the parameters which make up the feed, so that requests which
+    // differ otherwise share a cache entry and link to the same feed
+    rss_filter.with_self_url(subscription_url(url).as_str())
+}
+
+/// Response extension set when a stale filtered feed was served on the
+/// understanding that it will be refreshed: front ends should call
+/// [`revalidate`] with `uri` and `headers` once the response has gone. `uri`
+/// is what the feed was filtered for, which for a posted filter definition
+/// isn't what the request was made to, and `headers` are the request's once
+/// it was authenticated, so without our API key.
+#[derive(Clone, Debug)]
+pub struct Revalidate {
+    pub uri: Uri,
+    pub headers: HeaderMap,
+}
+
+/// Refreshes the cached filtered feed for a request which was answered with a
+/// stale response. This runs after the response has been sent.
+#[instrument(skip(headers, config, backend))]
+pub async fn revalidate(
+    uri: &Uri,
+    headers: &HeaderMap,
+    config: &Config,
+    backend: &Backend,
+) -> Result<(), RssHandlerError> {
+    let url = uri.to_string().parse().map_err(ValidationError::from)?;
+    let policy = url_policy(&url, config);
+    let params = validate_parameters(&url, &policy, config.url_signer.as_ref())?;
+    let filter_regexes: FilterRegexes = (&params.regex_params).into();
+
+    create_rss_filter(&filter_regexes, config, policy, &url, backend)
+        .revalidate(&params.url, filter_request_headers(headers))
+        .await?;
+
+    Ok(())
+}
+
+/// Performs one-time initialisation of OpenTelemetry tracing subscriber. This sets up a global, so
+/// it can't be called multiple times.
+pub fn initialise_otel_with_config(config: &WorkerConfig) -> &'static Result<(), RssHandlerError> {
+    use std::sync::OnceLock;
+
+    use rssfilter_telemetry::init_default_subscriber;
+
+    static INIT_SUBSCRIBER: OnceLock<Result<(), RssHandlerError>> = OnceLock::new();
+
+    let initialisation_result = INIT_SUBSCRIBER.get_or_init(|| {
+        let _tracer_provider = init_default_subscriber(config.clone())?;
+
+        debug!("Initialised tracing subscriber with worker environment variables");
+
+        Ok(())
+    });
+
+    initialisation_result
+}
+
+/// Handles a request, fetching and caching feeds with `backend`. This is
+/// everything the service does apart from reading its environment, so every
+/// front end which calls it behaves the same.
+///
+/// Accepts GET and HEAD requests to "/" with query parameters:
+/// - `url`: The RSS feed URL to filter (required)
+/// - `title_filter_regex`: Regex to filter items by title (at least one filter required)
+/// - `guid_filter_regex`: Regex to filter items by GUID (at least one filter required)
+/// - `link_filter_regex`: Regex to filter items by link (at least one filter required)
+///
+/// The parameters can instead be posted to "/" as a filter definition: a
+/// JSON object with a string or list of strings for each, or a form. This is
+/// handled as the GET it stands for. A feed can be posted too, as RSS, Atom
+/// or XML, to be filtered with the filters in the query instead of fetched.
+///
+/// HEAD requests get the headers a GET would, without the body. OPTIONS
+/// requests, including CORS preflights, are answered with 204 and the allowed
+/// methods. Every response carries the CORS headers for its `Origin`, as
+/// configured by [`Config::cors`].
+///
+/// GET and HEAD requests to `/api/v1/filter`, with the same parameters, are
+/// answered with JSON saying which items filtering keeps and which it
+/// removes: `{"url": ..., "kept": [...], "removed": [...]}`, where each item
+/// has its `title`, `guid` and `link`, or `null`.
+///
+/// GET and HEAD requests to `/api/v1/preview` list every item of the feed
+/// in order, with whether it's `removed` and which fields the filters
+/// `matched`: `{"url": ..., "subscription_url": ..., "needs_signature": ...,
+/// "items": [...]}`. The filters are optional, and without any there's no
+/// `subscription_url`. Previews don't need to be signed. When only signed
+/// URLs are filtered, the `subscription_url` is signed for requests made
+/// with an API key, and otherwise `needs_signature` says whether it still
+/// has to be, such as with `rssfilter sign-url`.
+/// `/ui` is a page which uses this to preview filters as they're typed.
+///
+/// An OPML subscription list posted to `/api/v1/opml/filter` comes back with
+/// every feed's `xmlUrl` pointing here, filtered with the filters in the
+/// query. Posted to `/api/v1/opml/unfilter`, it comes back with the feeds
+/// filtered here pointing at the feeds themselves again.
+///
+/// Errors are `text/plain` messages, unless the client prefers JSON in
+/// `Accept` or is calling the JSON API. Then they are RFC 9457 problem
+/// details, `application/problem+json`, whose `code` names the error.
+///
+/// Requests which filter or preview feeds, or rewrite subscription lists,
+/// count against the client rate limit of [`Config::rate_limit`], by the
+/// [`ClientAddr`] front ends attach to them. Fetches count against the
+/// upstream limit for the feed's host. Over either, the response is 429 with
+/// `Retry-After`.
+///
+/// When [`Config::auth`] has API keys, those requests must also carry one,
+/// as a bearer token or in the `key` query parameter. The key is taken out
+/// of the request before it's handled, and only its id is traced, as
+/// `key_id`. Filtered feeds' URLs don't include it.
+///
+/// Monitors can use these paths, which never fetch a feed:
+/// - `/healthz`: 200 whenever we're running
+/// - `/readyz`: 200 once telemetry is initialised, as filtering needs it, or
+///   503 with the reason it failed
+/// - `/version`: JSON with the `version`, the `git_revision` we were built
+///   from, if known, and the optional `features` which are enabled
+///
+/// Returns:
+/// - 200: Filtered RSS feed
+/// - 304: The client's `If-None-Match` matches the filtered feed, or the
+///   upstream feed hasn't changed since its `If-Modified-Since`
+/// - 400: Invalid parameters or malformed request
+/// - 401: No API key was given, when one is needed
+/// - 403: The API key isn't valid, or the feed URL isn't allowed to be
+///   fetched: it isn't http or https, is a private address, points back at
+///   us, or is excluded by the configured host lists
+/// - 404: Unknown path
+/// - 405: Wrong HTTP method (not GET, HEAD or OPTIONS, or POST to "/" or
+///   the OPML paths, which take only POST and OPTIONS)
+/// - 413: RSS feed too large, or has too many items
+/// - 415: Invalid content type (not RSS/XML), or a posted body which is
+///   neither a filter definition nor a feed
+/// - 422: Error processing the RSS feed, or its elements are nested too deeply
+/// - 429: The client has made too many requests, or the feed's host has been
+///   fetched from too often
+/// - 502: Error fetching the upstream RSS feed, or, from the JSON API, the
+///   upstream server responded with an error
+/// - 504: The upstream server didn't respond in time
+///
+/// See [`Config::from_vars`] for the configuration.
+pub async fn real_main(
+    mut req: Request<Bytes>,
+    config: Config,
+    backend: &Backend,
+) -> Response<Bytes> {
+    let origin = req.headers().get(ORIGIN).cloned();
+    let method = req.method().clone();
+    let format = ErrorFormat::for_request(&req);
+
+    // Validate request early
+    let mut response = match validate_request(&req) {
+        Err(validation_error) => {
+            let mut response = format.respond(validation_error.code(), validation_error.into());
+            if let Some(route) = Route::from_path(req.uri().path()) {
+                response
+                    .headers_mut()
+                    .insert(ALLOW, allow_header(route.methods()));
+            }
+            response
+        }
+        Ok(route) if method == Method::OPTIONS => {
+            preflight(req.headers(), &config.cors, route.methods())
+        }
+        Ok(Route::Healthz) => healthz(),
+        Ok(Route::Readyz) => readyz(initialise_otel_with_config(&config.telemetry)),
+        Ok(Route::Version) => version(&config),
+        Ok(Route::Ui) => ui(),
+        Ok(route) => match admit(&mut req, &config, backend).await {
+            Ok(()) => filter(req, route, format, &config, backend).await,
+            Err(err) => {
+                info!(err = %err, "Refused request");
+                format.respond(err.code(), err.into())
+            }
+        },
+    };
+
+    if method == Method::HEAD {
+        response = without_body(response);
+    }
+
+    add_cors_headers(origin.as_ref(), &mut response, &config.cors);
+
+    response
+}
+
+/// Checks that a request which does work may go ahead: that its client isn't
+/// over the rate limit, and that it has an API key if one is needed.
+async fn admit(
+    req: &mut Request<Bytes>,
+    config: &Config,
+    backend: &Backend,
+) -> Result<(), RssHandlerError> {
+    check_client(req.extensions().get(), &config.rate_limit, backend).await?;
+    authenticate(req, &config.auth)?;
+
+    Ok(())
+}
+
+/// Filters the feed a request is for, either way, tracing it in a span of
+/// its own.
+async fn filter(
+    req: Request<Bytes>,
+    route: Route,
+    format: ErrorFormat,
+    config: &Config,
+    backend: &Backend,
+) -> Response<Bytes> {
+    // Check the stored result and return early if it failed
+    if let Err(err) = initialise_otel_with_config(&config.telemetry) {
+        return format.respond(err.code(), err.into());
+    };
+    use rssfilter_telemetry::extract_context_from_headers;
+
+    let request_id = Uuid::new_v4().to_string();
+
+    let parent_ctx = extract_context_from_headers(HeaderExtractor(req.headers()));
+
+    // Add request ID to tracing span
+    let span = tracing::info_span!(
+        "request",
+        request_id = %request_id,
+        key_id = tracing::field::Empty,
+    );
+    if let Some(ApiKeyId(key_id)) = req.extensions().get() {
+        span.record("key_id", key_id.as_str());
+    }
+    if let Err(err) = span.set_parent(parent_ctx) {
+        // TODO: move to our `From` once
+        // https://github.com/tokio-rs/tracing-opentelemetry/issues/236 is solved.
+        return Response::builder()
+            .status(*INTERNAL_SERVER_ERROR)
+            .body(err.to_string().into())
+            .unwrap();
+    };
+
+    let result = match route {
+        Route::ApiFilter => {
+            api_filter_handler(req, config, backend)
+                .instrument(span)
+                .await
+        }
+        Route::ApiPreview => {
+            api_preview_handler(req, config, backend)
+                .instrument(span)
+                .await
+        }
+        Route::ApiOpmlFilter => opml_handler(req, config, false).instrument(span).await,
+        Route::ApiOpmlUnfilter => opml_handler(req, config, true).instrument(span).await,
+        _ if req.method() == Method::POST => {
+            post_handler(req, config, backend).instrument(span).await
+        }
+        _ => rss_handler(req, config, backend).instrument(span).await,
+    };
+
+    result.unwrap_or_else(|err| {
+        info!(
+          err = %err,
+          "Error processing request",
+        );
+
+        format.respond(err.code(), err.into())
+    })
+}
+
+// Integration tests that require mockito (non-WASM only)
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod integration_tests {
+    use super::*;
+
+    use filter_rss_feed::{FilterRegexes, RateLimit, RssFilter, create_rate_limit_store};
+    use matches::assert_matches;
+    use std::sync::LazyLock;
+    use test_case::test_case;
+    use test_utils::feed::serve_test_rss_feed;
+    use test_utils::test_request_builder;
+
+    static TEMPORARY_REDIRECT: LazyLock<u16> =
+        LazyLock::new(|| StatusCode::TEMPORARY_REDIRECT.as_u16());
+
+    fn backend() -> Backend {
+        Backend::new().expect("Failed to create backend")
+    }
+
+    /// The mock servers listen on loopback, which the default policy refuses.
+    fn local_config() -> Config {
+        Config {
+            url_policy: UrlPolicy {
+                allow_private_addresses: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn contains_string(data: &Bytes, needle: &str) -> bool {
+        data.as_ref()
+            .windows(needle.len())
+            .any(|window| window == needle.as_bytes())
+    }
+
+    #[tokio::test]
+    async fn test_parameter_validation_no_params() {
+        let url = "https://test.example.com/".parse().unwrap();
+        let result = validate_parameters(&url, &UrlPolicy::default(), None);
+        assert!(result.is_err());
+        assert!(matches!(
+            result.unwrap_err(),
+            ValidationError::NoParametersProvided
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_parameter_validation_no_url() {
+        let url = "https://test.example.com/?title_filter_regex=test"
+            .parse()
+            .unwrap();
+        let result = validate_parameters(&url, &UrlPolicy::default(), None);
+        assert!(result.is_err());
+        assert!(matches!(
+            result.unwrap_err(),
+            ValidationError::NoUrlProvided
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_parameter_validation_no_filters() {
+        let url = "https://test.example.com/?url=http://example.com/rss"
+            .parse()
+            .unwrap();
+        let result = validate_parameters(&url, &UrlPolicy::default(), None);
+        assert!(result.is_err());
+        assert!(matches!(
+            result.unwrap_err(),
+            ValidationError::NoFiltersProvided
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_parameter_validation_invalid_regex() {
+        let url =
+            "https://test.example.com/?url=http://example.com/rss&title_filter_regex=[invalid"
+                .parse()
+                .unwrap();
+        let result = validate_parameters(&url, &UrlPolicy::default(), None);
+        assert!(result.is_err());
+        assert!(matches!(
+            result.unwrap_err(),
+            ValidationError::InvalidRegex { .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_parameter_validation_success() {
+        let url = "https://test.example.com/?url=http://example.com/rss&title_filter_regex=test"
+            .parse()
+            .unwrap();
+        let result = validate_parameters(&url, &UrlPolicy::default(), None);
+        assert!(result.is_ok());
+        let params = result.unwrap();
+        assert_eq!(params.url, "http://example.com/rss");
+        assert_eq!(params.regex_params.title_regexes.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_parameter_validation_multiple_regexes() {
+        let url = "https://test.example.com/?url=http://example.com/rss&title_filter_regex=test1&title_filter_regex=test2&guid_filter_regex=guid".parse().unwrap();
+        let result = validate_parameters(&url, &UrlPolicy::default(), None);
+        assert!(result.is_ok());
+        let params = result.unwrap();
+        assert_eq!(params.regex_params.title_regexes.len(), 2);
+        assert_eq!(params.regex_params.guid_regexes.len(), 1);
+        assert_eq!(params.regex_params.link_regexes.len(), 0);
+    }
+
+    #[test_case("http://127.0.0.1/rss" ; "loopback")]
+    #[test_case("http://169.254.169.254/latest/meta-data" ; "link-local")]
+    #[test_case("file:///etc/passwd" ; "file scheme")]
+    #[test_case("https://test.example.com/?url=http://example.com/rss" ; "ourselves")]
+    #[tokio::test]
+    async fn test_parameter_validation_url_policy(feed_url: &str) {
+        let request = test_request_builder::RequestBuilder::new()
+            .with_feed_url(feed_url)
+            .with_title_filter_regex(".*")
+            .build()
+            .expect("Failed to build request");
+        let url = request.uri().to_string().parse().unwrap();
+
+        let result = validate_parameters(&url, &url_policy(&url, &Config::default()), None);
+        assert_matches!(result, Err(ValidationError::UrlPolicy(_)));
+
+        let response = real_main(request, Config::default(), &backend()).await;
+        assert_eq!(response.status().as_u16(), *FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_parameter_validation_host_lists() {
+        let config = Config {
+            url_policy: UrlPolicy {
+                allowed_hosts: vec!["example.com".to_string()],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let url = "https://test.example.com/?url=https://example.org/rss&title_filter_regex=test"
+            .parse()
+            .unwrap();
+
+        let result = validate_parameters(&url, &url_policy(&url, &config), None);
+        assert_matches!(
+            result,
+            Err(ValidationError::UrlPolicy(UrlPolicyError::HostNotAllowed(
+                _
+            )))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rss_filtering_basic() {
+        let server = serve_test_rss_feed(&["1", "2"]).await.unwrap();
+        let url = server.url();
+
+        let title_regex = Regex::new("Test Item 1").unwrap();
+        let filter_regexes = FilterRegexes {
+            title_regexes: &[title_regex],
+            guid_regexes: &[],
+            link_regexes: &[],
+        };
+
+        let rss_filter = RssFilter::new(&filter_regexes).expect("Failed to create RSS filter");
+        let response = rss_filter.fetch(&url, Default::default()).await.unwrap();
+        let body = rss_filter.filter_response(response).await.unwrap();
+
+        // Should filter out item 1, keep item 2
+        assert!(!contains_string(&body, "Item 1"));
+        assert!(contains_string(&body, "Item 2"));
+    }
+
+    #[tokio::test]
+    async fn test_rss_filtering_guid() {
+        let server = serve_test_rss_feed(&["1", "2", "3"]).await.unwrap();
+        let url = server.url();
+
+        let guid_regex = Regex::new("^2$").unwrap();
+        let filter_regexes = FilterRegexes {
+            title_regexes: &[],
+            guid_regexes: &[guid_regex],
+            link_regexes: &[],
+        };
+
+        let rss_filter = RssFilter::new(&filter_regexes).expect("Failed to create RSS filter");
+        let response = rss_filter.fetch(&url, Default::default()).await.unwrap();
+        let body = rss_filter.filter_response(response).await.unwrap();
+
+        // Should filter out item 2, keep items 1 and 3
+        assert!(contains_string(&body, "Item 1"));
+        assert!(!contains_string(&body, "Item 2"));
+        assert!(contains_string(&body, "Item 3"));
+    }
+
+    #[tokio::test]
+    async fn test_rss_filtering_link() {
+        let server = serve_test_rss_feed(&["1", "2"]).await.unwrap();
+        let url = server.url();
+
+        let link_regex = Regex::new("test1").unwrap();
+        let filter_regexes = FilterRegexes {
+            title_regexes: &[],
+            guid_regexes: &[],
+            link_regexes: &[link_regex],
+        };
+
+        let rss_filter = RssFilter::new(&filter_regexes).expect("Failed to create RSS filter");
+        let response = rss_filter.fetch(&url, Default::default()).await.unwrap();
+        let body = rss_filter.filter_response(response).await.unwrap();
+
+        // Should filter out item 1 (link contains "test1"), keep item 2
+        assert!(!contains_string(&body, "Item 1"));
+        assert!(contains_string(&body, "Item 2"));
+    }
+
+    #[tokio::test]
+    async fn test_http_error_handling() {
+        // Test with a URL that will return an error
+        let title_regex = Regex::new("test").unwrap();
+        let filter_regexes = FilterRegexes {
+            title_regexes: &[title_regex],
+            guid_regexes: &[],
+            link_regexes: &[],
+        };
+
+        let rss_filter = RssFilter::new(&filter_regexes).expect("Failed to create RSS filter");
+        let result = rss_filter
+            .fetch("http://localhost:99999/nonexistent", Default::default())
+            .await;
+
+        // Should get a network error
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_url_encoding_in_parameters() {
+        // Test URL with encoded parameters
+        let url =
+            "https://test.example.com/?url=http%3A//example.com/rss&title_filter_regex=Test%20Item"
+                .parse()
+                .unwrap();
+        let result = validate_parameters(&url, &UrlPolicy::default(), None);
+        assert!(result.is_ok());
+        let params = result.unwrap();
+        assert_eq!(params.url, "http://example.com/rss");
+        assert_eq!(params.regex_params.title_regexes[0].as_str(), "Test Item");
+    }
+
+    #[tokio::test]
+    async fn test_empty_regex_matches() {
+        let server = serve_test_rss_feed(&["1", "2"]).await.unwrap();
+        let url = server.url();
+
+        // Test regex that matches everything
+        let title_regex = Regex::new(".*").unwrap();
+        let filter_regexes = FilterRegexes {
+            title_regexes: &[title_regex],
+            guid_regexes: &[],
+            link_regexes: &[],
+        };
+
+        let rss_filter = RssFilter::new(&filter_regexes).expect("Failed to create RSS filter");
+        let response = rss_filter.fetch(&url, Default::default()).await.unwrap();
+        let body = rss_filter.filter_response(response).await.unwrap();
+
+        // Should filter out all items since regex matches everything
+        assert!(!contains_string(&body, "Item 1"));
+        assert!(!contains_string(&body, "Item 2"));
+    }
+
+    #[tokio::test]
+    async fn test_regex_no_matches() {
+        let server = serve_test_rss_feed(&["1", "2"]).await.unwrap();
+        let url = server.url();
+
+        let title_regex = Regex::new("^nonexistent$").unwrap();
+        let filter_regexes = FilterRegexes {
+            title_regexes: &[title_regex],
+            guid_regexes: &[],
+            link_regexes: &[],
+        };
+
+        let rss_filter = RssFilter::new(&filter_regexes).expect("Failed to create RSS filter");
+        let response = rss_filter.fetch(&url, Default::default()).await.unwrap();
+        let body = rss_filter.filter_response(response).await.unwrap();
+
+        // Should keep all items since regex matches nothing
+        assert!(contains_string(&body, "Item 1"));
+        assert!(contains_string(&body, "Item 2"));
+    }
+
+    #[tokio::test]
+    async fn test_mixed_filter_types() {
+        let server = serve_test_rss_feed(&["1", "2", "3"]).await.unwrap();
+        let url = server.url();
+
+        // Mix of title and guid filters
+        let title_regex = Regex::new("Test Item 1").unwrap();
+        let guid_regex = Regex::new("3").unwrap();
+        let filter_regexes = FilterRegexes {
+            title_regexes: &[title_regex],
+            guid_regexes: &[guid_regex],
+            link_regexes: &[],
+        };
+
+        let rss_filter = RssFilter::new(&filter_regexes).expect("Failed to create RSS filter");
+        let response = rss_filter.fetch(&url, Default::default()).await.unwrap();
+        let body = rss_filter.filter_response(response).await.unwrap();
+
+        // Should filter out items 1 and 3, keep item 2
+        assert!(!contains_string(&body, "Item 1"));
+        assert!(contains_string(&body, "Item 2"));
+        assert!(!contains_string(&body, "Item 3"));
+    }
+
+    #[tokio::test]
+    async fn test_filter_link_multiple() {
+        let server = serve_test_rss_feed(&["1", "2", "3"]).await.unwrap();
+        let url = server.url();
+
+        let link_regex1 = Regex::new("test1").unwrap();
+        let link_regex2 = Regex::new("test2").unwrap();
+        let filter_regexes = FilterRegexes {
+            title_regexes: &[],
+            guid_regexes: &[],
+            link_regexes: &[link_regex1, link_regex2],
+        };
+
+        let rss_filter = RssFilter::new(&filter_regexes).expect("Failed to create RSS filter");
+        let response = rss_filter.fetch(&url, Default::default()).await.unwrap();
+        let body = rss_filter.filter_response(response).await.unwrap();
+
+        let body_str = std::str::from_utf8(&body).unwrap();
+
+        assert!(!body_str.contains("Item 1"));
+        assert!(!body_str.contains("Item 2"));
+        assert!(body_str.contains("Item 3"));
+    }
+
+    #[tokio::test]
+    async fn test_404() {
+        use http::{Method, Request};
+
+        let req = Request::builder()
+            .method(Method::GET)
+            .uri("https://test.example.com/favicon.ico")
+            .body(Bytes::new())
+            .unwrap();
+
+        let error = validate_request(&req).expect_err("Expected request validation to fail");
+        assert_matches!(error, RequestValidationError::NotFound);
+    }
+
+    #[tokio::test]
+    async fn test_header_passthrough() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("GET", "/")
+            .with_status(*TEMPORARY_REDIRECT as usize)
+            .with_header("my-test-header", "value")
+            .create_async()
+            .await;
+
+        let url = server.url();
+
+        let mut headers = http::HeaderMap::new();
+        headers.insert("my-test-header", "value".parse().unwrap());
+
+        let request = test_request_builder::RequestBuilder::new()
+            .with_method(Method::GET)
+            .with_feed_url(&url)
+            .with_title_filter_regex(".*")
+            .build()
+            .expect("Failed to build request");
+
+        let response = real_main(request, local_config(), &backend()).await;
+
+        assert_eq!(response.status().as_u16(), *TEMPORARY_REDIRECT);
+        let headers = response.headers();
+        assert_eq!(headers.get("my-test-header").unwrap(), "value",);
+    }
+
+    #[tokio::test]
+    async fn test_head_request() {
+        let server = serve_test_rss_feed(&["1", "2"]).await.unwrap();
+        let request = |method| {
+            test_request_builder::RequestBuilder::new()
+                .with_method(method)
+                .with_feed_url(&server.url())
+                .with_title_filter_regex("Test Item 1")
+                .build()
+                .expect("Failed to build request")
+        };
+
+        let get = real_main(request(Method::GET), local_config(), &backend()).await;
+        let head = real_main(request(Method::HEAD), local_config(), &backend()).await;
+
+        assert_eq!(head.status(), get.status());
+        for name in ["content-type", "etag", "x-rssfilter-items-removed"] {
+            assert_eq!(head.headers().get(name), get.headers().get(name), "{name}");
+        }
+        assert_eq!(head.headers()[CONTENT_LENGTH], get.body().len().to_string());
+        assert!(head.body().is_empty());
+    }
+
+    fn json_body(response: &Response<Bytes>) -> serde_json::Value {
+        serde_json::from_slice(response.body()).expect("Response should be JSON")
+    }
+
+    #[tokio::test]
+    async fn test_api_filter() {
+        let server = serve_test_rss_feed(&["1", "2"]).await.unwrap();
+        let request = test_request_builder::RequestBuilder::new()
+            .with_path(API_FILTER_PATH)
+            .with_feed_url(&server.url())
+            .with_title_filter_regex("Test Item 1")
+            .build()
+            .expect("Failed to build request");
+
+        let response = real_main(request, local_config(), &backend()).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[CONTENT_TYPE], "application/json");
+
+        let body = json_body(&response);
+        assert_eq!(body["url"], server.url());
+        let titles = |items: &serde_json::Value| {
+            items
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|item| item["title"].clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(titles(&body["kept"]), [json!("Test Item 2")]);
+        assert_eq!(titles(&body["removed"]), [json!("Test Item 1")]);
+    }
+
+    #[tokio::test]
+    async fn test_api_filter_upstream_error() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("GET", "/")
+            .with_status(404)
+            .create_async()
+            .await;
+
+        let request = test_request_builder::RequestBuilder::new()
+            .with_path(API_FILTER_PATH)
+            .with_feed_url(&server.url())
+            .with_title_filter_regex(".*")
+            .build()
+            .expect("Failed to build request");
+
+        let response = real_main(request, local_config(), &backend()).await;
+
+        assert_eq!(response.status().as_u16(), *BAD_GATEWAY);
+        assert_eq!(response.headers()[CONTENT_TYPE], "application/problem+json");
+        assert_eq!(json_body(&response)["code"], "upstream_status");
+    }
+
+    #[tokio::test]
+    async fn test_api_preview() {
+        let server = serve_test_rss_feed(&["1", "2"]).await.unwrap();
+        let request = test_request_builder::RequestBuilder::new()
+            .with_path(API_PREVIEW_PATH)
+            .with_feed_url(&server.url())
+            .with_title_filter_regex("Test Item 1")
+            .build()
+            .expect("Failed to build request");
+
+        let response = real_main(request, local_config(), &backend()).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[CONTENT_TYPE], "application/json");
+
+        let body = json_body(&response);
+        assert_eq!(body["url"], server.url());
+
+        let subscription_url = Url::parse(body["subscription_url"].as_str().unwrap()).unwrap();
+        assert_eq!(subscription_url.path(), "/");
+        assert_eq!(
+            subscription_url
+                .query_pairs()
+                .into_owned()
+                .collect::<Vec<_>>(),
+            [
+                ("url".to_string(), server.url()),
+                ("title_filter_regex".to_string(), "Test Item 1".to_string()),
+            ]
+        );
+
+        assert_eq!(
+            body["items"],
+            json!([
+                {
+                    "title": "Test Item 1",
+                    "guid": "1",
+                    "link": "http://www.example.com/test1",
+                    "removed": true,
+                    "matched": ["title"],
+                },
+                {
+                    "title": "Test Item 2",
+                    "guid": "2",
+                    "link": "http://www.example.com/test2",
+                    "removed": false,
+                    "matched": [],
+                },
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_api_preview_without_filters() {
+        let server = serve_test_rss_feed(&["1", "2"]).await.unwrap();
+        let request = test_request_builder::RequestBuilder::new()
+            .with_path(API_PREVIEW_PATH)
+            .with_feed_url(&server.url())
+            .build()
+            .expect("Failed to build request");
+
+        let response = real_main(request, local_config(), &backend()).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = json_body(&response);
+        assert_eq!(body["subscription_url"], serde_json::Value::Null);
+        assert_eq!(body["items"].as_array().unwrap().len(), 2);
+        assert!(
+            body["items"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .all(|item| item["removed"] == false)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_api_preview_no_url() {
+        let request = test_request_builder::RequestBuilder::new()
+            .with_path(API_PREVIEW_PATH)
+            .with_title_filter_regex("Test Item 1")
+            .build()
+            .expect("Failed to build request");
+
+        let response = real_main(request, local_config(), &backend()).await;
+
+        assert_eq!(response.status().as_u16(), *BAD_REQUEST);
+        assert_eq!(json_body(&response)["code"], "no_url");
+    }
+
+    #[test]
+    fn test_subscription_url() {
+        let url = Url::parse(
+            "https://rssfilter.example.com/api/v1/preview?url=https%3A%2F%2Fexample.com%2Ffeed&other=1&link_filter_regex=%2Fads%2F",
+        )
+        .unwrap();
+
+        assert_eq!(
+            subscription_url(&url).as_str(),
+            "https://rssfilter.example.com/?url=https%3A%2F%2Fexample.com%2Ffeed&link_filter_regex=%2Fads%2F"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_self_link_is_the_subscription_url() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("GET", "/")
+            .with_header("content-type", "application/rss+xml")
+            .with_header("cache-control", "max-age=300")
+            .with_body(
+                r#"<rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom"><channel><title>Feed</title><atom:link href="https://example.com/feed" rel="self"/></channel></rss>"#,
+            )
+            .expect(1)
+            .create_async()
+            .await;
+        let backend = backend();
+        let request = |extra: &[(&str, &str)]| {
+            let url = Url::parse_with_params(
+                "https://test.example.com/",
+                [
+                    ("url", server.url().as_str()),
+                    ("title_filter_regex", "Drop"),
+                ]
+                .iter()
+                .chain(extra),
+            )
+            .unwrap();
+            Request::builder()
+                .uri(url.as_str())
+                .body(Bytes::new())
+                .unwrap()
+        };
+        let expected = Url::parse_with_params(
+            "https://test.example.com/",
+            [
+                ("url", server.url().as_str()),
+                ("title_filter_regex", "Drop"),
+            ],
+        )
+        .unwrap();
+
+        for extra in [&[][..], &[("utm_source", "reader")][..]] {
+            let response = real_main(request(extra), local_config(), &backend).await;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            let self_link = format!(r#"href="{}""#, expected.as_str().replace('&', "&amp;"));
+            assert!(contains_string(response.body(), &self_link));
+        }
+
+        // Both requests are for the same filtered feed
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_ui() {
+        let request = Request::builder()
+            .uri("https://test.example.com/ui")
+            .body(Bytes::new())
+            .unwrap();
+
+        let response = real_main(request, local_config(), &backend()).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(
+            response.headers()[CONTENT_TYPE]
+                .to_str()
+                .unwrap()
+                .starts_with("text/html")
+        );
+    }
+
+    #[test_case(API_FILTER_PATH, None; "api")]
+    #[test_case(API_PREVIEW_PATH, None; "preview api")]
+    #[test_case("/", Some("application/json"); "accept json")]
+    #[tokio::test]
+    async fn test_problem_details(path: &str, accept: Option<&str>) {
+        let mut request = test_request_builder::RequestBuilder::new()
+            .with_path(path)
+            .with_feed_url("https://example.com/feed")
+            .with_title_filter_regex("[")
+            .build()
+            .expect("Failed to build request");
+        if let Some(accept) = accept {
+            request
+                .headers_mut()
+                .insert("accept", http::HeaderValue::from_str(accept).unwrap());
+        }
+
+        let response = real_main(request, local_config(), &backend()).await;
+
+        assert_eq!(response.status().as_u16(), *BAD_REQUEST);
+        assert_eq!(response.headers()[CONTENT_TYPE], "application/problem+json");
+
+        let body = json_body(&response);
+        assert_eq!(body["status"], 400);
+        assert_eq!(body["title"], "Bad Request");
+        assert_eq!(body["code"], "invalid_regex");
+        assert!(
+            body["detail"]
+                .as_str()
+                .unwrap()
+                .contains("title_filter_regex")
+        );
+    }
+
+    fn post(path: &str, content_type: &'static str, body: impl Into<Bytes>) -> Request<Bytes> {
+        Request::builder()
+            .method(Method::POST)
+            .uri(format!("https://test.example.com{path}"))
+            .header(CONTENT_TYPE, content_type)
+            .body(body.into())
+            .unwrap()
+    }
+
+    #[test_case(
+        "application/json",
+        r#"{"url": "URL", "title_filter_regex": ["^Test Item 1$", "^Test Item 3$"]}"#;
+        "json"
+    )]
+    #[test_case(
+        "application/x-www-form-urlencoded",
+        "url=URL&title_filter_regex=%5ETest+Item+1%24&title_filter_regex=%5ETest+Item+3%24";
+        "form"
+    )]
+    #[tokio::test]
+    async fn test_post_definition(content_type: &'static str, body: &str) {
+        let server = serve_test_rss_feed(&["1", "2", "3"]).await.unwrap();
+        let url = server.url();
+        let body = match content_type {
+            "application/json" => body.replace("URL", &url),
+            _ => body.replace("URL", &urlencoding::encode(&url)),
+        };
+
+        let response = real_main(post("/", content_type, body), local_config(), &backend()).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["x-rssfilter-items-removed"], "2");
+        assert!(!contains_string(response.body(), "Test Item 1"));
+        assert!(contains_string(response.body(), "Test Item 2"));
+        assert!(!contains_string(response.body(), "Test Item 3"));
+    }
+
+    #[tokio::test]
+    async fn test_post_feed() {
+        let feed = r#"<rss version="2.0"><channel><title>Feed</title><item><title>Keep</title></item><item><title>Drop</title></item></channel></rss>"#;
+
+        let response = real_main(
+            post(
+                "/?title_filter_regex=%5EDrop%24",
+                "application/rss+xml; charset=utf-8",
+                feed,
+            ),
+            Config::default(),
+            &backend(),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["x-rssfilter-items-removed"], "1");
+        assert!(contains_string(response.body(), "Keep"));
+        assert!(!contains_string(response.body(), "Drop"));
+    }
+
+    #[test_case("/", "application/rss+xml", "<rss/>", *BAD_REQUEST; "feed without filters")]
+    #[test_case("/?title_filter_regex=x", "text/plain", "hello", *UNSUPPORTED_MEDIA_TYPE; "unsupported body")]
+    #[test_case("/", "application/json", "[]", *BAD_REQUEST; "invalid definition")]
+    #[test_case("/", "application/json", "{}", *BAD_REQUEST; "empty definition")]
+    #[tokio::test]
+    async fn test_post_errors(
+        path: &str,
+        content_type: &'static str,
+        body: &'static str,
+        status: u16,
+    ) {
+        let response = real_main(
+            post(path, content_type, body),
+            Config::default(),
+            &backend(),
+        )
+        .await;
+
+        assert_eq!(response.status().as_u16(), status);
+    }
+
+    #[tokio::test]
+    async fn test_post_feed_too_large() {
+        let config = Config {
+            limits: filter_rss_feed::FeedLimits {
+                max_body_bytes: 10,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let response = real_main(
+            post(
+                "/?title_filter_regex=x",
+                "application/rss+xml",
+                "<rss version=\"2.0\"/>",
+            ),
+            config,
+            &backend(),
+        )
+        .await;
+
+        assert_eq!(response.status().as_u16(), *PAYLOAD_TOO_LARGE);
+    }
+
+    const OPML: &str = r#"<opml version="2.0"><body><outline text="Feed" xmlUrl="https://example.com/feed"/></body></opml>"#;
+
+    #[tokio::test]
+    async fn test_opml_filter() {
+        let response = real_main(
+            post(
+                &format!("{API_OPML_FILTER_PATH}?title_filter_regex=%5EAd%3A"),
+                "text/x-opml",
+                OPML,
+            ),
+            Config::default(),
+            &backend(),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[CONTENT_TYPE], "text/x-opml");
+        assert!(contains_string(
+            response.body(),
+            r#"xmlUrl="https://test.example.com/?url=https%3A%2F%2Fexample.com%2Ffeed&amp;title_filter_regex=%5EAd%3A""#
+        ));
+
+        // And back again
+        let response = real_main(
+            post(API_OPML_UNFILTER_PATH, "text/x-opml", response.into_body()),
+            Config::default(),
+            &backend(),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.body(), OPML);
+    }
+
+    /// The first feed URL in a rewritten subscription list.
+    fn first_xml_url(response: &Response<Bytes>) -> Url {
+        let opml = std::str::from_utf8(response.body()).unwrap();
+        let xml_url = opml
+            .split("xmlUrl=\"")
+            .nth(1)
+            .and_then(|rest| rest.split('"').next())
+            .unwrap()
+            .replace("&amp;", "&");
+
+        Url::parse(&xml_url).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_opml_filter_signed() {
+        let signer = UrlSigner::new("shared secret");
+        let config = Config {
+            url_signer: Some(signer.clone()),
+            ..private_config()
+        };
+        let mut request = post(
+            &format!("{API_OPML_FILTER_PATH}?title_filter_regex=%5EAd%3A"),
+            "text/x-opml",
+            OPML,
+        );
+        request
+            .headers_mut()
+            .insert("authorization", "Bearer secret".parse().unwrap());
+
+        let response = real_main(request, config, &backend()).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(signer.verify(&first_xml_url(&response)), Ok(()));
+    }
+
+    #[tokio::test]
+    async fn test_opml_filter_not_signed_without_api_key() {
+        let config = Config {
+            url_signer: Some(UrlSigner::new("shared secret")),
+            ..Default::default()
+        };
+
+        let response = real_main(
+            post(
+                &format!("{API_OPML_FILTER_PATH}?title_filter_regex=%5EAd%3A"),
+                "text/x-opml",
+                OPML,
+            ),
+            config,
+            &backend(),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(
+            !first_xml_url(&response)
+                .query_pairs()
+                .any(|(name, _)| name == SIGNATURE_PARAM)
+        );
+    }
+
+    #[test_case(API_OPML_FILTER_PATH, OPML, "no_filters"; "no filters")]
+    #[test_case(
+        &format!("{API_OPML_FILTER_PATH}?title_filter_regex=%5B"),
+        OPML,
+        "invalid_regex";
+        "invalid regex"
+    )]
+    #[test_case(API_OPML_UNFILTER_PATH, "<rss/>", "invalid_opml"; "not opml")]
+    #[tokio::test]
+    async fn test_opml_errors(path: &str, body: &'static str, code: &str) {
+        let response = real_main(
+            post(path, "text/x-opml", body),
+            Config::default(),
+            &backend(),
+        )
+        .await;
+
+        assert_eq!(response.status().as_u16(), *BAD_REQUEST);
+        assert_eq!(json_body(&response)["code"], code);
+    }
+
+    #[test]
+    fn test_definition_request() {
+        let req = post("/?url=x", "application/json", "{}");
+
+        let req = definition_request(
+            req,
+            vec![("title_filter_regex".to_string(), "^a b$".to_string())],
+        )
+        .unwrap();
+
+        assert_eq!(req.method(), Method::GET);
+        assert_eq!(
+            req.uri(),
+            "https://test.example.com/?url=x&title_filter_regex=%5Ea+b%24"
+        );
+        assert!(!req.headers().contains_key(CONTENT_TYPE));
+        assert!(req.body().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_cors_headers() {
+        let server = serve_test_rss_feed(&["1"]).await.unwrap();
+        let config = Config {
+            cors: CorsConfig {
+                allowed_origins: vec!["https://reader.example.com".to_string()],
+            },
+            ..local_config()
+        };
+        let request = |builder: test_request_builder::RequestBuilder| {
+            let mut request = builder.build().expect("Failed to build request");
+            request.headers_mut().insert(
+                ORIGIN,
+                http::HeaderValue::from_static("https://reader.example.com"),
+            );
+            request
+        };
+
+        let feed = request(
+            test_request_builder::RequestBuilder::new()
+                .with_feed_url(&server.url())
+                .with_title_filter_regex("Test Item 1"),
+        );
+        let response = real_main(feed, config.clone(), &backend()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()["access-control-allow-origin"],
+            "https://reader.example.com"
+        );
+
+        // Errors too, so that scripts can tell what went wrong
+        let error = request(test_request_builder::RequestBuilder::new());
+        let response = real_main(error, config.clone(), &backend()).await;
+        assert_eq!(response.status().as_u16(), *BAD_REQUEST);
+        assert_eq!(
+            response.headers()["access-control-allow-origin"],
+            "https://reader.example.com"
+        );
+
+        let mut preflight =
+            request(test_request_builder::RequestBuilder::new().with_method(Method::OPTIONS));
+        preflight.headers_mut().insert(
+            "access-control-request-method",
+            http::HeaderValue::from_static("GET"),
+        );
+        let response = real_main(preflight, config, &backend()).await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert_eq!(
+            response.headers()["access-control-allow-origin"],
+            "https://reader.example.com"
+        );
+        assert_eq!(
+            response.headers()["access-control-allow-methods"],
+            "GET, HEAD, OPTIONS, POST"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_revalidate() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("GET", "/")
+            .with_status(200)
+            .with_header("content-type", "application/rss+xml")
+            .with_body(r#"<rss version="2.0"><channel><title>Feed</title></channel></rss>"#)
+            .expect(1)
+            .create_async()
+            .await;
+
+        let request = test_request_builder::RequestBuilder::new()
+            .with_feed_url(&server.url())
+            .with_title_filter_regex(".*")
+            .build()
+            .expect("Failed to build request");
+
+        revalidate(
+            request.uri(),
+            request.headers(),
+            &local_config(),
+            &backend(),
+        )
+        .await
+        .expect("Revalidation should succeed");
+        mock.assert_async().await;
+
+        let invalid = test_request_builder::RequestBuilder::new()
+            .build()
+            .expect("Failed to build request");
+        let err = revalidate(
+            invalid.uri(),
+            invalid.headers(),
+            &Config::default(),
+            &backend(),
+        )
+        .await
+        .unwrap_err();
+        assert_matches!(err, RssHandlerError::Validation(_));
+    }
+
+    fn rate_limited_config(rate_limit: RateLimitConfig) -> Config {
+        Config {
+            rate_limit,
+            ..local_config()
+        }
+    }
+
+    const ONE_A_MINUTE: RateLimit = RateLimit {
+        per_minute: 1,
+        burst: 1,
+    };
+
+    #[tokio::test]
+    async fn test_client_rate_limit() {
+        let server = serve_test_rss_feed(&["1", "2"]).await.unwrap();
+        let config = rate_limited_config(RateLimitConfig {
+            client: Some(ONE_A_MINUTE),
+            ..Default::default()
+        });
+        let backend = backend();
+        let request = |path: &str| {
+            let mut request = test_request_builder::RequestBuilder::new()
+                .with_path(path)
+                .with_feed_url(&server.url())
+                .with_title_filter_regex("Test Item 1")
+                .build()
+                .expect("Failed to build request");
+            request
+                .extensions_mut()
+                .insert(ClientAddr("192.0.2.1".parse().unwrap()));
+            request
+        };
+
+        let response = real_main(request("/"), config.clone(), &backend).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = real_main(request(API_FILTER_PATH), config.clone(), &backend).await;
+        assert_eq!(response.status(), *TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[RETRY_AFTER], "60");
+        assert_eq!(json_body(&response)["code"], "rate_limited");
+
+        // Monitors aren't limited
+        let response = real_main(request("/healthz"), config.clone(), &backend).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_upstream_rate_limit() {
+        let server = serve_test_rss_feed(&["1", "2"]).await.unwrap();
+        let config = rate_limited_config(RateLimitConfig {
+            upstream: Some(ONE_A_MINUTE),
+            ..Default::default()
+        });
+        let backend = Backend::with_config(&config, Arc::from(create_rate_limit_store())).unwrap();
+        let request = |title_filter_regex: &str| {
+            test_request_builder::RequestBuilder::new()
+                .with_feed_url(&server.url())
+                .with_title_filter_regex(title_filter_regex)
+                .build()
+                .expect("Failed to build request")
+        };
+
+        let response = real_main(request("Test Item 1"), config.clone(), &backend).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // Filtering the feed another way needs it fetched again
+        let response = real_main(request("Test Item 2"), config.clone(), &backend).await;
+        assert_eq!(response.status(), *TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[RETRY_AFTER], "60");
+    }
+
+    #[tokio::test]
+    async fn test_upstream_rate_limit_spares_cached_feeds() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("GET", "/")
+            .with_header("content-type", "application/rss+xml")
+            .with_header("cache-control", "max-age=300")
+            .with_body(
+                "<rss version=\"2.0\"><channel><title>Feed</title>\
+                 <item><title>Test Item 1</title></item>\
+                 <item><title>Test Item 2</title></item></channel></rss>",
+            )
+            .expect(1)
+            .create_async()
+            .await;
+        let config = rate_limited_config(RateLimitConfig {
+            upstream: Some(ONE_A_MINUTE),
+            ..Default::default()
+        });
+        let backend = Backend::with_config(&config, Arc::from(create_rate_limit_store())).unwrap();
+        let request = |title_filter_regex: &str| {
+            test_request_builder::RequestBuilder::new()
+                .with_feed_url(&server.url())
+                .with_title_filter_regex(title_filter_regex)
+                .build()
+                .expect("Failed to build request")
+        };
+
+        let response = real_main(request("Test Item 1"), config.clone(), &backend).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // The feed is still cached, so filtering it another way doesn't
+        // fetch it again
+        let response = real_main(request("Test Item 2"), config.clone(), &backend).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        mock.assert_async().await;
+    }
+
+    fn private_config() -> Config {
+        Config {
+            auth: AuthConfig {
+                api_keys: Some(vec![
+                    // The SHA-256 of "secret"
+                    "team:2bb80d537b1da3e38bd30361aa855686bde0eacd7162fef6a25fe97bf527a25b"
+                        .parse()
+                        .unwrap(),
+                ]),
+            },
+            ..local_config()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_api_key() {
+        let mut server = mockito::Server::new_async().await;
+        // Our key is ours, and isn't passed on to the feed's server
+        let mock = server
+            .mock("GET", "/")
+            .match_header("authorization", mockito::Matcher::Missing)
+            .with_header("content-type", "application/rss+xml")
+            .with_body("<rss version=\"2.0\"><channel><title>Feed</title></channel></rss>")
+            .expect(2)
+            .create_async()
+            .await;
+        let request = || {
+            test_request_builder::RequestBuilder::new()
+                .with_feed_url(&server.url())
+                .with_title_filter_regex("Ad")
+                .build()
+                .expect("Failed to build request")
+        };
+
+        let mut with_bearer = request();
+        with_bearer
+            .headers_mut()
+            .insert("authorization", "Bearer secret".parse().unwrap());
+        let response = real_main(with_bearer, private_config(), &backend()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // Feed readers can only give it in the URL
+        let mut with_param = request();
+        *with_param.uri_mut() = format!("{}&key=secret", with_param.uri()).parse().unwrap();
+        let response = real_main(with_param, private_config(), &backend()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = String::from_utf8_lossy(response.body());
+        assert!(!body.contains("secret"), "{body}");
+
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_api_key_not_sent_when_revalidating() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("GET", "/")
+            .match_header("authorization", mockito::Matcher::Missing)
+            .with_header("content-type", "application/rss+xml")
+            .with_header("cache-control", "max-age=1")
+            .with_body("<rss version=\"2.0\"><channel><title>Feed</title></channel></rss>")
+            .expect(2)
+            .create_async()
+            .await;
+        let request = || {
+            let mut request = test_request_builder::RequestBuilder::new()
+                .with_feed_url(&server.url())
+                .with_title_filter_regex("Ad")
+                .build()
+                .expect("Failed to build request");
+            request
+                .headers_mut()
+                .insert("authorization", "Bearer secret".parse().unwrap());
+            request
+        };
+        let mut config = private_config();
+        config.cache.min_ttl_seconds = 0;
+        let backend = backend();
+
+        let response = real_main(request(), config.clone(), &backend).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // Stale, but within stale-while-revalidate
+        std::thread::sleep(Duration::from_secs(2));
+        let response = real_main(request(), config.clone(), &backend).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let Revalidate { uri, headers } = response
+            .extensions()
+            .get::<Revalidate>()
+            .expect("The stale feed should be refreshed");
+        assert!(!headers.contains_key("authorization"));
+
+        revalidate(uri, headers, &config, &backend)
+            .await
+            .expect("Revalidation should succeed");
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_api_key_refused() {
+        let request = test_request_builder::RequestBuilder::new()
+            .with_path(API_FILTER_PATH)
+            .with_feed_url("https://example.com/feed")
+            .with_title_filter_regex("Ad")
+            .build()
+            .expect("Failed to build request");
+
+        let response = real_main(request.clone(), private_config(), &backend()).await;
+        assert_eq!(response.status(), *UNAUTHORIZED);
+        assert_eq!(response.headers()[WWW_AUTHENTICATE], "Bearer");
+        assert_eq!(json_body(&response)["code"], "missing_api_key");
+
+        let mut wrong = request;
+        wrong
+            .headers_mut()
+            .insert("authorization", "Bearer wrong".parse().unwrap());
+        let response = real_main(wrong, private_config(), &backend()).await;
+        assert_eq!(response.status(), *FORBIDDEN);
+        assert_eq!(json_body(&response)["code"], "invalid_api_key");
+
+        // Monitors don't need a key
+        let healthz = test_request_builder::RequestBuilder::new()
+            .with_path("/healthz")
+            .build()
+            .expect("Failed to build request");
+        let response = real_main(healthz, private_config(), &backend()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    fn signed_config() -> Config {
+        Config {
+            url_signer: Some(UrlSigner::new("shared secret")),
+            ..local_config()
+        }
+    }
+
+    fn sign(req: &mut Request<Bytes>) {
+        let url = Url::parse(&req.uri().to_string()).unwrap();
+        let signed = UrlSigner::new("shared secret").sign(&url);
+        *req.uri_mut() = signed.as_str().parse().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_signed_url() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("GET", "/")
+            .with_header("content-type", "application/rss+xml")
+            .with_body("<rss version=\"2.0\"><channel><title>Feed</title></channel></rss>")
+            .create_async()
+            .await;
+        let mut request = test_request_builder::RequestBuilder::new()
+            .with_feed_url(&server.url())
+            .with_title_filter_regex("Ad")
+            .build()
+            .expect("Failed to build request");
+        sign(&mut request);
+
+        let response = real_main(request, signed_config(), &backend()).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_signed_url_refused() {
+        let request = || {
+            test_request_builder::RequestBuilder::new()
+                .with_path(API_FILTER_PATH)
+                .with_feed_url("https://example.com/feed")
+                .with_title_filter_regex("Ad")
+        };
+
+        let unsigned = request().build().expect("Failed to build request");
+        let response = real_main(unsigned, signed_config(), &backend()).await;
+        assert_eq!(response.status(), *FORBIDDEN);
+        assert_eq!(json_body(&response)["code"], "missing_signature");
+
+        // A shared link can't be pointed at another feed, or filtered differently
+        let mut tampered = request().build().expect("Failed to build request");
+        sign(&mut tampered);
+        *tampered.uri_mut() = tampered
+            .uri()
+            .to_string()
+            .replace("title_filter_regex=Ad", "title_filter_regex=News")
+            .parse()
+            .unwrap();
+        let response = real_main(tampered, signed_config(), &backend()).await;
+        assert_eq!(response.status(), *FORBIDDEN);
+        assert_eq!(json_body(&response)["code"], "invalid_signature");
+    }
+
+    fn preview_request(feed_url: &str) -> Request<Bytes> {
+        test_request_builder::RequestBuilder::new()
+            .with_path(API_PREVIEW_PATH)
+            .with_feed_url(feed_url)
+            .with_title_filter_regex("Test Item 1")
+            .build()
+            .expect("Failed to build request")
+    }
+
+    fn preview_subscription_url(response: &Response<Bytes>) -> Url {
+        Url::parse(json_body(response)["subscription_url"].as_str().unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_api_preview_signed_config() {
+        let server = serve_test_rss_feed(&["1", "2"]).await.unwrap();
+
+        let response = real_main(preview_request(&server.url()), signed_config(), &backend()).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = json_body(&response);
+        assert_eq!(body["needs_signature"], true);
+        assert_eq!(body["items"][0]["removed"], true);
+        assert!(
+            !preview_subscription_url(&response)
+                .query_pairs()
+                .any(|(name, _)| name == SIGNATURE_PARAM)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_api_preview_keeps_signature() {
+        let server = serve_test_rss_feed(&["1", "2"]).await.unwrap();
+        let mut request = preview_request(&server.url());
+        sign(&mut request);
+
+        let response = real_main(request, signed_config(), &backend()).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(json_body(&response)["needs_signature"], false);
+        assert_eq!(
+            UrlSigner::new("shared secret").verify(&preview_subscription_url(&response)),
+            Ok(())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_api_preview_signed_with_api_key() {
+        let server = serve_test_rss_feed(&["1", "2"]).await.unwrap();
+        let config = Config {
+            url_signer: Some(UrlSigner::new("shared secret")),
+            ..private_config()
+        };
+        let mut request = preview_request(&server.url());
+        request
+            .headers_mut()
+            .insert("authorization", "Bearer secret".parse().unwrap());
+
+        let response = real_main(request, config, &backend()).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(json_body(&response)["needs_signature"], false);
+        assert_eq!(
+            UrlSigner::new("shared secret").verify(&preview_subscription_url(&response)),
+            Ok(())
+        );
+    }
+}
+
+#[cfg(all(test, target_arch = "wasm32"))]
+mod wasm_tests {
+    use super::*;
+
+    use http::{Method, Request};
+    use matches::assert_matches;
+    use test_utils::test_request_builder::RequestBuilder;
+    use wasm_bindgen_test::{wasm_bindgen_test, wasm_bindgen_test_configure};
+
+    wasm_bindgen_test_configure!(run_in_node_experimental);
+
+    fn backend() -> Backend {
+        Backend::new().expect("Failed to create backend")
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_no_query_params() {
+        let req = RequestBuilder::new().build().unwrap();
+
+        let res = rss_handler(req, &Config::default(), &backend()).await;
+
+        assert!(res.is_err());
+        let res_err = res.unwrap_err();
+        assert_matches!(
+            res_err,
+            RssHandlerError::Validation(ValidationError::NoParametersProvided)
+        );
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_no_url_param() {
+        let req = RequestBuilder::new()
+            .with_title_filter_regex(".*")
+            .build()
+            .unwrap();
+
+        let res = rss_handler(req, &Config::default(), &backend()).await;
+
+        assert!(res.is_err());
+        let res_err = res.unwrap_err();
+        assert_matches!(
+            res_err,
+            RssHandlerError::Validation(ValidationError::NoUrlProvided)
+        );
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_no_filters() {
+        let req = RequestBuilder::new()
+            .with_feed_url("http://example.com/rss")
+            .build()
+            .unwrap();
+
+        let res = rss_handler(req, &Config::default(), &backend()).await;
+
+        assert!(res.is_err());
+        let res_err = res.unwrap_err();
+        assert_matches!(
+            res_err,
+            RssHandlerError::Validation(ValidationError::NoFiltersProvided)
+        );
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_invalid_regex() {
+        let req = RequestBuilder::new()
+            .with_feed_url("http://example.com/rss")
+            .with_title_filter_regex("[invalid regex") // Invalid regex
+            .build()
+            .unwrap();
+
+        let res = rss_handler(req, &Config::default(), &backend()).await;
+        assert!(res.is_err());
+
+        let rss_error = res.unwrap_err();
+        assert_matches!(
+            rss_error,
+            RssHandlerError::Validation(ValidationError::InvalidRegex { .. })
+        );
+
+        let response: Response<Bytes> = rss_error.into();
+        assert_eq!(response.status().as_u16(), *BAD_REQUEST);
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_malformed_url_encoding() {
+        // Create a request with invalid UTF-8 in URL encoding
+        let url_str = "https://test.example.com/?url=http://example.com&title_filter_regex=%FF%FE";
+        let req = Request::builder()
+            .method(Method::GET)
+            .uri(url_str)
+            .body(Bytes::new())
+            .unwrap();
+
+        let res = rss_handler(req, &Config::default(), &backend()).await;
+        assert!(res.is_err());
+
+        let rss_error = res.unwrap_err();
+        // The error might be different depending on how the URL is parsed and what response we get
+        // It could be MalformedParameter, InvalidRegex, InvalidContentType, or a network error
+        let is_expected_error = matches!(
+            rss_error,
+            RssHandlerError::Validation(ValidationError::MalformedParameter { .. })
+                | RssHandlerError::Validation(ValidationError::InvalidRegex { .. })
+                | RssHandlerError::Processing(ProcessingError::Rss(RssError::HttpClient { .. }))
+                | RssHandlerError::Processing(ProcessingError::Rss(
+                    RssError::InvalidContentType { .. }
+                ))
+        );
+        assert!(
+            is_expected_error,
+            "Expected a parameter-related or content error, got: {rss_error:?}"
+        );
+
+        let response: Response<Bytes> = rss_error.into();
+        // The status code could be 400, 415, or 502 depending on the specific error
+        assert!(
+            response.status().as_u16() == *BAD_REQUEST
+                || response.status().as_u16() == *BAD_GATEWAY
+                || response.status().as_u16() == *UNSUPPORTED_MEDIA_TYPE
+        );
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_multiple_regex_parameters() {
+        let fake_client = FakeHttpClientBuilder::default()
+            .with_json_response("https://example.com/json", r#"{"key": "value"}"#)
+            .build()
+            .expect("Failed to build fake client");
+
+        let title_regex1 = Regex::new(".*1.*").expect("Invalid regex");
+        let title_regex2 = Regex::new(".*2.*").expect("Invalid regex");
+        let guid_regex = Regex::new("test").expect("Invalid regex");
+
+        let filter_regexes = FilterRegexes {
+            title_regexes: &[title_regex1, title_regex2],
+            guid_regexes: &[guid_regex],
+            link_regexes: &[],
+        };
+
+        let rss_filter = RssFilter::new_with_http_client(&filter_regexes, Box::new(fake_client));
+        let result = rss_filter
+            .fetch_and_filter("https://example.com/json")
+            .await;
+
+        assert!(result.is_err());
+        let error = result.unwrap_err();
+
+        assert_matches!(error, RssError::InvalidContentType { .. });
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_parse_error() {
+        let fake_client = FakeHttpClientBuilder::default()
+            .with_xml_response(
+                "https://example.com/xml",
+                "<root><item>not rss</item></root>",
+            )
+            .build()
+            .expect("Failed to build fake client");
+
+        let title_regex = Regex::new(".*1.*").expect("Invalid regex");
+        let guid_regex = Regex::new("test").expect("Invalid regex");
+
+        let filter_regexes = FilterRegexes {
+            title_regexes: &[title_regex],
+            guid_regexes: &[guid_regex],
+            link_regexes: &[],
+        };
+
+        let rss_filter = RssFilter::new_with_http_client(&filter_regexes, Box::new(fake_client));
+        let result = rss_filter.fetch_and_filter("https://example.com/xml").await;
+
+        assert!(result.is_err());
+        let error = result.unwrap_err();
+
+        assert_matches!(error, RssError::RSSParse { .. });
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_error_status_mapping_bad_request() {
+        let req = RequestBuilder::new().build().unwrap();
+
+        let res = rss_handler(req, &Config::default(), &backend()).await;
+        assert!(res.is_err());
+
+        let rss_error = res.unwrap_err();
+        let response: Response<Bytes> = rss_error.into();
+
+        assert_eq!(response.status().as_u16(), *BAD_REQUEST);
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_error_status_mapping_bad_gateway() {
+        let req = RequestBuilder::new()
+            // The .invalid TLD never resolves
+            .with_feed_url("https://unreachable.invalid")
+            .with_title_filter_regex(".*")
+            .build()
+            .unwrap();
+
+        let res = rss_handler(req, &Config::default(), &backend()).await;
+        assert!(res.is_err());
+
+        let rss_error = res.unwrap_err();
+        let response: Response<Bytes> = rss_error.into();
+
+        assert_eq!(response.status().as_u16(), *BAD_GATEWAY);
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_main_wrong_path() {
+        let req = RequestBuilder::new().with_path("/wrong").build().unwrap();
+
+        let result = real_main(req, Config::default(), &backend()).await;
+        assert_eq!(result.status().as_u16(), *NOT_FOUND);
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_main_wrong_method() {
+        let req = RequestBuilder::new()
+            .with_method(Method::PUT)
+            .build()
+            .unwrap();
+
+        let result = real_main(req, Config::default(), &backend()).await;
+        assert_eq!(result.status().as_u16(), *METHOD_NOT_ALLOWED);
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_main_no_params() {
+        let req = RequestBuilder::new().build().unwrap();
+
+        let result = real_main(req, Config::default(), &backend()).await;
+        assert_eq!(result.status().as_u16(), *BAD_REQUEST);
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_validate_request_function() {
+        let valid_req = RequestBuilder::new().build().unwrap();
+        assert!(validate_request(&valid_req).is_ok());
+
+        let wrong_path = RequestBuilder::new().with_path("/wrong").build().unwrap();
+        let err = validate_request(&wrong_path).unwrap_err();
+        assert_matches!(err, RequestValidationError::NotFound);
+
+        let wrong_method = RequestBuilder::new()
+            .with_method(Method::PUT)
+            .build()
+            .unwrap();
+
+        let err = validate_request(&wrong_method).expect_err("Expected method validation to fail");
+        assert_matches!(err, RequestValidationError::MethodNotAllowed);
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod request_validation_integration_tests {
+    use super::*;
+    use headers::{ContentType, HeaderMapExt};
+    use http::{Method, Request};
+    use matches::assert_matches;
+    use test_case::test_case;
+
+    fn backend() -> Backend {
+        Backend::new().expect("Failed to create backend")
+    }
+
+    #[tokio::test]
+    async fn test_validate_request_not_found_integration() {
+        let req = Request::builder()
+            .method(Method::GET)
+            .uri("https://test.example.com/nonexistent")
+            .body(Bytes::new())
+            .unwrap();
+
+        let response = real_main(req, Config::default(), &backend()).await;
+        assert_eq!(response.status().as_u16(), *NOT_FOUND);
+
+        let body = response.into_body();
+        let body_str = std::str::from_utf8(&body).unwrap();
+        assert_eq!(body_str, "Not Found");
+    }
+
+    #[test_case(Method::PUT; "put method")]
+    #[test_case(Method::DELETE; "delete method")]
+    #[test_case(Method::PATCH; "patch method")]
+    #[test_case(Method::CONNECT; "connect method")]
+    #[test_case(Method::TRACE; "trace method")]
+    #[tokio::test]
+    async fn test_validate_request_method_not_allowed(method: Method) {
+        let req = Request::builder()
+            .method(method)
+            .uri("https://test.example.com/")
+            .body(Bytes::new())
+            .unwrap();
+
+        let response = real_main(req, Config::default(), &backend()).await;
+        assert_eq!(response.status().as_u16(), *METHOD_NOT_ALLOWED);
+        assert_eq!(response.headers()["allow"], "GET, HEAD, OPTIONS, POST");
+    }
+
+    #[test_case(Method::GET; "get method")]
+    #[test_case(Method::HEAD; "head method")]
+    #[test_case(Method::OPTIONS; "options method")]
+    #[test_case(Method::POST; "post method")]
+    fn test_validate_request_allowed_methods(method: Method) {
+        let req = Request::builder()
+            .method(method)
+            .uri("https://test.example.com/")
+            .body(Bytes::new())
+            .unwrap();
+
+        assert!(validate_request(&req).is_ok());
+    }
+
+    #[test_case("/", Route::Filter; "filter")]
+    #[test_case(API_FILTER_PATH, Route::ApiFilter; "api filter")]
+    #[test_case(API_PREVIEW_PATH, Route::ApiPreview; "api preview")]
+    #[test_case("/ui", Route::Ui; "ui")]
+    #[test_case("/healthz", Route::Healthz; "healthz")]
+    #[test_case("/readyz", Route::Readyz; "readyz")]
+    #[test_case("/version", Route::Version; "version")]
+    fn test_validate_request_routes(path: &str, route: Route) {
+        let req = Request::builder()
+            .method(Method::GET)
+            .uri(format!("https://test.example.com{path}?url=x"))
+            .body(Bytes::new())
+            .unwrap();
+
+        assert_eq!(validate_request(&req).unwrap(), route);
+    }
+
+    #[test_case(API_OPML_FILTER_PATH, Route::ApiOpmlFilter; "opml filter")]
+    #[test_case(API_OPML_UNFILTER_PATH, Route::ApiOpmlUnfilter; "opml unfilter")]
+    fn test_validate_request_opml_routes(path: &str, route: Route) {
+        let post = Request::builder()
+            .method(Method::POST)
+            .uri(format!("https://test.example.com{path}"))
+            .body(Bytes::new())
+            .unwrap();
+        assert_eq!(validate_request(&post).unwrap(), route);
+
+        let get = Request::builder()
+            .uri(format!("https://test.example.com{path}"))
+            .body(Bytes::new())
+            .unwrap();
+        assert_matches!(
+            validate_request(&get),
+            Err(RequestValidationError::MethodNotAllowed)
+        );
+    }
+
+    #[test_case("/healthz", "ok"; "healthz")]
+    #[test_case("/readyz", "ready"; "readyz")]
+    #[tokio::test]
+    async fn test_health_endpoints(path: &str, body: &str) {
+        let req = Request::builder()
+            .method(Method::GET)
+            .uri(format!("https://test.example.com{path}"))
+            .body(Bytes::new())
+            .unwrap();
+
+        let response = real_main(req, Config::default(), &backend()).await;
+        assert_eq!(response.status().as_u16(), 200);
+        assert_eq!(response.body(), body);
+    }
+
+    #[tokio::test]
+    async fn test_version_endpoint() {
+        let req = Request::builder()
+            .method(Method::GET)
+            .uri("https://test.example.com/version")
+            .body(Bytes::new())
+            .unwrap();
+
+        let response = real_main(req, Config::default(), &backend()).await;
+        assert_eq!(response.status().as_u16(), 200);
+
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body["version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(body["features"], serde_json::json!(["self_link"]));
+    }
+
+    #[tokio::test]
+    async fn test_health_endpoint_method_not_allowed() {
+        let req = Request::builder()
+            .method(Method::POST)
+            .uri("https://test.example.com/healthz")
+            .body(Bytes::new())
+            .unwrap();
+
+        let response = real_main(req, Config::default(), &backend()).await;
+        assert_eq!(response.status().as_u16(), *METHOD_NOT_ALLOWED);
+        assert_eq!(response.headers()["allow"], "GET, HEAD, OPTIONS");
+    }
+
+    #[tokio::test]
+    async fn test_options_without_cors() {
+        let req = Request::builder()
+            .method(Method::OPTIONS)
+            .uri("https://test.example.com/")
+            .header("origin", "https://reader.example.com")
+            .header("access-control-request-method", "GET")
+            .body(Bytes::new())
+            .unwrap();
+
+        let response = real_main(req, Config::default(), &backend()).await;
+        assert_eq!(response.status().as_u16(), 204);
+        assert_eq!(response.headers()["allow"], "GET, HEAD, OPTIONS, POST");
+        assert!(
+            !response
+                .headers()
+                .contains_key("access-control-allow-origin")
+        );
+    }
+
+    #[test_case("/favicon.ico"; "favicon")]
+    #[test_case("/robots.txt"; "robots")]
+    #[test_case("/api/v1/something"; "api endpoint")]
+    #[test_case("/health"; "health check")]
+    #[test_case("/status"; "status check")]
+    #[test_case("/healthz/"; "health check with trailing slash")]
+    #[test_case("/version.json"; "version with extension")]
+    #[test_case("/.well-known/something"; "well known")]
+    #[tokio::test]
+    async fn test_validate_request_various_wrong_paths(path: &str) {
+        let req = Request::builder()
+            .method(Method::GET)
+            .uri(format!("https://test.example.com{path}"))
+            .body(Bytes::new())
+            .unwrap();
+
+        let response = real_main(req, Config::default(), &backend()).await;
+        assert_eq!(
+            response.status().as_u16(),
+            *NOT_FOUND,
+            "Expected 404 for path: {path}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_validate_request_content_type_header() {
+        // Test that content-type is set correctly for validation errors
+        let req = Request::builder()
+            .method(Method::PUT)
+            .uri("https://test.example.com/")
+            .body(Bytes::new())
+            .unwrap();
+
+        let response = real_main(req, Config::default(), &backend()).await;
+        assert_eq!(response.status().as_u16(), *METHOD_NOT_ALLOWED);
+
+        let content_type = response
+            .headers()
+            .typed_get::<headers::ContentType>()
+            .expect("Content-Type header should be present");
+        assert_eq!(content_type, ContentType::text());
+    }
+
+    #[tokio::test]
+    async fn test_validate_request_successful_validation() {
+        // Test that a valid request passes validation and reaches parameter validation
+        let req = Request::builder()
+            .method(Method::GET)
+            .uri("https://test.example.com/")
+            .body(Bytes::new())
+            .unwrap();
+
+        let response = real_main(req, Config::default(), &backend()).await;
+        // Should get 400 for missing parameters, not 404/405 for validation
+        assert_eq!(response.status().as_u16(), *BAD_REQUEST);
+    }
+}
+
+#[cfg(test)]
+mod error_conversion_tests {
+    use super::*;
+
+    #[test]
+    fn test_error_conversion() {
+        let error = RssHandlerError::Validation(ValidationError::NoParametersProvided);
+        let response: Response<Bytes> = error.into();
+        assert_eq!(response.status().as_u16(), *BAD_REQUEST);
+
+        let error = RssHandlerError::Processing(ProcessingError::Rss(RssError::FeedTooLarge {
+            max_size: 1024 * 1024, // 1MB example
+        }));
+        let response: Response<Bytes> = error.into();
+        assert_eq!(response.status().as_u16(), *PAYLOAD_TOO_LARGE);
+
+        let error = RssHandlerError::from(RssError::TooManyItems { max_items: 10 });
+        let response: Response<Bytes> = error.into();
+        assert_eq!(response.status().as_u16(), *PAYLOAD_TOO_LARGE);
+
+        let error = RssHandlerError::from(RssError::NestingTooDeep { max_depth: 4 });
+        let response: Response<Bytes> = error.into();
+        assert_eq!(response.status().as_u16(), *UNPROCESSABLE_ENTITY);
+
+        let error =
+            RssHandlerError::Processing(ProcessingError::Rss(RssError::InvalidContentType {
+                content_type: "text/html".to_string(),
+            }));
+        let response: Response<Bytes> = error.into();
+        assert_eq!(response.status().as_u16(), *UNSUPPORTED_MEDIA_TYPE);
+
+        let error = RssHandlerError::from(RssError::HttpClient(HttpClientError::Timeout(
+            Duration::from_secs(15),
+        )));
+        let response: Response<Bytes> = error.into();
+        assert_eq!(response.status().as_u16(), *GATEWAY_TIMEOUT);
+    }
+
+    #[test]
+    fn test_request_validation_error_conversion() {
+        let error = RequestValidationError::NotFound;
+        let response: Response<Bytes> = error.into();
+        assert_eq!(response.status().as_u16(), *NOT_FOUND);
+
+        let body = response.into_body();
+        let body_str = std::str::from_utf8(&body).unwrap();
+        assert_eq!(body_str, "Not Found");
+
+        let error = RequestValidationError::MethodNotAllowed;
+        let response: Response<Bytes> = error.into();
+        assert_eq!(response.status().as_u16(), *METHOD_NOT_ALLOWED);
+
+        let body = response.into_body();
+        let body_str = std::str::from_utf8(&body).unwrap();
+        assert_eq!(body_str, "Method Not Allowed");
+    }
+
+    #[test]
+    fn test_worker_config_default() {
+        let config = WorkerConfig::default();
+        assert_eq!(config.log_format, None);
+        assert_eq!(config.rust_log, None);
+    }
+
+    #[test]
+    fn test_worker_config_debug_clone() {
+        let config = WorkerConfig {
+            log_format: Some("json".to_string()),
+            rust_log: Some("debug".to_string()),
+        };
+
+        let cloned = config.clone();
+        assert_eq!(config.log_format, cloned.log_format);
+        assert_eq!(config.rust_log, cloned.rust_log);
+
+        // Verify Debug trait works
+        let debug_str = format!("{config:?}");
+        assert!(debug_str.contains("json"));
+        assert!(debug_str.contains("debug"));
+    }
+}
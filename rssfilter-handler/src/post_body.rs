@@ -0,0 +1,183 @@
+use http::HeaderMap;
+use http::header::CONTENT_TYPE;
+use mediatype::MediaType;
+use serde_json::Value;
+
+use crate::ValidationError;
+
+/// Media types of feeds which can be posted to be filtered.
+const FEED_MEDIA_TYPES: &[&str] = &[
+    "application/rss+xml",
+    "application/atom+xml",
+    "application/xml",
+    "text/xml",
+];
+
+/// What a `POST` to "/" carries, going by its `Content-Type`.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum PostBody {
+    /// A filter definition, as the query parameters it stands for.
+    Definition(Vec<(String, String)>),
+    /// A feed to filter with the filters in the query, and its media type
+    /// without any parameters.
+    Feed(&'static str),
+}
+
+fn invalid(reason: impl Into<String>) -> ValidationError {
+    ValidationError::InvalidBody(reason.into())
+}
+
+/// Reads a filter definition sent as JSON: an object whose members are the
+/// query parameters, each a string or a list of strings, such as
+/// `{"url": "...", "title_filter_regex": ["^Ad:", "^Sponsored:"]}`.
+fn json_definition(body: &[u8]) -> Result<Vec<(String, String)>, ValidationError> {
+    let Value::Object(members) =
+        serde_json::from_slice(body).map_err(|err| invalid(err.to_string()))?
+    else {
+        return Err(invalid("the filter definition must be a JSON object"));
+    };
+
+    let mut pairs = Vec::new();
+
+    for (name, value) in members {
+        let values = match value {
+            Value::String(value) => vec![Value::String(value)],
+            Value::Array(values) => values,
+            _ => {
+                return Err(invalid(format!(
+                    "{name} must be a string or a list of strings"
+                )));
+            }
+        };
+
+        for value in values {
+            let Value::String(value) = value else {
+                return Err(invalid(format!("{name} must only contain strings")));
+            };
+            pairs.push((name.clone(), value));
+        }
+    }
+
+    Ok(pairs)
+}
+
+/// Works out what a `POST` body is: a filter definition, as JSON or a form,
+/// or a feed.
+pub(crate) fn parse_post_body(
+    headers: &HeaderMap,
+    body: &[u8],
+) -> Result<PostBody, ValidationError> {
+    let content_type = headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    let unsupported = || ValidationError::UnsupportedBody {
+        content_type: content_type.to_string(),
+    };
+
+    let media_type = MediaType::parse(content_type).map_err(|_| unsupported())?;
+    let essence = media_type.essence().to_string();
+
+    match essence.as_str() {
+        "application/json" => Ok(PostBody::Definition(json_definition(body)?)),
+        "application/x-www-form-urlencoded" => Ok(PostBody::Definition(
+            url::form_urlencoded::parse(body).into_owned().collect(),
+        )),
+        _ => FEED_MEDIA_TYPES
+            .iter()
+            .find(|feed_type| **feed_type == essence)
+            .map(|feed_type| PostBody::Feed(feed_type))
+            .ok_or_else(unsupported),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::HeaderValue;
+    use matches::assert_matches;
+    use test_case::test_case;
+
+    fn parse(content_type: &'static str, body: &str) -> Result<PostBody, ValidationError> {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static(content_type));
+        parse_post_body(&headers, body.as_bytes())
+    }
+
+    fn pairs(pairs: &[(&str, &str)]) -> PostBody {
+        PostBody::Definition(
+            pairs
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_json_definition() {
+        let body = parse(
+            "application/json",
+            r#"{"url": "https://example.com/feed", "title_filter_regex": ["^Ad:", "^Sponsored:"], "link_filter_regex": "/ads/"}"#,
+        )
+        .unwrap();
+
+        // Members come out by name, which doesn't matter to the filter
+        assert_eq!(
+            body,
+            pairs(&[
+                ("link_filter_regex", "/ads/"),
+                ("title_filter_regex", "^Ad:"),
+                ("title_filter_regex", "^Sponsored:"),
+                ("url", "https://example.com/feed"),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_form_definition() {
+        let body = parse(
+            "application/x-www-form-urlencoded; charset=utf-8",
+            "url=https%3A%2F%2Fexample.com%2Ffeed&title_filter_regex=%5EAd%3A&title_filter_regex=%5ESponsored%3A",
+        )
+        .unwrap();
+
+        assert_eq!(
+            body,
+            pairs(&[
+                ("url", "https://example.com/feed"),
+                ("title_filter_regex", "^Ad:"),
+                ("title_filter_regex", "^Sponsored:"),
+            ])
+        );
+    }
+
+    #[test_case("application/rss+xml", "application/rss+xml"; "rss")]
+    #[test_case("application/atom+xml", "application/atom+xml"; "atom")]
+    #[test_case("text/xml; charset=utf-8", "text/xml"; "xml with charset")]
+    fn test_feed(content_type: &'static str, expected: &'static str) {
+        assert_eq!(
+            parse(content_type, "<rss/>").unwrap(),
+            PostBody::Feed(expected)
+        );
+    }
+
+    #[test_case("[]"; "not an object")]
+    #[test_case("{"; "not json")]
+    #[test_case(r#"{"url": 1}"#; "not a string")]
+    #[test_case(r#"{"title_filter_regex": ["a", 1]}"#; "not a list of strings")]
+    fn test_invalid_json(body: &str) {
+        assert_matches!(
+            parse("application/json", body),
+            Err(ValidationError::InvalidBody(_))
+        );
+    }
+
+    #[test_case("text/plain"; "text")]
+    #[test_case("not a media type"; "invalid")]
+    fn test_unsupported(content_type: &'static str) {
+        assert_matches!(
+            parse(content_type, ""),
+            Err(ValidationError::UnsupportedBody { .. })
+        );
+    }
+}
@@ -0,0 +1,274 @@
+use std::sync::LazyLock;
+
+use bytes::Bytes;
+use headers::HeaderMapExt;
+use headers_accept::Accept;
+use http::header::CONTENT_TYPE;
+use http::{HeaderValue, Request, Response};
+use mediatype::MediaType;
+use serde_json::json;
+
+use filter_rss_feed::{HttpClientError, RssError, SignatureError, UrlPolicyError};
+
+use crate::{AuthError, ProcessingError, RequestValidationError, RssHandlerError, ValidationError};
+
+/// Where the JSON API's paths start.
+const API_PREFIX: &str = "/api/";
+
+static TEXT_PLAIN: LazyLock<MediaType<'static>> =
+    LazyLock::new(|| MediaType::parse("text/plain").expect("Invalid media type"));
+static APPLICATION_JSON: LazyLock<MediaType<'static>> =
+    LazyLock::new(|| MediaType::parse("application/json").expect("Invalid media type"));
+static APPLICATION_PROBLEM_JSON: LazyLock<MediaType<'static>> =
+    LazyLock::new(|| MediaType::parse("application/problem+json").expect("Invalid media type"));
+
+/// How errors are reported to a client.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ErrorFormat {
+    /// The error message as `text/plain`, as feed readers have always had.
+    Text,
+    /// RFC 9457 problem details, as `application/problem+json`, with a
+    /// machine-readable `code`.
+    Problem,
+}
+
+impl ErrorFormat {
+    /// The JSON API always gets problem details. Elsewhere, clients get them
+    /// by preferring JSON in `Accept`; anything else, including no `Accept`
+    /// or one which doesn't parse, gets text.
+    pub(crate) fn for_request<T>(req: &Request<T>) -> Self {
+        if req.uri().path().starts_with(API_PREFIX) {
+            return Self::Problem;
+        }
+
+        let Some(accept) = req.headers().typed_get::<Accept>() else {
+            return Self::Text;
+        };
+
+        // Text comes first, so that it wins ties such as `*/*`
+        let available = [&*TEXT_PLAIN, &*APPLICATION_PROBLEM_JSON, &*APPLICATION_JSON];
+
+        match accept.negotiate(available) {
+            Some(media_type) if media_type != &*TEXT_PLAIN => Self::Problem,
+            _ => Self::Text,
+        }
+    }
+
+    /// Reports an error with the given `code`, whose text response is
+    /// `response`. Problem details keep the text response's status and
+    /// headers, with its message as the `detail`.
+    pub(crate) fn respond(self, code: &'static str, response: Response<Bytes>) -> Response<Bytes> {
+        if self == Self::Text {
+            return response;
+        }
+
+        let (mut parts, message) = response.into_parts();
+        let detail = String::from_utf8_lossy(&message);
+
+        let problem = json!({
+            "type": "about:blank",
+            "title": parts.status.canonical_reason(),
+            "status": parts.status.as_u16(),
+            "detail": detail,
+            "code": code,
+        });
+
+        parts.headers.insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static("application/problem+json"),
+        );
+
+        Response::from_parts(parts, problem.to_string().into())
+    }
+}
+
+fn url_policy_code(err: &UrlPolicyError) -> &'static str {
+    match err {
+        UrlPolicyError::Invalid(_) => "invalid_url",
+        UrlPolicyError::UnsupportedScheme(_) => "unsupported_scheme",
+        UrlPolicyError::NoHost => "no_host",
+        UrlPolicyError::PrivateAddress(_) => "private_address",
+        UrlPolicyError::SelfReference(_) => "self_reference",
+        UrlPolicyError::DeniedHost(_) => "denied_host",
+        UrlPolicyError::HostNotAllowed(_) => "host_not_allowed",
+    }
+}
+
+impl RequestValidationError {
+    /// A stable, machine-readable name for the error.
+    pub fn code(&self) -> &'static str {
+        match self {
+            RequestValidationError::NotFound => "not_found",
+            RequestValidationError::MethodNotAllowed => "method_not_allowed",
+        }
+    }
+}
+
+impl ValidationError {
+    /// A stable, machine-readable name for the error.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ValidationError::MalformedParameter { .. } => "malformed_parameter",
+            ValidationError::InvalidRegex { .. } => "invalid_regex",
+            ValidationError::NoParametersProvided => "no_parameters",
+            ValidationError::NoFiltersProvided => "no_filters",
+            ValidationError::UrlParseError { .. } => "invalid_url",
+            ValidationError::NoUrlProvided => "no_url",
+            ValidationError::UrlPolicy(err) => url_policy_code(err),
+            ValidationError::InvalidBody(_) => "invalid_body",
+            ValidationError::UnsupportedBody { .. } => "unsupported_body",
+            ValidationError::InvalidOpml(_) => "invalid_opml",
+            ValidationError::Signature(SignatureError::Missing) => "missing_signature",
+            ValidationError::Signature(SignatureError::Invalid) => "invalid_signature",
+        }
+    }
+}
+
+impl RssHandlerError {
+    /// A stable, machine-readable name for the error.
+    pub fn code(&self) -> &'static str {
+        match self {
+            RssHandlerError::Validation(err) => err.code(),
+            RssHandlerError::Processing(ProcessingError::RequestBuild { .. }) => "request_build",
+            RssHandlerError::Processing(ProcessingError::UpstreamStatus { .. }) => {
+                "upstream_status"
+            }
+            RssHandlerError::Processing(ProcessingError::Rss(err)) => match err {
+                RssError::Http(_) => "request_build",
+                RssError::HttpClient(HttpClientError::Timeout(_)) => "upstream_timeout",
+                RssError::HttpClient(HttpClientError::UrlPolicy(err)) => url_policy_code(err),
+                RssError::HttpClient(HttpClientError::TooManyRedirects { .. }) => {
+                    "too_many_redirects"
+                }
+                RssError::HttpClient(HttpClientError::Redirect(_)) => "redirect_refused",
+                RssError::HttpClient(HttpClientError::RateLimited { .. }) => {
+                    "upstream_rate_limited"
+                }
+                RssError::HttpClient(_) => "upstream_error",
+                RssError::FeedTooLarge { .. } => "feed_too_large",
+                RssError::TooManyItems { .. } => "too_many_items",
+                RssError::NestingTooDeep { .. } => "nesting_too_deep",
+                RssError::InvalidContentType { .. } => "invalid_content_type",
+                RssError::RSSParse(_) => "invalid_feed",
+                RssError::IO(_) => "io_error",
+                RssError::UTF8(_) => "invalid_utf8",
+            },
+            RssHandlerError::Tracing(_) => "tracing_error",
+            RssHandlerError::RateLimited { .. } => "rate_limited",
+            RssHandlerError::Auth(AuthError::MissingKey) => "missing_api_key",
+            RssHandlerError::Auth(AuthError::InvalidKey) => "invalid_api_key",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{API_FILTER_PATH, API_PREVIEW_PATH};
+    use http::StatusCode;
+    use test_case::test_case;
+
+    fn request(path: &str, accept: Option<&str>) -> Request<()> {
+        let mut builder = Request::builder().uri(format!("https://test.example.com{path}"));
+        if let Some(accept) = accept {
+            builder = builder.header("accept", accept);
+        }
+        builder.body(()).unwrap()
+    }
+
+    #[test_case("/", None, ErrorFormat::Text; "no accept")]
+    #[test_case("/", Some("*/*"), ErrorFormat::Text; "anything")]
+    #[test_case(
+        "/",
+        Some("application/rss+xml, application/xml;q=0.9, */*;q=0.8"),
+        ErrorFormat::Text;
+        "feed reader"
+    )]
+    #[test_case("/", Some("application/json"), ErrorFormat::Problem; "json")]
+    #[test_case("/", Some("application/problem+json"), ErrorFormat::Problem; "problem json")]
+    #[test_case(
+        "/",
+        Some("text/plain;q=0.5, application/json"),
+        ErrorFormat::Problem;
+        "json preferred"
+    )]
+    #[test_case(
+        "/",
+        Some("application/json;q=0.5, text/plain"),
+        ErrorFormat::Text;
+        "text preferred"
+    )]
+    #[test_case("/", Some("not a media type"), ErrorFormat::Text; "invalid accept")]
+    #[test_case(API_FILTER_PATH, None, ErrorFormat::Problem; "api")]
+    #[test_case(API_FILTER_PATH, Some("text/plain"), ErrorFormat::Problem; "api with text")]
+    #[test_case(API_PREVIEW_PATH, None, ErrorFormat::Problem; "preview api")]
+    fn test_for_request(path: &str, accept: Option<&str>, expected: ErrorFormat) {
+        assert_eq!(ErrorFormat::for_request(&request(path, accept)), expected);
+    }
+
+    #[test]
+    fn test_problem_response() {
+        let err = RssHandlerError::from(RssError::FeedTooLarge { max_size: 10 });
+        let response = ErrorFormat::Problem.respond(err.code(), Response::from(&err));
+
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(response.headers()[CONTENT_TYPE], "application/problem+json");
+
+        let problem: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(
+            problem,
+            json!({
+                "type": "about:blank",
+                "title": "Payload Too Large",
+                "status": 413,
+                "detail": err.to_string(),
+                "code": "feed_too_large",
+            })
+        );
+    }
+
+    #[test]
+    fn test_text_response() {
+        let err = RequestValidationError::NotFound;
+        let code = err.code();
+        let response = ErrorFormat::Text.respond(code, Response::from(err));
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(response.headers()[CONTENT_TYPE], "text/plain");
+        assert_eq!(response.body(), "Not Found");
+    }
+
+    #[test_case(ValidationError::NoUrlProvided, "no_url"; "no url")]
+    #[test_case(
+        ValidationError::UrlPolicy(UrlPolicyError::PrivateAddress("127.0.0.1".to_string())),
+        "private_address";
+        "url policy"
+    )]
+    #[test_case(ValidationError::Signature(SignatureError::Invalid), "invalid_signature"; "signature")]
+    fn test_validation_codes(err: ValidationError, code: &str) {
+        assert_eq!(RssHandlerError::from(err).code(), code);
+    }
+
+    #[test_case(
+        RssError::HttpClient(HttpClientError::Timeout(std::time::Duration::from_secs(1))),
+        "upstream_timeout";
+        "timeout"
+    )]
+    #[test_case(
+        RssError::HttpClient(HttpClientError::UrlPolicy(UrlPolicyError::DeniedHost(
+            "example.com".to_string()
+        ))),
+        "denied_host";
+        "redirect to denied host"
+    )]
+    #[test_case(
+        RssError::HttpClient(HttpClientError::Request("connection refused".to_string())),
+        "upstream_error";
+        "request failed"
+    )]
+    #[test_case(RssError::TooManyItems { max_items: 1 }, "too_many_items"; "too many items")]
+    #[test_case(RssError::NestingTooDeep { max_depth: 1 }, "nesting_too_deep"; "nesting too deep")]
+    fn test_rss_codes(err: RssError, code: &str) {
+        assert_eq!(RssHandlerError::from(err).code(), code);
+    }
+}
@@ -0,0 +1,128 @@
+use std::net::{IpAddr, Ipv6Addr};
+use std::time::Duration;
+
+use http::HeaderValue;
+use tracing::warn;
+
+use filter_rss_feed::{RateLimit, RateLimitDecision};
+
+use crate::{Backend, RssHandlerError};
+
+/// How often clients may have feeds filtered, and how often feeds may be
+/// fetched from each upstream host. Either limit is off when `None`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RateLimitConfig {
+    /// Requests each client may make, by [`ClientAddr`]
+    pub client: Option<RateLimit>,
+    /// Fetches which may be made from each upstream host. Feeds answered
+    /// from a cache aren't fetched, so don't count. Only applies to a
+    /// [`Backend`](crate::Backend) made with
+    /// [`Backend::with_config`](crate::Backend::with_config)
+    pub upstream: Option<RateLimit>,
+}
+
+/// Request extension with the address of the client a request came from,
+/// which front ends set for the client rate limit. Requests without one
+/// aren't limited.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ClientAddr(pub IpAddr);
+
+impl ClientAddr {
+    /// The key the client's requests are counted under. IPv6 clients are
+    /// usually given a whole /64, so it's counted as one.
+    fn key(&self) -> String {
+        match self.0 {
+            IpAddr::V4(ip) => format!("client:{ip}"),
+            IpAddr::V6(ip) => {
+                let prefix = u128::from(ip) & (u128::MAX << 64);
+                format!("client:{}/64", Ipv6Addr::from(prefix))
+            }
+        }
+    }
+}
+
+/// Takes a request from the client's allowance, if there's a client limit.
+/// When the store fails the request goes ahead, since it's only counting.
+pub(crate) async fn check_client(
+    client: Option<&ClientAddr>,
+    config: &RateLimitConfig,
+    backend: &Backend,
+) -> Result<(), RssHandlerError> {
+    let (Some(client), Some(limit)) = (client, config.client) else {
+        return Ok(());
+    };
+
+    match backend.rate_limits.take(&client.key(), &limit).await {
+        Ok(RateLimitDecision::Allowed) => Ok(()),
+        Ok(RateLimitDecision::Limited { retry_after }) => {
+            Err(RssHandlerError::RateLimited { retry_after })
+        }
+        Err(err) => {
+            warn!(%err, "Failed to check the client rate limit");
+            Ok(())
+        }
+    }
+}
+
+/// Whole seconds to wait, rounded up so that clients don't come back too
+/// soon, and at least one.
+pub(crate) fn retry_after_seconds(retry_after: Duration) -> u64 {
+    let seconds = retry_after
+        .as_secs()
+        .saturating_add(u64::from(retry_after.subsec_nanos() > 0));
+    seconds.max(1)
+}
+
+/// `Retry-After`, in seconds.
+pub(crate) fn retry_after_header(retry_after: Duration) -> HeaderValue {
+    HeaderValue::from(retry_after_seconds(retry_after))
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use matches::assert_matches;
+    use test_case::test_case;
+
+    #[test_case("192.0.2.1", "client:192.0.2.1"; "ipv4")]
+    #[test_case("2001:db8:1:2:3:4:5:6", "client:2001:db8:1:2::/64"; "ipv6")]
+    fn test_client_key(ip: &str, expected: &str) {
+        assert_eq!(ClientAddr(ip.parse().unwrap()).key(), expected);
+    }
+
+    #[test_case(Duration::ZERO => 1; "zero")]
+    #[test_case(Duration::from_millis(1500) => 2; "fraction")]
+    #[test_case(Duration::from_secs(30) => 30; "whole")]
+    #[test_case(Duration::MAX => u64::MAX; "forever")]
+    fn test_retry_after_seconds(retry_after: Duration) -> u64 {
+        retry_after_seconds(retry_after)
+    }
+
+    #[tokio::test]
+    async fn test_check_client() {
+        let backend = Backend::new().unwrap();
+        let config = RateLimitConfig {
+            client: Some(RateLimit {
+                per_minute: 1,
+                burst: 1,
+            }),
+            ..Default::default()
+        };
+        let client = ClientAddr("192.0.2.1".parse().unwrap());
+        let other = ClientAddr("192.0.2.2".parse().unwrap());
+
+        assert_matches!(check_client(Some(&client), &config, &backend).await, Ok(()));
+        assert_matches!(
+            check_client(Some(&client), &config, &backend).await,
+            Err(RssHandlerError::RateLimited { .. })
+        );
+        assert_matches!(check_client(Some(&other), &config, &backend).await, Ok(()));
+
+        // Without an address or a limit, nothing is counted
+        assert_matches!(check_client(None, &config, &backend).await, Ok(()));
+        assert_matches!(
+            check_client(Some(&client), &RateLimitConfig::default(), &backend).await,
+            Ok(())
+        );
+    }
+}
@@ -0,0 +1,36 @@
+use bytes::Bytes;
+use http::header::{CACHE_CONTROL, CONTENT_TYPE};
+use http::{Response, StatusCode};
+
+/// The page, which is self-contained so that every front end can serve it.
+const INDEX_HTML: &str = include_str!("../static/index.html");
+
+/// Answers `/ui`: a page for writing filters, which previews them against
+/// the feed as they're typed and gives the URL to subscribe to.
+pub(crate) fn ui() -> Response<Bytes> {
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "text/html; charset=utf-8")
+        .header(CACHE_CONTROL, "public, max-age=300")
+        .body(Bytes::from_static(INDEX_HTML.as_bytes()))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ui() {
+        let response = ui();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[CONTENT_TYPE], "text/html; charset=utf-8");
+
+        let body = std::str::from_utf8(response.body()).unwrap();
+        assert!(body.contains("/api/v1/preview"));
+        for field in ["title", "guid", "link"] {
+            assert!(body.contains(&format!("{field}_filter_regex")), "{field}");
+        }
+    }
+}
@@ -0,0 +1,544 @@
+use std::convert::Infallible;
+use std::env;
+use std::error::Error;
+use std::future::{Future, pending};
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use bytes::Bytes;
+use clap::Parser;
+use http::header::{CONTENT_TYPE, HOST};
+use http::uri::{Authority, Scheme};
+use http::{Request, Response, StatusCode, Uri};
+use http_body_util::{BodyExt, Full, Limited};
+use hyper::body::Incoming;
+use hyper::service::service_fn;
+use hyper_util::rt::{TokioExecutor, TokioIo, TokioTimer};
+use hyper_util::server::conn::auto;
+use hyper_util::server::graceful::GracefulShutdown;
+use tokio::net::TcpListener;
+use tracing::{debug, info, warn};
+
+use filter_rss_feed::create_rate_limit_store;
+use rssfilter_handler::{
+    Backend, ClientAddr, Config, Revalidate, initialise_otel_with_config, real_main, revalidate,
+};
+
+#[derive(Parser, Debug)]
+#[command(name = "rssfilter-server", version)]
+struct Opt {
+    /// Address to listen on.
+    #[arg(short, long, default_value = "127.0.0.1:8080")]
+    bind: SocketAddr,
+
+    /// Seconds to wait for requests in flight to finish when shutting down.
+    #[arg(long, default_value_t = 30)]
+    shutdown_timeout: u64,
+
+    /// Seconds clients have to send a request's headers before their
+    /// connection is closed.
+    #[arg(long, default_value_t = 30)]
+    header_timeout: u64,
+
+    /// The scheme and host clients reach the server on, such as
+    /// `https://rss.example.com` behind a proxy which terminates TLS.
+    /// Filtered feeds link to it. When unset, the `Host` each request is sent
+    /// with is trusted, over `http`.
+    #[arg(long, value_parser = parse_public_url)]
+    public_url: Option<Uri>,
+}
+
+/// How long to wait after failing to accept a connection, such as when out
+/// of file descriptors, before trying again.
+const ACCEPT_ERROR_BACKOFF: Duration = Duration::from_millis(100);
+
+/// What every request is handled with: the same configuration as the worker,
+/// read from the same environment variables, one backend shared by all
+/// requests, and the URL clients reach us on.
+struct State {
+    config: Config,
+    backend: Backend,
+    public_url: Option<Uri>,
+}
+
+fn parse_public_url(value: &str) -> Result<Uri, String> {
+    let uri: Uri = value.parse().map_err(|err| format!("{err}"))?;
+    if uri.scheme().is_none() || uri.authority().is_none() {
+        return Err("must be an absolute URL, such as https://rss.example.com".to_string());
+    }
+
+    Ok(uri)
+}
+
+pub async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
+    let opt = Opt::parse();
+    let config = Config::from_vars(|name| env::var(name).ok());
+
+    if let Err(err) = initialise_otel_with_config(&config.telemetry) {
+        return Err(err.to_string().into());
+    }
+
+    let backend = Backend::with_config(&config, Arc::from(create_rate_limit_store()))?;
+
+    let listener = TcpListener::bind(opt.bind).await?;
+    info!(address = %listener.local_addr()?, "Listening");
+
+    serve(
+        listener,
+        State {
+            config,
+            backend,
+            public_url: opt.public_url,
+        },
+        shutdown_signal(),
+        Timeouts {
+            header: Duration::from_secs(opt.header_timeout),
+            shutdown: Duration::from_secs(opt.shutdown_timeout),
+        },
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// How long clients get to send their headers, and requests in flight get to
+/// finish once we're shutting down.
+struct Timeouts {
+    header: Duration,
+    shutdown: Duration,
+}
+
+/// Serves requests on `listener` until `shutdown` completes. Then no more
+/// connections are accepted, and requests in flight are given
+/// `timeouts.shutdown` to finish.
+async fn serve(
+    listener: TcpListener,
+    state: State,
+    shutdown: impl Future<Output = ()>,
+    timeouts: Timeouts,
+) -> io::Result<()> {
+    let state = Arc::new(state);
+    let mut builder = auto::Builder::new(TokioExecutor::new());
+    // Clients which trickle their headers in don't get to hold connections
+    // open for ever
+    builder
+        .http1()
+        .timer(TokioTimer::new())
+        .header_read_timeout(timeouts.header);
+    builder.http2().timer(TokioTimer::new());
+    let graceful = GracefulShutdown::new();
+    let grace = timeouts.shutdown;
+
+    tokio::pin!(shutdown);
+
+    loop {
+        let (stream, remote) = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok(accepted) => accepted,
+                Err(err) => {
+                    warn!(%err, "Failed to accept connection");
+                    tokio::time::sleep(ACCEPT_ERROR_BACKOFF).await;
+                    continue;
+                }
+            },
+            () = &mut shutdown => break,
+        };
+
+        let state = Arc::clone(&state);
+        let service = service_fn(move |req| {
+            let state = Arc::clone(&state);
+            async move { Ok::<_, Infallible>(handle(req, remote, state).await) }
+        });
+
+        let connection = builder
+            .serve_connection_with_upgrades(TokioIo::new(stream), service)
+            .into_owned();
+        let connection = graceful.watch(connection);
+
+        tokio::spawn(async move {
+            if let Err(err) = connection.await {
+                debug!(%remote, %err, "Connection failed");
+            }
+        });
+    }
+
+    drop(listener);
+    info!("Shutting down, waiting for requests in flight to finish");
+
+    if tokio::time::timeout(grace, graceful.shutdown())
+        .await
+        .is_err()
+    {
+        warn!(?grace, "Gave up waiting for requests in flight");
+    }
+
+    Ok(())
+}
+
+/// The whole URL a request was made to. Clients usually send only the path
+/// and query, with the host in `Host`, but the handler wants the URL as the
+/// worker sees it. With a `public_url`, that's where requests are said to
+/// have been made to, whatever they say themselves.
+fn absolute_uri<B>(req: &Request<B>, public_url: Option<&Uri>) -> Uri {
+    let uri = req.uri();
+
+    let (scheme, authority) =
+        match public_url.and_then(|url| Some((url.scheme()?, url.authority()?))) {
+            Some((scheme, authority)) => (scheme.clone(), authority.clone()),
+            None if uri.authority().is_some() => return uri.clone(),
+            None => (
+                Scheme::HTTP,
+                req.headers()
+                    .get(HOST)
+                    .and_then(|host| Authority::try_from(host.as_bytes()).ok())
+                    .unwrap_or(Authority::from_static("localhost")),
+            ),
+        };
+
+    let mut parts = uri.clone().into_parts();
+    parts.scheme = Some(scheme);
+    parts.authority = Some(authority);
+    if parts.path_and_query.is_none() {
+        parts.path_and_query = Some("/".parse().expect("/ is a valid path"));
+    }
+
+    Uri::from_parts(parts).unwrap_or_else(|_| uri.clone())
+}
+
+/// Reads the whole body of a request, which may be a filter definition or a
+/// feed, so no larger than the feeds we fetch.
+async fn collect_request(
+    req: Request<Incoming>,
+    state: &State,
+) -> Result<Request<Bytes>, Response<Full<Bytes>>> {
+    let uri = absolute_uri(&req, state.public_url.as_ref());
+    let (mut parts, body) = req.into_parts();
+    parts.uri = uri;
+
+    let limit = usize::try_from(state.config.limits.max_body_bytes).unwrap_or(usize::MAX);
+    let body = match Limited::new(body, limit).collect().await {
+        Ok(body) => body.to_bytes(),
+        Err(err) => {
+            debug!(%err, "Failed to read request body");
+
+            return Err(Response::builder()
+                .status(StatusCode::PAYLOAD_TOO_LARGE)
+                .header(CONTENT_TYPE, "text/plain")
+                .body(Full::new(Bytes::from("Request body is too large")))
+                .expect("Valid response"));
+        }
+    };
+
+    Ok(Request::from_parts(parts, body))
+}
+
+/// Handles a request from `remote`. Clients are rate limited by the address
+/// they connect from, so a proxy in front of the server counts as one client.
+async fn handle(
+    req: Request<Incoming>,
+    remote: SocketAddr,
+    state: Arc<State>,
+) -> Response<Full<Bytes>> {
+    let mut req = match collect_request(req, &state).await {
+        Ok(req) => req,
+        Err(response) => return response,
+    };
+    req.extensions_mut().insert(ClientAddr(remote.ip()));
+
+    let response = real_main(req, state.config.clone(), &state.backend).await;
+
+    // A stale feed was served from the cache: refresh it in the background,
+    // as the worker does once its response has gone
+    if let Some(Revalidate { uri, headers }) = response.extensions().get::<Revalidate>().cloned() {
+        tokio::spawn(async move {
+            if let Err(err) = revalidate(&uri, &headers, &state.config, &state.backend).await {
+                warn!(err = %err, "Failed to refresh stale filtered feed");
+            }
+        });
+    }
+
+    response.map(Full::new)
+}
+
+/// Completes on Ctrl-C, or on `SIGTERM` where there is one.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(err) = tokio::signal::ctrl_c().await {
+            warn!(%err, "Failed to listen for Ctrl-C");
+            pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(err) => {
+                warn!(%err, "Failed to listen for SIGTERM");
+                pending::<()>().await;
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = pending::<()>();
+
+    tokio::select! {
+        () = ctrl_c => {}
+        () = terminate => {}
+    }
+
+    info!("Received shutdown signal");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use filter_rss_feed::UrlPolicy;
+    use test_utils::feed::serve_test_rss_feed;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::sync::oneshot;
+    use tokio::task::JoinHandle;
+    use url::Url;
+
+    struct TestServer {
+        address: SocketAddr,
+        shutdown: oneshot::Sender<()>,
+        handle: JoinHandle<io::Result<()>>,
+    }
+
+    /// Starts a server on a free port. The feeds are served from loopback,
+    /// which the default policy refuses.
+    async fn start() -> TestServer {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let (shutdown, shutdown_rx) = oneshot::channel();
+
+        let state = State {
+            config: Config {
+                url_policy: UrlPolicy {
+                    allow_private_addresses: true,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            backend: Backend::new().unwrap(),
+            public_url: None,
+        };
+
+        let handle = tokio::spawn(serve(
+            listener,
+            state,
+            async {
+                let _ = shutdown_rx.await;
+            },
+            Timeouts {
+                header: Duration::from_secs(5),
+                shutdown: Duration::from_secs(5),
+            },
+        ));
+
+        TestServer {
+            address,
+            shutdown,
+            handle,
+        }
+    }
+
+    #[test]
+    fn test_absolute_uri() {
+        let req = Request::builder()
+            .uri("/?url=x")
+            .header(HOST, "rssfilter.example.com:8080")
+            .body(())
+            .unwrap();
+        assert_eq!(
+            absolute_uri(&req, None),
+            "http://rssfilter.example.com:8080/?url=x"
+        );
+
+        let req = Request::builder()
+            .uri("https://rssfilter.example.com/?url=x")
+            .header(HOST, "other.example.com")
+            .body(())
+            .unwrap();
+        assert_eq!(
+            absolute_uri(&req, None),
+            "https://rssfilter.example.com/?url=x"
+        );
+
+        let req = Request::builder().uri("/").body(()).unwrap();
+        assert_eq!(absolute_uri(&req, None), "http://localhost/");
+    }
+
+    #[test]
+    fn test_absolute_uri_with_public_url() {
+        let public_url = parse_public_url("https://rss.example.com").unwrap();
+
+        // Neither the Host nor an absolute request target is believed
+        let req = Request::builder()
+            .uri("/?url=x")
+            .header(HOST, "attacker.example.com")
+            .body(())
+            .unwrap();
+        assert_eq!(
+            absolute_uri(&req, Some(&public_url)),
+            "https://rss.example.com/?url=x"
+        );
+
+        let req = Request::builder()
+            .uri("http://attacker.example.com/ui")
+            .body(())
+            .unwrap();
+        assert_eq!(
+            absolute_uri(&req, Some(&public_url)),
+            "https://rss.example.com/ui"
+        );
+    }
+
+    #[test]
+    fn test_parse_public_url() {
+        assert!(parse_public_url("https://rss.example.com:8443").is_ok());
+        assert!(parse_public_url("rss.example.com").is_err());
+        assert!(parse_public_url("/feeds").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_header_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let state = State {
+            config: Config::default(),
+            backend: Backend::new().unwrap(),
+            public_url: None,
+        };
+        tokio::spawn(serve(
+            listener,
+            state,
+            pending(),
+            Timeouts {
+                header: Duration::from_millis(100),
+                shutdown: Duration::from_secs(5),
+            },
+        ));
+
+        // Headers which never finish
+        let mut stream = tokio::net::TcpStream::connect(address).await.unwrap();
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: x\r\n")
+            .await
+            .unwrap();
+
+        let mut response = Vec::new();
+        tokio::time::timeout(Duration::from_secs(5), stream.read_to_end(&mut response))
+            .await
+            .expect("Connection should be closed")
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_filters_feed() {
+        let feed = serve_test_rss_feed(&["1", "2"]).await.unwrap();
+        let server = start().await;
+
+        // The feed is on 127.0.0.1 too, which would otherwise be ourselves
+        let url = Url::parse_with_params(
+            &format!("http://localhost:{}/", server.address.port()),
+            [
+                ("url", feed.url().as_str()),
+                ("title_filter_regex", "Test Item 1"),
+            ],
+        )
+        .unwrap();
+        let response = reqwest::get(url).await.unwrap();
+
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.headers()["x-rssfilter-items-removed"], "1");
+
+        let body = response.text().await.unwrap();
+        assert!(!body.contains("Test Item 1"));
+        assert!(body.contains("Test Item 2"));
+    }
+
+    #[tokio::test]
+    async fn test_head() {
+        let feed = serve_test_rss_feed(&["1", "2"]).await.unwrap();
+        let server = start().await;
+
+        let url = Url::parse_with_params(
+            &format!("http://localhost:{}/", server.address.port()),
+            [
+                ("url", feed.url().as_str()),
+                ("title_filter_regex", "Test Item 1"),
+            ],
+        )
+        .unwrap();
+        let client = reqwest::Client::new();
+        let get = client.get(url.clone()).send().await.unwrap();
+        let head = client.head(url).send().await.unwrap();
+
+        assert_eq!(head.status(), 200);
+        assert_eq!(
+            head.headers()["content-length"],
+            get.text().await.unwrap().len().to_string()
+        );
+        assert!(head.bytes().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_post_definition() {
+        let feed = serve_test_rss_feed(&["1", "2"]).await.unwrap();
+        let server = start().await;
+
+        let definition = format!(
+            r#"{{"url": "{}", "title_filter_regex": "Test Item 1"}}"#,
+            feed.url()
+        );
+        let response = reqwest::Client::new()
+            .post(format!("http://localhost:{}/", server.address.port()))
+            .header(CONTENT_TYPE, "application/json")
+            .body(definition)
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.headers()["x-rssfilter-items-removed"], "1");
+    }
+
+    #[tokio::test]
+    async fn test_same_errors_as_worker() {
+        let server = start().await;
+
+        let not_found = reqwest::get(format!("http://{}/feed", server.address))
+            .await
+            .unwrap();
+        assert_eq!(not_found.status(), 404);
+
+        let no_params = reqwest::get(format!("http://{}/", server.address))
+            .await
+            .unwrap();
+        assert_eq!(no_params.status(), 400);
+    }
+
+    #[tokio::test]
+    async fn test_graceful_shutdown() {
+        let server = start().await;
+
+        server.shutdown.send(()).unwrap();
+        server.handle.await.unwrap().unwrap();
+
+        assert!(
+            reqwest::get(format!("http://{}/", server.address))
+                .await
+                .is_err()
+        );
+    }
+}
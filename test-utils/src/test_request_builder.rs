@@ -1,5 +1,5 @@
+use bytes::Bytes;
 use http::{Method, Request};
-use worker::Body;
 
 pub struct RequestBuilder {
     path: String,
@@ -50,7 +50,7 @@ impl RequestBuilder {
         self
     }
 
-    pub fn build(self) -> Result<Request<Body>, http::Error> {
+    pub fn build(self) -> Result<Request<Bytes>, http::Error> {
         let mut url = format!("https://test.example.com{}", self.path);
 
         if !self.query_params.is_empty() {
@@ -67,6 +67,6 @@ impl RequestBuilder {
         Request::builder()
             .method(self.method)
             .uri(url)
-            .body(Body::empty())
+            .body(Bytes::new())
     }
 }
@@ -13,4 +13,7 @@ export PATH="${HOME}/.cargo/bin:${PATH}"
 
 cargo install -q worker-build
 
+# Reported by `/version`. Workers Builds checks out the commit it was given.
+export RSSFILTER_GIT_REVISION="${WORKERS_CI_COMMIT_SHA:-$(git rev-parse HEAD 2>/dev/null || true)}"
+
 cd workers-rssfilter && worker-build --release